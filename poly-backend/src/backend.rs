@@ -1,179 +1,258 @@
 use std::{
 	borrow::Cow,
 	collections::{HashMap, HashSet},
-	path::PathBuf,
-	sync::{Arc, Mutex, RwLock},
+	fmt::Display,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+		Arc, Mutex, RwLock, Weak,
+	},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use directories::ProjectDirs;
-use futures_util::StreamExt;
-pub use llm::{InferenceFeedback, InferenceResponse};
+use futures_util::{Stream, StreamExt};
 use llm::{
-	InferenceParameters, InferenceSession, InferenceSessionConfig, InferenceSnapshot, InferenceStats, Model, ModelParameters, OutputRequest, Prompt,
-	TokenId, TokenizerSource,
+	InferenceError, InferenceParameters, InferenceSession, InferenceSessionConfig, InferenceSnapshot, InferenceStats, Model, OutputRequest, Prompt,
+	TokenId, Tokenizer,
 };
+pub use llm::{InferenceFeedback, InferenceResponse};
+use poly_bias::{json::JsonSchema, vocabulary::StringTokens};
 use regex::Regex;
+use sha2::{Digest, Sha256};
 use tokio::{fs::File, io::AsyncWriteExt, sync::mpsc::Sender, task::spawn_blocking};
+use uuid::Uuid;
 
 use crate::{
-	config::{BackendConfig, ModelConfig},
-	memory::{hierarchically_chunk, Memory, MemoryError},
-	session::BackendSession,
-	stats::TaskStats,
-	types::{BackendError, EmbeddingResponse, PromptRequest, SessionRequest, TokenResponse, TokenizationResponse},
+	cache::{CachedCompletion, ResponseCache},
+	check::check_config,
+	config::{BackendConfig, ModelConfig, SamplerConfig, TaskConfig},
+	memory::{cosine_similarity, filter_chunks, hierarchically_chunk, normalize_text, overlap_chunks, Memory, TokenWithCharacters},
+	session::{ActiveSession, BackendSession},
+	stats::{MemoryStats, ModelStats, TaskStats},
+	types::{
+		BackendError, DetokenizationResponse, EmbeddingResponse, IngestProgress, MemorizeSummary, PromptRequest, RecalledChunk, ReloadResponse,
+		ScoreRequest, ScoreResponse, SessionInfo, SessionRequest, TokenResponse, TokenScore, TokenizationResponse,
+	},
 };
 
 use tracing::*;
 
 pub struct BackendStats {
 	pub task_stats: Mutex<HashMap<String, TaskStats>>,
+	pub model_stats: Mutex<HashMap<String, ModelStats>>,
+
+	/// Recall and ingest counters per memory (the number of items stored is not tracked here, see [Backend::memory_counts])
+	pub memory_stats: Mutex<HashMap<String, MemoryStats>>,
 }
 
 pub struct Backend {
-	pub config: BackendConfig,
-	pub models: HashMap<String, Arc<Box<dyn llm::Model>>>,
-	pub memories: HashMap<String, Arc<Box<dyn Memory>>>,
+	/// The current configuration (replaced when the configuration is reloaded, see [Backend::reload])
+	config: RwLock<Arc<BackendConfig>>,
+	pub models: RwLock<HashMap<String, Arc<Box<dyn llm::Model>>>>,
+	pub memories: RwLock<HashMap<String, Arc<Box<dyn Memory>>>>,
 	pub stats: Arc<BackendStats>,
-	pub prelude_snapshots: RwLock<HashMap<String, InferenceSnapshot>>,
+	/// Snapshots of the model state after feeding a prelude, keyed by [Backend::prelude_snapshot_key]
+	pub prelude_snapshots: Mutex<ResponseCache<InferenceSnapshot>>,
+	model_last_used: Mutex<HashMap<String, Instant>>,
+
+	/// Held while a model is being loaded, so that each model is only loaded once when it is needed by several sessions
+	model_load_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+
+	/// Tokens of the vocabulary of each loaded model that can appear in strings, shared by the biasers of all sessions
+	/// using the model (replaced whenever the model is loaded again)
+	string_tokens: Mutex<HashMap<String, Arc<StringTokens>>>,
+
+	/// Held while the configuration is being reloaded, so that reloads do not run concurrently
+	reload_lock: tokio::sync::Mutex<()>,
+
+	/// Registry of the sessions that are active for each task
+	active_sessions: Mutex<HashMap<String, Vec<Weak<ActiveSession>>>>,
+
+	/// Identifier assigned to the next session that is started
+	next_session_id: AtomicU64,
+
+	/// Cached responses for tasks that have response caching enabled
+	response_cache: Mutex<ResponseCache<CachedCompletion>>,
+
+	/// Sessions that are kept between requests so that they can be continued (see [Backend::create_session])
+	stored_sessions: Mutex<HashMap<Uuid, StoredSession>>,
+}
+
+/// A session that is kept between requests
+struct StoredSession {
+	task_name: String,
+	sub: Option<String>,
+	last_used: Instant,
+	session: Arc<Mutex<BackendSession>>,
 }
 
 const CACHE_MODELS_DIR: &str = "models";
 
+/// When recalling with deduplication, this many times the requested number of items are fetched as candidates
+const RECALL_DEDUP_CANDIDATE_FACTOR: usize = 3;
+
+/// Number of chunks that are embedded before they are stored in memory at once
+const MEMORIZE_BATCH_SIZE: usize = 32;
+
+/// Number of responses kept in the response cache when not configured
+const DEFAULT_RESPONSE_CACHE_SIZE: usize = 128;
+
+/// Number of prelude snapshots kept when not configured
+const DEFAULT_MAX_PRELUDE_SNAPSHOTS: usize = 16;
+
+/// Total size (in bytes) of the prelude snapshots kept when not configured
+const DEFAULT_MAX_PRELUDE_SNAPSHOTS_SIZE: usize = 2 << 30;
+
+/// Number of seconds after which an unused stored session is discarded when not configured
+const DEFAULT_SESSION_TTL_SECS: u64 = 600;
+
+/// Number of sessions that are stored at most when not configured
+const DEFAULT_MAX_STORED_SESSIONS: usize = 64;
+
+/// Number of sessions that are stored at most for a single user when not configured
+const DEFAULT_MAX_STORED_SESSIONS_PER_USER: usize = 8;
+
 impl Backend {
-	pub async fn from(mut config: BackendConfig, progress: Option<Sender<f64>>) -> Backend {
-		// Determine cache path
-		if config.cache_path.is_none() {
-			if let Some(pd) = ProjectDirs::from("nl.dialogic", "Dialogic", "Poly") {
-				config.cache_path = Some(pd.cache_dir().to_path_buf());
-			}
+	/// Create a backend for the configuration without loading any models or memories
+	fn unloaded(config: BackendConfig) -> Backend {
+		let response_cache = ResponseCache::new(
+			config.response_cache_size.unwrap_or(DEFAULT_RESPONSE_CACHE_SIZE),
+			config.response_cache_ttl_secs.map(Duration::from_secs),
+		);
+		let prelude_snapshots = ResponseCache::new(config.max_prelude_snapshots.unwrap_or(DEFAULT_MAX_PRELUDE_SNAPSHOTS), None)
+			.with_max_size(config.max_prelude_snapshots_size.unwrap_or(DEFAULT_MAX_PRELUDE_SNAPSHOTS_SIZE));
+		Backend {
+			config: RwLock::new(Arc::new(config)),
+			models: RwLock::new(HashMap::new()),
+			stats: Arc::new(BackendStats::default()),
+			memories: RwLock::new(HashMap::new()),
+			prelude_snapshots: Mutex::new(prelude_snapshots),
+			model_last_used: Mutex::new(HashMap::new()),
+			model_load_locks: Mutex::new(HashMap::new()),
+			string_tokens: Mutex::new(HashMap::new()),
+			reload_lock: tokio::sync::Mutex::new(()),
+			active_sessions: Mutex::new(HashMap::new()),
+			next_session_id: AtomicU64::new(1),
+			response_cache: Mutex::new(response_cache),
+			stored_sessions: Mutex::new(HashMap::new()),
 		}
+	}
+
+	/// Instantiates a backend from its configuration, loading (and if needed downloading) all models and memories. Models
+	/// that cannot be loaded and other configuration problems result in an error (see [`crate::check`] to verify a
+	/// configuration beforehand).
+	pub async fn from(mut config: BackendConfig, progress: Option<Sender<f64>>) -> Result<Backend, BackendError> {
+		// Determine cache path
+		Self::set_default_cache_path(&mut config);
 
 		// Ensure cache directory exists (if there is one)
 		let cache_path = config.cache_path.clone();
 		if let Some(ref cache_path) = cache_path {
-			tokio::fs::create_dir_all(cache_path.join(CACHE_MODELS_DIR)).await.unwrap();
+			tokio::fs::create_dir_all(cache_path.join(CACHE_MODELS_DIR))
+				.await
+				.map_err(|e| BackendError::InvalidConfig(format!("could not create cache directory: {e}")))?;
 		}
 
+		// Resolve preludes that are loaded from a file
+		Self::resolve_preludes(&mut config).await.map_err(BackendError::InvalidConfig)?;
+
 		tracing::info!(
 			metal = cfg!(feature = "metal"),
 			cache_path = cache_path.as_ref().map(|x| x.to_str().map(|y| y.to_string())),
 			"backend instantiating"
 		);
-		let mut backend = Backend {
-			config,
-			models: HashMap::new(),
-			stats: Arc::new(BackendStats::default()),
-			memories: HashMap::new(),
-			prelude_snapshots: RwLock::new(HashMap::new()),
-		};
+		let backend = Self::unloaded(config);
 
 		// Load models
-		let n_models = backend.config.models.len();
-		for (index, (model_name, model_config)) in backend.config.models.iter().enumerate() {
-			// Warn about invalid configurations
-			if !model_config.use_gpu && model_config.gpu_layers.is_some() {
-				tracing::warn!("gpu_layers set but ignored because use_gpu is not set to true");
-			}
-			if cfg!(feature = "metal") && model_config.use_gpu && model_config.gpu_layers.is_some() {
-				tracing::warn!("gpu_layers set but ignored because with the Metal backend, all layers are run on the GPU");
-			}
-
-			// Check if we already have a copy of the model, or download it
-			let actual_model_path = model_config.model_path.clone().unwrap_or_else(|| {
-				cache_path
-					.clone()
-					.expect("cache path is set when models without path are specified")
-					.join(CACHE_MODELS_DIR)
-					.join(format!("{model_name}.bin"))
-			});
-
-			if !actual_model_path.exists() {
-				// See if we can download this file
-				if let Some(ref url) = model_config.url {
-					// Download
-					tracing::info!("downloading model {model_name} from {url}");
-					Self::download_model(url, &actual_model_path).await.expect("could not download model");
-					if !actual_model_path.exists() {
-						panic!("model file not found for model {model_name} at path {actual_model_path:?} even after downloading");
-					}
-				} else {
-					panic!("model file not found for model {model_name} at path {actual_model_path:?}");
-				}
-			}
-
-			// Set up hyperparameters
-			let params = ModelParameters {
-				prefer_mmap: true,
-				context_size: model_config.context_size,
-				lora_adapters: model_config.lora_adapters.clone(),
-				use_gpu: model_config.use_gpu,
-				gpu_layers: model_config.gpu_layers,
-				rope_overrides: None,
-				n_gqa: None,
-			};
-
-			// Actually load the model
-			let model_config = model_config.clone();
-			let model_name_copy = model_name.clone();
-
+		let config = backend.config();
+		let n_models = config.models.len();
+		for (index, (model_name, model_config)) in config.models.iter().enumerate() {
 			let progress_sender = progress.clone();
-			let model = spawn_blocking(move || {
-				Arc::new(
-					llm::load_dynamic(
-						Some(model_config.architecture),
-						&actual_model_path,
-						TokenizerSource::Embedded,
-						params,
-						|load_progress| {
-							let fp: f64 = match load_progress {
-								llm::LoadProgress::HyperparametersLoaded => 0.0,
-								llm::LoadProgress::ContextSize { .. } => 0.0,
-								llm::LoadProgress::LoraApplied { .. } => 0.0,
-								llm::LoadProgress::TensorLoaded {
-									current_tensor,
-									tensor_count,
-								} => (current_tensor as f64) / (tensor_count as f64),
-								llm::LoadProgress::Loaded { .. } => 1.0,
-							};
-							if let Some(ref p) = progress_sender {
-								_ = p.blocking_send((index as f64 + fp) / n_models as f64);
-							}
-							trace!("Loading model {model_name_copy}: {load_progress:#?}");
-						},
-					)
-					.expect("load model"),
-				)
+			let model = Self::prepare_model(&config, model_name, model_config, move |fp| {
+				if let Some(ref p) = progress_sender {
+					_ = p.blocking_send((index as f64 + fp) / n_models as f64);
+				}
 			})
-			.await
-			.unwrap();
+			.await?;
 
-			backend.models.insert(model_name.clone(), model);
-			info!("Loaded model {} use_gpu={:?}", model_name, model_config.use_gpu);
+			if let Some(model) = model {
+				backend.model_last_used.lock().unwrap().insert(model_name.clone(), Instant::now());
+				backend.insert_model(model_name, model);
+				info!("Loaded model {} use_gpu={:?}", model_name, model_config.use_gpu);
+			}
 		}
 
 		info!("All models loaded");
 
 		// Load memories
-		for (memory_name, memory_config) in backend.config.memories.iter() {
+		for (memory_name, memory_config) in config.memories.iter() {
 			info!("Loading memory {memory_name}");
-			if !backend.models.contains_key(&memory_config.embedding_model) {
-				panic!("embedding model {} not found for memory {}", memory_config.embedding_model, memory_name);
+			if !config.models.contains_key(&memory_config.embedding_model) {
+				return Err(BackendError::InvalidConfig(format!(
+					"embedding model {} not found for memory {}",
+					memory_config.embedding_model, memory_name
+				)));
+			}
+			if config.verify_embedding_models {
+				let embedding = match backend
+					.embedding_async(
+						&memory_config.embedding_model,
+						&PromptRequest {
+							prompt: String::from(" "),
+							assistant_prefix: None,
+						},
+					)
+					.await
+				{
+					Ok(embedding) => embedding,
+					Err(e) => {
+						return Err(BackendError::EmbeddingUnsupported(format!(
+							"embedding model {} for memory {memory_name} failed verification: {e}",
+							memory_config.embedding_model
+						)))
+					}
+				};
+				if embedding.embedding.len() != memory_config.dimensions {
+					return Err(BackendError::InvalidConfig(format!(
+						"embedding model {} produces embeddings with {} dimensions, but memory {memory_name} is configured for {}",
+						memory_config.embedding_model,
+						embedding.embedding.len(),
+						memory_config.dimensions
+					)));
+				}
 			}
-			let mem = memory_config.store.from(memory_config).expect("memory construction");
-			backend.memories.insert(memory_name.clone(), Arc::new(mem));
+			let mem = memory_config
+				.store
+				.from(memory_config)
+				.map_err(|e| BackendError::InvalidConfig(format!("memory {memory_name} could not be constructed: {e}")))?;
+			backend.memories.write().unwrap().insert(memory_name.clone(), Arc::new(mem));
 		}
 
 		info!("All memories loaded");
 
 		// Verify tasks
-		for (task_name, task_config) in &backend.config.tasks {
-			if !backend.models.contains_key(&task_config.model) {
-				panic!("model {} not found for task {}", task_config.model, task_name);
+		for (task_name, task_config) in &config.tasks {
+			if !config.models.contains_key(&task_config.model) {
+				return Err(BackendError::InvalidConfig(format!(
+					"model {} not found for task {}",
+					task_config.model, task_name
+				)));
 			}
 
 			if let Some(memorization) = &task_config.memorization {
-				if !backend.memories.contains_key(&memorization.memory) {
-					panic!("memory {} not found for task {}", memorization.memory, task_name);
+				if !backend.memories.read().unwrap().contains_key(&memorization.memory) {
+					return Err(BackendError::InvalidConfig(format!(
+						"memory {} not found for task {}",
+						memorization.memory, task_name
+					)));
+				}
+
+				if memorization.store_prompts && config.memories[&memorization.memory].read_only {
+					return Err(BackendError::InvalidConfig(format!(
+						"memory {} is read-only but task {} is configured to store prompts",
+						memorization.memory, task_name
+					)));
 				}
 			}
 		}
@@ -184,34 +263,522 @@ impl Backend {
 			_ = p.send(1.0).await;
 		}
 
-		backend
+		Ok(backend)
 	}
 
-	/// Downloads a file to the indicated location
-	async fn download_model(url: &str, target_path: &PathBuf) -> Result<(), String> {
-		let client = reqwest::Client::new();
-		let res = client.get(url).send().await.map_err(|x| x.to_string())?;
+	/// Replace the configuration of the backend with a new one, without restarting. Models and memories that are newly
+	/// configured are loaded; those no longer configured are dropped (sessions still using them keep them alive until
+	/// they end). Models and memories that remain configured are kept as they are, even when their configuration changed.
+	/// Tasks are always replaced by their new configuration. The new configuration is validated first (see
+	/// [crate::check::check_config]) and not applied when it is invalid.
+	pub async fn reload(&self, mut new_config: BackendConfig) -> Result<ReloadResponse, BackendError> {
+		let _reload_guard = self.reload_lock.lock().await;
+		let old_config = self.config();
+
+		Self::set_default_cache_path(&mut new_config);
+		let problems: Vec<String> = check_config(&new_config)
+			.into_iter()
+			.filter(|result| !result.is_ok())
+			.map(|result| format!("{}: {}", result.item, result.problems.join(", ")))
+			.collect();
+		if !problems.is_empty() {
+			return Err(BackendError::InvalidConfig(problems.join("; ")));
+		}
+		Self::resolve_preludes(&mut new_config).await.map_err(BackendError::InvalidConfig)?;
+		if let Some(ref cache_path) = new_config.cache_path {
+			tokio::fs::create_dir_all(cache_path.join(CACHE_MODELS_DIR))
+				.await
+				.map_err(|e| BackendError::InvalidConfig(format!("could not create cache directory: {e}")))?;
+		}
 
+		let response = ReloadResponse {
+			added_models: added_keys(&old_config.models, &new_config.models),
+			removed_models: added_keys(&new_config.models, &old_config.models),
+			added_memories: added_keys(&old_config.memories, &new_config.memories),
+			removed_memories: added_keys(&new_config.memories, &old_config.memories),
+			added_tasks: added_keys(&old_config.tasks, &new_config.tasks),
+			removed_tasks: added_keys(&new_config.tasks, &old_config.tasks),
+		};
+
+		// Load new models and memories before switching, so that the new configuration is only applied when all succeed
+		let mut new_models = vec![];
+		for model_name in &response.added_models {
+			let model_config = &new_config.models[model_name];
+			if let Some(model) = Self::prepare_model(&new_config, model_name, model_config, |_| {}).await? {
+				new_models.push((model_name.clone(), model));
+			}
+		}
+		let mut new_memories = vec![];
+		for memory_name in &response.added_memories {
+			let memory_config = &new_config.memories[memory_name];
+			new_memories.push((memory_name.clone(), memory_config.store.from(memory_config)?));
+		}
+
+		*self.config.write().unwrap() = Arc::new(new_config);
+
+		{
+			let mut memories = self.memories.write().unwrap();
+			for memory_name in &response.removed_memories {
+				memories.remove(memory_name);
+			}
+			for (memory_name, memory) in new_memories {
+				memories.insert(memory_name, Arc::new(memory));
+			}
+		}
+
+		for model_name in &response.removed_models {
+			self.models.write().unwrap().remove(model_name);
+			self.model_last_used.lock().unwrap().remove(model_name);
+			self.string_tokens.lock().unwrap().remove(model_name);
+		}
+		for (model_name, model) in new_models {
+			self.model_last_used.lock().unwrap().insert(model_name.clone(), Instant::now());
+			self.insert_model(&model_name, model);
+		}
+
+		info!(?response, "configuration reloaded");
+		Ok(response)
+	}
+
+	/// The current configuration of the backend
+	pub fn config(&self) -> Arc<BackendConfig> {
+		self.config.read().unwrap().clone()
+	}
+
+	/// Append the contents of prelude files to the preludes of the tasks that have them
+	async fn resolve_preludes(config: &mut BackendConfig) -> Result<(), String> {
+		for (task_name, task_config) in config.tasks.iter_mut() {
+			if let Some(ref prelude_file) = task_config.prelude_file {
+				if !prelude_file.exists() {
+					return Err(format!("prelude file {prelude_file:?} not found for task {task_name}"));
+				}
+				let prelude_text = tokio::fs::read_to_string(prelude_file)
+					.await
+					.map_err(|e| format!("could not read prelude file {prelude_file:?} for task {task_name}: {e}"))?;
+				task_config.prelude = Some(format!("{}{prelude_text}", task_config.prelude.as_deref().unwrap_or("")));
+			}
+		}
+		Ok(())
+	}
+
+	/// Make sure the file for a model is available (downloading it when necessary) and load it, unless the model is
+	/// configured to be loaded lazily (in which case `None` is returned)
+	async fn prepare_model(
+		config: &BackendConfig,
+		model_name: &str,
+		model_config: &ModelConfig,
+		progress: impl FnMut(f64) + Send + 'static,
+	) -> Result<Option<Arc<Box<dyn Model>>>, BackendError> {
+		// Warn about invalid configurations
+		if !model_config.use_gpu && model_config.gpu_layers.is_some() {
+			tracing::warn!("gpu_layers set but ignored because use_gpu is not set to true");
+		}
+		if cfg!(feature = "metal") && model_config.use_gpu && model_config.gpu_layers.is_some() {
+			tracing::warn!("gpu_layers set but ignored because with the Metal backend, all layers are run on the GPU");
+		}
+
+		// Check if we already have a copy of the model, or download it
+		let actual_model_path = Self::model_path(config, model_name, model_config);
+
+		if !actual_model_path.exists() {
+			// See if we can download this file
+			if let Some(ref url) = model_config.url {
+				// Download
+				tracing::info!("downloading model {model_name} from {url}");
+				Self::download_model(url, &actual_model_path)
+					.await
+					.map_err(|e| BackendError::ModelDownload(model_name.to_string(), e))?;
+				if !actual_model_path.exists() {
+					return Err(BackendError::ModelFileNotFound(model_name.to_string(), actual_model_path));
+				}
+			} else {
+				return Err(BackendError::ModelFileNotFound(model_name.to_string(), actual_model_path));
+			}
+		}
+
+		if model_config.lazy {
+			info!("Model {model_name} will be loaded when it is first used");
+			return Ok(None);
+		}
+
+		// Actually load the model
+		let model_config = model_config.clone();
+		let model_name = model_name.to_string();
+		let model = spawn_blocking(move || {
+			let model = Self::load_model(&model_name, &model_config, &actual_model_path, progress);
+			if let (Ok(model), true) = (&model, model_config.warmup) {
+				Self::warmup_model(&model_name, &model_config, model.as_ref().as_ref());
+			}
+			model
+		})
+		.await
+		.unwrap()?;
+		Ok(Some(model))
+	}
+
+	/// Use the default cache directory for the platform when no cache path is configured
+	pub(crate) fn set_default_cache_path(config: &mut BackendConfig) {
+		if config.cache_path.is_none() {
+			if let Some(pd) = ProjectDirs::from("nl.dialogic", "Dialogic", "Poly") {
+				config.cache_path = Some(pd.cache_dir().to_path_buf());
+			}
+		}
+	}
+
+	/// Path where the file for a model is found (or should be downloaded to)
+	pub(crate) fn model_path(config: &BackendConfig, model_name: &str, model_config: &ModelConfig) -> PathBuf {
+		model_config.model_path.clone().unwrap_or_else(|| {
+			config
+				.cache_path
+				.clone()
+				.expect("cache path is set when models without path are specified")
+				.join(CACHE_MODELS_DIR)
+				.join(format!("{model_name}.bin"))
+		})
+	}
+
+	/// Load a model from file (blocking). Progress is reported as a fraction between zero and one.
+	fn load_model(
+		model_name: &str,
+		model_config: &ModelConfig,
+		model_path: &Path,
+		mut progress: impl FnMut(f64),
+	) -> Result<Arc<Box<dyn Model>>, BackendError> {
+		// Set up hyperparameters
+		let params = model_config.parameters();
+		info!(
+			model_name,
+			context_size = params.context_size,
+			rope_frequency_base = params.rope_overrides.as_ref().map(|r| r.frequency_base),
+			rope_frequency_scale = params.rope_overrides.as_ref().map(|r| r.frequency_scale),
+			n_gqa = params.n_gqa,
+			"loading model"
+		);
+
+		let model = llm::load_dynamic(
+			Some(model_config.architecture),
+			model_path,
+			model_config.tokenizer.source(),
+			params,
+			|load_progress| {
+				let fp: f64 = match load_progress {
+					llm::LoadProgress::HyperparametersLoaded => 0.0,
+					llm::LoadProgress::ContextSize { .. } => 0.0,
+					llm::LoadProgress::LoraApplied { .. } => 0.0,
+					llm::LoadProgress::TensorLoaded {
+						current_tensor,
+						tensor_count,
+					} => (current_tensor as f64) / (tensor_count as f64),
+					llm::LoadProgress::Loaded { .. } => 1.0,
+				};
+				progress(fp);
+				trace!("Loading model {model_name}: {load_progress:#?}");
+			},
+		)
+		.map_err(|source| BackendError::ModelLoad {
+			name: model_name.to_string(),
+			path: model_path.to_path_buf(),
+			source,
+		})?;
+		Ok(Arc::new(model))
+	}
+
+	/// Run a short throwaway inference so that kernels are compiled and buffers are allocated before the first request
+	fn warmup_model(model_name: &str, model_config: &ModelConfig, model: &dyn Model) {
+		let start = Instant::now();
+		let inference_config = InferenceSessionConfig {
+			n_threads: model_config.threads_per_session,
+			n_batch: model_config.batch_size,
+			..InferenceSessionConfig::default()
+		};
+		let mut session = model.start_session(inference_config);
+		let token_ids: Vec<TokenId> = match model.tokenizer().tokenize("Hello", true) {
+			Ok(tokens) => tokens.iter().map(|(_, token_id)| *token_id).collect(),
+			Err(e) => {
+				tracing::warn!("could not warm up model {model_name}: {e}");
+				return;
+			}
+		};
+		model.evaluate(&mut session, &token_ids, &mut OutputRequest::default());
+		info!("Warmed up model {model_name} in {:?}", start.elapsed());
+	}
+
+	/// Obtain a model by name. When the model was unloaded because it was idle, it is loaded again. Loading blocks the
+	/// calling thread, so this must not be called from asynchronous code (use [Backend::model_async] there).
+	pub fn model(&self, model_name: &str) -> Result<Arc<Box<dyn Model>>, BackendError> {
+		let config = self.config();
+		let Some(model_config) = config.models.get(model_name) else {
+			return Err(BackendError::ModelNotFound(model_name.to_string()));
+		};
+
+		self.model_last_used.lock().unwrap().insert(model_name.to_string(), Instant::now());
+		if let Some(model) = self.models.read().unwrap().get(model_name) {
+			return Ok(model.clone());
+		}
+
+		// Model was not loaded yet (or unloaded); hold the lock for this model while loading so it is only loaded once
+		let load_lock = self.model_load_lock(model_name);
+		let _load_guard = load_lock.blocking_lock();
+		if let Some(model) = self.models.read().unwrap().get(model_name) {
+			return Ok(model.clone());
+		}
+		info!(model_name, "loading model on demand");
+		let model = Self::load_model(model_name, model_config, &Self::model_path(&config, model_name, model_config), |_| {})?;
+		self.insert_model(model_name, model.clone());
+		Ok(model)
+	}
+
+	/// Obtain a model by name from asynchronous code (see [Backend::model]). A model that needs to be loaded again is
+	/// loaded on a blocking thread.
+	pub async fn model_async(&self, model_name: &str) -> Result<Arc<Box<dyn Model>>, BackendError> {
+		let config = self.config();
+		let Some(model_config) = config.models.get(model_name) else {
+			return Err(BackendError::ModelNotFound(model_name.to_string()));
+		};
+
+		self.model_last_used.lock().unwrap().insert(model_name.to_string(), Instant::now());
+		if let Some(model) = self.models.read().unwrap().get(model_name) {
+			return Ok(model.clone());
+		}
+
+		let load_lock = self.model_load_lock(model_name);
+		let _load_guard = load_lock.lock().await;
+		if let Some(model) = self.models.read().unwrap().get(model_name) {
+			return Ok(model.clone());
+		}
+		info!(model_name, "loading model on demand");
+		let model_path = Self::model_path(&config, model_name, model_config);
+		let (load_name, load_config) = (model_name.to_string(), model_config.clone());
+		let model = spawn_blocking(move || Self::load_model(&load_name, &load_config, &model_path, |_| {}))
+			.await
+			.unwrap()?;
+		self.insert_model(model_name, model.clone());
+		Ok(model)
+	}
+
+	/// The lock that is held while a model is being loaded
+	fn model_load_lock(&self, model_name: &str) -> Arc<tokio::sync::Mutex<()>> {
+		self.model_load_locks.lock().unwrap().entry(model_name.to_string()).or_default().clone()
+	}
+
+	/// Add a loaded model. When this makes the number of loaded models exceed `max_loaded_models`, the least recently
+	/// used other models are unloaded. Sessions that still use an unloaded model keep it in memory until they end.
+	fn insert_model(&self, model_name: &str, model: Arc<Box<dyn Model>>) {
+		// Locks are taken in the same order as in unload_idle_models
+		let last_used = self.model_last_used.lock().unwrap();
+		let mut models = self.models.write().unwrap();
+		models.insert(model_name.to_string(), model);
+		self.string_tokens.lock().unwrap().insert(model_name.to_string(), Arc::default());
+		if let Some(max_loaded_models) = self.config().max_loaded_models {
+			for evict_name in Self::models_to_evict(models.keys(), &last_used, model_name, max_loaded_models) {
+				info!(model_name = evict_name, "unloading least recently used model");
+				models.remove(&evict_name);
+			}
+		}
+	}
+
+	/// Determine which of the loaded models to unload (least recently used first) to keep at most `max_loaded_models`
+	/// loaded. The model named `keep` is never unloaded.
+	fn models_to_evict<'a>(
+		loaded: impl Iterator<Item = &'a String>,
+		last_used: &HashMap<String, Instant>,
+		keep: &str,
+		max_loaded_models: usize,
+	) -> Vec<String> {
+		let mut candidates: Vec<&String> = loaded.filter(|model_name| *model_name != keep).collect();
+		let n_evict = (candidates.len() + 1).saturating_sub(max_loaded_models.max(1));
+		candidates.sort_by_key(|model_name| last_used.get(*model_name).copied());
+		candidates.into_iter().take(n_evict).cloned().collect()
+	}
+
+	/// Unload models that have not been used for longer than their configured `unload_after_idle_secs`. Models that are
+	/// still in use by a session are kept. Unloaded models are loaded again when they are needed.
+	pub fn unload_idle_models(&self) {
+		let config = self.config();
+		let last_used = self.model_last_used.lock().unwrap();
+		let mut models = self.models.write().unwrap();
+		models.retain(|model_name, model| {
+			let Some(unload_after_idle_secs) = config.models.get(model_name).and_then(|m| m.unload_after_idle_secs) else {
+				return true;
+			};
+			let is_idle = last_used
+				.get(model_name)
+				.map(|t| t.elapsed().as_secs() >= unload_after_idle_secs)
+				.unwrap_or(true);
+			if is_idle && Arc::strong_count(model) == 1 {
+				info!(model_name, "unloading idle model");
+				return false;
+			}
+			true
+		});
+	}
+
+	/// Start a session for a task and keep it, so that it can be continued by later requests (see
+	/// [Backend::stored_session]). Returns the identifier of the session. When the maximum number of stored sessions
+	/// (in total or for the user) is reached, the least recently used sessions are discarded.
+	pub fn create_session(
+		&self,
+		task_name: &str,
+		request: &SessionRequest,
+		sub: Option<String>,
+		backend: Arc<Backend>,
+	) -> Result<Uuid, BackendError> {
+		let mut session = self.start(task_name, request, backend)?;
+		session.set_user(sub.clone());
+		session.deactivate();
+
+		self.evict_expired_sessions();
+		let config = self.config();
+		let mut stored_sessions = self.stored_sessions.lock().unwrap();
+		let evict = Self::sessions_to_evict(
+			stored_sessions.iter().map(|(id, stored)| (*id, stored.sub.as_deref(), stored.last_used)),
+			sub.as_deref(),
+			config.max_stored_sessions.unwrap_or(DEFAULT_MAX_STORED_SESSIONS),
+			config.max_stored_sessions_per_user.unwrap_or(DEFAULT_MAX_STORED_SESSIONS_PER_USER),
+		);
+		for id in evict {
+			if let Some(stored) = stored_sessions.remove(&id) {
+				info!(%id, task_name = stored.task_name, "discarding least recently used session");
+			}
+		}
+
+		let id = Uuid::new_v4();
+		stored_sessions.insert(
+			id,
+			StoredSession {
+				task_name: task_name.to_string(),
+				sub,
+				last_used: Instant::now(),
+				session: Arc::new(Mutex::new(session)),
+			},
+		);
+		Ok(id)
+	}
+
+	/// Determine which of the stored sessions (identifier, user, time of last use) to discard (least recently used first)
+	/// to make room for a new session of user `sub`, while keeping at most `max_per_user` sessions for that user (when
+	/// known) and `max_total` sessions in total.
+	fn sessions_to_evict<'a>(
+		stored: impl Iterator<Item = (Uuid, Option<&'a str>, Instant)>,
+		sub: Option<&str>,
+		max_total: usize,
+		max_per_user: usize,
+	) -> Vec<Uuid> {
+		let mut candidates: Vec<(Uuid, Option<&str>, Instant)> = stored.collect();
+		candidates.sort_by_key(|(_, _, last_used)| *last_used);
+
+		// First discard the least recently used sessions of the user
+		let n_user = candidates.iter().filter(|(_, user, _)| *user == sub).count();
+		let n_evict_user = if sub.is_some() {
+			(n_user + 1).saturating_sub(max_per_user.max(1))
+		} else {
+			0
+		};
+		let mut evict: Vec<Uuid> = candidates
+			.iter()
+			.filter(|(_, user, _)| *user == sub)
+			.take(n_evict_user)
+			.map(|(id, _, _)| *id)
+			.collect();
+
+		// Then discard the least recently used sessions overall
+		let n_evict_total = (candidates.len() - evict.len() + 1).saturating_sub(max_total.max(1));
+		let remaining: Vec<Uuid> = candidates
+			.iter()
+			.map(|(id, _, _)| *id)
+			.filter(|id| !evict.contains(id))
+			.take(n_evict_total)
+			.collect();
+		evict.extend(remaining);
+		evict
+	}
+
+	/// Returns a session previously created with [Backend::create_session] for the task. Each call counts as use of the
+	/// session (postponing its expiry).
+	pub fn stored_session(&self, task_name: &str, id: &str) -> Result<Arc<Mutex<BackendSession>>, BackendError> {
+		let mut stored_sessions = self.stored_sessions.lock().unwrap();
+		let stored = Uuid::parse_str(id)
+			.ok()
+			.and_then(|id| stored_sessions.get_mut(&id))
+			.filter(|stored| stored.task_name == task_name)
+			.ok_or_else(|| BackendError::SessionNotFound(id.to_string()))?;
+		stored.last_used = Instant::now();
+		Ok(stored.session.clone())
+	}
+
+	/// Discard stored sessions that have not been used for longer than the configured time to live. Returns the number
+	/// of sessions discarded.
+	pub fn evict_expired_sessions(&self) -> usize {
+		let ttl = Duration::from_secs(self.config().session_ttl_secs.unwrap_or(DEFAULT_SESSION_TTL_SECS));
+		let mut stored_sessions = self.stored_sessions.lock().unwrap();
+		let before = stored_sessions.len();
+		stored_sessions.retain(|id, stored| {
+			let expired = stored.last_used.elapsed() > ttl;
+			if expired {
+				info!(%id, task_name = stored.task_name, "discarding expired session");
+			}
+			!expired
+		});
+		before - stored_sessions.len()
+	}
+
+	/// Downloads a file to the indicated location. The file is first downloaded to a temporary file next to the target
+	/// location. When such a file already exists (i.e. an earlier download was interrupted), the download is resumed.
+	async fn download_model(url: &str, target_path: &PathBuf) -> Result<(), String> {
 		let mut temp_path = target_path.clone();
 		temp_path.set_extension("download");
-		let mut file = File::create(&temp_path)
-			.await
-			.map_err(|x| format!("could not create temp file at {temp_path:?}: {x}"))?;
-		let total_size = res.content_length().ok_or(format!("Failed to get content length from '{}'", &url))? as usize;
+		let offset = match tokio::fs::metadata(&temp_path).await {
+			Ok(metadata) => metadata.len() as usize,
+			Err(_) => 0,
+		};
 
+		let client = reqwest::Client::new();
+		let mut res = if offset > 0 {
+			tracing::info!(url, "resuming download at {offset} bytes");
+			client
+				.get(url)
+				.header(reqwest::header::RANGE, format!("bytes={offset}-"))
+				.send()
+				.await
+				.map_err(|x| x.to_string())?
+		} else {
+			client.get(url).send().await.map_err(|x| x.to_string())?
+		};
+
+		// The temporary file may already be complete (or invalid) in which case the range cannot be satisfied; start over
+		if res.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+			tracing::info!(url, "cannot resume download, restarting");
+			res = client.get(url).send().await.map_err(|x| x.to_string())?;
+		}
+		let res = res.error_for_status().map_err(|x| x.to_string())?;
+
+		// The server may ignore the range request and send the full file instead
+		let resumed = res.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+		let mut file = if resumed {
+			tokio::fs::OpenOptions::new().append(true).open(&temp_path).await
+		} else {
+			File::create(&temp_path).await
+		}
+		.map_err(|x| format!("could not open temp file at {temp_path:?}: {x}"))?;
+		let mut downloaded: usize = if resumed { offset } else { 0 };
+		let total_size = downloaded + res.content_length().ok_or(format!("Failed to get content length from '{}'", &url))? as usize;
+
+		// When the download is interrupted, the temporary file is kept so the download can be resumed later
 		let mut stream = res.bytes_stream();
-		let mut downloaded: usize = 0;
 		while let Some(item) = stream.next().await {
-			let chunk = item.or(Err("Error while downloading file".to_string()))?;
+			let chunk = item.map_err(|x| format!("Error while downloading file: {x}"))?;
 			file.write_all(&chunk).await.or(Err("Error while writing to file".to_string()))?;
 			downloaded += chunk.len();
 			tracing::debug!(url, "download: {}/{} bytes", downloaded, total_size);
 		}
+		file.flush().await.or(Err("Error while writing to file".to_string()))?;
+		drop(file);
+
 		if downloaded != total_size {
-			tracing::debug!(
-				url,
-				"download completed, but size mismatches: {downloaded} downloaded bytes, {total_size} total size bytes",
-			);
+			tokio::fs::remove_file(&temp_path).await.map_err(|x| x.to_string())?;
+			return Err(format!(
+				"download completed, but size mismatches: {downloaded} downloaded bytes, {total_size} total size bytes"
+			));
 		}
 		tracing::debug!(url, "download completed");
 
@@ -220,16 +787,108 @@ impl Backend {
 		Ok(())
 	}
 
+	/// Stores an uploaded file for a model at the location where it is expected. When a SHA-256 checksum (hex) is given,
+	/// the file is only stored when it matches. A loaded copy of the model is unloaded, so that the new file is loaded on
+	/// next use. Returns the size of the stored file.
+	pub async fn store_model_file<S, B, E>(&self, model_name: &str, mut stream: S, max_size: u64, sha256: Option<&str>) -> Result<u64, BackendError>
+	where
+		S: Stream<Item = Result<B, E>> + Unpin,
+		B: AsRef<[u8]>,
+		E: Display,
+	{
+		let config = self.config();
+		let Some(model_config) = config.models.get(model_name) else {
+			return Err(BackendError::ModelNotFound(model_name.to_string()));
+		};
+
+		// Each upload is written to its own temporary file, so that concurrent uploads for a model do not interfere
+		let target_path = Self::model_path(&config, model_name, model_config);
+		let mut temp_path = target_path.clone();
+		temp_path.set_extension(format!("{}.upload", Uuid::new_v4()));
+
+		let result = async {
+			let mut file = File::create(&temp_path)
+				.await
+				.map_err(|e| BackendError::ModelFileUpload(format!("could not create temp file at {temp_path:?}: {e}")))?;
+			let mut hasher = Sha256::new();
+			let mut size: u64 = 0;
+			while let Some(chunk) = stream.next().await {
+				let chunk = chunk.map_err(|e| BackendError::ModelFileUpload(e.to_string()))?;
+				let chunk = chunk.as_ref();
+				size += chunk.len() as u64;
+				if size > max_size {
+					return Err(BackendError::ModelFileTooLarge(max_size));
+				}
+				hasher.update(chunk);
+				file.write_all(chunk).await.map_err(|e| BackendError::ModelFileUpload(e.to_string()))?;
+			}
+			file.flush().await.map_err(|e| BackendError::ModelFileUpload(e.to_string()))?;
+
+			if let Some(expected) = sha256 {
+				let actual: String = hasher.finalize().iter().map(|b| format!("{b:02x}")).collect();
+				if !actual.eq_ignore_ascii_case(expected) {
+					return Err(BackendError::ModelFileChecksumMismatch {
+						expected: expected.to_string(),
+						actual,
+					});
+				}
+			}
+			Ok(size)
+		}
+		.await;
+
+		let size = match result {
+			Ok(size) => size,
+			Err(e) => {
+				_ = tokio::fs::remove_file(&temp_path).await;
+				return Err(e);
+			}
+		};
+
+		// Move the temp file to the right location
+		tokio::fs::rename(&temp_path, &target_path)
+			.await
+			.map_err(|e| BackendError::ModelFileUpload(e.to_string()))?;
+		info!(model_name, size, "stored uploaded model file at {target_path:?}");
+
+		// Make sure the new file is used from now on
+		self.models.write().unwrap().remove(model_name);
+		self.invalidate_model_caches(&config, model_name);
+		Ok(size)
+	}
+
+	/// Discard the prelude snapshots and cached responses of the tasks that use a model (e.g. because the model file was
+	/// replaced)
+	fn invalidate_model_caches(&self, config: &BackendConfig, model_name: &str) {
+		let tasks = config.tasks.iter().filter(|(_, task_config)| task_config.model == model_name);
+		let mut prelude_snapshots = self.prelude_snapshots.lock().unwrap();
+		let mut response_cache = self.response_cache.lock().unwrap();
+		for (task_name, task_config) in tasks {
+			if let Some(ref prelude) = task_config.prelude {
+				let snapshot_key = Self::prelude_snapshot_key(model_name, prelude);
+				prelude_snapshots.remove_where(|key| key == snapshot_key);
+			}
+			let key_prefix = Self::response_cache_key_prefix(task_name);
+			response_cache.remove_where(|key| key.starts_with(&key_prefix));
+		}
+	}
+
 	pub fn embedding(&self, model_name: &str, prompt: &PromptRequest) -> Result<EmbeddingResponse, BackendError> {
 		info!(model_name, "embedding request");
+		let model = self.model(model_name)?;
+		self.evaluate_embedding(model.as_ref().as_ref(), model_name, prompt)
+	}
 
-		if !self.models.contains_key(model_name) {
-			return Err(BackendError::ModelNotFound(model_name.to_string()));
-		};
+	/// Calculate an embedding from asynchronous code (see [Backend::embedding])
+	async fn embedding_async(&self, model_name: &str, prompt: &PromptRequest) -> Result<EmbeddingResponse, BackendError> {
+		info!(model_name, "embedding request");
+		let model = self.model_async(model_name).await?;
+		self.evaluate_embedding(model.as_ref().as_ref(), model_name, prompt)
+	}
 
-		let model = self.models.get(model_name).unwrap();
+	fn evaluate_embedding(&self, model: &dyn Model, model_name: &str, prompt: &PromptRequest) -> Result<EmbeddingResponse, BackendError> {
 		let inference_config = InferenceSessionConfig {
-			n_threads: self.config.models[model_name].threads_per_session,
+			n_threads: self.config().models[model_name].threads_per_session,
 			n_batch: 8,
 			..InferenceSessionConfig::default()
 		};
@@ -248,19 +907,78 @@ impl Backend {
 			.map(|(_, tok)| *tok)
 			.collect::<Vec<_>>();
 		model.evaluate(&mut session, &query_token_ids, &mut output_request);
-		Ok(EmbeddingResponse {
-			embedding: output_request.embeddings.unwrap(),
+		match output_request.embeddings {
+			Some(embedding) if !embedding.is_empty() => {
+				self.stats.add_embeddings(model_name, 1);
+				Ok(EmbeddingResponse { embedding })
+			}
+			_ => Err(BackendError::EmbeddingUnsupported(model_name.to_string())),
+		}
+	}
+
+	/// Calculate the log-likelihood and perplexity of a text according to a model
+	pub fn score(&self, model_name: &str, request: &ScoreRequest) -> Result<ScoreResponse, BackendError> {
+		info!(model_name, "score request");
+
+		let model = self.model(model_name)?;
+		let config = self.config();
+		let model_config = &config.models[model_name];
+
+		let tokens = model.tokenizer().tokenize(&request.prompt, true)?;
+		let token_ids: Vec<TokenId> = tokens.iter().map(|(_, token_id)| *token_id).collect();
+		if token_ids.len() > model_config.context_size {
+			return Err(InferenceError::ContextFull.into());
+		}
+
+		let inference_config = InferenceSessionConfig {
+			n_threads: model_config.threads_per_session,
+			n_batch: model_config.batch_size,
+			..InferenceSessionConfig::default()
+		};
+		let mut session = model.start_session(inference_config);
+		let mut output_request = OutputRequest {
+			embeddings: None,
+			all_logits: Some(Vec::new()),
+		};
+		model.evaluate(&mut session, &token_ids, &mut output_request);
+
+		let all_logits = output_request.all_logits.unwrap_or_default();
+		let n_vocab = model.tokenizer().len();
+		if all_logits.len() < token_ids.len() * n_vocab {
+			return Err(BackendError::InferenceError(String::from("model did not return logits for all tokens")));
+		}
+
+		// The logits obtained after evaluating token i predict token i + 1
+		let mut token_scores = Vec::with_capacity(token_ids.len());
+		let mut total_logprob: f64 = 0.0;
+		for index in 1..token_ids.len() {
+			let logits = &all_logits[((index - 1) * n_vocab)..(index * n_vocab)];
+			let logprob = log_softmax(logits, token_ids[index] as usize);
+			total_logprob += logprob;
+			token_scores.push(TokenScore {
+				text: String::from_utf8_lossy(&tokens[index].0).to_string(),
+				token: token_ids[index],
+				logprob,
+			});
+		}
+
+		let perplexity = if token_scores.is_empty() {
+			None
+		} else {
+			Some((-total_logprob / token_scores.len() as f64).exp())
+		};
+
+		Ok(ScoreResponse {
+			logprob: total_logprob,
+			perplexity,
+			tokens: if request.per_token { Some(token_scores) } else { None },
 		})
 	}
 
 	pub fn tokenize(&self, model_name: &str, prompt: &PromptRequest) -> Result<TokenizationResponse, BackendError> {
 		info!(model_name, "tokenization request");
 
-		if !self.models.contains_key(model_name) {
-			return Err(BackendError::ModelNotFound(model_name.to_string()));
-		};
-
-		let model = self.models.get(model_name).unwrap();
+		let model = self.model(model_name)?;
 		let res = model.tokenizer().tokenize(&prompt.prompt, true)?;
 		Ok(TokenizationResponse {
 			tokens: res
@@ -273,42 +991,229 @@ impl Backend {
 		})
 	}
 
-	pub async fn forget(&self, memory_name: &str) -> Result<(), BackendError> {
-		if !self.memories.contains_key(memory_name) {
+	/// Converts token IDs back to text using the tokenizer of the model (the inverse of [Backend::tokenize])
+	pub fn detokenize(&self, model_name: &str, tokens: Vec<TokenId>) -> Result<DetokenizationResponse, BackendError> {
+		info!(model_name, n_tokens = tokens.len(), "detokenization request");
+
+		let model = self.model(model_name)?;
+		Ok(DetokenizationResponse {
+			text: Self::decode_tokens(model.tokenizer(), tokens)?,
+		})
+	}
+
+	/// Decodes tokens to text. Token IDs that are not in the vocabulary are rejected. Byte sequences that are not valid
+	/// UTF-8 (e.g. when a multi-byte character is split over tokens that are not all included) are replaced.
+	fn decode_tokens(tokenizer: &Tokenizer, tokens: Vec<TokenId>) -> Result<String, BackendError> {
+		if let Some(invalid) = tokens.iter().find(|t| **t as usize >= tokenizer.len()) {
+			return Err(BackendError::InvalidTokenId(*invalid));
+		}
+		Ok(String::from_utf8_lossy(&tokenizer.decode(tokens, false)).to_string())
+	}
+
+	/// Returns the name of the model used to embed items in the memory (embeddings for recall must be calculated with the
+	/// same model, which may differ from the model used by the task)
+	pub fn memory_embedding_model(&self, memory_name: &str) -> Result<String, BackendError> {
+		self.config()
+			.memories
+			.get(memory_name)
+			.map(|memory_config| memory_config.embedding_model.clone())
+			.ok_or_else(|| BackendError::MemoryNotFound(memory_name.to_string()))
+	}
+
+	/// Returns an error when the memory does not exist or when it is configured to be read-only
+	pub fn check_memory_writable(&self, memory_name: &str) -> Result<(), BackendError> {
+		let config = self.config();
+		let Some(memory_config) = config.memories.get(memory_name) else {
 			return Err(BackendError::MemoryNotFound(memory_name.to_string()));
+		};
+		if memory_config.read_only {
+			return Err(BackendError::MemoryReadOnly(memory_name.to_string()));
 		}
-		let memory = self.memories.get(memory_name).unwrap();
+		Ok(())
+	}
+
+	/// Returns the store of a memory
+	fn memory(&self, memory_name: &str) -> Result<Arc<Box<dyn Memory>>, BackendError> {
+		self.memories
+			.read()
+			.unwrap()
+			.get(memory_name)
+			.cloned()
+			.ok_or_else(|| BackendError::MemoryNotFound(memory_name.to_string()))
+	}
+
+	/// Returns a page of the chunks stored in memory, along with the total number of chunks in memory
+	pub async fn memory_items(&self, memory_name: &str, offset: usize, limit: usize) -> Result<(Vec<String>, usize), BackendError> {
+		let memory = self.memory(memory_name)?;
+		Ok((memory.list(offset, limit).await?, memory.count().await?))
+	}
+
+	/// Remove a single chunk (identified by its text) from memory. Returns whether a chunk was removed.
+	pub async fn forget_item(&self, memory_name: &str, text: &str) -> Result<bool, BackendError> {
+		self.check_memory_writable(memory_name)?;
+		let memory = self.memory(memory_name)?;
+		Ok(memory.delete(text).await?)
+	}
+
+	pub async fn forget(&self, memory_name: &str) -> Result<(), BackendError> {
+		self.check_memory_writable(memory_name)?;
+		let memory = self.memory(memory_name)?;
 		tracing::info!("clearing memory {memory_name}");
 		memory.clear().await.map_err(BackendError::Memory)
 	}
 
-	pub async fn recall(&self, memory_name: &str, prompt: &str, top_n: usize) -> Result<Vec<String>, BackendError> {
-		if !self.memories.contains_key(memory_name) {
-			return Err(BackendError::MemoryNotFound(memory_name.to_string()));
+	/// Returns the number of items stored in each memory. The count is `None` for memories that could not be queried
+	/// (e.g. because the store is unavailable).
+	pub async fn memory_counts(&self) -> HashMap<String, Option<usize>> {
+		let mut counts = HashMap::new();
+		let memories: Vec<(String, Arc<Box<dyn Memory>>)> = self.memories.read().unwrap().iter().map(|(n, m)| (n.clone(), m.clone())).collect();
+		for (memory_name, memory) in memories {
+			let count = match memory.count().await {
+				Ok(count) => Some(count),
+				Err(e) => {
+					tracing::warn!(memory_name, "could not count items in memory: {e}");
+					None
+				}
+			};
+			counts.insert(memory_name, count);
 		}
+		counts
+	}
+
+	/// Returns the number of items stored in a memory
+	pub async fn memory_count(&self, memory_name: &str) -> Result<usize, BackendError> {
+		Ok(self.memory(memory_name)?.count().await?)
+	}
 
-		let memory_config = &self.config.memories[memory_name];
+	/// Retrieve the `top_n` items most relevant to the prompt from memory. When `dedup` is set, items whose normalized text
+	/// is identical to, or whose embedding is very similar to, that of an item already recalled are skipped, and
+	/// additional candidates are fetched to take their place. Items are returned most relevant first.
+	pub async fn recall(&self, memory_name: &str, prompt: &str, top_n: usize, dedup: bool) -> Result<Vec<RecalledChunk>, BackendError> {
+		let memory = self.memory(memory_name)?;
+		let config = self.config();
+		let memory_config = &config.memories[memory_name];
 
 		// Generate embedding for prompt
-		let embedding = self.embedding(&memory_config.embedding_model, &PromptRequest { prompt: prompt.to_string() })?;
-		let memory = self.memories.get(memory_name).unwrap();
-		memory.get(&embedding.embedding, top_n).await.map_err(BackendError::Memory)
+		let embedding = self
+			.embedding_async(
+				&memory_config.embedding_model,
+				&PromptRequest {
+					prompt: prompt.to_string(),
+					assistant_prefix: None,
+				},
+			)
+			.await?;
+		self.stats.add_recall(memory_name);
+		if !dedup {
+			let recalled = memory.get_scored(&embedding.embedding, top_n).await.map_err(BackendError::Memory)?;
+			return Ok(recalled.into_iter().map(|(text, score)| RecalledChunk { text, score }).collect());
+		}
+
+		// Candidates are compared using their stored embeddings, so they do not need to be embedded again
+		let candidates = memory
+			.get_scored_with_embeddings(&embedding.embedding, top_n * RECALL_DEDUP_CANDIDATE_FACTOR)
+			.await
+			.map_err(BackendError::Memory)?;
+
+		let mut recalled = Vec::with_capacity(top_n);
+		let mut recalled_texts = HashSet::new();
+		let mut recalled_embeddings: Vec<Vec<f32>> = Vec::with_capacity(top_n);
+		for (candidate, score, candidate_embedding) in candidates {
+			if recalled.len() >= top_n {
+				break;
+			}
+
+			if !recalled_texts.insert(normalize_text(&candidate)) {
+				continue;
+			}
+
+			if recalled_embeddings
+				.iter()
+				.any(|e| cosine_similarity(e, &candidate_embedding) > memory_config.dedup_similarity_threshold)
+			{
+				continue;
+			}
+
+			recalled_embeddings.push(candidate_embedding);
+			recalled.push(RecalledChunk { text: candidate, score });
+		}
+		tracing::debug!(memory_name, top_n, n_recalled = recalled.len(), "recalled with deduplication");
+		Ok(recalled)
+	}
+
+	pub async fn memorize(&self, memory_name: &str, data: &str) -> Result<MemorizeSummary, BackendError> {
+		self.memorize_with_progress(memory_name, data, None).await
 	}
 
-	pub async fn memorize(&self, memory_name: &str, data: &str) -> Result<(), BackendError> {
-		// Obtain memorization configuration
+	/// Chunk, embed and store the provided data in memory. Progress is reported after each chunk is embedded. When the
+	/// receiving end of the progress channel is closed, ingestion stops (chunks embedded so far are still stored).
+	pub async fn memorize_with_progress(
+		&self,
+		memory_name: &str,
+		data: &str,
+		progress: Option<Sender<IngestProgress>>,
+	) -> Result<MemorizeSummary, BackendError> {
 		tracing::info!(memory_name, data_length = data.len(), "memorize");
-		let memory_config = &self.config.memories[memory_name];
-		let memory = self.memories[memory_name].clone();
-		let model_name = &memory_config.embedding_model;
+		self.check_memory_writable(memory_name)?;
+		let (chunks, skipped) = self.chunk(memory_name, data).await?;
+		let chunks_total = chunks.len() + skipped;
+		let stored = self.memorize_chunks(memory_name, chunks, progress).await?;
+		Ok(MemorizeSummary {
+			chunks: chunks_total,
+			stored,
+			skipped,
+		})
+	}
 
-		// Get embedding model
-		if !self.models.contains_key(model_name) {
-			return Err(BackendError::ModelNotFound(model_name.to_string()));
-		};
+	/// Chunk, embed and store multiple documents in memory. The chunks of all documents are embedded in batches of
+	/// `batch_size` chunks (see [ModelConfig::batch_size]), and each batch is stored in memory at once. Within a batch,
+	/// each chunk is still evaluated in a session of its own, because a single session cannot evaluate independent chunks
+	/// (the embedding of a chunk would depend on the chunks evaluated before it in the same session).
+	pub async fn memorize_batch(&self, memory_name: &str, documents: &[String]) -> Result<MemorizeSummary, BackendError> {
+		tracing::info!(memory_name, n_documents = documents.len(), "memorize batch");
+		self.check_memory_writable(memory_name)?;
+		let mut chunks = vec![];
+		let mut skipped = 0;
+		for document in documents {
+			let (document_chunks, document_skipped) = self.chunk(memory_name, document).await?;
+			chunks.extend(document_chunks);
+			skipped += document_skipped;
+		}
+
+		let config = self.config();
+		let memory = self.memory(memory_name)?;
+		let model_name = &config.memories[memory_name].embedding_model;
+		let model = self.model_async(model_name).await?;
+		let model_config = config.models[model_name].clone();
+
+		let mut stored = 0;
+		for batch in chunks.chunks(model_config.batch_size.max(1)) {
+			let texts = batch.iter().map(|chunk| {
+				let chars: Vec<u8> = chunk.iter().flat_map(|x| x.0.clone()).collect();
+				String::from_utf8_lossy(&chars).to_string()
+			});
+			let tokens: Vec<Vec<TokenId>> = batch.iter().map(|chunk| chunk.iter().map(|x| x.1).collect()).collect();
+			let embeddings = Self::embed_chunks(model.clone(), model_name, &model_config, tokens).await?;
+			let items: Vec<(String, Vec<f32>)> = texts.zip(embeddings).collect();
+			memory.store_batch(&items).await?;
+			stored += items.len();
+		}
 
-		let model = self.models.get(model_name).unwrap().clone();
-		let model_config = self.config.models[model_name].clone();
+		self.stats.add_embeddings(model_name, stored);
+		self.stats.add_ingest(memory_name, stored);
+		Ok(MemorizeSummary {
+			chunks: chunks.len() + skipped,
+			stored,
+			skipped,
+		})
+	}
+
+	/// Split data into chunks for storage in memory, after applying the filters configured for the memory. Returns the
+	/// chunks to store and the number of chunks that were dropped because they were empty after post-filtering.
+	async fn chunk(&self, memory_name: &str, data: &str) -> Result<(Vec<Vec<TokenWithCharacters>>, usize), BackendError> {
+		let config = self.config();
+		let memory_config = &config.memories[memory_name];
+		let model = self.model_async(&memory_config.embedding_model).await?;
 
 		// Apply pre-filter
 		let mut data = Cow::from(data);
@@ -325,20 +1230,13 @@ impl Backend {
 
 		// Split the input by all separators
 		let vocab = model.tokenizer();
-		let separator_tokens: Vec<TokenId> = memory_config
-			.chunk_separators
-			.iter()
-			.map(|s| {
-				let tokens = vocab.tokenize(s, false)?;
-				if tokens.len() != 1 {
-					return Err(BackendError::InvalidChunkSeparator(s.clone()));
-				}
-				Ok(tokens[0].1)
-			})
-			.collect::<Result<Vec<TokenId>, BackendError>>()?;
+		if let Some(separator) = memory_config.chunk_separators.iter().find(|s| s.is_empty()) {
+			return Err(BackendError::InvalidChunkSeparator(separator.clone()));
+		}
 
 		let body_tokens = vocab.tokenize(data.as_ref(), false)?;
-		let chunks = hierarchically_chunk(body_tokens, &separator_tokens, memory_config.chunk_max_tokens);
+		let chunks = hierarchically_chunk(body_tokens, &memory_config.chunk_separators, memory_config.chunk_max_tokens);
+		let chunks = overlap_chunks(chunks, memory_config.chunk_overlap_tokens, memory_config.chunk_max_tokens);
 
 		let post_filter_tokens = memory_config
 			.post_filter
@@ -352,37 +1250,84 @@ impl Backend {
 			})
 			.collect::<Result<HashSet<TokenId>, BackendError>>()?;
 
-		for mut chunk in chunks {
+		// Apply post filter
+		for chunk in chunks.iter() {
 			assert!(
 				chunk.len() <= memory_config.chunk_max_tokens,
 				"chunk size ({}) must not exceed maximum ({})",
 				chunk.len(),
 				memory_config.chunk_max_tokens
 			);
-			// Apply post filter
-			chunk.retain(|t| !post_filter_tokens.contains(&t.1));
+		}
+		let (chunks, skipped) = filter_chunks(chunks, &post_filter_tokens);
+		if skipped > 0 {
+			tracing::debug!(memory_name, skipped, "skipping chunks that are empty after post-filtering");
+		}
+		Ok((chunks, skipped))
+	}
 
-			if !chunk.is_empty() {
-				let chunk_tokens: Vec<TokenId> = chunk.iter().map(|x| x.1).collect();
-				let chars: Vec<u8> = chunk.iter().flat_map(|x| x.0.clone()).collect();
-				let chunk_text = String::from_utf8_lossy(&chars);
-				tracing::trace!(?chunk_text, chunk_size_tokens = chunk_tokens.len(), "chunk for ingest");
-				Self::memorize_chunk(model.clone(), &model_config, &chunk_text, chunk_tokens, memory.clone()).await?;
+	/// Embed and store chunks in memory (see [Backend::memorize_with_progress]). Returns the number of chunks stored.
+	async fn memorize_chunks(
+		&self,
+		memory_name: &str,
+		chunks: Vec<Vec<TokenWithCharacters>>,
+		progress: Option<Sender<IngestProgress>>,
+	) -> Result<usize, BackendError> {
+		let config = self.config();
+		let memory_config = &config.memories[memory_name];
+		let memory = self.memory(memory_name)?;
+		let model_name = &memory_config.embedding_model;
+		let model = self.model_async(model_name).await?;
+		let model_config = config.models[model_name].clone();
+
+		let chunks_total = chunks.len();
+		let mut stored = 0;
+		let mut batch: Vec<(String, Vec<f32>)> = Vec::with_capacity(MEMORIZE_BATCH_SIZE);
+		for (index, chunk) in chunks.into_iter().enumerate() {
+			let chunk_tokens: Vec<TokenId> = chunk.iter().map(|x| x.1).collect();
+			let chars: Vec<u8> = chunk.iter().flat_map(|x| x.0.clone()).collect();
+			let chunk_text = String::from_utf8_lossy(&chars);
+			tracing::trace!(?chunk_text, chunk_size_tokens = chunk_tokens.len(), "chunk for ingest");
+			let embedding = Self::embed_chunk(model.clone(), model_name, &model_config, &chunk_text, chunk_tokens).await?;
+			batch.push((chunk_text.to_string(), embedding));
+
+			if batch.len() >= MEMORIZE_BATCH_SIZE {
+				memory.store_batch(&batch).await?;
+				stored += batch.len();
+				batch.clear();
+			}
+
+			if let Some(ref progress) = progress {
+				let update = IngestProgress {
+					chunks_embedded: index + 1,
+					chunks_total,
+				};
+				if progress.send(update).await.is_err() {
+					tracing::info!(memory_name, chunks_embedded = index + 1, chunks_total, "ingest cancelled");
+					break;
+				}
 			}
 		}
 
-		Ok(())
+		if !batch.is_empty() {
+			memory.store_batch(&batch).await?;
+			stored += batch.len();
+		}
+
+		self.stats.add_embeddings(model_name, stored);
+		self.stats.add_ingest(memory_name, stored);
+		Ok(stored)
 	}
 
-	async fn memorize_chunk(
+	async fn embed_chunk(
 		model: Arc<Box<dyn Model>>,
+		model_name: &str,
 		model_config: &ModelConfig,
 		text: &str,
 		tokens: Vec<TokenId>,
-		memory: Arc<Box<dyn Memory>>,
-	) -> Result<(), MemoryError> {
+	) -> Result<Vec<f32>, BackendError> {
 		// Calculate embedding
-		tracing::trace!(n_tokens = tokens.len(), ?text, "memorize chunk");
+		tracing::trace!(n_tokens = tokens.len(), ?text, "embed chunk");
 
 		let inference_config = InferenceSessionConfig {
 			n_threads: model_config.threads_per_session,
@@ -398,49 +1343,301 @@ impl Backend {
 				all_logits: None,
 			};
 			model.evaluate(&mut session, &tokens, &mut output_request);
-			output_request.embeddings.unwrap()
+			output_request.embeddings
 		})
 		.await
 		.unwrap();
 
-		memory.store(text, &embeddings).await?;
-		Ok(())
+		embeddings
+			.filter(|e| !e.is_empty())
+			.ok_or_else(|| BackendError::EmbeddingUnsupported(model_name.to_string()))
 	}
 
-	pub fn start(&self, task_name: &str, _request: &SessionRequest, backend: Arc<Backend>) -> Result<BackendSession, BackendError> {
-		info!("Start session {task_name}");
+	/// Calculate the embeddings of a batch of chunks on a single blocking thread (see [Backend::memorize_batch])
+	async fn embed_chunks(
+		model: Arc<Box<dyn Model>>,
+		model_name: &str,
+		model_config: &ModelConfig,
+		chunks: Vec<Vec<TokenId>>,
+	) -> Result<Vec<Vec<f32>>, BackendError> {
+		tracing::trace!(n_chunks = chunks.len(), "embed chunks");
+		let (n_threads, n_batch) = (model_config.threads_per_session, model_config.batch_size);
+
+		let embeddings = spawn_blocking(move || {
+			chunks
+				.iter()
+				.map(|tokens| {
+					let mut session = model.start_session(InferenceSessionConfig {
+						n_threads,
+						n_batch,
+						..InferenceSessionConfig::default()
+					});
+					let mut output_request = OutputRequest {
+						embeddings: Some(Vec::new()),
+						all_logits: None,
+					};
+					model.evaluate(&mut session, tokens, &mut output_request);
+					output_request.embeddings.filter(|e| !e.is_empty())
+				})
+				.collect::<Option<Vec<Vec<f32>>>>()
+		})
+		.await
+		.unwrap();
+
+		embeddings.ok_or_else(|| BackendError::EmbeddingUnsupported(model_name.to_string()))
+	}
+
+	/// Key under which the response to a request is cached, or `None` when the response should not be cached (because
+	/// caching is not enabled for the task, or the response may differ between requests)
+	pub fn response_cache_key(&self, task_name: &str, request: &SessionRequest, prompt: &PromptRequest) -> Result<Option<String>, BackendError> {
+		let task_config = self.task_config(task_name, request)?;
+		if !task_config.cache_responses || !task_config.is_deterministic() || task_config.memorization.is_some() || request.debug {
+			return Ok(None);
+		}
+
+		// The key starts with the task name (see [Backend::response_cache_key_prefix])
+		let mut logit_bias: Vec<(&String, &f32)> = request.logit_bias.iter().flatten().collect();
+		logit_bias.sort_by(|a, b| a.0.cmp(b.0));
+		let key = serde_json::to_string(&(
+			task_name,
+			&task_config,
+			logit_bias,
+			request.echo,
+			&prompt.prompt,
+			&prompt.assistant_prefix,
+		))
+		.unwrap();
+		Ok(Some(key))
+	}
+
+	/// Common start of the response cache keys of a task (the keys are JSON arrays that start with the task name)
+	fn response_cache_key_prefix(task_name: &str) -> String {
+		format!("[{},", serde_json::to_string(task_name).unwrap())
+	}
+
+	/// Returns the cached response for the key (see [Backend::response_cache_key]) if there is one
+	pub fn cached_response(&self, key: &str) -> Option<CachedCompletion> {
+		self.response_cache.lock().unwrap().get(key)
+	}
+
+	pub fn cache_response(&self, key: String, completion: CachedCompletion) {
+		self.response_cache.lock().unwrap().insert(key, completion);
+	}
 
-		if !self.config.tasks.contains_key(task_name) {
+	/// Returns the effective configuration for a task after applying per-request overrides
+	pub fn task_config(&self, task_name: &str, request: &SessionRequest) -> Result<TaskConfig, BackendError> {
+		let config = self.config();
+		let Some(task_config) = config.tasks.get(task_name) else {
 			return Err(BackendError::TaskNotFound(task_name.to_string()));
 		};
 
-		let task_config = self.config.tasks.get(task_name).unwrap();
+		request.validate()?;
+		let mut task_config = task_config.clone();
+		if request.min_tokens.is_some() {
+			task_config.min_tokens = request.min_tokens;
+		}
+
+		// A request can lower, but not raise the maximum number of tokens configured for the task
+		if let Some(max_tokens) = request.max_tokens {
+			task_config.max_tokens = Some(task_config.max_tokens.map_or(max_tokens, |configured| configured.min(max_tokens)));
+		}
 
-		let memory = task_config.memorization.as_ref().map(|mc| self.memories.get(&mc.memory).unwrap());
+		// Apply per-request sampler overrides
+		if let SamplerConfig::Standard(ref mut sampler_config) = task_config.sampler {
+			if let Some(temperature) = request.temperature {
+				sampler_config.temperature = temperature;
+			}
+			if let Some(top_k) = request.top_k {
+				sampler_config.top_k = top_k;
+			}
+			if let Some(top_p) = request.top_p {
+				sampler_config.top_p = top_p;
+			}
+			if let Some(repeat_penalty) = request.repeat_penalty {
+				sampler_config.repeat_penalty = repeat_penalty;
+			}
+			if let Some(presence_penalty) = request.presence_penalty {
+				sampler_config.presence_penalty = presence_penalty;
+			}
+			if let Some(frequency_penalty) = request.frequency_penalty {
+				sampler_config.frequency_penalty = frequency_penalty;
+			}
+		}
+
+		Ok(task_config)
+	}
+
+	/// Returns the JSON schema that the output of a task conforms to (only for tasks that use a biaser)
+	pub fn task_schema(&self, task_name: &str) -> Result<JsonSchema, BackendError> {
+		let config = self.config();
+		let Some(task_config) = config.tasks.get(task_name) else {
+			return Err(BackendError::TaskNotFound(task_name.to_string()));
+		};
+
+		let Some(biaser_config) = &task_config.biaser else {
+			return Err(BackendError::TaskSchemaNotFound(task_name.to_string()));
+		};
+		Ok(biaser_config.json_schema()?.into_owned())
+	}
+
+	/// Signal all sessions that are active for a task to stop generating. Returns the number of sessions signalled.
+	pub fn abort_task(&self, task_name: &str) -> Result<usize, BackendError> {
+		if !self.config().tasks.contains_key(task_name) {
+			return Err(BackendError::TaskNotFound(task_name.to_string()));
+		}
+
+		let active_sessions = self.active_sessions.lock().unwrap();
+		let cancelled: Vec<Arc<ActiveSession>> = active_sessions
+			.get(task_name)
+			.map(|sessions| sessions.iter().filter_map(|session| session.upgrade()).collect())
+			.unwrap_or_default();
+		for session in cancelled.iter() {
+			session.cancelled.store(true, Ordering::SeqCst);
+		}
+		info!(task_name, sessions = cancelled.len(), "aborted sessions for task");
+		Ok(cancelled.len())
+	}
+
+	/// Signal the sessions for a task that were started with the indicated request identifier (see
+	/// [SessionRequest::request_id]) by the indicated user to stop generating. Generation stops at the next token, and
+	/// the completion ends with the output generated so far. Returns the number of sessions signalled.
+	pub fn cancel_request(&self, task_name: &str, request_id: &str, sub: Option<&str>) -> Result<usize, BackendError> {
+		if !self.config().tasks.contains_key(task_name) {
+			return Err(BackendError::TaskNotFound(task_name.to_string()));
+		}
+
+		let active_sessions = self.active_sessions.lock().unwrap();
+		let cancelled: Vec<Arc<ActiveSession>> = active_sessions
+			.get(task_name)
+			.map(|sessions| {
+				sessions
+					.iter()
+					.filter_map(|session| session.upgrade())
+					.filter(|session| session.request_id.as_deref() == Some(request_id) && session.sub.lock().unwrap().as_deref() == sub)
+					.collect()
+			})
+			.unwrap_or_default();
+		for session in cancelled.iter() {
+			session.cancelled.store(true, Ordering::SeqCst);
+		}
+		info!(task_name, request_id, sessions = cancelled.len(), "cancelled request");
+		Ok(cancelled.len())
+	}
+
+	/// List the sessions that are currently active, ordered by identifier (i.e. oldest first)
+	pub fn sessions(&self) -> Vec<SessionInfo> {
+		let active_sessions = self.active_sessions.lock().unwrap();
+		let mut sessions: Vec<SessionInfo> = active_sessions
+			.values()
+			.flat_map(|sessions| sessions.iter().filter_map(|session| session.upgrade()))
+			.map(|session| SessionInfo {
+				id: session.id,
+				task: session.task_name.clone(),
+				started_at: session.started.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+				duration_ms: session.started.elapsed().map(|d| d.as_millis()).unwrap_or(0),
+				tokens_generated: session.tokens_generated.load(Ordering::SeqCst),
+				sub: session.sub.lock().unwrap().clone(),
+				request_id: session.request_id.clone(),
+			})
+			.collect();
+		sessions.sort_by_key(|session| session.id);
+		sessions
+	}
+
+	/// Number of threads to use for a new session of a model, taking into account the requested number of threads and
+	/// the global maximum number of threads (which is shared among the active sessions)
+	fn session_threads(&self, model_name: &str, requested_threads: Option<usize>) -> usize {
+		let config = self.config();
+		let threads_per_session = config.models[model_name].threads_per_session;
+		let mut n_threads = requested_threads
+			.map(|n| n.max(1).min(threads_per_session))
+			.unwrap_or(threads_per_session);
+
+		if let Some(max_total_threads) = config.max_total_threads {
+			let active_sessions: usize = self
+				.active_sessions
+				.lock()
+				.unwrap()
+				.values()
+				.map(|sessions| sessions.iter().filter(|session| session.strong_count() > 0).count())
+				.sum();
+			n_threads = n_threads.min((max_total_threads / (active_sessions + 1)).max(1));
+		}
+		n_threads
+	}
+
+	/// Register a new session for a task (entries of sessions that have ended are removed)
+	pub(crate) fn register_session(&self, task_name: &str, request_id: Option<String>) -> Arc<ActiveSession> {
+		let active = Arc::new(ActiveSession {
+			id: self.next_session_id.fetch_add(1, Ordering::SeqCst),
+			task_name: task_name.to_string(),
+			started: SystemTime::now(),
+			sub: Mutex::new(None),
+			request_id,
+			tokens_generated: AtomicUsize::new(0),
+			cancelled: AtomicBool::new(false),
+		});
+
+		let mut active_sessions = self.active_sessions.lock().unwrap();
+		let sessions = active_sessions.entry(task_name.to_string()).or_default();
+		sessions.retain(|session| session.strong_count() > 0);
+		sessions.push(Arc::downgrade(&active));
+		active
+	}
 
-		let model = self.models.get(&task_config.model).unwrap().clone();
-		let n_threads = self.config.models[&task_config.model].threads_per_session;
+	/// Key under which the snapshot of the state of a model after feeding a prelude is cached. Tasks that use the same
+	/// model and prelude share a snapshot.
+	fn prelude_snapshot_key(model_name: &str, prelude: &str) -> String {
+		let mut hasher = Sha256::new();
+		hasher.update(model_name.as_bytes());
+		hasher.update([0u8]);
+		hasher.update(prelude.as_bytes());
+		hasher.finalize().iter().map(|b| format!("{b:02x}")).collect()
+	}
+
+	pub fn start(&self, task_name: &str, request: &SessionRequest, backend: Arc<Backend>) -> Result<BackendSession, BackendError> {
+		info!("Start session {task_name}");
+
+		let task_config = self.task_config(task_name, request)?;
+		let memory = task_config.memorization.as_ref().map(|mc| self.memory(&mc.memory)).transpose()?;
+
+		let model = self.model(&task_config.model)?;
+		let string_tokens = self.string_tokens.lock().unwrap().entry(task_config.model.clone()).or_default().clone();
+		let n_threads = self.session_threads(&task_config.model, request.n_threads);
 		let inference_config: InferenceSessionConfig = InferenceSessionConfig {
 			n_threads,
-			n_batch: self.config.models[&task_config.model].batch_size,
+			n_batch: self.config().models[&task_config.model].batch_size,
 			..InferenceSessionConfig::default()
 		};
 
 		let inference_parameters: InferenceParameters = task_config.clone().into();
 
+		// Resolve the tokens in the requested logit bias
+		let logit_bias = match request.logit_bias {
+			Some(ref logit_bias) => logit_bias
+				.iter()
+				.map(|(token_text, bias)| {
+					let tokens = model.tokenizer().tokenize(token_text, false)?;
+					if tokens.len() != 1 {
+						return Err(BackendError::InvalidLogitBiasToken(token_text.clone()));
+					}
+					Ok((tokens[0].1, *bias))
+				})
+				.collect::<Result<Vec<(TokenId, f32)>, BackendError>>()?,
+			None => vec![],
+		};
+
 		let session = if let Some(ref prelude_prompt) = task_config.prelude {
 			if !prelude_prompt.is_empty() {
-				// Do we have a snapshot?
-				let cache = self.prelude_snapshots.read().unwrap();
-				if let Some(snapshot) = cache.get(task_name) {
+				// Do we have a snapshot? The lock is not held while feeding the prelude, which might cause us to generate
+				// the prelude twice, but that's okay.
+				let snapshot_key = Self::prelude_snapshot_key(&task_config.model, prelude_prompt);
+				let snapshot = self.prelude_snapshots.lock().unwrap().get_and_refresh(&snapshot_key);
+				if let Some(snapshot) = snapshot {
 					// We have a snapshot
 					tracing::debug!("Re-using prelude snapshot for task {task_name}");
-					InferenceSession::from_snapshot(snapshot.clone(), model.as_ref().as_ref()).expect("restore prelude")
+					InferenceSession::from_snapshot(snapshot, model.as_ref().as_ref()).expect("restore prelude")
 				} else {
-					// We are dropping the read lock here because further on we want to acquire a write lock, and RwLock
-					// has no way to upgrade the read lock to a write lock. This is fine for now - it might cause us to
-					// generate the prelude twice but that's okay.
-					drop(cache);
 					let mut session = model.start_session(inference_config);
 
 					tracing::debug!("feeding prelude prompt: '{prelude_prompt}'");
@@ -457,10 +1654,8 @@ impl Backend {
 					// Save snapshot
 					tracing::trace!("Caching prelude snapshot for task {task_name}");
 					let snapshot = unsafe { session.get_snapshot().to_owned() };
-					{
-						let mut cache = self.prelude_snapshots.write().unwrap();
-						cache.insert(task_name.to_string(), snapshot);
-					}
+					let size = snapshot_size(&snapshot);
+					self.prelude_snapshots.lock().unwrap().insert_sized(snapshot_key, snapshot, size);
 					session
 				}
 			} else {
@@ -472,22 +1667,54 @@ impl Backend {
 			model.start_session(inference_config)
 		};
 
+		let active = self.register_session(task_name, request.request_id.clone());
+
 		Ok(BackendSession {
 			model: model.clone(),
-			memory: memory.cloned(),
+			string_tokens,
+			memory,
 			session,
 			inference_parameters,
-			task_config: task_config.clone(),
+			logit_bias,
+			bias_steps: if request.debug { Some(vec![]) } else { None },
+			echo: request.echo,
+			task_config,
 			stats: self.stats.clone(),
 			task_name: task_name.to_string(),
 			n_threads,
 			backend,
+			active: Some(active),
+			request_id: request.request_id.clone(),
+			sub: None,
 		})
 	}
 }
 
+/// Approximate size of a snapshot in memory (in bytes), which is dominated by the key and value memory of the model
+fn snapshot_size(snapshot: &InferenceSnapshot) -> usize {
+	snapshot.memory_k.len()
+		+ snapshot.memory_v.len()
+		+ snapshot.last_logits.len() * std::mem::size_of::<f32>()
+		+ snapshot.tokens.len() * std::mem::size_of::<TokenId>()
+}
+
+/// Keys that are in `new` but not in `old` (sorted)
+fn added_keys<T>(old: &HashMap<String, T>, new: &HashMap<String, T>) -> Vec<String> {
+	let mut keys: Vec<String> = new.keys().filter(|key| !old.contains_key(*key)).cloned().collect();
+	keys.sort();
+	keys
+}
+
+/// Calculate the log-probability of the token at `index` given a set of logits
+fn log_softmax(logits: &[f32], index: usize) -> f64 {
+	let max = logits.iter().fold(f32::NEG_INFINITY, |a, b| a.max(*b)) as f64;
+	let sum: f64 = logits.iter().map(|l| (*l as f64 - max).exp()).sum();
+	logits[index] as f64 - max - sum.ln()
+}
+
 impl BackendStats {
-	pub fn add(&self, task_name: &str, stats: &InferenceStats, n_threads: usize) {
+	/// Record a completion generated for a task using a model
+	pub fn add(&self, task_name: &str, model_name: &str, stats: &InferenceStats, n_threads: usize) {
 		let mut ts = self.task_stats.lock().unwrap();
 		if let Some(task_stats) = ts.get_mut(task_name) {
 			task_stats.add_cycle(stats, n_threads);
@@ -496,6 +1723,37 @@ impl BackendStats {
 			task_stats.add_cycle(stats, n_threads);
 			ts.insert(task_name.to_string(), task_stats);
 		}
+		drop(ts);
+
+		let mut ms = self.model_stats.lock().unwrap();
+		ms.entry(model_name.to_string()).or_default().add_completion(stats);
+	}
+
+	/// Record that a number of embeddings were calculated using a model
+	pub fn add_embeddings(&self, model_name: &str, n_embeddings: usize) {
+		let mut ms = self.model_stats.lock().unwrap();
+		ms.entry(model_name.to_string()).or_default().embeddings += n_embeddings;
+	}
+
+	/// Record that items were recalled from a memory
+	pub fn add_recall(&self, memory_name: &str) {
+		let mut ms = self.memory_stats.lock().unwrap();
+		ms.entry(memory_name.to_string()).or_default().recalls += 1;
+	}
+
+	/// Record that documents were ingested into a memory, resulting in `chunks_stored` chunks being stored
+	pub fn add_ingest(&self, memory_name: &str, chunks_stored: usize) {
+		let mut ms = self.memory_stats.lock().unwrap();
+		let memory_stats = ms.entry(memory_name.to_string()).or_default();
+		memory_stats.ingests += 1;
+		memory_stats.chunks_stored += chunks_stored;
+	}
+
+	/// Clear all statistics (e.g. between benchmark runs)
+	pub fn reset(&self) {
+		self.task_stats.lock().unwrap().clear();
+		self.model_stats.lock().unwrap().clear();
+		self.memory_stats.lock().unwrap().clear();
 	}
 }
 
@@ -503,6 +1761,614 @@ impl Default for BackendStats {
 	fn default() -> Self {
 		BackendStats {
 			task_stats: Mutex::new(HashMap::new()),
+			model_stats: Mutex::new(HashMap::new()),
+			memory_stats: Mutex::new(HashMap::new()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::{
+		path::{Path, PathBuf},
+		sync::atomic::Ordering,
+		time::Duration,
+	};
+
+	use llm::{InferenceStats, TokenId, TokenizerSource};
+
+	use crate::{
+		cache::CachedCompletion,
+		config::{BackendConfig, ModelConfig, SamplerConfig, TokenizerConfig},
+		types::{BackendError, FinishReason, PromptRequest, SessionRequest},
+	};
+
+	use super::{Backend, BackendStats};
+
+	/// A HuggingFace word-level tokenizer with a tiny vocabulary
+	const TEST_TOKENIZER: &str = r#"{
+		"version": "1.0",
+		"truncation": null,
+		"padding": null,
+		"added_tokens": [],
+		"normalizer": null,
+		"pre_tokenizer": { "type": "WhitespaceSplit" },
+		"post_processor": null,
+		"decoder": null,
+		"model": { "type": "WordLevel", "vocab": { "[UNK]": 0, "Hello": 1, "world": 2 }, "unk_token": "[UNK]" }
+	}"#;
+
+	/// Model used by tests that need to run a model (in the `data` directory at the root of the repository)
+	static MODEL_PATH: &str = "../data/gpt2.bin";
+
+	/// Temporary directory that a test backend uses as cache path, holding a file `model.bin` that is not a valid model.
+	/// The directory is removed when the fixture is dropped (also when the test fails).
+	struct TestBackendDir {
+		dir: PathBuf,
+		model_path: PathBuf,
+	}
+
+	impl TestBackendDir {
+		fn new(model_contents: &str) -> TestBackendDir {
+			let dir = std::env::temp_dir().join(format!("poly-test-{}", uuid::Uuid::new_v4()));
+			std::fs::create_dir_all(&dir).unwrap();
+			let model_path = dir.join("model.bin");
+			std::fs::write(&model_path, model_contents).unwrap();
+			TestBackendDir { dir, model_path }
+		}
+
+		/// Configuration (TOML) that uses the directory as cache path and defines a model `test` that uses the model file.
+		/// `rest` is appended, so it may start with further settings for the model (e.g. `lazy = true`).
+		fn config_toml(&self, rest: &str) -> String {
+			format!(
+				"cache_path = {:?}\n\n[models.test]\narchitecture = \"llama\"\nmodel_path = {:?}\n{rest}\n",
+				self.dir, self.model_path
+			)
+		}
+
+		async fn backend(&self, rest: &str) -> Result<Backend, BackendError> {
+			Backend::from(toml::from_str(&self.config_toml(rest)).unwrap(), None).await
+		}
+	}
+
+	impl Drop for TestBackendDir {
+		fn drop(&mut self) {
+			_ = std::fs::remove_dir_all(&self.dir);
+		}
+	}
+
+	#[test]
+	pub fn test_task_config_overrides() {
+		let config: BackendConfig = toml::from_str(
+			r#"
+			[tasks.test]
+			model = "test"
+			max_tokens = 100
+			temperature = 0.7
+			top_k = 10
+			"#,
+		)
+		.unwrap();
+		let backend = Backend::unloaded(config);
+
+		// Without overrides, the configured values are used
+		let task_config = backend.task_config("test", &SessionRequest::default()).unwrap();
+		assert_eq!(task_config.max_tokens, Some(100));
+		let SamplerConfig::Standard(ref sampler_config) = task_config.sampler else {
+			panic!("expected standard sampler");
+		};
+		assert_eq!(sampler_config.temperature, 0.7);
+		assert_eq!(sampler_config.top_k, 10);
+		assert_eq!(sampler_config.top_p, 0.95);
+
+		// Overrides replace only the values that are specified
+		let request = SessionRequest {
+			max_tokens: Some(5),
+			temperature: Some(0.1),
+			top_p: Some(0.5),
+			..Default::default()
+		};
+		let task_config = backend.task_config("test", &request).unwrap();
+		assert_eq!(task_config.max_tokens, Some(5));
+		let SamplerConfig::Standard(ref sampler_config) = task_config.sampler else {
+			panic!("expected standard sampler");
+		};
+		assert_eq!(sampler_config.temperature, 0.1);
+		assert_eq!(sampler_config.top_k, 10);
+		assert_eq!(sampler_config.top_p, 0.5);
+
+		// The maximum number of tokens cannot be raised above the configured maximum
+		let request = SessionRequest {
+			max_tokens: Some(1000),
+			..Default::default()
+		};
+		assert_eq!(backend.task_config("test", &request).unwrap().max_tokens, Some(100));
+
+		// Out of range sampler parameters are rejected
+		for request in [
+			SessionRequest {
+				temperature: Some(-1.0),
+				..Default::default()
+			},
+			SessionRequest {
+				temperature: Some(f32::NAN),
+				..Default::default()
+			},
+			SessionRequest {
+				top_p: Some(1.5),
+				..Default::default()
+			},
+			SessionRequest {
+				repeat_penalty: Some(-0.5),
+				..Default::default()
+			},
+		] {
+			assert!(matches!(backend.task_config("test", &request), Err(BackendError::InvalidParameter(_))));
+		}
+	}
+
+	#[test]
+	pub fn test_low_confidence_steps() {
+		let config: BackendConfig = toml::from_str("[tasks.test]\nmodel = \"test\"\nlow_confidence_threshold = 0.1").unwrap();
+		assert_eq!(config.tasks["test"].low_confidence_steps.get(), 3);
+
+		// A warning after zero steps makes no sense (the threshold is left unset to disable warnings)
+		assert!(toml::from_str::<BackendConfig>("[tasks.test]\nmodel = \"test\"\nlow_confidence_steps = 0").is_err());
+	}
+
+	#[test]
+	pub fn test_invalidate_model_caches() {
+		let config: BackendConfig = toml::from_str(
+			r#"
+			[tasks.a]
+			model = "first"
+			prelude = "You are a helpful assistant."
+			cache_responses = true
+			top_k = 1
+
+			[tasks.b]
+			model = "second"
+			cache_responses = true
+			top_k = 1
+			"#,
+		)
+		.unwrap();
+		let backend = Backend::unloaded(config);
+		let prompt = PromptRequest {
+			prompt: String::from("Hello"),
+			assistant_prefix: None,
+		};
+		let completion = CachedCompletion {
+			text: String::from("world"),
+			finish_reason: FinishReason::EndOfText,
+			warnings: vec![],
+			value: None,
+			usage: Default::default(),
+		};
+		let key_a = backend.response_cache_key("a", &SessionRequest::default(), &prompt).unwrap().unwrap();
+		let key_b = backend.response_cache_key("b", &SessionRequest::default(), &prompt).unwrap().unwrap();
+		backend.cache_response(key_a.clone(), completion.clone());
+		backend.cache_response(key_b.clone(), completion.clone());
+
+		// Only the cached responses of the tasks using the model are discarded
+		backend.invalidate_model_caches(&backend.config(), "first");
+		assert_eq!(backend.cached_response(&key_a), None);
+		assert_eq!(backend.cached_response(&key_b), Some(completion));
+	}
+
+	#[test]
+	pub fn test_stored_session_not_found() {
+		let config: BackendConfig = toml::from_str(
+			r#"
+			[tasks.test]
+			model = "test"
+			"#,
+		)
+		.unwrap();
+		let backend = Backend::unloaded(config);
+
+		// Unknown (or malformed) session identifiers are rejected
+		assert!(backend.stored_session("test", "00000000-0000-0000-0000-000000000000").is_err());
+		assert!(backend.stored_session("test", "foo").is_err());
+		assert_eq!(backend.evict_expired_sessions(), 0);
+	}
+
+	#[test]
+	pub fn test_cancel_request() {
+		let config: BackendConfig = toml::from_str(
+			r#"
+			[tasks.test]
+			model = "test"
+			"#,
+		)
+		.unwrap();
+		let backend = Backend::unloaded(config);
+
+		let first = backend.register_session("test", Some("first".to_string()));
+		let second = backend.register_session("test", Some("second".to_string()));
+		*second.sub.lock().unwrap() = Some("user".to_string());
+
+		// Only sessions with the indicated request ID started by the same user are signalled
+		assert_eq!(backend.cancel_request("test", "second", None).unwrap(), 0);
+		assert_eq!(backend.cancel_request("test", "first", None).unwrap(), 1);
+		assert!(first.cancelled.load(Ordering::SeqCst));
+		assert!(!second.cancelled.load(Ordering::SeqCst));
+
+		assert_eq!(backend.cancel_request("test", "second", Some("user")).unwrap(), 1);
+		assert!(second.cancelled.load(Ordering::SeqCst));
+
+		assert!(backend.cancel_request("other", "first", None).is_err());
+	}
+
+	#[test]
+	pub fn test_memory_embedding_model() {
+		let config: BackendConfig = toml::from_str(
+			r#"
+			[memories.test]
+			store = { hora = {} }
+			dimensions = 3
+			embedding_model = "embedder"
+
+			[tasks.test]
+			model = "generator"
+			memorization = { memory = "test", store_prompts = false, retrieve = 1 }
+			"#,
+		)
+		.unwrap();
+		let backend = Backend::unloaded(config);
+
+		// The model of the memory is used for embeddings, not the model of the task
+		let config = backend.config();
+		let memory_name = &config.tasks["test"].memorization.as_ref().unwrap().memory;
+		assert_eq!(backend.memory_embedding_model(memory_name).unwrap(), "embedder");
+		assert!(backend.memory_embedding_model("other").is_err());
+	}
+
+	#[tokio::test]
+	pub async fn test_download_resume() {
+		use tokio::{
+			io::{AsyncReadExt, AsyncWriteExt},
+			net::TcpListener,
+		};
+
+		let data: Vec<u8> = (0..10_000u32).map(|x| (x % 251) as u8).collect();
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!("http://{}/model.bin", listener.local_addr().unwrap());
+
+		let served = data.clone();
+		let server = tokio::spawn(async move {
+			// First request: announce the full file but drop the connection halfway
+			let (mut socket, _) = listener.accept().await.unwrap();
+			let mut buf = [0u8; 4096];
+			let _ = socket.read(&mut buf).await.unwrap();
+			let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", served.len());
+			socket.write_all(header.as_bytes()).await.unwrap();
+			socket.write_all(&served[0..served.len() / 2]).await.unwrap();
+			drop(socket);
+
+			// Second request: should ask for the remainder of the file
+			let (mut socket, _) = listener.accept().await.unwrap();
+			let n = socket.read(&mut buf).await.unwrap();
+			let request = String::from_utf8_lossy(&buf[0..n]).to_lowercase();
+			let offset: usize = request
+				.lines()
+				.find_map(|line| line.strip_prefix("range: bytes="))
+				.and_then(|range| range.trim_end_matches('-').parse().ok())
+				.expect("expected a range request");
+			let header = format!(
+				"HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {offset}-{}/{}\r\n\r\n",
+				served.len() - offset,
+				served.len() - 1,
+				served.len()
+			);
+			socket.write_all(header.as_bytes()).await.unwrap();
+			socket.write_all(&served[offset..]).await.unwrap();
+		});
+
+		let target_path = std::env::temp_dir().join(format!("poly-test-{}.bin", uuid::Uuid::new_v4()));
+		assert!(Backend::download_model(&url, &target_path).await.is_err());
+		assert!(!target_path.exists());
+
+		Backend::download_model(&url, &target_path).await.unwrap();
+		server.await.unwrap();
+		assert_eq!(std::fs::read(&target_path).unwrap(), data);
+		std::fs::remove_file(&target_path).unwrap();
+	}
+
+	#[tokio::test]
+	pub async fn test_model_load_error() {
+		let dir = TestBackendDir::new("version https://git-lfs.github.com/spec/v1\n");
+		match dir.backend("").await {
+			Err(BackendError::ModelLoad { name, path, .. }) => {
+				assert_eq!(name, "test");
+				assert_eq!(path, dir.model_path);
+			}
+			Err(e) => panic!("expected model load error, got {e}"),
+			Ok(_) => panic!("expected model load error"),
+		}
+	}
+
+	#[tokio::test]
+	pub async fn test_invalid_config_error() {
+		let dir = TestBackendDir::new("not a model");
+		let result = dir.backend("lazy = true\n\n[tasks.test]\nmodel = \"nonexistent\"").await;
+		assert!(matches!(result, Err(BackendError::InvalidConfig(_))));
+	}
+
+	#[tokio::test]
+	pub async fn test_embedding_verification_error() {
+		let dir = TestBackendDir::new("not a model");
+		let mut config: BackendConfig = toml::from_str(&dir.config_toml(
+			r#"
+			lazy = true
+
+			[memories.test]
+			embedding_model = "test"
+			dimensions = 4
+			store = { memory = {} }
+			"#,
+		))
+		.unwrap();
+		config.verify_embedding_models = true;
+
+		// The (lazily loaded) embedding model cannot be loaded, so it fails verification
+		match Backend::from(config, None).await {
+			Err(BackendError::EmbeddingUnsupported(message)) => assert!(message.contains("memory test")),
+			Err(e) => panic!("expected embedding verification error, got {e}"),
+			Ok(_) => panic!("expected embedding verification error"),
+		}
+	}
+
+	#[test]
+	pub fn test_model_parameters() {
+		let model_config: ModelConfig = toml::from_str(
+			r#"
+			architecture = "llama"
+			context_size = 4096
+			rope_frequency_scale = 0.5
+			n_gqa = 8
+			"#,
+		)
+		.unwrap();
+		let params = model_config.parameters();
+		assert_eq!(params.context_size, 4096);
+		assert_eq!(params.n_gqa, Some(8));
+		let rope_overrides = params.rope_overrides.unwrap();
+		assert_eq!(rope_overrides.frequency_scale, 0.5);
+		assert_eq!(rope_overrides.frequency_base, 10_000);
+
+		// Without any RoPE settings, the defaults of the model are used
+		let model_config: ModelConfig = toml::from_str(r#"architecture = "llama""#).unwrap();
+		let params = model_config.parameters();
+		assert!(params.rope_overrides.is_none());
+		assert!(params.n_gqa.is_none());
+	}
+
+	#[tokio::test]
+	pub async fn test_lazy_model() {
+		let dir = TestBackendDir::new("not a model");
+
+		// The (invalid) model is not loaded at startup, only when it is first used
+		let backend = dir.backend("lazy = true").await.unwrap();
+		assert!(backend.models.read().unwrap().is_empty());
+		assert!(matches!(backend.model_async("test").await, Err(BackendError::ModelLoad { .. })));
+	}
+
+	#[test]
+	pub fn test_models_to_evict() {
+		let now = std::time::Instant::now();
+		let last_used = std::collections::HashMap::from([
+			(String::from("a"), now),
+			(String::from("b"), now + std::time::Duration::from_secs(1)),
+			(String::from("c"), now + std::time::Duration::from_secs(2)),
+		]);
+		let loaded = [String::from("a"), String::from("b"), String::from("c")];
+
+		assert!(Backend::models_to_evict(loaded.iter(), &last_used, "c", 3).is_empty());
+		assert_eq!(Backend::models_to_evict(loaded.iter(), &last_used, "c", 2), vec!["a"]);
+		assert_eq!(Backend::models_to_evict(loaded.iter(), &last_used, "a", 2), vec!["b"]);
+		assert_eq!(Backend::models_to_evict(loaded.iter(), &last_used, "c", 1), vec!["a", "b"]);
+	}
+
+	#[test]
+	pub fn test_sessions_to_evict() {
+		let now = std::time::Instant::now();
+		let ids: Vec<uuid::Uuid> = (0..4).map(|_| uuid::Uuid::new_v4()).collect();
+		let stored = [
+			(ids[0], Some("alice"), now),
+			(ids[1], Some("bob"), now + std::time::Duration::from_secs(1)),
+			(ids[2], Some("alice"), now + std::time::Duration::from_secs(2)),
+			(ids[3], None, now + std::time::Duration::from_secs(3)),
+		];
+
+		assert!(Backend::sessions_to_evict(stored.iter().copied(), Some("alice"), 5, 3).is_empty());
+		assert_eq!(Backend::sessions_to_evict(stored.iter().copied(), Some("alice"), 5, 2), vec![ids[0]]);
+		assert_eq!(Backend::sessions_to_evict(stored.iter().copied(), Some("bob"), 4, 2), vec![ids[0]]);
+		assert_eq!(
+			Backend::sessions_to_evict(stored.iter().copied(), Some("alice"), 3, 1),
+			vec![ids[0], ids[2]]
+		);
+		assert_eq!(
+			Backend::sessions_to_evict(stored.iter().copied(), Some("alice"), 2, 1),
+			vec![ids[0], ids[2], ids[1]]
+		);
+		assert_eq!(
+			Backend::sessions_to_evict(stored.iter().copied(), None, 2, 1),
+			vec![ids[0], ids[1], ids[2]]
+		);
+	}
+
+	#[tokio::test]
+	pub async fn test_reload() {
+		let dir = TestBackendDir::new("not a model");
+		let config_toml = dir.config_toml("lazy = true\n\n[tasks.first]\nmodel = \"test\"\n");
+		let backend = Backend::from(toml::from_str(&config_toml).unwrap(), None).await.unwrap();
+		assert!(!backend.config().tasks.contains_key("second"));
+
+		// Append a task to the configuration
+		let config_toml = format!("{config_toml}\n[tasks.second]\nmodel = \"test\"\n");
+		let response = backend.reload(toml::from_str(&config_toml).unwrap()).await.unwrap();
+		assert_eq!(response.added_tasks, vec!["second"]);
+		assert!(response.removed_tasks.is_empty());
+		assert!(backend.config().tasks.contains_key("first"));
+		assert!(backend.config().tasks.contains_key("second"));
+
+		// An invalid configuration is not applied
+		let config_toml = format!("{config_toml}\n[tasks.third]\nmodel = \"nonexistent\"\n");
+		assert!(matches!(
+			backend.reload(toml::from_str(&config_toml).unwrap()).await,
+			Err(BackendError::InvalidConfig(_))
+		));
+		assert!(!backend.config().tasks.contains_key("third"));
+	}
+
+	#[tokio::test]
+	pub async fn test_memorize_batch() {
+		let dir = TestBackendDir::new("not a model");
+		let backend = dir
+			.backend(&format!(
+				r#"
+				lazy = true
+
+				[models.gpt2]
+				architecture = "gpt2"
+				model_path = {MODEL_PATH:?}
+				batch_size = 2
+
+				[memories.test]
+				embedding_model = "gpt2"
+				dimensions = 768
+				store = {{ memory = {{}} }}
+				"#
+			))
+			.await
+			.unwrap();
+
+		// Each document is a single chunk; the chunks are embedded in a batch of two and a batch of one
+		let mut documents = vec![
+			String::from("The quick brown fox jumps over the lazy dog."),
+			String::from("A journey of a thousand miles begins with a single step."),
+			String::from("All that glitters is not gold."),
+		];
+		let summary = backend.memorize_batch("test", &documents).await.unwrap();
+		assert_eq!(summary.chunks, 3);
+		assert_eq!(summary.stored, 3);
+		assert_eq!(summary.skipped, 0);
+
+		// All chunks can be retrieved from memory
+		let (mut items, count) = backend.memory_items("test", 0, 10).await.unwrap();
+		assert_eq!(count, 3);
+		items.sort();
+		documents.sort();
+		assert_eq!(items, documents);
+	}
+
+	#[test]
+	pub fn test_prelude_snapshot_key() {
+		let key = Backend::prelude_snapshot_key("model", "You are a helpful assistant.");
+		assert_eq!(key, Backend::prelude_snapshot_key("model", "You are a helpful assistant."));
+		assert_ne!(key, Backend::prelude_snapshot_key("other", "You are a helpful assistant."));
+		assert_ne!(key, Backend::prelude_snapshot_key("model", "You are a grumpy assistant."));
+		assert_ne!(Backend::prelude_snapshot_key("ab", "c"), Backend::prelude_snapshot_key("a", "bc"));
+	}
+
+	#[test]
+	pub fn test_decode_tokens() {
+		let tokenizer = TokenizerSource::HuggingFaceTokenizerString(String::from(TEST_TOKENIZER))
+			.retrieve(Path::new(""))
+			.unwrap();
+
+		let text = "Hello world";
+		let tokens: Vec<TokenId> = tokenizer.tokenize(text, false).unwrap().iter().map(|t| t.1).collect();
+		assert_eq!(tokens, vec![1, 2]);
+		assert_eq!(Backend::decode_tokens(&tokenizer, tokens).unwrap(), text);
+
+		assert!(matches!(
+			Backend::decode_tokens(&tokenizer, vec![1, 3]),
+			Err(BackendError::InvalidTokenId(3))
+		));
+	}
+
+	#[test]
+	pub fn test_tokenizer_config() {
+		let model_config: ModelConfig = toml::from_str(r#"architecture = "llama""#).unwrap();
+		assert_eq!(model_config.tokenizer, TokenizerConfig::Embedded);
+		assert!(matches!(model_config.tokenizer.source(), TokenizerSource::Embedded));
+
+		let model_config: ModelConfig = toml::from_str(
+			r#"
+			architecture = "llama"
+			tokenizer = { huggingface = { repo = "meta-llama/Llama-2-7b-hf" } }
+			"#,
+		)
+		.unwrap();
+		assert!(matches!(model_config.tokenizer.source(), TokenizerSource::HuggingFaceRemote(repo) if repo == "meta-llama/Llama-2-7b-hf"));
+
+		// A tokenizer read from a file tokenizes differently than the (empty) embedded tokenizer
+		let dir = std::env::temp_dir().join(format!("poly-test-{}", uuid::Uuid::new_v4()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let tokenizer_path = dir.join("tokenizer.json");
+		std::fs::write(&tokenizer_path, TEST_TOKENIZER).unwrap();
+		let model_config: ModelConfig = toml::from_str(&format!(
+			r#"
+			architecture = "llama"
+			tokenizer = {{ file = {{ path = {tokenizer_path:?} }} }}
+			"#
+		))
+		.unwrap();
+		assert_eq!(model_config.tokenizer, TokenizerConfig::File { path: tokenizer_path });
+
+		let model_path = dir.join("model.bin");
+		let tokenize = |source: TokenizerSource| -> Option<Vec<TokenId>> {
+			let tokenizer = source.retrieve(&model_path).unwrap();
+			tokenizer.tokenize("Hello world", false).ok().map(|t| t.iter().map(|t| t.1).collect())
+		};
+		assert_eq!(tokenize(model_config.tokenizer.source()), Some(vec![1, 2]));
+		assert_ne!(tokenize(TokenizerConfig::Embedded.source()), Some(vec![1, 2]));
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	pub fn test_backend_stats() {
+		let stats = BackendStats::default();
+		let inference_stats = InferenceStats {
+			feed_prompt_duration: Duration::from_millis(10),
+			prompt_tokens: 12,
+			predict_duration: Duration::from_millis(20),
+			predict_tokens: 5,
+		};
+
+		// Completions for two tasks using the same model are aggregated for the model
+		stats.add("first", "model", &inference_stats, 1);
+		stats.add("second", "model", &inference_stats, 1);
+		stats.add_embeddings("model", 3);
+		{
+			let model_stats = stats.model_stats.lock().unwrap();
+			let model_stats = &model_stats["model"];
+			assert_eq!(model_stats.completions, 2);
+			assert_eq!(model_stats.prompt_tokens, 24);
+			assert_eq!(model_stats.predict_tokens, 10);
+			assert_eq!(model_stats.embeddings, 3);
 		}
+		assert_eq!(stats.task_stats.lock().unwrap().len(), 2);
+
+		stats.add_recall("memory");
+		stats.add_recall("memory");
+		stats.add_ingest("memory", 7);
+		let memory_stats = stats.memory_stats.lock().unwrap();
+		let memory_stats = &memory_stats["memory"];
+		assert_eq!(memory_stats.recalls, 2);
+		assert_eq!(memory_stats.ingests, 1);
+		assert_eq!(memory_stats.chunks_stored, 7);
+		drop(memory_stats);
+
+		stats.reset();
+		assert!(stats.task_stats.lock().unwrap().is_empty());
+		assert!(stats.model_stats.lock().unwrap().is_empty());
+		assert!(stats.memory_stats.lock().unwrap().is_empty());
+
+		// Statistics are recorded again after a reset
+		stats.add("first", "model", &inference_stats, 1);
+		assert_eq!(stats.model_stats.lock().unwrap()["model"].completions, 1);
 	}
 }
@@ -0,0 +1,222 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	time::{Duration, Instant},
+};
+
+use crate::{
+	stats::Usage,
+	types::{CompletionWarning, FinishReason},
+};
+
+/// A completion stored in the response cache
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedCompletion {
+	pub text: String,
+	pub finish_reason: FinishReason,
+	pub warnings: Vec<CompletionWarning>,
+	pub value: Option<serde_json::Value>,
+	pub usage: Usage,
+}
+
+/// Bounded cache of values with an optional time to live. When the cache is full, the oldest entry is evicted. Besides
+/// the number of entries, the total size of the values can be bounded (see [ResponseCache::with_max_size]).
+#[derive(Debug)]
+pub struct ResponseCache<V: Clone> {
+	entries: HashMap<String, (Instant, V, usize)>,
+	order: VecDeque<String>,
+	max_entries: usize,
+	ttl: Option<Duration>,
+	size: usize,
+	max_size: Option<usize>,
+}
+
+impl<V: Clone> ResponseCache<V> {
+	pub fn new(max_entries: usize, ttl: Option<Duration>) -> Self {
+		ResponseCache {
+			entries: HashMap::new(),
+			order: VecDeque::new(),
+			max_entries,
+			ttl,
+			size: 0,
+			max_size: None,
+		}
+	}
+
+	/// Limit the total size of the values in the cache (as indicated to [ResponseCache::insert_sized]). Entries are evicted
+	/// (oldest first) until the new value fits. Values larger than the limit are not stored.
+	pub fn with_max_size(mut self, max_size: usize) -> Self {
+		self.max_size = Some(max_size);
+		self
+	}
+
+	/// Total size of the values in the cache
+	pub fn size(&self) -> usize {
+		self.size
+	}
+
+	/// Return the value stored for the key, unless it has expired
+	pub fn get(&mut self, key: &str) -> Option<V> {
+		let (stored, value, _) = self.entries.get(key)?;
+		if self.ttl.is_some_and(|ttl| stored.elapsed() > ttl) {
+			self.remove(key);
+			return None;
+		}
+		Some(value.clone())
+	}
+
+	/// Like [ResponseCache::get], but also marks the entry as the newest, so that it is evicted last (this makes the cache
+	/// evict the least recently used entry instead of the oldest one)
+	pub fn get_and_refresh(&mut self, key: &str) -> Option<V> {
+		let value = self.get(key)?;
+		if let Some(position) = self.order.iter().position(|k| k == key) {
+			if let Some(k) = self.order.remove(position) {
+				self.order.push_back(k);
+			}
+		}
+		Some(value)
+	}
+
+	/// Remove the entries for which `remove` returns true
+	pub fn remove_where(&mut self, mut remove: impl FnMut(&str) -> bool) {
+		let mut removed_size = 0;
+		self.entries.retain(|key, (_, _, size)| {
+			let keep = !remove(key);
+			if !keep {
+				removed_size += *size;
+			}
+			keep
+		});
+		self.size -= removed_size;
+		self.order.retain(|key| self.entries.contains_key(key));
+	}
+
+	fn remove(&mut self, key: &str) {
+		if let Some((_, _, size)) = self.entries.remove(key) {
+			self.size -= size;
+		}
+		self.order.retain(|k| k != key);
+	}
+
+	pub fn insert(&mut self, key: String, value: V) {
+		self.insert_sized(key, value, 0);
+	}
+
+	/// Insert a value of the indicated size (in units of the limit set with [ResponseCache::with_max_size])
+	pub fn insert_sized(&mut self, key: String, value: V, size: usize) {
+		if self.max_entries == 0 || self.max_size.is_some_and(|max_size| size > max_size) {
+			return;
+		}
+
+		self.remove(&key);
+		self.entries.insert(key.clone(), (Instant::now(), value, size));
+		self.order.push_back(key);
+		self.size += size;
+
+		while self.order.len() > self.max_entries || self.max_size.is_some_and(|max_size| self.size > max_size) {
+			let Some(oldest) = self.order.pop_front() else {
+				break;
+			};
+			if let Some((_, _, size)) = self.entries.remove(&oldest) {
+				self.size -= size;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use super::ResponseCache;
+
+	#[test]
+	pub fn test_response_cache() {
+		let mut cache = ResponseCache::new(2, None);
+		cache.insert("a".to_string(), 1);
+		cache.insert("b".to_string(), 2);
+		assert_eq!(cache.get("a"), Some(1));
+		assert_eq!(cache.get("c"), None);
+
+		// The oldest entry is evicted
+		cache.insert("c".to_string(), 3);
+		assert_eq!(cache.get("a"), None);
+		assert_eq!(cache.get("b"), Some(2));
+		assert_eq!(cache.get("c"), Some(3));
+
+		// Replacing an entry makes it the newest
+		cache.insert("b".to_string(), 4);
+		cache.insert("d".to_string(), 5);
+		assert_eq!(cache.get("b"), Some(4));
+		assert_eq!(cache.get("c"), None);
+
+		// Expired entries are not returned
+		let mut cache = ResponseCache::new(2, Some(Duration::ZERO));
+		cache.insert("a".to_string(), 1);
+		std::thread::sleep(Duration::from_millis(1));
+		assert_eq!(cache.get("a"), None);
+
+		// A cache without room stores nothing
+		let mut cache = ResponseCache::new(0, None);
+		cache.insert("a".to_string(), 1);
+		assert_eq!(cache.get("a"), None);
+	}
+
+	#[test]
+	pub fn test_get_and_refresh() {
+		let mut cache = ResponseCache::new(2, None);
+		cache.insert("a".to_string(), 1);
+		cache.insert("b".to_string(), 2);
+
+		// Using an entry keeps it from being evicted
+		assert_eq!(cache.get_and_refresh("a"), Some(1));
+		cache.insert("c".to_string(), 3);
+		assert_eq!(cache.get("a"), Some(1));
+		assert_eq!(cache.get("b"), None);
+		assert_eq!(cache.get("c"), Some(3));
+		assert_eq!(cache.get_and_refresh("b"), None);
+	}
+
+	#[test]
+	pub fn test_max_size() {
+		let mut cache = ResponseCache::new(10, None).with_max_size(100);
+		cache.insert_sized("a".to_string(), 1, 40);
+		cache.insert_sized("b".to_string(), 2, 40);
+		assert_eq!(cache.size(), 80);
+
+		// Entries are evicted until the new value fits, even though the number of entries is below the limit
+		cache.insert_sized("c".to_string(), 3, 40);
+		assert_eq!(cache.get("a"), None);
+		assert_eq!(cache.get("b"), Some(2));
+		assert_eq!(cache.size(), 80);
+
+		// Replacing an entry replaces its size
+		cache.insert_sized("b".to_string(), 4, 10);
+		assert_eq!(cache.size(), 50);
+
+		// Values larger than the limit are not stored (and do not evict other entries)
+		cache.insert_sized("d".to_string(), 5, 101);
+		assert_eq!(cache.get("d"), None);
+		assert_eq!(cache.get("c"), Some(3));
+
+		cache.remove_where(|key| key == "c");
+		assert_eq!(cache.size(), 10);
+	}
+
+	#[test]
+	pub fn test_remove_where() {
+		let mut cache = ResponseCache::new(3, None);
+		cache.insert("a1".to_string(), 1);
+		cache.insert("b1".to_string(), 2);
+		cache.insert("a2".to_string(), 3);
+
+		cache.remove_where(|key| key.starts_with('a'));
+		assert_eq!(cache.get("a1"), None);
+		assert_eq!(cache.get("a2"), None);
+		assert_eq!(cache.get("b1"), Some(2));
+
+		// Removed entries no longer take up room
+		cache.insert("c".to_string(), 4);
+		cache.insert("d".to_string(), 5);
+		assert_eq!(cache.get("b1"), Some(2));
+	}
+}
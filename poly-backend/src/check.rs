@@ -0,0 +1,237 @@
+use std::{collections::HashSet, fmt::Display};
+
+use crate::{
+	backend::Backend,
+	config::{BackendConfig, BiaserConfig, TokenizerConfig},
+	memory::MemoryStoreConfig,
+};
+
+/// Outcome of validating a single item (model, memory or task) of a configuration
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+	/// Description of the item that was checked (e.g. "model foo")
+	pub item: String,
+
+	/// Problems found with the item (empty when the item is valid)
+	pub problems: Vec<String>,
+
+	/// Remarks that do not make the item invalid
+	pub notes: Vec<String>,
+}
+
+impl CheckResult {
+	fn new(item: String) -> Self {
+		CheckResult {
+			item,
+			problems: vec![],
+			notes: vec![],
+		}
+	}
+
+	pub fn is_ok(&self) -> bool {
+		self.problems.is_empty()
+	}
+}
+
+impl Display for CheckResult {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{} {}", if self.is_ok() { "OK  " } else { "FAIL" }, self.item)?;
+		for problem in &self.problems {
+			write!(f, "\n     - {problem}")?;
+		}
+		for note in &self.notes {
+			write!(f, "\n     (note: {note})")?;
+		}
+		Ok(())
+	}
+}
+
+/// Validate a backend configuration without loading models. Performs the checks that are otherwise performed when the
+/// backend is instantiated (`Backend::from`), but reports all problems instead of stopping at the first one.
+pub fn check_config(config: &BackendConfig) -> Vec<CheckResult> {
+	let mut config = config.clone();
+	Backend::set_default_cache_path(&mut config);
+
+	let mut results = vec![];
+
+	// Models
+	let mut model_names: Vec<&String> = config.models.keys().collect();
+	model_names.sort();
+	for model_name in model_names {
+		let model_config = &config.models[model_name];
+		let mut result = CheckResult::new(format!("model {model_name}"));
+		if model_config.model_path.is_none() && config.cache_path.is_none() {
+			result
+				.problems
+				.push(String::from("no model_path set and no cache path available to store the model in"));
+		} else {
+			let model_path = Backend::model_path(&config, model_name, model_config);
+			if !model_path.exists() {
+				if let Some(ref url) = model_config.url {
+					result.notes.push(format!("model file {model_path:?} will be downloaded from {url}"));
+				} else {
+					result.problems.push(format!("model file not found at path {model_path:?}"));
+				}
+			}
+		}
+
+		if let TokenizerConfig::File { ref path } = model_config.tokenizer {
+			if !path.exists() {
+				result.problems.push(format!("tokenizer file not found at path {path:?}"));
+			}
+		}
+
+		if !model_config.use_gpu && model_config.gpu_layers.is_some() {
+			result
+				.notes
+				.push(String::from("gpu_layers set but ignored because use_gpu is not set to true"));
+		}
+		results.push(result);
+	}
+
+	// Memories
+	let mut memory_names: Vec<&String> = config.memories.keys().collect();
+	memory_names.sort();
+	for memory_name in memory_names {
+		let memory_config = &config.memories[memory_name];
+		let mut result = CheckResult::new(format!("memory {memory_name}"));
+		if !config.models.contains_key(&memory_config.embedding_model) {
+			result
+				.problems
+				.push(format!("embedding model {} not found", memory_config.embedding_model));
+		}
+
+		// The store itself is not constructed, as that may create or modify files
+		if let MemoryStoreConfig::Hora { path: Some(ref path), .. } = memory_config.store {
+			if !path.exists() {
+				result.notes.push(format!("memory file {path:?} does not exist yet and will be created"));
+			}
+		}
+		results.push(result);
+	}
+
+	// Tasks
+	let mut task_names: Vec<&String> = config.tasks.keys().collect();
+	task_names.sort();
+	for task_name in task_names {
+		let task_config = &config.tasks[task_name];
+		let mut result = CheckResult::new(format!("task {task_name}"));
+		if !config.models.contains_key(&task_config.model) {
+			result.problems.push(format!("model {} not found", task_config.model));
+		}
+
+		if let Some(ref prelude_file) = task_config.prelude_file {
+			if !prelude_file.exists() {
+				result.problems.push(format!("prelude file {prelude_file:?} not found"));
+			}
+		}
+
+		if let Some(ref memorization) = task_config.memorization {
+			match config.memories.get(&memorization.memory) {
+				None => result.problems.push(format!("memory {} not found", memorization.memory)),
+				Some(memory_config) if memorization.store_prompts && memory_config.read_only => result.problems.push(format!(
+					"memory {} is read-only but the task is configured to store prompts",
+					memorization.memory
+				)),
+				Some(_) => {}
+			}
+		}
+
+		if let Some(ref biaser) = task_config.biaser {
+			if let BiaserConfig::Tools(ref tools) = biaser {
+				if tools.is_empty() {
+					result.problems.push(String::from("no tools configured"));
+				}
+				let mut tool_names = HashSet::new();
+				for tool in tools {
+					if tool.name.is_empty() {
+						result.problems.push(String::from("tool name is empty"));
+					} else if !tool_names.insert(&tool.name) {
+						result.problems.push(format!("tool {} is configured more than once", tool.name));
+					}
+				}
+			}
+
+			match biaser.json_schema() {
+				Ok(schema) => {
+					if let Err(e) = schema.check() {
+						result.problems.push(format!("invalid schema: {e}"));
+					}
+				}
+				Err(e) => result.problems.push(e.to_string()),
+			}
+		}
+		results.push(result);
+	}
+
+	results
+}
+
+#[cfg(test)]
+mod test {
+	use crate::config::BackendConfig;
+
+	use super::check_config;
+
+	#[test]
+	fn test_check_config() {
+		let model_path = std::env::temp_dir().join(format!("poly-test-{}.bin", uuid::Uuid::new_v4()));
+		std::fs::write(&model_path, b"").unwrap();
+
+		let config: BackendConfig = toml::from_str(&format!(
+			r#"
+			[models.test]
+			architecture = "llama"
+			model_path = {model_path:?}
+
+			[tasks.boolean]
+			model = "test"
+			biaser = {{ json_schema = {{ type = "boolean" }} }}
+
+			[tasks.assistant]
+			model = "test"
+
+			[[tasks.assistant.biaser.tools]]
+			name = "get_weather"
+			arguments = {{ type = "object", required = ["city"], properties = {{ city = {{ type = "string", pattern = "^[A-Z]" }} }} }}
+			"#
+		))
+		.unwrap();
+		let results = check_config(&config);
+		assert_eq!(results.len(), 3);
+		assert!(results.iter().all(|r| r.is_ok()), "{results:?}");
+
+		let config: BackendConfig = toml::from_str(
+			r#"
+			[models.test]
+			architecture = "llama"
+			model_path = "/nonexistent/model.bin"
+
+			[tasks.schema]
+			model = "missing"
+			biaser = { json_schema_file = "/nonexistent/schema.json" }
+
+			[tasks.assistant]
+			model = "test"
+
+			[[tasks.assistant.biaser.tools]]
+			name = "get_weather"
+			arguments = { type = "object", required = ["city"], properties = { city = { type = "string", pattern = "[A-Z" } } }
+
+			[[tasks.assistant.biaser.tools]]
+			name = "get_weather"
+			arguments = { type = "null" }
+			"#,
+		)
+		.unwrap();
+		let results = check_config(&config);
+		assert_eq!(
+			results.iter().map(|r| (r.item.as_str(), r.problems.len())).collect::<Vec<_>>(),
+			vec![("model test", 1), ("task assistant", 2), ("task schema", 2)]
+		);
+		assert!(results[1].problems[0].contains("more than once"));
+		assert!(results[1].problems[1].starts_with("invalid schema: invalid pattern '[A-Z'"));
+
+		std::fs::remove_file(model_path).unwrap();
+	}
+}
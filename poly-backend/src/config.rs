@@ -1,17 +1,22 @@
 use llm::samplers::{
 	llm_samplers::{
 		configure::{SamplerChainBuilder, SamplerSlot},
-		samplers::{SampleRandDistrib, SampleRepetition, SampleTemperature, SampleTopK, SampleTopP},
+		samplers::{SampleFreqPresence, SampleRandDistrib, SampleRepetition, SampleTemperature, SampleTopK, SampleTopP},
 		types::SamplerChain,
 	},
 	ConfiguredSamplers,
 };
 pub use llm::ModelArchitecture;
+use llm::{ModelParameters, RoPEOverrides, TokenizerSource};
 use poly_bias::json::JsonSchema;
 use serde::{Deserialize, Deserializer, Serialize};
-use std::{collections::HashMap, path::PathBuf, str::FromStr};
+use serde_json::Value;
+use std::{borrow::Cow, collections::HashMap, fs::File, io::BufReader, num::NonZeroUsize, path::PathBuf, str::FromStr};
 
-use crate::memory::MemoryStoreConfig;
+use crate::{
+	memory::{MemoryMetric, MemoryStoreConfig},
+	types::BackendError,
+};
 
 fn architecture_from_str<'de, D>(deserializer: D) -> Result<ModelArchitecture, D::Error>
 where
@@ -40,7 +45,9 @@ pub struct MemoryConfig {
 	/// Model to use for embedding
 	pub embedding_model: String,
 
-	/// Separators to use while chunking
+	/// Separators to use while chunking, in order of preference (e.g. `["\n\n", ". "]` to split on paragraphs first, then
+	/// on sentences). A separator may span multiple tokens; it is matched against the text of the tokens, and chunks are
+	/// split at the token boundary closest to the end of each occurrence.
 	#[serde(default = "default_chunk_separators")]
 	pub chunk_separators: Vec<String>,
 
@@ -48,6 +55,11 @@ pub struct MemoryConfig {
 	#[serde(default = "default_chunk_max_tokens")]
 	pub chunk_max_tokens: usize,
 
+	/// Number of tokens at the end of a chunk that are repeated at the start of the next chunk (the overlap is reduced
+	/// where needed so that chunks never exceed `chunk_max_tokens`)
+	#[serde(default)]
+	pub chunk_overlap_tokens: usize,
+
 	/// Remove the following patterns (regular expressions) before chunking, replacing them with a single space (after
 	/// which double spaces are eliminated)
 	#[serde(default = "default_pre_filter")]
@@ -56,6 +68,25 @@ pub struct MemoryConfig {
 	/// Remove the following tokens after chunking (strings must refer to single tokens)
 	#[serde(default = "default_post_filter")]
 	pub post_filter: Vec<String>,
+
+	/// When set, items cannot be stored in or removed from the memory (recall is still possible)
+	#[serde(default)]
+	pub read_only: bool,
+
+	/// When recalling with deduplication, items whose embedding has a cosine similarity above this threshold with an item
+	/// that was already recalled are skipped
+	#[serde(default = "default_dedup_similarity_threshold")]
+	pub dedup_similarity_threshold: f32,
+
+	/// Distance metric used to find the items nearest to a query (default cosine; does not apply to Qdrant memories, for
+	/// which the distance is configured on the collection). A Hora index records the metric it was built with and keeps
+	/// using it when this is not set (indexes created before the metric was recorded use euclidean). Setting a different
+	/// metric than that of an existing index is an error.
+	pub metric: Option<MemoryMetric>,
+}
+
+const fn default_dedup_similarity_threshold() -> f32 {
+	0.95
 }
 
 fn default_pre_filter() -> Vec<String> {
@@ -113,6 +144,92 @@ pub struct ModelConfig {
 	/// A reasonable default value is 8.
 	#[serde(default = "default_batch_size")]
 	pub batch_size: usize,
+
+	/// When set, the model is unloaded after it has not been used for this number of seconds (it is loaded again when it
+	/// is needed)
+	pub unload_after_idle_secs: Option<u64>,
+
+	/// Whether to run a short inference after loading the model, so that (GPU) kernels are compiled and memory is allocated
+	/// before the first actual request comes in
+	#[serde(default)]
+	pub warmup: bool,
+
+	/// Strings that are removed from generated text (e.g. special tokens that the tokenizer of the model leaks into its
+	/// output). Removal happens before stop sequences are detected.
+	#[serde(default)]
+	pub output_strip: Vec<String>,
+
+	/// Base frequency for rotary position embeddings (RoPE). Models that were trained (or fine-tuned) for an extended
+	/// context size may require a different value than the default (10000).
+	pub rope_frequency_base: Option<usize>,
+
+	/// Scaling factor for rotary position embeddings (RoPE), used to run models with a context size larger than the one
+	/// they were trained for (default: 1.0, i.e. no scaling)
+	pub rope_frequency_scale: Option<f32>,
+
+	/// Grouped-query attention factor, required for models that use grouped-query attention (e.g. 8 for LLaMA-2 70B)
+	pub n_gqa: Option<usize>,
+
+	/// When set, the model is not loaded at startup but only when it is first used
+	#[serde(default)]
+	pub lazy: bool,
+
+	/// Where to obtain the tokenizer for the model from (by default the tokenizer embedded in the model file is used)
+	#[serde(default)]
+	pub tokenizer: TokenizerConfig,
+}
+
+/// Source of the tokenizer for a model
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenizerConfig {
+	/// Use the tokenizer embedded in the model file
+	#[default]
+	Embedded,
+
+	/// Download the tokenizer from a repository on the HuggingFace hub
+	Huggingface {
+		/// Name of the repository (e.g. "meta-llama/Llama-2-7b-hf")
+		repo: String,
+	},
+
+	/// Read the tokenizer from a HuggingFace `tokenizer.json` file
+	File { path: PathBuf },
+}
+
+impl TokenizerConfig {
+	pub fn source(&self) -> TokenizerSource {
+		match self {
+			TokenizerConfig::Embedded => TokenizerSource::Embedded,
+			TokenizerConfig::Huggingface { repo } => TokenizerSource::HuggingFaceRemote(repo.clone()),
+			TokenizerConfig::File { path } => TokenizerSource::HuggingFaceTokenizerFile(path.clone()),
+		}
+	}
+}
+
+impl ModelConfig {
+	/// The parameters with which the model should be loaded
+	pub fn parameters(&self) -> ModelParameters {
+		let rope_overrides = if self.rope_frequency_base.is_some() || self.rope_frequency_scale.is_some() {
+			let defaults = RoPEOverrides::default();
+			Some(RoPEOverrides {
+				frequency_base: self.rope_frequency_base.unwrap_or(defaults.frequency_base),
+				frequency_scale: self.rope_frequency_scale.unwrap_or(defaults.frequency_scale),
+			})
+		} else {
+			None
+		};
+
+		ModelParameters {
+			prefer_mmap: true,
+			context_size: self.context_size,
+			lora_adapters: self.lora_adapters.clone(),
+			use_gpu: self.use_gpu,
+			gpu_layers: self.gpu_layers,
+			rope_overrides,
+			n_gqa: self.n_gqa,
+		}
+	}
 }
 
 const fn default_use_gpu() -> bool {
@@ -135,7 +252,7 @@ fn default_chunk_separators() -> Vec<String> {
 	vec![String::from(" ")]
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum BiaserConfig {
 	/// Configure Biaser from JSON schema included directly in the configuration
@@ -143,9 +260,69 @@ pub enum BiaserConfig {
 
 	/// Configure Biaser using an external file containing a JSON schema (in JSON)
 	JsonSchemaFile(PathBuf),
+
+	/// Configure Biaser using an external file containing a schema in the standard JSON Schema vocabulary (see
+	/// [JsonSchema::from_standard] for the supported keywords)
+	StandardJsonSchemaFile(PathBuf),
+
+	/// Have the model select one of the tools and generate arguments for it. The output will be of the form
+	/// `{"tool": "<name>", "arguments": <arguments>}` (see [crate::types::ToolCall])
+	Tools(Vec<ToolConfig>),
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Serialize)]
+pub struct ToolConfig {
+	/// Name of the tool
+	pub name: String,
+
+	/// Schema for the arguments to the tool
+	pub arguments: JsonSchema,
+}
+
+impl ToolConfig {
+	/// Schema for a call to this tool
+	fn call_schema(&self) -> JsonSchema {
+		JsonSchema::Object {
+			required: vec![String::from("tool"), String::from("arguments")],
+			properties: HashMap::from([
+				(
+					String::from("tool"),
+					Box::new(JsonSchema::Const {
+						value: Value::String(self.name.clone()),
+					}),
+				),
+				(String::from("arguments"), Box::new(self.arguments.clone())),
+			]),
+			optional: vec![],
+			additional_properties: None,
+		}
+	}
+}
+
+impl BiaserConfig {
+	/// Returns the JSON schema for this biaser (reading it from file if necessary)
+	pub fn json_schema(&self) -> Result<Cow<JsonSchema>, BackendError> {
+		Ok(match self {
+			BiaserConfig::JsonSchema(schema) => Cow::Borrowed(schema),
+			BiaserConfig::JsonSchemaFile(path) => {
+				let file = File::open(path).map_err(|e| BackendError::SchemaFile(path.clone(), e.to_string()))?;
+				let rdr = BufReader::new(file);
+				Cow::Owned(serde_json::from_reader(rdr).map_err(|e| BackendError::SchemaFile(path.clone(), e.to_string()))?)
+			}
+			BiaserConfig::StandardJsonSchemaFile(path) => {
+				let file = File::open(path).map_err(|e| BackendError::SchemaFile(path.clone(), e.to_string()))?;
+				let rdr = BufReader::new(file);
+				let standard_schema: Value = serde_json::from_reader(rdr).map_err(|e| BackendError::SchemaFile(path.clone(), e.to_string()))?;
+				Cow::Owned(JsonSchema::from_standard(&standard_schema).map_err(|e| BackendError::UnsupportedSchema(path.clone(), e))?)
+			}
+			BiaserConfig::Tools(tools) => Cow::Owned(JsonSchema::OneOf {
+				alternatives: tools.iter().map(|tool| tool.call_schema()).collect(),
+			}),
+		})
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct TaskMemorizationConfig {
 	/// The memory to use
 	pub memory: String,
@@ -155,27 +332,85 @@ pub struct TaskMemorizationConfig {
 
 	/// How many items from the memory to retrieve
 	pub retrieve: Option<usize>,
+
+	/// Where the retrieved items are placed in the prompt (defaults to before the prefix)
+	#[serde(default)]
+	pub position: RetrievalPosition,
+
+	/// Template used to format each retrieved item. The `{index}` placeholder is replaced with the (one-based) index of
+	/// the item, `{text}` with the item text itself.
+	#[serde(default = "default_chunk_template")]
+	pub chunk_template: String,
+
+	/// Separator placed between retrieved items
+	#[serde(default = "default_chunk_join")]
+	pub join: String,
+
+	/// Maximum number of tokens the retrieved items (after formatting) may take up in the prompt. Items are included in
+	/// order of relevance until the budget is reached.
+	pub max_context_tokens: Option<usize>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+fn default_chunk_template() -> String {
+	String::from("{text}")
+}
+
+fn default_chunk_join() -> String {
+	String::from("\n")
+}
+
+#[derive(Deserialize, Debug, Clone, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RetrievalPosition {
+	/// Retrieved items are fed before the prefix
+	#[default]
+	BeforePrefix,
+
+	/// Retrieved items are fed after the prefix, right before the user prompt
+	AfterPrefix,
+
+	/// Retrieved items are fed after the postfix
+	AfterPostfix,
+
+	/// Retrieved items replace the `{context}` placeholder in the prefix and/or postfix
+	Placeholder,
+}
+
+#[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct TaskConfig {
 	pub model: String,
 
 	/// Text to start each conversation with
 	pub prelude: Option<String>,
 
+	/// File containing text to start each conversation with (appended to `prelude` when both are specified). The file
+	/// is read once when the backend is instantiated.
+	pub prelude_file: Option<PathBuf>,
+
 	/// Text to prefix each user input with
 	pub prefix: Option<String>,
 
 	/// Text to postfix each user input with
 	pub postfix: Option<String>,
 
+	/// Prompt to use when a request provides an empty prompt (e.g. for tasks that generate from the prelude alone)
+	pub default_prompt: Option<String>,
+
+	/// Tokenize the prefix, user prompt and postfix as a whole instead of separately, so that tokens can span the
+	/// boundaries between them (token healing). Useful when the prefix ends mid-word.
+	#[serde(default)]
+	pub token_healing: bool,
+
 	/// Tokens that users should not be able to input as they are used for signalling
 	pub private_tokens: Option<Vec<String>>,
 
 	/// Maximum number of tokens to be generated (when biaser is enabled: applies only to unbiased phase when bias_prompt is used)
 	pub max_tokens: Option<usize>,
 
+	/// Minimum number of tokens to be generated. Until this number is reached, the end-of-text token cannot be generated
+	/// and stop sequences are ignored (does not apply when a biaser is enabled)
+	pub min_tokens: Option<usize>,
+
 	/// Biaser: the biaser to apply to the output (if any)
 	pub biaser: Option<BiaserConfig>,
 
@@ -183,32 +418,68 @@ pub struct TaskConfig {
 	/// a biased response is generated.
 	pub bias_prompt: Option<String>,
 
-	/// Sequences that when they occur end generation (just like end-of-text token)
+	/// When a biaser is configured that generates an array, this prompt is fed after each item of the array (after the
+	/// comma, before the next item is generated). The prompt is not part of the output.
+	pub reprompt_between_items: Option<String>,
+
+	/// Maximum number of tokens generated while a biaser is active. Once reached, generation ends as soon as the biaser
+	/// allows it to; when the biaser does not allow ending at that point, generation stops and the output is incomplete
+	/// (finish reason `max_tokens`)
+	pub max_biased_tokens: Option<usize>,
+
+	/// When a biaser is configured and set, a low-confidence warning is returned when the probability the model assigns to
+	/// the most likely allowed token is below this threshold for `low_confidence_steps` consecutive tokens. This indicates
+	/// the schema is 'fighting' the model.
+	pub low_confidence_threshold: Option<f32>,
+
+	/// Number of consecutive low-confidence tokens after which a warning is returned (see `low_confidence_threshold`).
+	/// Must be at least one (to disable the warning, leave `low_confidence_threshold` unset).
+	#[serde(default = "default_low_confidence_steps")]
+	pub low_confidence_steps: NonZeroUsize,
+
+	/// Bias added to the logit of the end-of-text token when no biaser is configured. Negative values make the model less
+	/// likely to stop early, positive values encourage shorter responses. Generation still ends at `max_tokens` or when a
+	/// stop sequence is encountered, regardless of this bias.
+	pub eot_bias: Option<f32>,
+
+	/// Sequences that when they occur end generation (just like end-of-text token). These also apply when a biaser is
+	/// configured; a stop sequence that occurs inside a JSON value truncates the output (which is then not a complete
+	/// value).
 	#[serde(default = "default_stop_sequences")]
 	pub stop_sequences: Vec<String>,
 
+	/// End generation as soon as the braces, brackets and parentheses in the output are balanced again after the first
+	/// one was opened (useful to bound code or JSON-like output without a biaser)
+	#[serde(default)]
+	pub stop_on_balanced_braces: bool,
+
 	/// Sampler configuration
 	#[serde(flatten)]
 	pub sampler: SamplerConfig,
 
 	/// Memorization config
 	pub memorization: Option<TaskMemorizationConfig>,
+
+	/// Cache responses to identical requests. Only applies when sampling is deterministic (`top_k = 1`) and no memory is
+	/// used by the task
+	#[serde(default)]
+	pub cache_responses: bool,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub enum SamplerConfig {
 	Advanced(AdvancedSamplerConfig),
 	Standard(StandardSamplerConfig),
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct AdvancedSamplerConfig {
 	// Samplers to apply
 	pub samplers: Vec<String>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct StandardSamplerConfig {
 	/// The top K words by score are kept during sampling.
 	#[serde(default = "default_top_k")]
@@ -228,9 +499,19 @@ pub struct StandardSamplerConfig {
 	#[serde(default = "default_temperature")]
 	pub temperature: f32,
 
-	/// The number of tokens to consider for the repetition penalty.
+	/// The number of tokens to consider for the repetition penalty (as well as the presence and frequency penalties).
 	#[serde(default = "default_repetition_penalty_last_n")]
 	pub repetition_penalty_last_n: usize,
+
+	/// OpenAI-style presence penalty: subtracted once from the logit of any token that occurs in the last
+	/// `repetition_penalty_last_n` tokens. Applied after (and in addition to) `repeat_penalty`.
+	#[serde(default)]
+	pub presence_penalty: f32,
+
+	/// OpenAI-style frequency penalty: subtracted from the logit of a token for each time it occurs in the last
+	/// `repetition_penalty_last_n` tokens. Applied after (and in addition to) `repeat_penalty`.
+	#[serde(default)]
+	pub frequency_penalty: f32,
 }
 
 impl SamplerConfig {
@@ -264,7 +545,8 @@ impl StandardSamplerConfig {
 			top_k,
 			top_p,
 			temperature,
-			..
+			presence_penalty,
+			frequency_penalty,
 		} = self.clone();
 
 		SamplerChainBuilder::from([
@@ -275,6 +557,20 @@ impl StandardSamplerConfig {
 					[],
 				),
 			),
+			(
+				"freqpresence",
+				SamplerSlot::new_chain(
+					move || {
+						Box::new(
+							SampleFreqPresence::default()
+								.frequency(frequency_penalty)
+								.presence(presence_penalty)
+								.last_n(repetition_penalty_last_n),
+						)
+					},
+					[],
+				),
+			),
 			(
 				"topk",
 				SamplerSlot::new_single(move || Box::new(SampleTopK::default().k(top_k)), Option::<SampleTopK>::None),
@@ -300,6 +596,19 @@ impl TaskConfig {
 	pub(crate) fn sampler_chain(&self) -> SamplerChain {
 		self.sampler.sampler_chain()
 	}
+
+	/// Whether the output for a prompt is always the same (i.e. sampling is greedy). Tasks using an advanced sampler
+	/// configuration are never considered deterministic.
+	pub fn is_deterministic(&self) -> bool {
+		match self.sampler {
+			SamplerConfig::Standard(ref sampler_config) => sampler_config.top_k == 1,
+			SamplerConfig::Advanced(_) => false,
+		}
+	}
+}
+
+fn default_low_confidence_steps() -> NonZeroUsize {
+	NonZeroUsize::new(3).unwrap()
 }
 
 const fn default_stop_sequences() -> Vec<String> {
@@ -344,4 +653,43 @@ pub struct BackendConfig {
 
 	/// Directory to store downloaded assets
 	pub cache_path: Option<PathBuf>,
+
+	/// Whether to verify at startup that each model used as embedding model for a memory actually produces embeddings
+	/// of the configured dimensionality
+	pub verify_embedding_models: bool,
+
+	/// Maximum number of inference threads used by all sessions together. When set, the number of threads for a new
+	/// session is reduced (but never below one) so that the threads are shared among the sessions that are active
+	pub max_total_threads: Option<usize>,
+
+	/// Maximum number of responses kept in the response cache (for tasks with `cache_responses` enabled, default 128)
+	pub response_cache_size: Option<usize>,
+
+	/// Number of seconds after which a cached response expires (when not set, responses do not expire)
+	pub response_cache_ttl_secs: Option<u64>,
+
+	/// Number of seconds after which a stored session that has not been used is discarded (default 600)
+	pub session_ttl_secs: Option<u64>,
+
+	/// Maximum number of stored sessions (default 64). When a session is created and the limit is reached, the least
+	/// recently used stored session is discarded.
+	pub max_stored_sessions: Option<usize>,
+
+	/// Maximum number of stored sessions for a single user (identified by the `sub` claim, default 8). When a user
+	/// creates a session and the limit is reached, the least recently used stored session of that user is discarded.
+	pub max_stored_sessions_per_user: Option<usize>,
+
+	/// Maximum number of snapshots of the model state after the prelude of a task kept in memory (default 16). Tasks that
+	/// use the same model and prelude share a snapshot. The least recently used snapshot is discarded when the limit is
+	/// reached.
+	pub max_prelude_snapshots: Option<usize>,
+
+	/// Maximum total size (in bytes) of the prelude snapshots kept in memory (default 2 GiB). A snapshot contains the
+	/// key/value memory of the model, so its size grows with the context size. The least recently used snapshots are
+	/// discarded to make room for a new one; a snapshot larger than the limit is not kept.
+	pub max_prelude_snapshots_size: Option<usize>,
+
+	/// Maximum number of models that are loaded at the same time. When loading a model would exceed this number, the
+	/// least recently used other model is unloaded (it is loaded again when it is needed)
+	pub max_loaded_models: Option<usize>,
 }
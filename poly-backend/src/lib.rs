@@ -1,4 +1,6 @@
 pub mod backend;
+pub mod cache;
+pub mod check;
 pub mod config;
 pub mod memory;
 pub mod sequence;
@@ -1,92 +1,549 @@
-use std::path::PathBuf;
+use std::{
+	collections::{BTreeMap, HashSet},
+	fmt::Display,
+	fs::File,
+	io::{BufReader, BufWriter, Write},
+	path::{Path, PathBuf},
+};
 
-use crate::memory::{Memory, MemoryError};
+use crate::memory::{Memory, MemoryError, MemoryMetric};
 use async_trait::async_trait;
 use hora::core::ann_index::ANNIndex;
 use hora::core::ann_index::SerializableIndex;
+use hora::core::metrics::Metric;
 use hora::index::hnsw_idx::HNSWIndex;
 use hora::index::hnsw_params::HNSWParams;
+use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 
+/// The index is rebuilt when the number of stale nodes exceeds this fraction of the number of items...
+const COMPACT_STALE_FRACTION: f64 = 0.25;
+
+/// ...and there are at least this many stale nodes
+const COMPACT_MIN_STALE: usize = 16;
+
+/// The items in a memory, persisted next to the index. The HNSW index can neither enumerate nor remove items, so this
+/// is the authoritative list of items, from which the index is rebuilt when necessary.
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct HoraItems {
+	/// Embedding of each item, by text
+	items: BTreeMap<String, Vec<f32>>,
+
+	/// Number of nodes in the index that do not (or no longer) correspond to an item, e.g. because the item was deleted.
+	/// These are skipped when searching and removed when the index is rebuilt.
+	stale: usize,
+
+	/// Metric the index is built with. When not recorded, the index was created before the metric became configurable
+	/// and uses [MemoryMetric::Euclidean].
+	#[serde(default)]
+	metric: Option<MemoryMetric>,
+}
+
+struct HoraState {
+	index: HNSWIndex<f32, String>,
+
+	/// Items in the index. This is `None` for an index that was created before items were tracked separately, and whose
+	/// items could not all be recovered from the index.
+	items: Option<HoraItems>,
+}
+
 pub struct HoraMemory {
 	path: Option<PathBuf>,
-	index: Mutex<HNSWIndex<f32, String>>,
+	state: Mutex<HoraState>,
+	metric: MemoryMetric,
+}
+
+impl From<MemoryMetric> for Metric {
+	fn from(metric: MemoryMetric) -> Self {
+		match metric {
+			MemoryMetric::Euclidean => Metric::Euclidean,
+			MemoryMetric::Cosine => Metric::CosineSimilarity,
+			MemoryMetric::DotProduct => Metric::DotProduct,
+		}
+	}
+}
+
+fn storage_error(error: impl Display) -> MemoryError {
+	MemoryError::Storage(error.to_string())
 }
 
 impl HoraMemory {
-	pub fn new(path: Option<PathBuf>, dims: usize) -> Result<HoraMemory, MemoryError> {
-		let index = if let Some(ref path) = path {
-			if path.exists() {
-				HNSWIndex::<f32, String>::load(path.to_str().unwrap()).unwrap()
-			} else {
-				HNSWIndex::<f32, String>::new(dims, &HNSWParams::<f32>::default())
+	/// Create HNSW parameters, using the defaults for parameters that are not specified
+	pub fn params(m: Option<usize>, ef_construction: Option<usize>, ef_search: Option<usize>) -> HNSWParams<f32> {
+		let mut params = HNSWParams::<f32>::default();
+		if let Some(m) = m {
+			params = params.n_neighbor(m).n_neighbor0(m * 2);
+		}
+		if let Some(ef_construction) = ef_construction {
+			params = params.ef_build(ef_construction);
+		}
+		if let Some(ef_search) = ef_search {
+			params = params.ef_search(ef_search);
+		}
+		params
+	}
+
+	/// Open or create a memory. The parameters are only used when a new index is created; an index loaded from file keeps
+	/// the parameters it was created with (these are persisted along with the index). The same goes for the metric: a new
+	/// index uses the configured metric (or the default), an existing index keeps the metric it was built with (indexes
+	/// created before the metric became configurable use [MemoryMetric::Euclidean]). Configuring a metric that differs
+	/// from that of an existing index is an error.
+	pub fn new(path: Option<PathBuf>, dims: usize, params: HNSWParams<f32>, metric: Option<MemoryMetric>) -> Result<HoraMemory, MemoryError> {
+		let (mut index, mut items, loaded_items) = match path {
+			Some(ref path) if path.exists() => {
+				let index = HNSWIndex::<f32, String>::load(Self::path_str(path)?).map_err(storage_error)?;
+				let items_path = Self::items_path(path);
+				if items_path.exists() {
+					let file = File::open(&items_path).map_err(storage_error)?;
+					let items: HoraItems = serde_json::from_reader(BufReader::new(file)).map_err(storage_error)?;
+					(index, Some(items), true)
+				} else {
+					let items = Self::recover_items(&index);
+					(index, items, false)
+				}
+			}
+			Some(_) => (HNSWIndex::<f32, String>::new(dims, &params), Some(HoraItems::default()), false),
+			None => {
+				tracing::warn!("creating a memory store that is non-persistent");
+				(HNSWIndex::<f32, String>::new(dims, &params), Some(HoraItems::default()), false)
 			}
-		} else {
-			tracing::warn!("creating a memory store that is non-persistent");
-			HNSWIndex::<f32, String>::new(dims, &HNSWParams::<f32>::default())
 		};
 
 		if index.dimension() != dims {
 			return Err(MemoryError::DimensionalityMismatch);
 		}
 
+		// An existing index keeps the metric it was built with
+		let metric = if path.as_ref().is_some_and(|path| path.exists()) {
+			let index_metric = items.as_ref().and_then(|items| items.metric).unwrap_or(MemoryMetric::Euclidean);
+			match metric {
+				Some(configured) if configured != index_metric => {
+					return Err(MemoryError::MetricMismatch {
+						configured,
+						index: index_metric,
+					})
+				}
+				_ => index_metric,
+			}
+		} else {
+			metric.unwrap_or_default()
+		};
+
+		if let Some(ref mut items) = items {
+			items.metric = Some(metric);
+			if loaded_items {
+				// The index is written after the item list, so when writing the index was interrupted, it does not match
+				if index.nodes_size() != items.items.len() + items.stale {
+					tracing::warn!(?path, "index does not match the stored items; rebuilding it");
+					Self::rebuild(metric, &mut index, items)?;
+					Self::persist(path.as_deref(), &mut index, Some(items))?;
+				}
+			} else if path.as_ref().is_some_and(|path| path.exists()) {
+				// Items were recovered from an index created by an older version; from now on, keep track of them
+				Self::persist(path.as_deref(), &mut index, Some(items))?;
+			}
+		}
+
 		Ok(HoraMemory {
-			index: Mutex::new(index),
+			state: Mutex::new(HoraState { index, items }),
 			path,
+			metric,
 		})
 	}
-}
 
-impl Drop for HoraMemory {
-	fn drop(&mut self) {
-		if let Some(ref path) = self.path {
-			self.index.blocking_lock().dump(path.to_str().unwrap()).unwrap();
+	fn path_str(path: &Path) -> Result<&str, MemoryError> {
+		path.to_str().ok_or_else(|| MemoryError::Storage(format!("invalid path: {path:?}")))
+	}
+
+	/// Path of the file holding the items of the index at `path`
+	fn items_path(path: &Path) -> PathBuf {
+		let mut items_path = path.as_os_str().to_owned();
+		items_path.push(".items");
+		PathBuf::from(items_path)
+	}
+
+	/// Recover the items from an index that was created before items were tracked separately. The index does not provide
+	/// a way to enumerate items; a search for as many items as there are in the index visits most, but not necessarily
+	/// all nodes. Returns `None` when not all nodes could be found.
+	fn recover_items(index: &HNSWIndex<f32, String>) -> Option<HoraItems> {
+		let n_nodes = index.nodes_size();
+		let mut items = BTreeMap::new();
+		if n_nodes > 0 {
+			let nodes = index.search_nodes(&vec![1.0; index.dimension()], n_nodes);
+			if nodes.len() < n_nodes {
+				tracing::warn!(
+					n_nodes,
+					n_found = nodes.len(),
+					"could not recover all items from index; items cannot be listed or deleted until the memory is cleared"
+				);
+				return None;
+			}
+			for (node, _) in nodes {
+				if let Some(text) = node.idx().clone() {
+					items.insert(text, node.vectors().clone());
+				}
+			}
+		}
+
+		// Nodes with the same text as another node are stale (only one of them is kept)
+		Some(HoraItems {
+			stale: n_nodes - items.len(),
+			items,
+			metric: None,
+		})
+	}
+
+	fn untracked_error() -> MemoryError {
+		MemoryError::Storage(String::from(
+			"the items of this index (created by an older version) cannot be enumerated; clear the memory and ingest the documents again to enable listing and deleting items",
+		))
+	}
+
+	/// Rebuild the index from the items, removing stale nodes
+	fn rebuild(metric: MemoryMetric, index: &mut HNSWIndex<f32, String>, items: &mut HoraItems) -> Result<(), MemoryError> {
+		index.clear();
+		for (text, embedding) in items.items.iter() {
+			index.add(embedding, text.clone()).map_err(storage_error)?;
+		}
+		if !items.items.is_empty() {
+			index.build(metric.into()).map_err(storage_error)?;
 		}
+		items.stale = 0;
+		Ok(())
+	}
+
+	/// Write the items and the index to disk (when the memory is persistent)
+	fn persist(path: Option<&Path>, index: &mut HNSWIndex<f32, String>, items: Option<&HoraItems>) -> Result<(), MemoryError> {
+		let Some(path) = path else {
+			return Ok(());
+		};
+
+		if let Some(items) = items {
+			// Write to a temporary file first, so that an interrupted write does not leave a corrupt item list
+			let items_path = Self::items_path(path);
+			let mut temp_path = items_path.as_os_str().to_owned();
+			temp_path.push(".tmp");
+			let mut writer = BufWriter::new(File::create(&temp_path).map_err(storage_error)?);
+			serde_json::to_writer(&mut writer, items).map_err(storage_error)?;
+			writer.flush().map_err(storage_error)?;
+			drop(writer);
+			std::fs::rename(&temp_path, &items_path).map_err(storage_error)?;
+		}
+		index.dump(Self::path_str(path)?).map_err(storage_error)
 	}
 }
 
 #[async_trait]
 impl Memory for HoraMemory {
 	async fn store(&self, text: &str, embedding: &[f32]) -> Result<(), MemoryError> {
-		let mut index = self.index.lock().await;
-		assert_eq!(embedding.len(), index.dimension());
-		// TODO: error handling
-		index.add(embedding, text.to_string()).unwrap();
-		index.build(hora::core::metrics::Metric::Euclidean).unwrap();
-		if let Some(ref path) = self.path {
-			index.dump(path.to_str().unwrap()).unwrap();
+		self.store_batch(&[(text.to_string(), embedding.to_vec())]).await
+	}
+
+	async fn store_batch(&self, items: &[(String, Vec<f32>)]) -> Result<(), MemoryError> {
+		let mut state = self.state.lock().await;
+		let HoraState { index, items: tracked } = &mut *state;
+		if items.iter().any(|(_, embedding)| embedding.len() != index.dimension()) {
+			return Err(MemoryError::DimensionalityMismatch);
+		}
+
+		let mut added = false;
+		for (text, embedding) in items {
+			if let Some(tracked) = tracked {
+				match tracked.items.insert(text.clone(), embedding.clone()) {
+					// Storing an item that is already in the index is a no-op
+					Some(previous) if previous == *embedding => continue,
+					// The node holding the previous embedding for this text is now stale
+					Some(_) => tracked.stale += 1,
+					None => {}
+				}
+			}
+			index.add(embedding, text.clone()).map_err(storage_error)?;
+			added = true;
+		}
+
+		if added {
+			// Building the index is expensive, so only do it once for the whole batch
+			index.build(self.metric.into()).map_err(storage_error)?;
+			Self::persist(self.path.as_deref(), index, tracked.as_ref())?;
 		}
 		Ok(())
 	}
 
-	async fn get(&self, embedding: &[f32], top_n: usize) -> Result<Vec<String>, MemoryError> {
-		let index = self.index.lock().await;
-		assert_eq!(embedding.len(), index.dimension());
-		Ok(index.search(embedding, top_n))
+	async fn get_scored_with_embeddings(&self, embedding: &[f32], top_n: usize) -> Result<Vec<(String, f32, Vec<f32>)>, MemoryError> {
+		let state = self.state.lock().await;
+		if embedding.len() != state.index.dimension() {
+			return Err(MemoryError::DimensionalityMismatch);
+		}
+
+		// Fetch additional nodes to make up for stale nodes that are skipped. The distances reported by the index are
+		// metric-specific, so calculate the score from the stored vectors.
+		if state.index.nodes_size() == 0 {
+			return Ok(vec![]);
+		}
+		let stale = state.items.as_ref().map(|items| items.stale).unwrap_or(0);
+		let mut seen = HashSet::new();
+		let mut scored: Vec<(String, f32, Vec<f32>)> = state
+			.index
+			.search_nodes(embedding, top_n + stale)
+			.into_iter()
+			.filter_map(|(node, _)| {
+				let text = node.idx().clone()?;
+				if let Some(ref items) = state.items {
+					if items.items.get(&text) != Some(node.vectors()) {
+						return None;
+					}
+				}
+				if !seen.insert(text.clone()) {
+					return None;
+				}
+				let score = self.metric.score(embedding, node.vectors());
+				Some((text, score, node.vectors().clone()))
+			})
+			.collect();
+		scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+		scored.truncate(top_n);
+		Ok(scored)
+	}
+
+	async fn list(&self, offset: usize, limit: usize) -> Result<Vec<String>, MemoryError> {
+		let state = self.state.lock().await;
+		let items = state.items.as_ref().ok_or_else(Self::untracked_error)?;
+		Ok(items.items.keys().skip(offset).take(limit).cloned().collect())
+	}
+
+	async fn delete(&self, text: &str) -> Result<bool, MemoryError> {
+		let mut state = self.state.lock().await;
+		let HoraState { index, items } = &mut *state;
+		let items = items.as_mut().ok_or_else(Self::untracked_error)?;
+		if items.items.remove(text).is_none() {
+			return Ok(false);
+		}
+
+		// The index does not support removing items. The node for the item is skipped when searching, and removed when
+		// enough nodes have become stale to warrant rebuilding the index.
+		items.stale += 1;
+		if items.stale >= COMPACT_MIN_STALE && items.stale as f64 > items.items.len() as f64 * COMPACT_STALE_FRACTION {
+			tracing::debug!(stale = items.stale, n_items = items.items.len(), "rebuilding index to remove stale nodes");
+			Self::rebuild(self.metric, index, items)?;
+		}
+		Self::persist(self.path.as_deref(), index, Some(items))?;
+		Ok(true)
 	}
 
 	async fn clear(&self) -> Result<(), MemoryError> {
-		let mut index = self.index.lock().await;
+		let mut state = self.state.lock().await;
+		let HoraState { index, items } = &mut *state;
 		index.clear();
-		if let Some(ref path) = self.path {
-			index.dump(path.to_str().unwrap()).unwrap();
-		}
-		Ok(())
+		*items = Some(HoraItems {
+			metric: Some(self.metric),
+			..Default::default()
+		});
+		Self::persist(self.path.as_deref(), index, items.as_ref())
+	}
+
+	async fn count(&self) -> Result<usize, MemoryError> {
+		let state = self.state.lock().await;
+		Ok(match state.items {
+			Some(ref items) => items.items.len(),
+			None => state.index.nodes_size(),
+		})
 	}
 }
 
 #[cfg(test)]
 mod test {
-	use super::HoraMemory;
-	use crate::memory::Memory;
+	use hora::core::ann_index::{ANNIndex, SerializableIndex};
+	use hora::index::hnsw_idx::HNSWIndex;
+
+	use super::{HoraMemory, COMPACT_MIN_STALE};
+	use crate::memory::{Memory, MemoryError, MemoryMetric};
+
+	fn temp_dir() -> std::path::PathBuf {
+		let dir = std::env::temp_dir().join(format!("poly-test-{}", uuid::Uuid::new_v4()));
+		std::fs::create_dir_all(&dir).unwrap();
+		dir
+	}
 
 	#[tokio::test]
 	pub async fn test_store() {
-		let hm = HoraMemory::new(None, 3).unwrap();
+		let hm = HoraMemory::new(None, 3, HoraMemory::params(None, None, None), Some(MemoryMetric::Euclidean)).unwrap();
 		hm.store("foo", &[1.0, 2.0, 3.0]).await.unwrap();
 		hm.store("bar", &[-1.0, 2.0, 3.0]).await.unwrap();
 		hm.store("baz", &[1.0, -2.0, 3.0]).await.unwrap();
 		hm.store("boo", &[1.0, -2.0, -3.0]).await.unwrap();
 		assert_eq!(hm.get(&[0.0, -1.0, 0.0], 2).await.unwrap(), vec!["baz", "boo"]);
+		assert_eq!(hm.count().await.unwrap(), 4);
+	}
+
+	#[tokio::test]
+	pub async fn test_store_batch() {
+		let hm = HoraMemory::new(None, 3, HoraMemory::params(None, None, None), Some(MemoryMetric::Euclidean)).unwrap();
+		hm.store_batch(&[
+			("foo".to_string(), vec![1.0, 2.0, 3.0]),
+			("bar".to_string(), vec![-1.0, 2.0, 3.0]),
+			("baz".to_string(), vec![1.0, -2.0, 3.0]),
+			("boo".to_string(), vec![1.0, -2.0, -3.0]),
+		])
+		.await
+		.unwrap();
+		assert_eq!(hm.get(&[0.0, -1.0, 0.0], 2).await.unwrap(), vec!["baz", "boo"]);
+		assert_eq!(hm.count().await.unwrap(), 4);
+	}
+
+	#[tokio::test]
+	pub async fn test_list() {
+		let hm = HoraMemory::new(None, 2, HoraMemory::params(None, None, None), Some(MemoryMetric::Euclidean)).unwrap();
+		hm.store_batch(&[
+			("c".to_string(), vec![1.0, 0.0]),
+			("a".to_string(), vec![0.0, 1.0]),
+			("e".to_string(), vec![-1.0, 0.0]),
+			("b".to_string(), vec![0.0, -1.0]),
+			("d".to_string(), vec![1.0, 1.0]),
+		])
+		.await
+		.unwrap();
+
+		// Items are listed in alphabetical order
+		assert_eq!(hm.list(0, 2).await.unwrap(), vec!["a", "b"]);
+		assert_eq!(hm.list(2, 2).await.unwrap(), vec!["c", "d"]);
+		assert_eq!(hm.list(4, 2).await.unwrap(), vec!["e"]);
+		assert!(hm.list(5, 2).await.unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	pub async fn test_delete() {
+		let hm = HoraMemory::new(None, 2, HoraMemory::params(None, None, None), Some(MemoryMetric::Euclidean)).unwrap();
+		hm.store("foo", &[1.0, 0.0]).await.unwrap();
+		hm.store("bar", &[0.0, 1.0]).await.unwrap();
+
+		assert!(hm.delete("foo").await.unwrap());
+		assert!(!hm.delete("foo").await.unwrap());
+		assert_eq!(hm.count().await.unwrap(), 1);
+		assert_eq!(hm.get(&[1.0, 0.0], 2).await.unwrap(), vec!["bar"]);
+	}
+
+	#[tokio::test]
+	pub async fn test_metric() {
+		// The long vector points in the same direction as the query, the short one is closer to it in space
+		let items = [("long".to_string(), vec![10.0, 0.0]), ("short".to_string(), vec![0.5, 0.5])];
+		let query = [1.0, 0.0];
+
+		let hm = HoraMemory::new(None, 2, HoraMemory::params(None, None, None), Some(MemoryMetric::Euclidean)).unwrap();
+		hm.store_batch(&items).await.unwrap();
+		assert_eq!(hm.get(&query, 1).await.unwrap(), vec!["short"]);
+
+		let hm = HoraMemory::new(None, 2, HoraMemory::params(None, None, None), Some(MemoryMetric::Cosine)).unwrap();
+		hm.store_batch(&items).await.unwrap();
+		assert_eq!(hm.get(&query, 1).await.unwrap(), vec!["long"]);
+
+		let hm = HoraMemory::new(None, 2, HoraMemory::params(None, None, None), Some(MemoryMetric::DotProduct)).unwrap();
+		hm.store_batch(&items).await.unwrap();
+		assert_eq!(hm.get(&query, 1).await.unwrap(), vec!["long"]);
+	}
+
+	#[tokio::test]
+	pub async fn test_get_scored() {
+		let hm = HoraMemory::new(None, 2, HoraMemory::params(None, None, None), Some(MemoryMetric::Cosine)).unwrap();
+		hm.store_batch(&[
+			("same".to_string(), vec![2.0, 0.0]),
+			("orthogonal".to_string(), vec![0.0, 1.0]),
+			("opposite".to_string(), vec![-1.0, 0.0]),
+		])
+		.await
+		.unwrap();
+
+		let scored = hm.get_scored(&[1.0, 0.0], 3).await.unwrap();
+		let texts: Vec<&str> = scored.iter().map(|(text, _)| text.as_str()).collect();
+		assert_eq!(texts, vec!["same", "orthogonal", "opposite"]);
+		assert!((scored[0].1 - 1.0).abs() < 1e-6);
+		assert!(scored[1].1.abs() < 1e-6);
+		assert!((scored[2].1 + 1.0).abs() < 1e-6);
+	}
+
+	#[tokio::test]
+	pub async fn test_persistence() {
+		let dir = temp_dir();
+		let path = dir.join("test.index");
+		let open = || HoraMemory::new(Some(path.clone()), 2, HoraMemory::params(None, None, None), Some(MemoryMetric::Euclidean)).unwrap();
+
+		let hm = open();
+		hm.store_batch(&[("a".to_string(), vec![1.0, 0.0]), ("b".to_string(), vec![0.0, 1.0])])
+			.await
+			.unwrap();
+		assert!(hm.delete("a").await.unwrap());
+		drop(hm);
+		assert!(HoraMemory::items_path(&path).exists());
+
+		// Deleted items stay deleted after reopening, even though the index still holds a (stale) node for them
+		let hm = open();
+		assert_eq!(hm.count().await.unwrap(), 1);
+		assert_eq!(hm.list(0, 10).await.unwrap(), vec!["b"]);
+		assert_eq!(hm.get(&[1.0, 0.0], 2).await.unwrap(), vec!["b"]);
+		drop(hm);
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[tokio::test]
+	pub async fn test_persisted_metric() {
+		let dir = temp_dir();
+		let path = dir.join("test.index");
+		let open = |metric| HoraMemory::new(Some(path.clone()), 2, HoraMemory::params(None, None, None), metric);
+
+		// A new index uses the default metric, which is recorded along with the index
+		let hm = open(None).unwrap();
+		assert_eq!(hm.metric, MemoryMetric::Cosine);
+		hm.store("a", &[1.0, 0.0]).await.unwrap();
+		drop(hm);
+
+		assert_eq!(open(None).unwrap().metric, MemoryMetric::Cosine);
+		assert_eq!(open(Some(MemoryMetric::Cosine)).unwrap().metric, MemoryMetric::Cosine);
+		assert!(matches!(open(Some(MemoryMetric::DotProduct)), Err(MemoryError::MetricMismatch { .. })));
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[tokio::test]
+	pub async fn test_compaction() {
+		let hm = HoraMemory::new(None, 2, HoraMemory::params(None, None, None), Some(MemoryMetric::Euclidean)).unwrap();
+		let n_items = COMPACT_MIN_STALE * 2;
+		let items: Vec<(String, Vec<f32>)> = (0..n_items).map(|i| (format!("item {i}"), vec![i as f32, 1.0])).collect();
+		hm.store_batch(&items).await.unwrap();
+
+		for (text, _) in items.iter().take(COMPACT_MIN_STALE) {
+			assert!(hm.delete(text).await.unwrap());
+		}
+
+		// Enough nodes became stale to rebuild the index without them
+		let state = hm.state.lock().await;
+		assert_eq!(state.items.as_ref().unwrap().stale, 0);
+		assert_eq!(state.index.nodes_size(), n_items - COMPACT_MIN_STALE);
+		drop(state);
+		assert_eq!(hm.count().await.unwrap(), n_items - COMPACT_MIN_STALE);
+		assert_eq!(hm.get(&[0.0, 1.0], 1).await.unwrap(), vec![format!("item {COMPACT_MIN_STALE}")]);
+	}
+
+	#[tokio::test]
+	pub async fn test_recover_items() {
+		// An index written by an older version, without an item list
+		let dir = temp_dir();
+		let path = dir.join("legacy.index");
+		let mut index = HNSWIndex::<f32, String>::new(2, &HoraMemory::params(None, None, None));
+		index.add(&[1.0, 0.0], "a".to_string()).unwrap();
+		index.add(&[0.0, 1.0], "b".to_string()).unwrap();
+		index.build(MemoryMetric::Euclidean.into()).unwrap();
+		index.dump(path.to_str().unwrap()).unwrap();
+
+		// The index was built with the Euclidean metric, which cannot be changed
+		assert!(matches!(
+			HoraMemory::new(Some(path.clone()), 2, HoraMemory::params(None, None, None), Some(MemoryMetric::Cosine)),
+			Err(MemoryError::MetricMismatch { .. })
+		));
+		let hm = HoraMemory::new(Some(path.clone()), 2, HoraMemory::params(None, None, None), None).unwrap();
+		assert_eq!(hm.metric, MemoryMetric::Euclidean);
+		assert!(HoraMemory::items_path(&path).exists());
+		assert_eq!(hm.list(0, 10).await.unwrap(), vec!["a", "b"]);
+		assert!(hm.delete("a").await.unwrap());
+		assert_eq!(hm.get(&[1.0, 0.0], 2).await.unwrap(), vec!["b"]);
+		drop(hm);
+		std::fs::remove_dir_all(&dir).unwrap();
 	}
 }
@@ -1,9 +1,10 @@
 mod hora;
+mod vector;
 
 #[cfg(feature = "qdrant")]
 mod qdrant;
 
-use std::path::PathBuf;
+use std::{collections::HashSet, path::PathBuf};
 
 use async_trait::async_trait;
 use llm::TokenId;
@@ -19,6 +20,11 @@ pub enum MemoryError {
 
 	#[error("storage error: {0}")]
 	Storage(String),
+
+	#[error(
+		"the index was built with the {index:?} metric, but the {configured:?} metric is configured (clear the index files to change the metric)"
+	)]
+	MetricMismatch { configured: MemoryMetric, index: MemoryMetric },
 }
 
 #[async_trait]
@@ -26,21 +32,94 @@ pub trait Memory: Send + Sync {
 	/// Store the provided chunk in the memory
 	async fn store(&self, text: &str, embedding: &[f32]) -> Result<(), MemoryError>;
 
+	/// Store the provided chunks (text and embedding) in the memory. Stores may override this to store all chunks at once,
+	/// which is more efficient than storing them one by one.
+	async fn store_batch(&self, items: &[(String, Vec<f32>)]) -> Result<(), MemoryError> {
+		for (text, embedding) in items {
+			self.store(text, embedding).await?;
+		}
+		Ok(())
+	}
+
 	/// Retrieve relevant chunks from memory given an embedding. At most `top_n` chunks will be returned
-	async fn get(&self, embedding: &[f32], top_n: usize) -> Result<Vec<String>, MemoryError>;
+	async fn get(&self, embedding: &[f32], top_n: usize) -> Result<Vec<String>, MemoryError> {
+		Ok(self.get_scored(embedding, top_n).await?.into_iter().map(|(text, _)| text).collect())
+	}
+
+	/// Retrieve relevant chunks from memory given an embedding, along with their similarity score (a higher score means
+	/// more relevant; the scale depends on the metric used). At most `top_n` chunks will be returned, most relevant first.
+	async fn get_scored(&self, embedding: &[f32], top_n: usize) -> Result<Vec<(String, f32)>, MemoryError> {
+		Ok(self
+			.get_scored_with_embeddings(embedding, top_n)
+			.await?
+			.into_iter()
+			.map(|(text, score, _)| (text, score))
+			.collect())
+	}
+
+	/// Like [Memory::get_scored], but also returns the stored embedding of each chunk
+	async fn get_scored_with_embeddings(&self, embedding: &[f32], top_n: usize) -> Result<Vec<(String, f32, Vec<f32>)>, MemoryError>;
+
+	/// Return the stored chunks, skipping the first `offset` and returning at most `limit` chunks. The order is stable as
+	/// long as the memory is not modified.
+	async fn list(&self, offset: usize, limit: usize) -> Result<Vec<String>, MemoryError>;
+
+	/// Remove the chunk with the provided text (or, for stores that assign IDs to chunks, ID) from the memory. Returns whether a
+	/// chunk was removed.
+	async fn delete(&self, text: &str) -> Result<bool, MemoryError>;
 
 	/// Clear the memory
 	async fn clear(&self) -> Result<(), MemoryError>;
+
+	/// Return the number of items stored in the memory
+	async fn count(&self) -> Result<usize, MemoryError>;
+}
+
+/// Measure of distance between embeddings used to find the items nearest to a query
+#[derive(Deserialize, Debug, Clone, Copy, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryMetric {
+	Euclidean,
+
+	#[default]
+	Cosine,
+
+	DotProduct,
+}
+
+impl MemoryMetric {
+	/// Similarity between two embeddings according to this metric (higher means more similar). For the Euclidean metric
+	/// this is the negated distance.
+	pub fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+		match self {
+			MemoryMetric::Euclidean => -a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt(),
+			MemoryMetric::Cosine => cosine_similarity(a, b),
+			MemoryMetric::DotProduct => a.iter().zip(b.iter()).map(|(x, y)| x * y).sum(),
+		}
+	}
 }
 
 #[derive(Deserialize, Debug, Clone, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum MemoryStoreConfig {
 	Hora {
-		/// Path to the memory file (no path means not persisted)
+		/// Path to the memory file (no path means not persisted). The items in the memory are stored alongside it, in a file
+		/// with the same name and an `.items` suffix.
 		path: Option<PathBuf>,
+
+		/// Number of neighbours of each node in the HNSW graph (M)
+		m: Option<usize>,
+
+		/// Size of the candidate list used while building the index
+		ef_construction: Option<usize>,
+
+		/// Size of the candidate list used while searching the index
+		ef_search: Option<usize>,
 	},
 
+	/// Non-persistent memory that scans all items when recalling (for tests and small sets of items)
+	Memory {},
+
 	#[cfg(feature = "qdrant")]
 	Qdrant {
 		/// URL to the Qdrant server
@@ -60,7 +139,22 @@ fn default_qdrant_url() -> String {
 impl MemoryStoreConfig {
 	pub fn from(&self, memory_config: &MemoryConfig) -> Result<Box<dyn Memory>, MemoryError> {
 		match self {
-			Self::Hora { path } => Ok(Box::new(hora::HoraMemory::new(path.clone(), memory_config.dimensions)?)),
+			Self::Hora {
+				path,
+				m,
+				ef_construction,
+				ef_search,
+			} => Ok(Box::new(hora::HoraMemory::new(
+				path.clone(),
+				memory_config.dimensions,
+				hora::HoraMemory::params(*m, *ef_construction, *ef_search),
+				memory_config.metric,
+			)?)),
+
+			Self::Memory {} => Ok(Box::new(vector::VectorMemory::new(
+				memory_config.dimensions,
+				memory_config.metric.unwrap_or_default(),
+			))),
 
 			#[cfg(feature = "qdrant")]
 			Self::Qdrant { url, collection } => Ok(Box::new(qdrant::QdrantMemory::new(url, collection, memory_config.dimensions)?)),
@@ -68,11 +162,30 @@ impl MemoryStoreConfig {
 	}
 }
 
-type TokenWithCharacters = (Vec<u8>, TokenId);
+/// Normalize text for comparison (lowercase, whitespace collapsed), so that items that only differ in formatting are
+/// considered identical
+pub fn normalize_text(text: &str) -> String {
+	text.split_whitespace().map(|word| word.to_lowercase()).collect::<Vec<_>>().join(" ")
+}
+
+/// Cosine similarity between two vectors (zero when either vector has zero length)
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+	assert_eq!(a.len(), b.len());
+	let dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+	let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+	let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+	if norm_a == 0.0 || norm_b == 0.0 {
+		0.0
+	} else {
+		dot / (norm_a * norm_b)
+	}
+}
+
+pub type TokenWithCharacters = (Vec<u8>, TokenId);
 
 /// Apply successive separators to a chunk of text until it fits in a specific number of tokens. When there is no
-/// separator anymore, just chunk.
-pub fn hierarchically_chunk(tokens: Vec<TokenWithCharacters>, separators: &[TokenId], max_chunk_tokens: usize) -> Vec<Vec<TokenWithCharacters>> {
+/// separator anymore, just chunk. Text is split after each occurrence of a separator (see [split_after_sequence]).
+pub fn hierarchically_chunk(tokens: Vec<TokenWithCharacters>, separators: &[String], max_chunk_tokens: usize) -> Vec<Vec<TokenWithCharacters>> {
 	tracing::trace!(n_tokens = tokens.len(), ?separators, max_chunk_tokens, "hierarchically chunk");
 	// If the full chunk is small enough, no need to split anything
 	if tokens.len() <= max_chunk_tokens {
@@ -83,11 +196,13 @@ pub fn hierarchically_chunk(tokens: Vec<TokenWithCharacters>, separators: &[Toke
 			let next_separators = &separators[1..];
 			let mut chunks = vec![];
 			let mut current_chunk: Vec<TokenWithCharacters> = vec![];
-			for split in tokens.split_inclusive(|x| x.1 == *separator) {
+			for split in split_after_sequence(&tokens, separator.as_bytes()) {
 				if split.len() > max_chunk_tokens {
 					// Can never make a chunk from this that is small enough
 					assert!(current_chunk.len() <= max_chunk_tokens);
-					chunks.push(std::mem::take(&mut current_chunk));
+					if !current_chunk.is_empty() {
+						chunks.push(std::mem::take(&mut current_chunk));
+					}
 					chunks.append(&mut hierarchically_chunk(split.to_vec(), next_separators, max_chunk_tokens));
 				} else if split.len() + current_chunk.len() < max_chunk_tokens {
 					// Can append this split to the current chunk
@@ -95,7 +210,9 @@ pub fn hierarchically_chunk(tokens: Vec<TokenWithCharacters>, separators: &[Toke
 					assert!(current_chunk.len() <= max_chunk_tokens);
 				} else {
 					// Make a new chunk
-					chunks.push(std::mem::take(&mut current_chunk));
+					if !current_chunk.is_empty() {
+						chunks.push(std::mem::take(&mut current_chunk));
+					}
 					assert_eq!(current_chunk.len(), 0);
 					current_chunk.extend_from_slice(split);
 					assert!(current_chunk.len() <= max_chunk_tokens);
@@ -111,3 +228,238 @@ pub fn hierarchically_chunk(tokens: Vec<TokenWithCharacters>, separators: &[Toke
 		}
 	}
 }
+
+/// Split tokens after each occurrence of the separator. A token may cover multiple characters, and the tokens of a
+/// separator depend on the surrounding text (e.g. a space is usually merged into the token of the word that follows it),
+/// so the separator is searched for in the text of the tokens. The split is made at the token boundary closest to the
+/// end of each occurrence (the later one when both are equally close). An empty separator does not split.
+fn split_after_sequence<'a>(tokens: &'a [TokenWithCharacters], separator: &[u8]) -> Vec<&'a [TokenWithCharacters]> {
+	let mut splits = vec![];
+	let mut start = 0;
+	if !separator.is_empty() {
+		let text: Vec<u8> = tokens.iter().flat_map(|(bytes, _)| bytes.iter().copied()).collect();
+
+		// Offset in the text at which each token ends
+		let token_ends: Vec<usize> = tokens
+			.iter()
+			.scan(0, |offset, (bytes, _)| {
+				*offset += bytes.len();
+				Some(*offset)
+			})
+			.collect();
+
+		let mut search_from = 0;
+		while let Some(position) = text[search_from..].windows(separator.len()).position(|window| window == separator) {
+			let occurrence_end = search_from + position + separator.len();
+			search_from = occurrence_end;
+
+			// The token in which the occurrence ends
+			let index = token_ends.partition_point(|end| *end < occurrence_end);
+			let token_start = if index == 0 { 0 } else { token_ends[index - 1] };
+			let split_at = if token_ends[index] - occurrence_end <= occurrence_end - token_start {
+				index + 1
+			} else {
+				index
+			};
+
+			if split_at > start {
+				splits.push(&tokens[start..split_at]);
+				start = split_at;
+			}
+		}
+	}
+	if start < tokens.len() {
+		splits.push(&tokens[start..]);
+	}
+	splits
+}
+
+/// Remove the tokens in `filter_tokens` from each chunk. Chunks that are empty after filtering are dropped; the number of
+/// chunks dropped is returned alongside the remaining chunks.
+pub fn filter_chunks(chunks: Vec<Vec<TokenWithCharacters>>, filter_tokens: &HashSet<TokenId>) -> (Vec<Vec<TokenWithCharacters>>, usize) {
+	let n_chunks = chunks.len();
+	let filtered: Vec<Vec<TokenWithCharacters>> = chunks
+		.into_iter()
+		.map(|mut chunk| {
+			chunk.retain(|t| !filter_tokens.contains(&t.1));
+			chunk
+		})
+		.filter(|chunk| !chunk.is_empty())
+		.collect();
+	let n_skipped = n_chunks - filtered.len();
+	(filtered, n_skipped)
+}
+
+/// Make consecutive chunks overlap by prepending (at most) `overlap_tokens` trailing tokens of the previous chunk to each
+/// chunk. The overlap is reduced where necessary to ensure chunks do not exceed `max_chunk_tokens`.
+pub fn overlap_chunks(chunks: Vec<Vec<TokenWithCharacters>>, overlap_tokens: usize, max_chunk_tokens: usize) -> Vec<Vec<TokenWithCharacters>> {
+	if overlap_tokens == 0 {
+		return chunks;
+	}
+
+	let mut overlapped = Vec::with_capacity(chunks.len());
+	for (index, chunk) in chunks.iter().enumerate() {
+		if index == 0 {
+			overlapped.push(chunk.clone());
+			continue;
+		}
+
+		let previous = &chunks[index - 1];
+		let n_overlap = overlap_tokens.min(previous.len()).min(max_chunk_tokens.saturating_sub(chunk.len()));
+		let mut overlapped_chunk = Vec::with_capacity(n_overlap + chunk.len());
+		overlapped_chunk.extend_from_slice(&previous[(previous.len() - n_overlap)..]);
+		overlapped_chunk.extend_from_slice(chunk);
+		overlapped.push(overlapped_chunk);
+	}
+	overlapped
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::HashSet;
+
+	use super::{
+		cosine_similarity, filter_chunks, hierarchically_chunk, normalize_text, overlap_chunks, split_after_sequence, MemoryMetric,
+		TokenWithCharacters,
+	};
+
+	#[test]
+	fn test_normalize_text() {
+		assert_eq!(normalize_text("  Hello,\n  World "), "hello, world");
+		assert_eq!(normalize_text("hello, world"), normalize_text("Hello,  WORLD"));
+	}
+
+	#[test]
+	fn test_cosine_similarity() {
+		assert!((cosine_similarity(&[1.0, 2.0], &[2.0, 4.0]) - 1.0).abs() < 1e-6);
+		assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+		assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+		assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+	}
+
+	#[test]
+	fn test_metric_score() {
+		assert!((MemoryMetric::Euclidean.score(&[1.0, 0.0], &[4.0, 4.0]) + 5.0).abs() < 1e-6);
+		assert!((MemoryMetric::Cosine.score(&[1.0, 0.0], &[4.0, 4.0]) - 0.5f32.sqrt()).abs() < 1e-6);
+		assert!((MemoryMetric::DotProduct.score(&[1.0, 0.0], &[4.0, 4.0]) - 4.0).abs() < 1e-6);
+
+		// Higher scores mean more similar for each metric
+		for metric in [MemoryMetric::Euclidean, MemoryMetric::Cosine, MemoryMetric::DotProduct] {
+			assert!(metric.score(&[1.0, 0.0], &[1.0, 0.1]) > metric.score(&[1.0, 0.0], &[-1.0, 0.0]));
+		}
+	}
+
+	#[test]
+	fn test_overlap_chunks() {
+		let chunks = vec![
+			vec![(vec![b'a'], 1), (vec![b'b'], 2), (vec![b'c'], 3)],
+			vec![(vec![b'd'], 4), (vec![b'e'], 5)],
+			vec![(vec![b'f'], 6), (vec![b'g'], 7), (vec![b'h'], 8)],
+		];
+
+		let ids = |chunks: Vec<Vec<(Vec<u8>, u32)>>| -> Vec<Vec<u32>> { chunks.iter().map(|c| c.iter().map(|t| t.1).collect()).collect() };
+
+		assert_eq!(ids(overlap_chunks(chunks.clone(), 0, 4)), vec![vec![1, 2, 3], vec![4, 5], vec![6, 7, 8]]);
+		assert_eq!(
+			ids(overlap_chunks(chunks.clone(), 2, 4)),
+			vec![vec![1, 2, 3], vec![2, 3, 4, 5], vec![5, 6, 7, 8]]
+		);
+		assert_eq!(
+			ids(overlap_chunks(chunks, 2, 10)),
+			vec![vec![1, 2, 3], vec![2, 3, 4, 5], vec![4, 5, 6, 7, 8]]
+		);
+	}
+
+	#[test]
+	fn test_hierarchically_chunk() {
+		// A tiny vocabulary: '.' = 1, ' ' = 2, '\n' = 3, any other character = 10
+		let tokens = |text: &str| -> Vec<TokenWithCharacters> {
+			text.chars()
+				.map(|c| {
+					let id = match c {
+						'.' => 1,
+						' ' => 2,
+						'\n' => 3,
+						_ => 10,
+					};
+					(c.to_string().into_bytes(), id)
+				})
+				.collect()
+		};
+		let texts = |chunks: Vec<Vec<TokenWithCharacters>>| -> Vec<String> {
+			chunks
+				.iter()
+				.map(|c| String::from_utf8(c.iter().flat_map(|t| t.0.clone()).collect()).unwrap())
+				.collect()
+		};
+
+		let separators = |separators: &[&str]| -> Vec<String> { separators.iter().map(|s| s.to_string()).collect() };
+
+		// Split on ". " (two tokens); a period that is not followed by a space is not a boundary
+		let chunks = hierarchically_chunk(tokens("aa. b.b. cc"), &separators(&[". "]), 6);
+		assert_eq!(texts(chunks), vec!["aa. ", "b.b. ", "cc"]);
+
+		// Split on paragraphs ("\n\n") first, then on sentences
+		let text = "aa. bb.\n\ncc. dd";
+		let chunks = hierarchically_chunk(tokens(text), &separators(&["\n\n", ". "]), 6);
+		assert_eq!(texts(chunks), vec!["aa. ", "bb.\n\n", "cc. dd"]);
+
+		// Without separators, text is split by size
+		let chunks = hierarchically_chunk(tokens("abcdefg"), &[], 3);
+		assert_eq!(texts(chunks), vec!["abc", "def", "g"]);
+	}
+
+	#[test]
+	fn test_split_merged_tokens() {
+		// A vocabulary in which spaces are merged into the word that follows, and some punctuation is merged as well
+		let tokens: Vec<TokenWithCharacters> = ["aa", ".", " bb", ".\n\n", "cc", ". ", "dd"]
+			.iter()
+			.enumerate()
+			.map(|(id, text)| (text.as_bytes().to_vec(), id as u32))
+			.collect();
+		let texts = |splits: Vec<&[TokenWithCharacters]>| -> Vec<String> {
+			splits
+				.iter()
+				.map(|c| String::from_utf8(c.iter().flat_map(|t| t.0.clone()).collect()).unwrap())
+				.collect()
+		};
+
+		// The space after the first period is part of the next token, so the split is made before that token
+		assert_eq!(texts(split_after_sequence(&tokens, b". ")), vec!["aa.", " bb.\n\ncc. ", "dd"]);
+
+		// The separator is the end of a single merged token
+		assert_eq!(texts(split_after_sequence(&tokens, b"\n\n")), vec!["aa. bb.\n\n", "cc. dd"]);
+
+		// A separator that does not occur, or is empty, does not split
+		assert_eq!(texts(split_after_sequence(&tokens, b"?")), vec!["aa. bb.\n\ncc. dd"]);
+		assert_eq!(texts(split_after_sequence(&tokens, b"")), vec!["aa. bb.\n\ncc. dd"]);
+
+		let chunks = hierarchically_chunk(tokens.clone(), &[String::from(". ")], 4);
+		let chunk_ids: Vec<Vec<u32>> = chunks.iter().map(|c| c.iter().map(|t| t.1).collect()).collect();
+		assert_eq!(chunk_ids, vec![vec![0, 1], vec![2, 3, 4, 5], vec![6]]);
+	}
+
+	#[test]
+	fn test_filter_chunks() {
+		// Chunk a known document on sentence boundaries, then filter out periods (1) and spaces (2)
+		let document: Vec<TokenWithCharacters> = [10, 1, 2, 1, 2, 11, 12, 1, 2, 1, 2, 1]
+			.into_iter()
+			.map(|id| {
+				let text = match id {
+					1 => ".",
+					2 => " ",
+					_ => "x",
+				};
+				(text.as_bytes().to_vec(), id)
+			})
+			.collect();
+		let chunks = hierarchically_chunk(document, &[String::from(". ")], 2);
+		assert_eq!(chunks.len(), 7);
+
+		let (filtered, skipped) = filter_chunks(chunks, &HashSet::from([1, 2]));
+		let ids: Vec<Vec<u32>> = filtered.iter().map(|c| c.iter().map(|t| t.1).collect()).collect();
+		assert_eq!(ids, vec![vec![10], vec![11, 12]]);
+		assert_eq!(skipped, 5);
+		assert_eq!(filtered.len() + skipped, 7);
+	}
+}
@@ -1,5 +1,13 @@
+use std::collections::HashMap;
+
 use async_trait::async_trait;
-use qdrant_client::{prelude::*, qdrant::PointsSelector};
+use qdrant_client::{
+	prelude::*,
+	qdrant::{
+		points_selector::PointsSelectorOneOf, value::Kind, vectors::VectorsOptions, Condition, CountPoints, Filter, PointsIdsList, PointsSelector,
+		ScrollPoints, Value, Vectors,
+	},
+};
 use serde_json::json;
 
 use super::{Memory, MemoryError};
@@ -22,16 +30,29 @@ impl QdrantMemory {
 	}
 }
 
+/// Extract the text stored in the payload of a point. The payload value is converted to a string as-is when it is not a
+/// string (which should not happen for points stored by [QdrantMemory]).
+fn payload_text(payload: &HashMap<String, Value>) -> String {
+	match payload.get("text") {
+		Some(Value {
+			kind: Some(Kind::StringValue(text)),
+		}) => text.clone(),
+		Some(value) => value.to_string(),
+		None => String::new(),
+	}
+}
+
+/// Number of points scrolled past at once when skipping points to list items from an offset
+const LIST_SKIP_PAGE_SIZE: usize = 1000;
+
 const ITEM_NAMESPACE: uuid::Uuid = uuid::uuid!("067FB304-F9B1-4E74-8ACA-28051B8492AB");
 
 #[async_trait]
 impl Memory for QdrantMemory {
 	async fn store(&self, text: &str, embedding: &[f32]) -> Result<(), MemoryError> {
-		assert_eq!(
-			embedding.len(),
-			self.dimensions,
-			"embedding to store must have same dimensionality as configured for the memory"
-		);
+		if embedding.len() != self.dimensions {
+			return Err(MemoryError::DimensionalityMismatch);
+		}
 		let payload: Payload = json!({ "text": text }).try_into().unwrap();
 		let id = uuid::Uuid::new_v5(&ITEM_NAMESPACE, text.as_bytes());
 		let points = vec![PointStruct::new(id.to_string(), embedding.to_vec(), payload)];
@@ -42,12 +63,29 @@ impl Memory for QdrantMemory {
 		Ok(())
 	}
 
-	async fn get(&self, embedding: &[f32], top_n: usize) -> Result<Vec<String>, MemoryError> {
-		assert_eq!(
-			embedding.len(),
-			self.dimensions,
-			"embedding to search must have same dimensionality as configured for the memory"
-		);
+	async fn store_batch(&self, items: &[(String, Vec<f32>)]) -> Result<(), MemoryError> {
+		if items.iter().any(|(_, embedding)| embedding.len() != self.dimensions) {
+			return Err(MemoryError::DimensionalityMismatch);
+		}
+		let points = items
+			.iter()
+			.map(|(text, embedding)| {
+				let payload: Payload = json!({ "text": text }).try_into().unwrap();
+				let id = uuid::Uuid::new_v5(&ITEM_NAMESPACE, text.as_bytes());
+				PointStruct::new(id.to_string(), embedding.clone(), payload)
+			})
+			.collect::<Vec<_>>();
+		self.client
+			.upsert_points_blocking(&self.collection_name, None, points, None)
+			.await
+			.map_err(|x| MemoryError::Storage(x.to_string()))?;
+		Ok(())
+	}
+
+	async fn get_scored(&self, embedding: &[f32], top_n: usize) -> Result<Vec<(String, f32)>, MemoryError> {
+		if embedding.len() != self.dimensions {
+			return Err(MemoryError::DimensionalityMismatch);
+		}
 		let search_result = self
 			.client
 			.search_points(&SearchPoints {
@@ -61,7 +99,110 @@ impl Memory for QdrantMemory {
 			.await
 			.map_err(|x| MemoryError::Storage(x.to_string()))?;
 
-		Ok(search_result.result.into_iter().map(|r| r.payload["text"].to_string()).collect())
+		Ok(search_result.result.into_iter().map(|r| (payload_text(&r.payload), r.score)).collect())
+	}
+
+	async fn get_scored_with_embeddings(&self, embedding: &[f32], top_n: usize) -> Result<Vec<(String, f32, Vec<f32>)>, MemoryError> {
+		if embedding.len() != self.dimensions {
+			return Err(MemoryError::DimensionalityMismatch);
+		}
+		let search_result = self
+			.client
+			.search_points(&SearchPoints {
+				collection_name: self.collection_name.to_string(),
+				vector: embedding.to_vec(),
+				filter: None,
+				limit: top_n as u64,
+				with_payload: Some(true.into()),
+				with_vectors: Some(true.into()),
+				..Default::default()
+			})
+			.await
+			.map_err(|x| MemoryError::Storage(x.to_string()))?;
+
+		search_result
+			.result
+			.into_iter()
+			.map(|r| {
+				let Some(Vectors {
+					vectors_options: Some(VectorsOptions::Vector(vector)),
+				}) = r.vectors
+				else {
+					return Err(MemoryError::Storage(String::from("point does not have a (single, unnamed) vector")));
+				};
+				Ok((payload_text(&r.payload), r.score, vector.data))
+			})
+			.collect()
+	}
+
+	async fn list(&self, offset: usize, limit: usize) -> Result<Vec<String>, MemoryError> {
+		// Scrolling continues from a point ID rather than a numeric offset, so first scroll past the skipped points
+		// (without fetching their payload) to find the ID of the first point to return
+		let mut page_offset: Option<PointId> = None;
+		let mut skipped = 0;
+		while skipped < offset {
+			let page_size = (offset - skipped).min(LIST_SKIP_PAGE_SIZE);
+			let scroll_result = self
+				.client
+				.scroll(&ScrollPoints {
+					collection_name: self.collection_name.to_string(),
+					filter: None,
+					offset: page_offset.take(),
+					limit: Some(page_size as u32),
+					with_payload: Some(false.into()),
+					..Default::default()
+				})
+				.await
+				.map_err(|x| MemoryError::Storage(x.to_string()))?;
+			skipped += scroll_result.result.len();
+			match scroll_result.next_page_offset {
+				Some(next_page_offset) => page_offset = Some(next_page_offset),
+				None => return Ok(vec![]),
+			}
+		}
+
+		let limit = u32::try_from(limit).map_err(|_| MemoryError::Storage(format!("cannot list {limit} items at once")))?;
+		let scroll_result = self
+			.client
+			.scroll(&ScrollPoints {
+				collection_name: self.collection_name.to_string(),
+				filter: None,
+				offset: page_offset,
+				limit: Some(limit),
+				with_payload: Some(true.into()),
+				..Default::default()
+			})
+			.await
+			.map_err(|x| MemoryError::Storage(x.to_string()))?;
+
+		Ok(scroll_result.result.into_iter().map(|r| payload_text(&r.payload)).collect())
+	}
+
+	async fn delete(&self, text: &str) -> Result<bool, MemoryError> {
+		// Points are identified by an ID derived from their text (see store), but the ID itself may also be provided
+		let id = uuid::Uuid::parse_str(text).unwrap_or_else(|_| uuid::Uuid::new_v5(&ITEM_NAMESPACE, text.as_bytes()));
+		let id: PointId = id.to_string().into();
+		let count_result = self
+			.client
+			.count(&CountPoints {
+				collection_name: self.collection_name.to_string(),
+				filter: Some(Filter::must([Condition::has_id([id.clone()])])),
+				exact: Some(true),
+			})
+			.await
+			.map_err(|x| MemoryError::Storage(x.to_string()))?;
+		if count_result.result.map(|r| r.count).unwrap_or(0) == 0 {
+			return Ok(false);
+		}
+
+		let selector = PointsSelector {
+			points_selector_one_of: Some(PointsSelectorOneOf::Points(PointsIdsList { ids: vec![id] })),
+		};
+		self.client
+			.delete_points_blocking(&self.collection_name, None, &selector, None)
+			.await
+			.map_err(|x| MemoryError::Storage(x.to_string()))?;
+		Ok(true)
 	}
 
 	async fn clear(&self) -> Result<(), MemoryError> {
@@ -71,4 +212,38 @@ impl Memory for QdrantMemory {
 			.map_err(|x| MemoryError::Storage(x.to_string()))?;
 		Ok(())
 	}
+
+	async fn count(&self) -> Result<usize, MemoryError> {
+		let count_result = self
+			.client
+			.count(&CountPoints {
+				collection_name: self.collection_name.to_string(),
+				filter: None,
+				exact: Some(true),
+			})
+			.await
+			.map_err(|x| MemoryError::Storage(x.to_string()))?;
+		Ok(count_result.result.map(|r| r.count as usize).unwrap_or(0))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::HashMap;
+
+	use qdrant_client::qdrant::{value::Kind, Value};
+
+	use super::payload_text;
+
+	#[test]
+	pub fn test_payload_text() {
+		let payload = HashMap::from([(
+			"text".to_string(),
+			Value {
+				kind: Some(Kind::StringValue("hello".to_string())),
+			},
+		)]);
+		assert_eq!(payload_text(&payload), "hello");
+		assert_eq!(payload_text(&HashMap::new()), "");
+	}
 }
@@ -0,0 +1,119 @@
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::memory::{Memory, MemoryError, MemoryMetric};
+
+/// Memory that keeps all items in a list and scans through all of them when recalling. Items are not persisted. Useful
+/// for tests and small sets of items.
+pub struct VectorMemory {
+	dims: usize,
+	metric: MemoryMetric,
+	items: Mutex<Vec<(String, Vec<f32>)>>,
+}
+
+impl VectorMemory {
+	pub fn new(dims: usize, metric: MemoryMetric) -> VectorMemory {
+		VectorMemory {
+			dims,
+			metric,
+			items: Mutex::new(vec![]),
+		}
+	}
+}
+
+#[async_trait]
+impl Memory for VectorMemory {
+	async fn store(&self, text: &str, embedding: &[f32]) -> Result<(), MemoryError> {
+		if embedding.len() != self.dims {
+			return Err(MemoryError::DimensionalityMismatch);
+		}
+		self.items.lock().await.push((text.to_string(), embedding.to_vec()));
+		Ok(())
+	}
+
+	async fn get_scored_with_embeddings(&self, embedding: &[f32], top_n: usize) -> Result<Vec<(String, f32, Vec<f32>)>, MemoryError> {
+		if embedding.len() != self.dims {
+			return Err(MemoryError::DimensionalityMismatch);
+		}
+		let items = self.items.lock().await;
+		let mut scored: Vec<(String, f32, Vec<f32>)> = items
+			.iter()
+			.map(|(text, item_embedding)| (text.clone(), self.metric.score(embedding, item_embedding), item_embedding.clone()))
+			.collect();
+		scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+		scored.truncate(top_n);
+		Ok(scored)
+	}
+
+	async fn list(&self, offset: usize, limit: usize) -> Result<Vec<String>, MemoryError> {
+		let items = self.items.lock().await;
+		Ok(items.iter().skip(offset).take(limit).map(|(text, _)| text.clone()).collect())
+	}
+
+	async fn delete(&self, text: &str) -> Result<bool, MemoryError> {
+		let mut items = self.items.lock().await;
+		let n_items = items.len();
+		items.retain(|(item_text, _)| item_text != text);
+		Ok(items.len() != n_items)
+	}
+
+	async fn clear(&self) -> Result<(), MemoryError> {
+		self.items.lock().await.clear();
+		Ok(())
+	}
+
+	async fn count(&self) -> Result<usize, MemoryError> {
+		Ok(self.items.lock().await.len())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::VectorMemory;
+	use crate::memory::{Memory, MemoryError, MemoryMetric};
+
+	#[tokio::test]
+	pub async fn test_store() {
+		let vm = VectorMemory::new(3, MemoryMetric::Euclidean);
+		vm.store("foo", &[1.0, 2.0, 3.0]).await.unwrap();
+		vm.store("bar", &[-1.0, 2.0, 3.0]).await.unwrap();
+		vm.store("baz", &[1.0, -2.0, 3.0]).await.unwrap();
+		vm.store("boo", &[1.0, -2.0, -3.0]).await.unwrap();
+		assert_eq!(vm.get(&[0.0, -1.0, 0.0], 2).await.unwrap(), vec!["baz", "boo"]);
+		assert_eq!(vm.count().await.unwrap(), 4);
+
+		vm.clear().await.unwrap();
+		assert_eq!(vm.count().await.unwrap(), 0);
+		assert!(vm.get(&[0.0, -1.0, 0.0], 2).await.unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	pub async fn test_list() {
+		let vm = VectorMemory::new(1, MemoryMetric::Euclidean);
+		for (index, text) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+			vm.store(text, &[index as f32]).await.unwrap();
+		}
+		assert_eq!(vm.list(0, 2).await.unwrap(), vec!["a", "b"]);
+		assert_eq!(vm.list(2, 2).await.unwrap(), vec!["c", "d"]);
+		assert_eq!(vm.list(4, 2).await.unwrap(), vec!["e"]);
+		assert!(vm.list(6, 2).await.unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	pub async fn test_delete() {
+		let vm = VectorMemory::new(2, MemoryMetric::Euclidean);
+		vm.store("foo", &[1.0, 0.0]).await.unwrap();
+		vm.store("bar", &[0.0, 1.0]).await.unwrap();
+
+		assert!(vm.delete("foo").await.unwrap());
+		assert!(!vm.delete("foo").await.unwrap());
+		assert_eq!(vm.get(&[1.0, 0.0], 2).await.unwrap(), vec!["bar"]);
+	}
+
+	#[tokio::test]
+	pub async fn test_dimensionality() {
+		let vm = VectorMemory::new(3, MemoryMetric::Cosine);
+		assert!(matches!(vm.store("foo", &[1.0, 2.0]).await, Err(MemoryError::DimensionalityMismatch)));
+		assert!(matches!(vm.get(&[1.0, 2.0], 1).await, Err(MemoryError::DimensionalityMismatch)));
+	}
+}
@@ -75,10 +75,185 @@ impl SequenceSet {
 	}
 }
 
+/// Buffers generated text so that stop sequences (or parts of them) are never emitted. Text is held back for as long as
+/// it may be the start of a stop sequence.
+#[derive(Debug)]
+pub struct StopSequenceBuffer {
+	stop_sequences: Vec<String>,
+	pending: String,
+}
+
+impl StopSequenceBuffer {
+	pub fn new(stop_sequences: Vec<String>) -> StopSequenceBuffer {
+		StopSequenceBuffer {
+			stop_sequences: stop_sequences.into_iter().filter(|s| !s.is_empty()).collect(),
+			pending: String::new(),
+		}
+	}
+
+	/// Add generated text to the buffer. Returns the text that can safely be emitted, and whether a stop sequence was
+	/// encountered. In the latter case, the returned text is everything before the stop sequence, and the stop sequence
+	/// itself (and anything after it) remains in the buffer.
+	pub fn push(&mut self, text: &str) -> (String, bool) {
+		self.pending.push_str(text);
+
+		// Find the earliest complete stop sequence
+		if let Some(index) = self.stop_sequences.iter().filter_map(|s| self.pending.find(s.as_str())).min() {
+			let rest = self.pending.split_off(index);
+			return (std::mem::replace(&mut self.pending, rest), true);
+		}
+
+		// Hold back the longest tail of the text that is the start of a stop sequence
+		let hold = held_length(&self.pending, &self.stop_sequences);
+		let rest = self.pending.split_off(self.pending.len() - hold);
+		(std::mem::replace(&mut self.pending, rest), false)
+	}
+
+	/// Take all text that is held back in the buffer
+	pub fn flush(&mut self) -> String {
+		std::mem::take(&mut self.pending)
+	}
+}
+
+/// Length of the longest tail of `text` that is the start of (but not equal to) one of `sequences`
+fn held_length(text: &str, sequences: &[String]) -> usize {
+	sequences
+		.iter()
+		.filter_map(|s| {
+			(1..s.len())
+				.rev()
+				.find(|length| s.is_char_boundary(*length) && text.ends_with(&s[0..*length]))
+		})
+		.max()
+		.unwrap_or(0)
+}
+
+/// Removes configured strings from generated text. Text is held back for as long as it may be the start of one of the
+/// strings to remove.
+#[derive(Debug)]
+pub struct StripBuffer {
+	strip: Vec<String>,
+	pending: String,
+}
+
+impl StripBuffer {
+	pub fn new(strip: Vec<String>) -> StripBuffer {
+		StripBuffer {
+			strip: strip.into_iter().filter(|s| !s.is_empty()).collect(),
+			pending: String::new(),
+		}
+	}
+
+	/// Add generated text to the buffer. Returns the text that can safely be emitted.
+	pub fn push(&mut self, text: &str) -> String {
+		self.pending.push_str(text);
+
+		// Removing a string may make another occurrence appear, so repeat until nothing changes
+		while let Some(s) = self.strip.iter().find(|s| self.pending.contains(s.as_str())) {
+			self.pending = self.pending.replace(s.as_str(), "");
+		}
+
+		let hold = held_length(&self.pending, &self.strip);
+		let rest = self.pending.split_off(self.pending.len() - hold);
+		std::mem::replace(&mut self.pending, rest)
+	}
+
+	/// Take all text that is held back in the buffer
+	pub fn flush(&mut self) -> String {
+		std::mem::take(&mut self.pending)
+	}
+}
+
+/// Tracks the nesting depth of braces, brackets and parentheses in generated text, in order to detect the point at which
+/// they are balanced again after the first one was opened
+#[derive(Debug, Default)]
+pub struct BalancedBraces {
+	depth: usize,
+}
+
+impl BalancedBraces {
+	/// Add generated text. Returns the length of the text up to and including the character at which the braces are
+	/// balanced again (if this happens in the text)
+	pub fn push(&mut self, text: &str) -> Option<usize> {
+		for (index, c) in text.char_indices() {
+			match c {
+				'{' | '[' | '(' => self.depth += 1,
+				'}' | ']' | ')' if self.depth > 0 => {
+					self.depth -= 1;
+					if self.depth == 0 {
+						return Some(index + c.len_utf8());
+					}
+				}
+				_ => {}
+			}
+		}
+		None
+	}
+}
+
 #[cfg(test)]
 mod test {
+	use super::BalancedBraces;
 	use super::Sequence;
 	use super::SequenceSet;
+	use super::StopSequenceBuffer;
+	use super::StripBuffer;
+
+	#[test]
+	fn test_stop_sequence_buffer() {
+		let mut b = StopSequenceBuffer::new(vec!["<|im_end|>".to_string(), "\n\n".to_string()]);
+		assert_eq!(b.push("Hello"), ("Hello".to_string(), false));
+		assert_eq!(b.push(" world<|im"), (" world".to_string(), false));
+		assert_eq!(b.push("_e"), ("".to_string(), false));
+		assert_eq!(b.push("nd|>foo"), ("".to_string(), true));
+
+		// Stop sequence appears in the middle of a token
+		let mut b = StopSequenceBuffer::new(vec!["stop".to_string()]);
+		assert_eq!(b.push("bus"), ("bu".to_string(), false));
+		assert_eq!(b.push(" st"), ("s ".to_string(), false));
+		assert_eq!(b.push("opping"), ("".to_string(), true));
+		assert_eq!(b.flush(), "stopping");
+
+		// Text that turns out not to be a stop sequence is released
+		let mut b = StopSequenceBuffer::new(vec!["stop".to_string()]);
+		assert_eq!(b.push("a st"), ("a ".to_string(), false));
+		assert_eq!(b.push("ep"), ("step".to_string(), false));
+		assert_eq!(b.push("s sto"), ("s ".to_string(), false));
+		assert_eq!(b.flush(), "sto");
+
+		// Earliest stop sequence wins
+		let mut b = StopSequenceBuffer::new(vec!["def".to_string(), "b".to_string()]);
+		assert_eq!(b.push("abcdef"), ("a".to_string(), true));
+	}
+
+	#[test]
+	fn test_strip_buffer() {
+		let mut b = StripBuffer::new(vec!["<|endoftext|>".to_string(), "".to_string()]);
+		assert_eq!(b.push("Hello<|endoftext|> world"), "Hello world");
+
+		// Strings split over multiple pushes are held back until it is clear whether they should be removed
+		assert_eq!(b.push("!<|end"), "!");
+		assert_eq!(b.push("oftext|>"), "");
+		assert_eq!(b.push("<|im"), "<|im");
+		assert_eq!(b.push(" done<|"), " done");
+		assert_eq!(b.flush(), "<|");
+
+		// Removal that results in a new occurrence
+		let mut b = StripBuffer::new(vec!["ab".to_string()]);
+		assert_eq!(b.push("aabb"), "");
+	}
+
+	#[test]
+	fn test_balanced_braces() {
+		let mut b = BalancedBraces::default();
+		assert_eq!(b.push("{\n\tlet x = [1, (2"), None);
+		assert_eq!(b.push(")"), None);
+		assert_eq!(b.push("];\n}\n\nfn"), Some(4));
+
+		// Closing characters before anything was opened are ignored
+		let mut b = BalancedBraces::default();
+		assert_eq!(b.push(") foo {bar}"), Some(11));
+	}
 
 	#[test]
 	fn test_sequences() {
@@ -1,42 +1,94 @@
 use std::{
 	borrow::Cow,
 	fmt::Debug,
-	fs::File,
-	io::BufReader,
-	sync::{Arc, Mutex},
-	time::{Duration, Instant},
+	ops::Range,
+	sync::{
+		atomic::{AtomicBool, AtomicUsize, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant, SystemTime},
 };
 
 use llm::{
-	samplers::llm_samplers::types::SamplerChain, InferenceError, InferenceParameters, InferenceRequest, InferenceStats, OutputRequest, Prompt,
-	TokenId, TokenUtf8Buffer,
+	samplers::llm_samplers::{
+		samplers::SampleFlatBias,
+		types::{HasSamplerResources, Logits, Sampler, SamplerChain},
+	},
+	InferenceError, InferenceParameters, InferenceRequest, InferenceStats, OutputRequest, Prompt, TokenId, TokenUtf8Buffer,
 };
 use poly_bias::{
 	json::{JsonBiaser, JsonSchema},
-	Biaser, NullBiaser,
+	vocabulary::StringTokens,
+	Biaser, NullBiaser, TOKEN_FORBIDDEN,
 };
+use serde_json::Value;
 
 pub use llm::{InferenceFeedback, InferenceResponse};
 
 use crate::{
 	backend::{Backend, BackendStats},
-	config::{BiaserConfig, TaskConfig},
+	config::{RetrievalPosition, TaskConfig},
 	memory::Memory,
-	sequence::{Sequence, SequenceSet},
+	sequence::{BalancedBraces, StopSequenceBuffer, StripBuffer},
 	stats::InferenceStatsAdd,
-	types::{BackendError, PromptRequest},
+	types::{BackendError, BiasStep, CompletionWarning, FinishReason, PromptRequest},
 };
 
+/// Registry entry for a session that is currently active (see [Backend::sessions])
+#[derive(Debug)]
+pub struct ActiveSession {
+	pub id: u64,
+	pub task_name: String,
+	pub started: SystemTime,
+
+	/// User on whose behalf the session was started (if known)
+	pub(crate) sub: Mutex<Option<String>>,
+
+	/// Identifier of the request as chosen by the client (see [Backend::cancel_request])
+	pub(crate) request_id: Option<String>,
+
+	/// Number of tokens generated by the session so far (over all completions)
+	pub(crate) tokens_generated: AtomicUsize,
+
+	/// Set to abort generation (see [Backend::abort_task] and [Backend::cancel_request])
+	pub(crate) cancelled: AtomicBool,
+}
+
+/// The result of a completion
+#[derive(Debug, Clone)]
+pub struct CompletionResult {
+	pub stats: InferenceStats,
+	pub finish_reason: FinishReason,
+	pub warnings: Vec<CompletionWarning>,
+
+	/// The value generated under the biaser (only when a biaser is configured and it produced a complete value)
+	pub value: Option<Value>,
+}
+
 pub struct BackendSession {
 	pub(crate) model: Arc<Box<dyn llm::Model>>,
+	pub(crate) string_tokens: Arc<StringTokens>,
 	pub(crate) memory: Option<Arc<Box<dyn Memory>>>,
 	pub(crate) session: llm::InferenceSession,
 	pub(crate) inference_parameters: InferenceParameters,
+	pub(crate) logit_bias: Vec<(TokenId, f32)>,
+	pub(crate) bias_steps: Option<Vec<BiasStep>>,
+	pub(crate) echo: bool,
 	pub(crate) task_config: TaskConfig,
 	pub(crate) stats: Arc<BackendStats>,
 	pub(crate) task_name: String,
 	pub(crate) backend: Arc<Backend>,
 	pub(crate) n_threads: usize,
+
+	/// Entry for this session in the registry of active sessions. Stored sessions (see [Backend::create_session]) are
+	/// only registered while a completion is running on them.
+	pub(crate) active: Option<Arc<ActiveSession>>,
+
+	/// Identifier of the request that started this session (used when registering the session as active)
+	pub(crate) request_id: Option<String>,
+
+	/// The user on whose behalf this session runs
+	pub(crate) sub: Option<String>,
 }
 
 impl Debug for BackendSession {
@@ -49,28 +101,90 @@ impl Debug for BackendSession {
 	}
 }
 
+/// Sampler that applies a flat bias which can be replaced in between inference steps. The sampler chain of a completion
+/// holds a clone of it, which shares the bias.
+#[derive(Debug, Clone)]
+struct SharedFlatBias(Arc<Mutex<SampleFlatBias>>);
+
+impl SharedFlatBias {
+	fn new() -> SharedFlatBias {
+		SharedFlatBias(Arc::new(Mutex::new(SampleFlatBias::new(vec![]))))
+	}
+
+	fn set(&self, bias: Vec<(TokenId, f32)>) {
+		*self.0.lock().unwrap() = SampleFlatBias::new(bias);
+	}
+}
+
+impl Sampler for SharedFlatBias {
+	fn sample<'a>(&mut self, res: &mut dyn HasSamplerResources<TokenId = TokenId>, logits: &'a mut Logits) -> anyhow::Result<&'a mut Logits> {
+		self.0.lock().unwrap().sample(res, logits)
+	}
+}
+
+/// Placeholder in the prefix or postfix that is replaced with retrieved memory items (when configured)
+pub const CONTEXT_PLACEHOLDER: &str = "{context}";
+
 impl BackendSession {
+	/// Replace the context placeholder in a prefix or postfix with the remembered text, if the task is configured to do so
+	fn with_context<'a>(text: &'a str, position: &RetrievalPosition, remembered: Option<&str>) -> Cow<'a, str> {
+		if *position == RetrievalPosition::Placeholder {
+			Cow::Owned(text.replace(CONTEXT_PLACEHOLDER, remembered.unwrap_or("")))
+		} else {
+			Cow::Borrowed(text)
+		}
+	}
+
 	fn remember_prompt(&mut self, request: &PromptRequest) -> Result<Option<String>, BackendError> {
 		// Check if we need to recall items from memory first
 		if let Some(memorization) = &self.task_config.memorization {
 			if let Some(retrieve) = memorization.retrieve {
 				if retrieve > 0 {
-					// Calculate embedding for prompt
+					// Calculate embedding for prompt (using the same model that was used to embed the items in memory)
 					let backend = self.backend.clone();
-					let embedding = backend.embedding(&self.task_config.model, request)?;
+					let embedding = backend.embedding(&backend.memory_embedding_model(&memorization.memory)?, request)?;
 
 					let handle = tokio::runtime::Handle::current();
 					let _guard = handle.enter();
 					let memory = self.memory.clone().unwrap();
-					let remember_prompt = handle
+					let remembered = handle
 						.block_on(tokio::spawn(async move {
 							let rm = memory.get(&embedding.embedding, retrieve);
 							let remembered = rm.await?;
 							tracing::debug!("retrieved from memory: {remembered:?}");
-							let remember_prompt: String = remembered.join("\n");
-							Ok::<_, BackendError>(remember_prompt)
+							Ok::<_, BackendError>(remembered)
 						}))
 						.unwrap()?;
+
+					let mut remember_prompt = String::new();
+					let mut remember_tokens: usize = 0;
+					for (index, text) in remembered.iter().enumerate() {
+						let formatted = memorization
+							.chunk_template
+							.replace("{index}", &(index + 1).to_string())
+							.replace("{text}", text);
+						let item = if index == 0 {
+							formatted
+						} else {
+							format!("{}{formatted}", memorization.join)
+						};
+
+						// Stop adding items once the token budget would be exceeded
+						if let Some(max_context_tokens) = memorization.max_context_tokens {
+							let item_tokens = self.model.tokenizer().tokenize(&item, false)?.len();
+							if remember_tokens + item_tokens > max_context_tokens {
+								tracing::debug!(
+									remember_tokens,
+									item_tokens,
+									max_context_tokens,
+									"not including further retrieved items because token budget is exhausted"
+								);
+								break;
+							}
+							remember_tokens += item_tokens;
+						}
+						remember_prompt.push_str(&item);
+					}
 					tracing::info!("Remember prompt: {remember_prompt}");
 					return Ok(Some(remember_prompt));
 				}
@@ -84,25 +198,76 @@ impl BackendSession {
 		&mut self,
 		request: &PromptRequest,
 		callback: impl FnMut(InferenceResponse) -> Result<InferenceFeedback, BackendError>,
-	) -> Result<InferenceStats, BackendError> {
+	) -> Result<CompletionResult, BackendError> {
+		self.complete_with_partial_values(request, callback, None)
+	}
+
+	/// Perform a completion task like [Self::complete], and also report the value parsed by the biaser so far (when
+	/// the task has a biaser) to `partial_value_callback`, so a client can render structured output progressively.
+	///
+	/// The partial value is reported each time a generated token changes it, right after the token has been accepted by
+	/// the biaser and before the text of the token is passed to `callback`. Text may be held back (e.g. when it could be
+	/// part of a stop sequence), so a partial value can be ahead of the text received so far, but never behind it. Partial
+	/// values are reported in the order in which they were generated, and normally each one extends the previous one
+	/// (see [JsonBiaser::current_value]).
+	pub fn complete_with_partial_values(
+		&mut self,
+		request: &PromptRequest,
+		callback: impl FnMut(InferenceResponse) -> Result<InferenceFeedback, BackendError>,
+		partial_value_callback: Option<&mut dyn FnMut(Value) -> Result<(), BackendError>>,
+	) -> Result<CompletionResult, BackendError> {
+		// Use the default prompt of the task when the request does not provide a prompt
+		let default_request;
+		let request = match self.task_config.default_prompt {
+			Some(ref default_prompt) if request.prompt.is_empty() => {
+				default_request = PromptRequest {
+					prompt: default_prompt.clone(),
+					assistant_prefix: request.assistant_prefix.clone(),
+				};
+				&default_request
+			}
+			_ => request,
+		};
+
+		// Refuse before generating when the prompt cannot be stored (the configuration may have changed since startup)
+		if let Some(memorization) = &self.task_config.memorization {
+			if memorization.store_prompts {
+				self.backend.check_memory_writable(&memorization.memory)?;
+			}
+		}
+
+		// A session that is not registered as active (i.e. a stored session) is registered while the completion runs
+		let register = self.active.is_none();
+		if register {
+			let active = self.backend.register_session(&self.task_name, self.request_id.clone());
+			*active.sub.lock().unwrap() = self.sub.clone();
+			self.active = Some(active);
+		}
+
 		// Perform inference
-		let stats = self.complete_actual(request, callback)?;
+		let result = self.complete_actual(request, callback, partial_value_callback);
+		if register {
+			self.deactivate();
+		}
+		let result = result?;
+		let stats = &result.stats;
+		let finish_reason = result.finish_reason;
 		let prompt_tokens_per_s = (stats.prompt_tokens as f64) / stats.feed_prompt_duration.as_secs_f64();
 		let predict_tokens_per_s = (stats.predict_tokens as f64) / stats.predict_duration.as_secs_f64();
 
 		tracing::info!(
-			"completion finished; {prompt_tokens_per_s:.3} t/s prompt, {predict_tokens_per_s:.3} t/s predict; stats: {:?}",
+			"completion finished ({finish_reason:?}); {prompt_tokens_per_s:.3} t/s prompt, {predict_tokens_per_s:.3} t/s predict; stats: {:?}",
 			stats
 		);
-		self.stats.add(&self.task_name, &stats, self.n_threads);
+		self.stats.add(&self.task_name, &self.task_config.model, stats, self.n_threads);
 
 		// Perform memorization
 		if let Some(memorization) = &self.task_config.memorization {
 			if memorization.store_prompts {
 				let backend = self.backend.clone();
 
-				// Calculate embedding
-				let embedding = backend.embedding(&self.task_config.model, request)?;
+				// Calculate embedding (using the embedding model of the memory, so it matches the items already stored)
+				let embedding = backend.embedding(&backend.memory_embedding_model(&memorization.memory)?, request)?;
 
 				// Commit to memory in the background
 				let text = request.prompt.clone();
@@ -120,15 +285,50 @@ impl BackendSession {
 			}
 		}
 
-		Ok(stats)
+		Ok(result)
+	}
+
+	/// Take the diagnostic information on biased generation collected during the last completion (only available when
+	/// the session was started in debug mode)
+	pub fn take_bias_steps(&mut self) -> Option<Vec<BiasStep>> {
+		self.bias_steps.as_mut().map(std::mem::take)
+	}
+
+	/// Record the user on whose behalf this session runs (shown when listing active sessions)
+	pub fn set_user(&mut self, sub: Option<String>) {
+		if let Some(ref active) = self.active {
+			*active.sub.lock().unwrap() = sub.clone();
+		}
+		self.sub = sub;
+	}
+
+	/// Remove this session from the registry of active sessions. It is registered again for the duration of each
+	/// completion that runs on it.
+	pub(crate) fn deactivate(&mut self) {
+		self.active = None;
 	}
 
 	fn complete_actual(
 		&mut self,
 		request: &PromptRequest,
 		mut callback: impl FnMut(InferenceResponse) -> Result<InferenceFeedback, BackendError>,
-	) -> Result<InferenceStats, BackendError> {
+		mut partial_value_callback: Option<&mut dyn FnMut(Value) -> Result<(), BackendError>>,
+	) -> Result<CompletionResult, BackendError> {
+		// Only requests to abort made while this completion is running are relevant
+		if let Some(ref active) = self.active {
+			active.cancelled.store(false, Ordering::SeqCst);
+		}
+
 		let mut completion_stats = InferenceStats::default();
+		if let Some(ref mut bias_steps) = self.bias_steps {
+			bias_steps.clear();
+		}
+
+		// The biaser constrains output from its start, so it cannot continue from an arbitrary prefix
+		let assistant_prefix = request.assistant_prefix.as_deref().filter(|prefix| !prefix.is_empty());
+		if assistant_prefix.is_some() && self.task_config.biaser.is_some() {
+			return Err(BackendError::AssistantPrefixUnsupported(self.task_name.clone()));
+		}
 
 		// Generate tokens (prefix + prompt + postfix)
 		let beginning_of_sentence = self.model.bot_token_id().is_some() && self.session.n_past == 0;
@@ -136,41 +336,97 @@ impl BackendSession {
 			"beginning-of-text token is {:?}, beginning_of_sentence={beginning_of_sentence:?}",
 			self.model.bot_token_id()
 		);
-		let mut tokens = vec![];
+		// Retrieve remembered items and determine where they should go
+		let remember_prompt = self.remember_prompt(request)?;
+		let remember_position = self.task_config.memorization.as_ref().map(|m| m.position.clone()).unwrap_or_default();
+
+		// Collect the texts that make up the prompt, in order
+		let mut segments: Vec<Cow<str>> = vec![];
 
-		// Append remember tokens
-		if let Some(remember_prompt) = self.remember_prompt(request)? {
-			tokens.append(&mut Prompt::Text(&remember_prompt).to_tokens(self.model.tokenizer(), beginning_of_sentence && tokens.is_empty())?)
+		// Remembered items before prefix
+		if remember_position == RetrievalPosition::BeforePrefix {
+			if let Some(ref remember_prompt) = remember_prompt {
+				segments.push(Cow::Borrowed(remember_prompt));
+			}
 		}
 
-		// Append prefix tokens
+		// Prefix
 		if let Some(ref prefix) = self.task_config.prefix {
-			tokens.append(&mut Prompt::Text(prefix).to_tokens(self.model.tokenizer(), beginning_of_sentence && tokens.is_empty())?);
+			segments.push(Self::with_context(prefix, &remember_position, remember_prompt.as_deref()));
+		}
+
+		// Remembered items after prefix
+		if remember_position == RetrievalPosition::AfterPrefix {
+			if let Some(ref remember_prompt) = remember_prompt {
+				segments.push(Cow::Borrowed(remember_prompt));
+			}
 		}
 
-		// Generate user prompt tokens
-		let mut user_tokens = Prompt::Text(&request.prompt).to_tokens(self.model.tokenizer(), beginning_of_sentence && tokens.is_empty())?;
+		// User prompt
+		let user_segment = segments.len();
+		segments.push(Cow::Borrowed(&request.prompt));
+
+		// Postfix
+		if let Some(ref postfix) = self.task_config.postfix {
+			segments.push(Self::with_context(postfix, &remember_position, remember_prompt.as_deref()));
+		}
+
+		// Remembered items after postfix
+		if remember_position == RetrievalPosition::AfterPostfix {
+			if let Some(ref remember_prompt) = remember_prompt {
+				segments.push(Cow::Borrowed(remember_prompt));
+			}
+		}
+
+		// Start of the response that the model should continue from
+		if let Some(assistant_prefix) = assistant_prefix {
+			segments.push(Cow::Borrowed(assistant_prefix));
+		}
+
+		// Generate tokens for the segments. With token healing, the segments are tokenized as a whole so that tokens can
+		// span the boundaries between them. Otherwise each segment is tokenized separately.
+		let tokenizer = self.model.tokenizer();
+		let (mut tokens, user_tokens_range) = if self.task_config.token_healing {
+			tokenize_joined(&segments, user_segment, |text| tokenizer.tokenize(text, beginning_of_sentence))?
+		} else {
+			let mut tokens = vec![];
+			let mut user_tokens_range = 0..0;
+			for (index, segment) in segments.iter().enumerate() {
+				let mut segment_tokens = Prompt::Text(segment).to_tokens(tokenizer, beginning_of_sentence && tokens.is_empty())?;
+				if index == user_segment {
+					user_tokens_range = tokens.len()..(tokens.len() + segment_tokens.len());
+				}
+				tokens.append(&mut segment_tokens);
+			}
+			(tokens, user_tokens_range)
+		};
 
 		// Check for private tokens in user prompt
 		let private_tokens = self.task_config.private_tokens.clone().unwrap_or_default();
 		let private_token_ids: Vec<u32> = private_tokens
 			.iter()
 			.map(|token_str| {
-				let toks = self.model.tokenizer().tokenize(token_str, false).unwrap();
+				let toks = tokenizer.tokenize(token_str, false).unwrap();
 				if toks.len() != 1 {
 					panic!("invalid forbidden token configured: {token_str}");
 				}
 				toks[0].1
 			})
 			.collect();
-		if !private_token_ids.is_empty() && user_tokens.iter().any(|t| private_token_ids.contains(t)) {
+		if !private_token_ids.is_empty() && tokens[user_tokens_range.clone()].iter().any(|t| private_token_ids.contains(t)) {
 			return Err(BackendError::IllegalToken);
 		}
-		tokens.append(&mut user_tokens);
 
-		// Append postfix tokens
-		if let Some(ref postfix) = self.task_config.postfix {
-			tokens.append(&mut Prompt::Text(postfix).to_tokens(self.model.tokenizer(), beginning_of_sentence && tokens.is_empty())?);
+		// The assistant prefix is supplied by the user as well
+		if let Some(assistant_prefix) = assistant_prefix {
+			if !private_token_ids.is_empty()
+				&& tokenizer
+					.tokenize(assistant_prefix, false)?
+					.iter()
+					.any(|t| private_token_ids.contains(&t.1))
+			{
+				return Err(BackendError::IllegalToken);
+			}
 		}
 
 		tracing::trace!("prompt tokens: {tokens:?}");
@@ -190,6 +446,35 @@ impl BackendSession {
 			predict_tokens: 0,
 		});
 
+		// Play back the user prompt when requested
+		if self.echo {
+			let mut echo_buffer = TokenUtf8Buffer::new();
+			for token_id in &tokens[user_tokens_range] {
+				if let Some(text) = echo_buffer.push(&self.model.tokenizer().token(*token_id as usize)) {
+					if let InferenceFeedback::Halt = callback(InferenceResponse::PromptToken(text))? {
+						return Ok(CompletionResult {
+							stats: completion_stats,
+							finish_reason: FinishReason::Halted,
+							warnings: vec![],
+							value: None,
+						});
+					}
+				}
+			}
+
+			// The assistant prefix is played back once, as it is not part of the generated output
+			if let Some(assistant_prefix) = assistant_prefix {
+				if let InferenceFeedback::Halt = callback(InferenceResponse::PromptToken(assistant_prefix.to_string()))? {
+					return Ok(CompletionResult {
+						stats: completion_stats,
+						finish_reason: FinishReason::Halted,
+						warnings: vec![],
+						value: None,
+					});
+				}
+			}
+		}
+
 		// If a bias prompt is configured, let the model freely generate tokens, then feed the bias prompt and start
 		// biased prompt generation. The tokens generated before the bias prompt is fed are not returned.
 		let mut rng = rand::thread_rng();
@@ -243,44 +528,168 @@ impl BackendSession {
 		}
 
 		// Set up biaser
-		let schema: Option<Cow<JsonSchema>>;
-		let mut biaser: Box<dyn Biaser> = match self.task_config.biaser {
-			Some(BiaserConfig::JsonSchema(ref schema)) => Box::new(JsonBiaser::new(schema)),
-			Some(BiaserConfig::JsonSchemaFile(ref path)) => {
-				let file = File::open(path).unwrap();
-				let rdr = BufReader::new(file);
-				schema = Some(Cow::Owned(serde_json::from_reader(rdr).expect("valid JSON schema in file")));
-				Box::new(JsonBiaser::new(schema.as_ref().unwrap()))
-			}
+		let schema: Option<Cow<JsonSchema>> = self
+			.task_config
+			.biaser
+			.as_ref()
+			.map(|biaser_config| biaser_config.json_schema())
+			.transpose()?;
+		let mut biaser: Box<dyn Biaser> = match schema {
+			Some(ref schema) => Box::new(JsonBiaser::new(schema).with_string_tokens(self.string_tokens.clone())),
 			None => Box::new(NullBiaser {}),
 		};
 
+		// Tokens to feed between the items of an array generated by the biaser
+		let reprompt_tokens: Option<Vec<TokenId>> = match (&self.task_config.reprompt_between_items, &self.task_config.biaser) {
+			(Some(reprompt), Some(_)) => Some(
+				self.model
+					.tokenizer()
+					.tokenize(reprompt, false)?
+					.iter()
+					.map(|(_, token_id)| *token_id)
+					.collect(),
+			),
+			_ => None,
+		};
+
 		// Inference loop
-		let mut result_buffer = TokenUtf8Buffer::new();
+		let mut last_partial_value: Option<Value> = None;
 		let vocabulary = self.model.tokenizer();
 		let eot_token = self.model.eot_token_id();
-		let mut inference_params = self.inference_parameters.clone();
 		let mut tokens_generated: usize = 0;
-		let mut stop_sequences = if self.task_config.stop_sequences.is_empty() {
-			None
-		} else if self.task_config.biaser.is_some() {
-			tracing::warn!(
-				"a biaser is configured for task {}, therefore the stop sequences are ignored",
-				self.task_name
-			);
+		let mut output_request = OutputRequest::default();
+
+		// Track the confidence of the model in the tokens allowed by the biaser (requires the logits)
+		let low_confidence_threshold = self.task_config.biaser.as_ref().and(self.task_config.low_confidence_threshold);
+		if low_confidence_threshold.is_some() {
+			output_request.all_logits = Some(vec![]);
+		}
+		let mut low_confidence_start: Option<(usize, f32, bool)> = None;
+		let mut warnings = vec![];
+
+		// The sampler chain is set up once for the completion; the bias from the biaser is set before each token is sampled
+		let flat_bias = SharedFlatBias::new();
+		let mut samplers = SamplerChain::new();
+		samplers.push_sampler(flat_bias.clone());
+		if !self.logit_bias.is_empty() {
+			// Requested logit bias is added on top of the bias from the biaser
+			samplers.push_sampler(SampleFlatBias::new(self.logit_bias.clone()));
+		}
+		if let (Some(eot_bias), None) = (self.task_config.eot_bias, &self.task_config.biaser) {
+			// Make the model more or less likely to end generation (not applied when a biaser decides when to stop)
+			samplers.push_sampler(SampleFlatBias::new(vec![(eot_token, eot_bias)]));
+		}
+		samplers += self.task_config.sampler_chain();
+		tracing::debug!("sampler: {samplers:?}");
+		let inference_params = InferenceParameters {
+			sampler: Arc::new(Mutex::new(samplers)),
+		};
+		// Stop sequences are also checked when a biaser is configured (a stop sequence inside the value truncates it)
+		let stop_sequences = if self.task_config.stop_sequences.is_empty() {
 			None
 		} else {
-			Some(SequenceSet::new(
-				self.task_config.stop_sequences.iter().map(|x| Sequence::new(x.clone())).collect(),
-			))
+			if self.task_config.biaser.is_some() {
+				for stop_sequence in self.task_config.stop_sequences.iter().filter(|s| conflicts_with_json(s)) {
+					tracing::warn!(
+						stop_sequence,
+						"stop sequence for task {} consists of JSON syntax only and may end biased output prematurely",
+						self.task_name
+					);
+				}
+			}
+			Some(StopSequenceBuffer::new(self.task_config.stop_sequences.clone()))
 		};
 
+		let backend_config = self.backend.config();
+		let output_strip = &backend_config.models[&self.task_config.model].output_strip;
+		let mut output_filter = OutputFilter {
+			result_buffer: TokenUtf8Buffer::new(),
+			strip_buffer: if output_strip.is_empty() {
+				None
+			} else {
+				Some(StripBuffer::new(output_strip.clone()))
+			},
+			stop_sequences,
+			balanced_braces: if self.task_config.stop_on_balanced_braces {
+				Some(BalancedBraces::default())
+			} else {
+				None
+			},
+			private_tokens: &private_tokens,
+			min_tokens: self.task_config.min_tokens,
+		};
+
+		// Whether text held back by the stop sequence buffer should be emitted when generation ends
+		let mut flush_held_text = true;
+		let finish_reason;
+
 		loop {
+			if self.active.as_ref().is_some_and(|active| active.cancelled.load(Ordering::SeqCst)) {
+				tracing::info!("generation aborted");
+				flush_held_text = false;
+				finish_reason = FinishReason::Aborted;
+				break;
+			}
+
 			let mut biaser_bias = biaser.bias(vocabulary, eot_token);
 
 			// Remove private tokens from biaser
 			biaser_bias.retain_mut(|t| !private_token_ids.contains(&t.0));
 
+			// When the biaser allows no tokens at all (not even end-of-text), the output cannot be completed validly
+			if self.task_config.biaser.is_some() && biaser_bias.is_empty() {
+				let partial_output = biaser.partial_output().unwrap_or_default();
+				tracing::error!(partial_output, "biaser does not allow any further tokens");
+				return Err(BackendError::BiaserStuck(partial_output));
+			}
+			let n_allowed_tokens = biaser_bias.len();
+			let eot_allowed = biaser_bias.iter().any(|t| t.0 == eot_token);
+
+			// Enforce the token budget for biased generation
+			if let (Some(_), Some(max_biased_tokens)) = (&self.task_config.biaser, self.task_config.max_biased_tokens) {
+				if tokens_generated >= max_biased_tokens {
+					if eot_allowed {
+						tracing::debug!("ending biased generation because the token budget is used up");
+						finish_reason = FinishReason::EndOfText;
+					} else {
+						tracing::warn!(max_biased_tokens, "stopping biased generation before the output is complete");
+						finish_reason = FinishReason::MaxTokens;
+					}
+					break;
+				}
+			}
+
+			// Prevent generation from ending before the minimum number of tokens was generated
+			if self.task_config.biaser.is_none() {
+				biaser_bias.extend(min_tokens_bias(tokens_generated, self.task_config.min_tokens, eot_token));
+			}
+
+			// Check whether the model has confidence in any of the allowed tokens (the logits obtained after the previous step
+			// describe the distribution of the next token)
+			if let (Some(threshold), Some(all_logits)) = (low_confidence_threshold, &output_request.all_logits) {
+				let n_vocab = vocabulary.len();
+				let allowed_tokens: Vec<TokenId> = biaser_bias.iter().filter(|(_, bias)| *bias > 0.0).map(|(token, _)| *token).collect();
+				if allowed_tokens.len() > 1 && all_logits.len() >= n_vocab {
+					let probability = max_probability(&all_logits[(all_logits.len() - n_vocab)..], &allowed_tokens);
+					if probability < threshold {
+						let (start, min_probability, warned) = low_confidence_start.get_or_insert((tokens_generated, probability, false));
+						*min_probability = min_probability.min(probability);
+
+						// Steps where the biaser allows only one token are not checked, so the count may skip past the threshold
+						if !*warned && tokens_generated + 1 - *start >= self.task_config.low_confidence_steps.get() {
+							*warned = true;
+							tracing::warn!(token_index = *start, "model has low confidence in tokens allowed by biaser");
+							warnings.push(CompletionWarning::LowConfidence {
+								token_index: *start,
+								min_probability: *min_probability,
+							});
+						}
+					} else {
+						low_confidence_start = None;
+					}
+				}
+			}
+
 			// If there is only one token positively biased, that will be the next token
 			let out_token_id = if biaser_bias.len() == 1 && biaser_bias[0].1 > 0.0 {
 				tracing::debug!("only one token in bias, that will be our next: {:?}", biaser_bias[0]);
@@ -291,42 +700,42 @@ impl BackendSession {
 					self.session.feed_prompt(
 						self.model.as_ref().as_ref(),
 						Prompt::Tokens(&[only_possible_token as TokenId]),
-						&mut OutputRequest::default(),
+						&mut output_request,
 						|_| -> Result<InferenceFeedback, BackendError> { Ok(InferenceFeedback::Continue) },
 					)?;
+					// The token is part of the completion, even though it was not sampled
 					completion_stats.add(&InferenceStats {
-						feed_prompt_duration: Instant::now().duration_since(start),
-						prompt_tokens: 1,
-						predict_duration: Duration::ZERO,
-						predict_tokens: 0,
+						feed_prompt_duration: Duration::ZERO,
+						prompt_tokens: 0,
+						predict_duration: Instant::now().duration_since(start),
+						predict_tokens: 1,
 					});
 				}
 				only_possible_token
 			} else {
-				let mut samplers = SamplerChain::new();
-				let flat_bias = llm::samplers::llm_samplers::samplers::SampleFlatBias::new(biaser_bias);
-				samplers.push_sampler(flat_bias);
-				samplers += self.task_config.sampler_chain();
-				tracing::debug!("sampler: {samplers:?}");
-				inference_params.sampler = Arc::new(Mutex::new(samplers));
+				flat_bias.set(biaser_bias);
 
 				let start = Instant::now();
-				let out =
-					match self
-						.session
-						.infer_next_token(self.model.as_ref().as_ref(), &inference_params, &mut OutputRequest::default(), &mut rng)
-					{
-						Ok(out) => out,
-						Err(InferenceError::EndOfText) => break,
-						Err(InferenceError::ContextFull) => {
-							tracing::warn!("ending generation because context is full");
-							break;
-						}
-						Err(e) => {
-							tracing::error!("inference error: {e}");
-							break;
-						}
-					};
+				let out = match self
+					.session
+					.infer_next_token(self.model.as_ref().as_ref(), &inference_params, &mut output_request, &mut rng)
+				{
+					Ok(out) => out,
+					Err(InferenceError::EndOfText) => {
+						finish_reason = FinishReason::EndOfText;
+						break;
+					}
+					Err(InferenceError::ContextFull) => {
+						tracing::warn!("ending generation because context is full");
+						finish_reason = FinishReason::ContextFull;
+						break;
+					}
+					Err(e) => {
+						tracing::error!("inference error: {e}");
+						finish_reason = FinishReason::Error;
+						break;
+					}
+				};
 				completion_stats.add(&InferenceStats {
 					feed_prompt_duration: Duration::ZERO,
 					prompt_tokens: 0,
@@ -337,6 +746,19 @@ impl BackendSession {
 			};
 
 			tokens_generated += 1;
+			if let Some(ref active) = self.active {
+				active.tokens_generated.fetch_add(1, Ordering::SeqCst);
+			}
+
+			// Record diagnostic information
+			if let Some(ref mut bias_steps) = self.bias_steps {
+				bias_steps.push(BiasStep {
+					allowed_tokens: n_allowed_tokens,
+					eot_allowed,
+					token: out_token_id,
+					text: String::from_utf8_lossy(&vocabulary.token(out_token_id as usize)).to_string(),
+				});
+			}
 
 			// Save to transcript
 			if tracing::enabled!(tracing::Level::DEBUG) {
@@ -345,48 +767,395 @@ impl BackendSession {
 
 			// Check for end of text
 			if out_token_id == eot_token {
+				finish_reason = FinishReason::EndOfText;
 				break;
 			}
 
 			// Advance biaser
+			let completed_items = biaser.completed_items();
 			biaser.advance(vocabulary, out_token_id);
 
-			// Add token to result
-			tracing::trace!("token: {out_token_id}");
-			if let Some(output) = result_buffer.push(&vocabulary.token(out_token_id as usize)) {
-				tracing::trace!("text: {output}");
+			// Feed the reprompt when the biaser moves on to the next item of an array (the reprompt is not emitted)
+			if let Some(ref reprompt_tokens) = reprompt_tokens {
+				if let (Some(before), Some(after)) = (completed_items, biaser.completed_items()) {
+					if after > before {
+						tracing::debug!("feeding reprompt between items");
+						if tracing::enabled!(tracing::Level::DEBUG) {
+							tokens.extend(reprompt_tokens);
+						}
+						let start = Instant::now();
+						self.session.feed_prompt(
+							self.model.as_ref().as_ref(),
+							Prompt::Tokens(reprompt_tokens),
+							&mut OutputRequest::default(),
+							|_| -> Result<InferenceFeedback, BackendError> { Ok(InferenceFeedback::Continue) },
+						)?;
+						completion_stats.add(&InferenceStats {
+							feed_prompt_duration: Instant::now().duration_since(start),
+							prompt_tokens: reprompt_tokens.len(),
+							predict_duration: Duration::ZERO,
+							predict_tokens: 0,
+						});
+					}
+				}
+			}
 
-				if let Some(ref mut stop_sequences) = stop_sequences {
-					if stop_sequences.advance(&output) {
-						tracing::debug!("stop because stop sequence encountered");
-						break;
+			// Report the value parsed so far when it has changed
+			if let Some(partial_value_callback) = partial_value_callback.as_deref_mut() {
+				if let Some(value) = biaser.partial_value() {
+					if last_partial_value.as_ref() != Some(&value) {
+						partial_value_callback(value.clone())?;
+						last_partial_value = Some(value);
 					}
 				}
+			}
 
-				if !private_tokens.contains(&output) {
-					// Swallow private tokens
-					match callback(InferenceResponse::InferredToken(output))? {
-						InferenceFeedback::Continue => {}
-						InferenceFeedback::Halt => break,
+			// Add token to result
+			tracing::trace!("token: {out_token_id}");
+			let (text, stop) = output_filter.push(&vocabulary.token(out_token_id as usize), tokens_generated);
+			if !text.is_empty() {
+				match callback(InferenceResponse::InferredToken(text))? {
+					InferenceFeedback::Continue => {}
+					InferenceFeedback::Halt => {
+						flush_held_text = false;
+						finish_reason = FinishReason::Halted;
+						break;
 					}
 				}
 			}
 
+			if let Some(stop) = stop {
+				tracing::debug!("stop because of {stop:?}");
+				flush_held_text = false;
+				finish_reason = stop;
+				break;
+			}
+
 			// Stop once we have enough tokens (and not in biased mode, because then the biaser decides when we stop)
 			if self.task_config.biaser.is_none() {
 				if let Some(max_tokens) = self.task_config.max_tokens {
 					if tokens_generated >= max_tokens {
+						finish_reason = FinishReason::MaxTokens;
 						break;
 					}
 				}
 			}
 		}
 
+		// Emit text that was held back because it could have been the start of a string to strip or a stop sequence
+		if flush_held_text {
+			let text = output_filter.flush();
+			if !text.is_empty() {
+				callback(InferenceResponse::InferredToken(text))?;
+			}
+		}
+
 		if tracing::enabled!(tracing::Level::DEBUG) {
 			let decoded = self.model.tokenizer().decode(tokens, false);
 			let txt = String::from_utf8_lossy(&decoded);
 			tracing::debug!("full transcript (excluding prelude): {txt}");
 		}
-		Ok(completion_stats)
+
+		// The biaser only allows end-of-text once its value is complete
+		let value = if self.task_config.biaser.is_some() && matches!(finish_reason, FinishReason::EndOfText) {
+			biaser.partial_value()
+		} else {
+			None
+		};
+
+		Ok(CompletionResult {
+			stats: completion_stats,
+			finish_reason,
+			warnings,
+			value,
+		})
+	}
+}
+
+/// Turns the tokens generated by the model into the text that is returned. Text that may be (the start of) a string to
+/// strip or a stop sequence is held back until it is clear whether it should be emitted.
+struct OutputFilter<'a> {
+	result_buffer: TokenUtf8Buffer,
+	strip_buffer: Option<StripBuffer>,
+	stop_sequences: Option<StopSequenceBuffer>,
+	balanced_braces: Option<BalancedBraces>,
+	private_tokens: &'a [String],
+
+	/// Stop sequences are ignored until this number of tokens has been generated
+	min_tokens: Option<usize>,
+}
+
+impl OutputFilter<'_> {
+	/// Add a generated token (the `tokens_generated`th). Returns the text that can be emitted, and the reason generation
+	/// should stop after emitting it (if it should)
+	fn push(&mut self, token: &[u8], tokens_generated: usize) -> (String, Option<FinishReason>) {
+		let Some(output) = self.result_buffer.push(token) else {
+			return (String::new(), None);
+		};
+		tracing::trace!("text: {output}");
+
+		// Remove strings configured to be stripped from the output of the model
+		let output = match self.strip_buffer {
+			Some(ref mut strip_buffer) => strip_buffer.push(&output),
+			None => output,
+		};
+
+		// Hold back text that may be (part of) a stop sequence
+		let (mut text, mut stop) = match self.stop_sequences {
+			Some(ref mut stop_sequences) => stop_sequences.push(&output),
+			None => (output, false),
+		};
+
+		if stop && self.min_tokens.is_some_and(|min_tokens| tokens_generated < min_tokens) {
+			tracing::debug!("ignoring stop sequence because the minimum number of tokens was not generated yet");
+			text += &self.stop_sequences.as_mut().unwrap().flush();
+			stop = false;
+		}
+
+		let mut text = strip_private_tokens(text, self.private_tokens);
+
+		// Stop after the character at which braces are balanced again
+		if let Some(end) = self.balanced_braces.as_mut().and_then(|b| b.push(&text)) {
+			text.truncate(end);
+			return (text, Some(FinishReason::BalancedBraces));
+		}
+
+		(text, stop.then_some(FinishReason::StopSequence))
+	}
+
+	/// Returns the text that was held back, to be emitted when generation has ended without a stop sequence
+	fn flush(&mut self) -> String {
+		let mut text = self.strip_buffer.as_mut().map(|b| b.flush()).unwrap_or_default();
+		if let Some(stop_sequences) = self.stop_sequences.as_mut() {
+			let (before_stop, stop) = stop_sequences.push(&text);
+			text = if stop { before_stop } else { before_stop + &stop_sequences.flush() };
+		}
+		strip_private_tokens(text, self.private_tokens)
+	}
+}
+
+/// Whether a stop sequence consists only of JSON syntax (and whitespace), in which case it may be triggered by text that a
+/// JSON biaser requires to be generated (e.g. `}` would end generation after the first object)
+fn conflicts_with_json(stop_sequence: &str) -> bool {
+	!stop_sequence.is_empty() && stop_sequence.chars().all(|c| "{}[]\":,".contains(c) || c.is_whitespace())
+}
+
+/// Remove private tokens from generated text (these are swallowed)
+fn strip_private_tokens(text: String, private_tokens: &[String]) -> String {
+	private_tokens
+		.iter()
+		.filter(|private_token| !private_token.is_empty())
+		.fold(text, |text, private_token| text.replace(private_token.as_str(), ""))
+}
+
+/// Returns the highest probability of any of the indicated tokens given the logits (for the full vocabulary)
+fn max_probability(logits: &[f32], tokens: &[TokenId]) -> f32 {
+	let max_logit = logits.iter().fold(f32::NEG_INFINITY, |a, b| a.max(*b));
+	let sum: f32 = logits.iter().map(|l| (l - max_logit).exp()).sum();
+	tokens
+		.iter()
+		.map(|token| (logits[*token as usize] - max_logit).exp() / sum)
+		.fold(0.0, f32::max)
+}
+
+/// Bias that prevents the end-of-text token from being generated as long as less than `min_tokens` tokens were generated
+fn min_tokens_bias(tokens_generated: usize, min_tokens: Option<usize>, eot_token: TokenId) -> Option<(TokenId, f32)> {
+	match min_tokens {
+		Some(min_tokens) if tokens_generated < min_tokens => Some((eot_token, TOKEN_FORBIDDEN)),
+		_ => None,
+	}
+}
+
+/// Tokenize the concatenation of `segments` as a single text, so that tokens can span the boundaries between segments
+/// (token healing). Returns the tokens along with the range of tokens that (partially) cover the segment at `index`.
+fn tokenize_joined<E>(
+	segments: &[Cow<str>],
+	index: usize,
+	tokenize: impl FnOnce(&str) -> Result<Vec<(Vec<u8>, TokenId)>, E>,
+) -> Result<(Vec<TokenId>, Range<usize>), E> {
+	let segment_start: usize = segments[..index].iter().map(|s| s.len()).sum();
+	let segment_end = segment_start + segments[index].len();
+	let tokens = tokenize(&segments.concat())?;
+
+	let mut range_start = 0;
+	let mut range_end = 0;
+	let mut offset = 0;
+	for (token_bytes, _) in tokens.iter() {
+		let token_start = offset;
+		offset += token_bytes.len();
+		if offset <= segment_start {
+			range_start += 1;
+		}
+		if token_start < segment_end {
+			range_end += 1;
+		}
+	}
+
+	let token_ids = tokens.into_iter().map(|(_, token_id)| token_id).collect();
+	Ok((token_ids, range_start..range_end.max(range_start)))
+}
+
+#[cfg(test)]
+mod test {
+	use std::{borrow::Cow, convert::Infallible, path::Path};
+
+	use llm::{TokenId, TokenUtf8Buffer, TokenizerSource};
+	use poly_bias::{
+		json::{JsonBiaser, JsonSchema},
+		Biaser, TOKEN_FORBIDDEN,
+	};
+
+	use super::{conflicts_with_json, max_probability, min_tokens_bias, tokenize_joined, OutputFilter};
+	use crate::{sequence::StopSequenceBuffer, types::FinishReason};
+
+	#[test]
+	pub fn test_max_probability() {
+		let logits = [0.0, 0.0, 0.0, 0.0];
+		assert_eq!(max_probability(&logits, &[1, 2]), 0.25);
+		assert_eq!(max_probability(&logits, &[]), 0.0);
+
+		let logits = [(3.0f32).ln(), 0.0, 0.0];
+		assert!((max_probability(&logits, &[0, 1]) - 0.6).abs() < 1e-6);
+		assert!((max_probability(&logits, &[2]) - 0.2).abs() < 1e-6);
+	}
+
+	#[test]
+	pub fn test_conflicts_with_json() {
+		assert!(conflicts_with_json("}"));
+		assert!(conflicts_with_json("\"}\n"));
+		assert!(!conflicts_with_json("###"));
+		assert!(!conflicts_with_json("\"END"));
+		assert!(!conflicts_with_json(""));
+	}
+
+	/// Run a stream of tokens (as sampled by a model) through a JSON biaser and an [OutputFilter] with a stop sequence, the
+	/// way [super::BackendSession::complete_actual] does. Returns the emitted text, the reason generation stopped and the
+	/// biaser.
+	fn run_biased_tokens<'a>(schema: &'a JsonSchema, tokens: &[&str], stop_sequences: &[&str]) -> (String, Option<FinishReason>, JsonBiaser<'a>) {
+		// Word-level vocabulary in which each of the tokens is a single token
+		let vocab: serde_json::Map<String, serde_json::Value> = ["[EOT]", "\"", "abc", "##", "#", "def"]
+			.iter()
+			.enumerate()
+			.map(|(id, token)| (token.to_string(), id.into()))
+			.collect();
+		let tokenizer = TokenizerSource::HuggingFaceTokenizerString(
+			serde_json::json!({
+				"version": "1.0", "truncation": null, "padding": null, "added_tokens": [], "normalizer": null,
+				"pre_tokenizer": null, "post_processor": null, "decoder": null,
+				"model": { "type": "WordLevel", "vocab": vocab, "unk_token": "[EOT]" }
+			})
+			.to_string(),
+		)
+		.retrieve(Path::new(""))
+		.unwrap();
+		let eot_token: TokenId = 0;
+
+		let mut biaser = JsonBiaser::new(schema);
+		let mut filter = OutputFilter {
+			result_buffer: TokenUtf8Buffer::new(),
+			strip_buffer: None,
+			stop_sequences: Some(StopSequenceBuffer::new(stop_sequences.iter().map(|s| s.to_string()).collect())),
+			balanced_braces: None,
+			private_tokens: &[],
+			min_tokens: None,
+		};
+
+		let mut output = String::new();
+		for (index, token) in tokens.iter().enumerate() {
+			let token_id = tokenizer.tokenize(token, false).unwrap()[0].1;
+			let bias = biaser.bias(&tokenizer, eot_token);
+			assert!(
+				bias.iter().any(|(t, b)| *t == token_id && *b > 0.0),
+				"token {token:?} not allowed by biaser"
+			);
+			if token_id == eot_token {
+				return (output + &filter.flush(), Some(FinishReason::EndOfText), biaser);
+			}
+
+			biaser.advance(&tokenizer, token_id);
+			let (text, stop) = filter.push(&tokenizer.token(token_id as usize), index + 1);
+			output += &text;
+			if stop.is_some() {
+				return (output, stop, biaser);
+			}
+		}
+		(output + &filter.flush(), None, biaser)
+	}
+
+	#[test]
+	pub fn test_biased_output_with_stop_sequence() {
+		let schema = JsonSchema::String {
+			max_length: None,
+			r#enum: None,
+			min_length: None,
+			pattern: None,
+		};
+
+		// A stop sequence inside the value truncates it (text that may be part of the stop sequence is held back)
+		let (output, stop, biaser) = run_biased_tokens(&schema, &["\"", "abc", "##", "#", "def", "\"", "[EOT]"], &["###"]);
+		assert_eq!(output, "\"abc");
+		assert_eq!(stop, Some(FinishReason::StopSequence));
+		assert!(!biaser.can_end());
+
+		// Text held back for a partial match is emitted once it turns out not to be a stop sequence
+		let (output, stop, biaser) = run_biased_tokens(&schema, &["\"", "abc", "##", "def", "\"", "[EOT]"], &["###"]);
+		assert_eq!(output, "\"abc##def\"");
+		assert_eq!(stop, Some(FinishReason::EndOfText));
+		assert_eq!(biaser.partial_value(), Some(serde_json::json!("abc##def")));
+
+		// A stop sequence that consists of JSON syntax ends the output as soon as the biaser requires that syntax
+		let (output, stop, _) = run_biased_tokens(&schema, &["\"", "abc", "\"", "[EOT]"], &["\""]);
+		assert_eq!(output, "");
+		assert_eq!(stop, Some(FinishReason::StopSequence));
+	}
+
+	#[test]
+	pub fn test_min_tokens_bias() {
+		assert_eq!(min_tokens_bias(0, None, 2), None);
+		assert_eq!(min_tokens_bias(0, Some(3), 2), Some((2, TOKEN_FORBIDDEN)));
+		assert_eq!(min_tokens_bias(2, Some(3), 2), Some((2, TOKEN_FORBIDDEN)));
+		assert_eq!(min_tokens_bias(3, Some(3), 2), None);
+		assert_eq!(min_tokens_bias(10, Some(3), 2), None);
+	}
+
+	/// Greedy longest-match tokenizer over a small vocabulary (token ID is the index in the vocabulary)
+	fn tokenize(text: &str) -> Result<Vec<(Vec<u8>, TokenId)>, Infallible> {
+		const VOCAB: [&str; 8] = ["h", "e", "l", "o", " ", "hel", "lo", "hello"];
+		let mut tokens = vec![];
+		let mut rest = text;
+		while !rest.is_empty() {
+			let (id, token) = VOCAB
+				.iter()
+				.enumerate()
+				.filter(|(_, t)| rest.starts_with(**t))
+				.max_by_key(|(_, t)| t.len())
+				.unwrap();
+			tokens.push((token.as_bytes().to_vec(), id as TokenId));
+			rest = &rest[token.len()..];
+		}
+		Ok(tokens)
+	}
+
+	#[test]
+	pub fn test_tokenize_joined() {
+		// Tokenized separately, the boundary between "hel" and "lo" is kept
+		let separate: Vec<TokenId> = ["hel", "lo"].iter().flat_map(|s| tokenize(s).unwrap()).map(|t| t.1).collect();
+		assert_eq!(separate, vec![5, 6]);
+
+		// Tokenized as a whole, a single token spans the boundary
+		let segments = [Cow::Borrowed("hel"), Cow::Borrowed("lo"), Cow::Borrowed(" h")];
+		let (tokens, range) = tokenize_joined(&segments, 1, tokenize).unwrap();
+		assert_eq!(tokens, vec![7, 4, 0]);
+		assert_eq!(range, 0..1);
+
+		// The range covers all tokens that overlap the segment
+		let (tokens, range) = tokenize_joined(&segments, 2, tokenize).unwrap();
+		assert_eq!(tokens, vec![7, 4, 0]);
+		assert_eq!(range, 1..3);
+
+		// Empty segments yield an empty range at the right position
+		let segments = [Cow::Borrowed("hello"), Cow::Borrowed(""), Cow::Borrowed(" h")];
+		let (_, range) = tokenize_joined(&segments, 1, tokenize).unwrap();
+		assert_eq!(range, 1..1);
 	}
 }
@@ -16,6 +16,67 @@ impl InferenceStatsAdd for InferenceStats {
 	}
 }
 
+/// Token usage of a single completion
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+	/// Number of tokens fed to the model as prompt (including prompts fed during generation)
+	pub prompt_tokens: usize,
+
+	/// Number of tokens generated
+	pub completion_tokens: usize,
+
+	pub total_tokens: usize,
+}
+
+impl From<&InferenceStats> for Usage {
+	fn from(stats: &InferenceStats) -> Self {
+		Usage {
+			prompt_tokens: stats.prompt_tokens,
+			completion_tokens: stats.predict_tokens,
+			total_tokens: stats.prompt_tokens + stats.predict_tokens,
+		}
+	}
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct MemoryStats {
+	/// Number of items stored in the memory (`None` when the memory could not be queried)
+	pub count: Option<usize>,
+
+	/// Number of times items were recalled from the memory
+	pub recalls: usize,
+
+	/// Number of times documents were ingested into the memory (a batch of documents counts once)
+	pub ingests: usize,
+
+	/// Number of chunks stored in the memory by ingests
+	pub chunks_stored: usize,
+}
+
+/// Usage of a model, aggregated over all tasks and memories that use it
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ModelStats {
+	/// Number of completions generated with the model
+	pub completions: usize,
+
+	/// Number of tokens fed to the model as prompt for completions
+	pub prompt_tokens: usize,
+
+	/// Number of tokens generated by the model
+	pub predict_tokens: usize,
+
+	/// Number of embeddings calculated with the model (including those used for recall and ingest)
+	pub embeddings: usize,
+}
+
+impl ModelStats {
+	pub fn add_completion(&mut self, stats: &InferenceStats) {
+		self.completions += 1;
+		self.prompt_tokens += stats.prompt_tokens;
+		self.predict_tokens += stats.predict_tokens;
+	}
+}
+
 #[derive(Serialize, Debug, Clone)]
 pub struct TaskStats {
 	/// Number of completion cycles (`Backend::completion`) that were completed for this model
@@ -30,6 +91,12 @@ pub struct TaskStats {
 	prompt_duration: Duration,
 	prompt_duration_threads: Duration,
 	prompt_tokens: usize,
+
+	/// Distribution of the total duration (prompt feeding and prediction) of completions in milliseconds
+	request_duration_ms: Histogram,
+
+	/// Distribution of the prediction speed of completions in tokens per second
+	predict_tokens_per_second: Histogram,
 }
 
 impl Default for TaskStats {
@@ -44,6 +111,9 @@ impl Default for TaskStats {
 			prompt_duration: Duration::ZERO,
 			prompt_duration_threads: Duration::ZERO,
 			prompt_tokens: 0,
+
+			request_duration_ms: Histogram::default(),
+			predict_tokens_per_second: Histogram::default(),
 		}
 	}
 }
@@ -58,5 +128,141 @@ impl TaskStats {
 		self.predict_duration += stats.predict_duration;
 		self.predict_duration_threads += stats.predict_duration * (n_threads as u32);
 		self.cycles += 1;
+
+		self.request_duration_ms
+			.record((stats.feed_prompt_duration + stats.predict_duration).as_secs_f64() * 1000.0);
+		if stats.predict_tokens > 0 && !stats.predict_duration.is_zero() {
+			self.predict_tokens_per_second
+				.record(stats.predict_tokens as f64 / stats.predict_duration.as_secs_f64());
+		}
+	}
+}
+
+/// Smallest value that can be distinguished by a [Histogram]
+const HISTOGRAM_MIN_VALUE: f64 = 0.001;
+
+/// Factor by which the size of each subsequent bucket of a [Histogram] grows (determines the relative error)
+const HISTOGRAM_BUCKET_GROWTH: f64 = 1.05;
+
+/// Maximum number of buckets in a [Histogram] (larger values are counted in the last bucket)
+const HISTOGRAM_MAX_BUCKETS: usize = 800;
+
+/// Histogram of positive values using logarithmically sized buckets, so that percentiles can be estimated with a bounded
+/// relative error using a bounded amount of memory. Serializes as a summary (count and percentiles).
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+	buckets: Vec<u64>,
+	count: u64,
+}
+
+impl Histogram {
+	fn bucket_index(value: f64) -> usize {
+		if value <= HISTOGRAM_MIN_VALUE {
+			0
+		} else {
+			let index = ((value / HISTOGRAM_MIN_VALUE).ln() / HISTOGRAM_BUCKET_GROWTH.ln()).ceil() as usize;
+			index.min(HISTOGRAM_MAX_BUCKETS - 1)
+		}
+	}
+
+	/// Upper bound of the values counted in the bucket at the indicated index
+	fn bucket_value(index: usize) -> f64 {
+		HISTOGRAM_MIN_VALUE * HISTOGRAM_BUCKET_GROWTH.powi(index as i32)
+	}
+
+	pub fn record(&mut self, value: f64) {
+		let index = Self::bucket_index(value);
+		if self.buckets.len() <= index {
+			self.buckets.resize(index + 1, 0);
+		}
+		self.buckets[index] += 1;
+		self.count += 1;
+	}
+
+	/// Estimate the value below which the indicated fraction (0...1) of the recorded values falls
+	pub fn percentile(&self, fraction: f64) -> Option<f64> {
+		if self.count == 0 {
+			return None;
+		}
+
+		let target = ((fraction * self.count as f64).ceil() as u64).max(1);
+		let mut cumulative = 0;
+		for (index, bucket_count) in self.buckets.iter().enumerate() {
+			cumulative += bucket_count;
+			if cumulative >= target {
+				return Some(Self::bucket_value(index));
+			}
+		}
+		Some(Self::bucket_value(self.buckets.len() - 1))
+	}
+}
+
+#[derive(Serialize)]
+struct HistogramSummary {
+	count: u64,
+	p50: Option<f64>,
+	p90: Option<f64>,
+	p99: Option<f64>,
+}
+
+impl Serialize for Histogram {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		HistogramSummary {
+			count: self.count,
+			p50: self.percentile(0.5),
+			p90: self.percentile(0.9),
+			p99: self.percentile(0.99),
+		}
+		.serialize(serializer)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use llm::InferenceStats;
+
+	use super::{Histogram, InferenceStatsAdd, Usage};
+
+	#[test]
+	pub fn test_histogram() {
+		let mut h = Histogram::default();
+		assert_eq!(h.percentile(0.5), None);
+
+		for value in 1..=100 {
+			h.record(value as f64);
+		}
+
+		// Estimates have a relative error of at most 5%
+		let close = |estimate: Option<f64>, expected: f64| (estimate.unwrap() - expected).abs() / expected <= 0.05;
+		assert!(close(h.percentile(0.5), 50.0));
+		assert!(close(h.percentile(0.9), 90.0));
+		assert!(close(h.percentile(0.99), 99.0));
+		assert!(close(h.percentile(1.0), 100.0));
+	}
+
+	#[test]
+	pub fn test_usage() {
+		let mut stats = InferenceStats::default();
+		assert_eq!(Usage::from(&stats), Usage::default());
+
+		stats.add(&InferenceStats {
+			feed_prompt_duration: Duration::from_millis(10),
+			prompt_tokens: 12,
+			predict_duration: Duration::ZERO,
+			predict_tokens: 0,
+		});
+		stats.add(&InferenceStats {
+			feed_prompt_duration: Duration::ZERO,
+			prompt_tokens: 0,
+			predict_duration: Duration::from_millis(20),
+			predict_tokens: 5,
+		});
+
+		let usage = Usage::from(&stats);
+		assert_eq!(usage.prompt_tokens, 12);
+		assert_eq!(usage.completion_tokens, 5);
+		assert_eq!(usage.total_tokens, usage.prompt_tokens + usage.completion_tokens);
 	}
 }
@@ -1,17 +1,100 @@
-use llm::{InferenceError, InferenceParameters, TokenId, TokenizationError};
+use llm::{InferenceError, InferenceParameters, LoadError, TokenId, TokenizationError};
+use poly_bias::json::SchemaError;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
+use serde_json::Value;
+use std::{
+	collections::HashMap,
+	path::PathBuf,
+	sync::{Arc, Mutex},
+};
 use thiserror::Error;
 
-use crate::{config::TaskConfig, memory::MemoryError};
+use crate::{config::TaskConfig, memory::MemoryError, stats::Usage};
 
 #[derive(Deserialize, Clone, Debug, Default)]
 #[serde(default)]
-pub struct SessionRequest {}
+pub struct SessionRequest {
+	/// Bias to add to the logits of specific tokens during generation (token text to bias, like OpenAI's `logit_bias`).
+	/// Each key must tokenize to exactly one token.
+	pub logit_bias: Option<HashMap<String, f32>>,
+
+	/// Whether to collect diagnostic information on biased generation (see [BiasStep])
+	pub debug: bool,
+
+	/// Override for the presence penalty configured for the task (only applies to tasks using the standard sampler)
+	pub presence_penalty: Option<f32>,
+
+	/// Override for the frequency penalty configured for the task (only applies to tasks using the standard sampler)
+	pub frequency_penalty: Option<f32>,
+
+	/// Override for the minimum number of tokens to generate configured for the task
+	pub min_tokens: Option<usize>,
+
+	/// Maximum number of tokens to generate. When the task configures a maximum, the lower of both applies.
+	pub max_tokens: Option<usize>,
+
+	/// Override for the temperature configured for the task (only applies to tasks using the standard sampler)
+	pub temperature: Option<f32>,
+
+	/// Override for the top K configured for the task (only applies to tasks using the standard sampler)
+	pub top_k: Option<usize>,
+
+	/// Override for the top P configured for the task (only applies to tasks using the standard sampler, must be between 0
+	/// and 1)
+	pub top_p: Option<f32>,
+
+	/// Override for the repeat penalty configured for the task (only applies to tasks using the standard sampler)
+	pub repeat_penalty: Option<f32>,
+
+	/// Number of inference threads to use (capped at `threads_per_session` of the model), e.g. to use fewer threads for
+	/// low-priority work
+	pub n_threads: Option<usize>,
+
+	/// Whether to play back the user prompt (as prompt tokens) before the generated tokens
+	pub echo: bool,
+
+	/// Identifier chosen by the client for this request, that can be used to cancel generation while it is running
+	pub request_id: Option<String>,
+
+	/// Number of independent completions to generate for the prompt. When set, the completions are returned as a list
+	/// of choices (see [CompletionsResponse]). Each completion is generated in a fresh session (with its own biaser and
+	/// sampling randomness), except when the response can be served from the response cache.
+	pub n: Option<usize>,
+}
+
+impl SessionRequest {
+	/// Check that the sampler overrides in the request are within range
+	pub fn validate(&self) -> Result<(), BackendError> {
+		let check = |name: &str, value: Option<f32>, valid: &dyn Fn(f32) -> bool, requirement: &str| match value {
+			Some(value) if value.is_nan() || !valid(value) => Err(BackendError::InvalidParameter(format!("{name} {requirement} (got {value})"))),
+			_ => Ok(()),
+		};
+		check(
+			"temperature",
+			self.temperature,
+			&|v| v >= 0.0 && v.is_finite(),
+			"must be zero or positive",
+		)?;
+		check("top_p", self.top_p, &|v| (0.0..=1.0).contains(&v), "must be between 0 and 1")?;
+		check(
+			"repeat_penalty",
+			self.repeat_penalty,
+			&|v| v >= 0.0 && v.is_finite(),
+			"must be zero or positive",
+		)?;
+		check("presence_penalty", self.presence_penalty, &|v| v.is_finite(), "must be a finite number")?;
+		check("frequency_penalty", self.frequency_penalty, &|v| v.is_finite(), "must be a finite number")?;
+		Ok(())
+	}
+}
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct PromptRequest {
 	pub prompt: String,
+
+	/// Text that is fed after the prompt (and postfix) as the start of the response, so the model continues from it
+	#[serde(default)]
+	pub assistant_prefix: Option<String>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -23,11 +106,73 @@ pub struct SessionAndPromptRequest {
 	pub prompt: PromptRequest,
 }
 
+/// Progress of ingesting a document into memory
+#[derive(Serialize, Clone, Debug)]
+pub struct IngestProgress {
+	/// Number of chunks that have been embedded so far
+	pub chunks_embedded: usize,
+
+	/// Total number of chunks the document was split into
+	pub chunks_total: usize,
+}
+
+/// Summary of ingesting one or more documents into memory
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct MemorizeSummary {
+	/// Number of chunks the document(s) were split into
+	pub chunks: usize,
+
+	/// Number of chunks that were embedded and stored
+	pub stored: usize,
+
+	/// Number of chunks that were skipped because they were empty after applying the post-filter
+	pub skipped: usize,
+}
+
+/// An item recalled from memory
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RecalledChunk {
+	pub text: String,
+
+	/// Similarity of the item to the prompt (higher is more relevant; the scale depends on the metric of the memory)
+	pub score: f32,
+}
+
 #[derive(Serialize, Clone, Debug, Default)]
 pub struct EmbeddingResponse {
 	pub embedding: Vec<f32>,
 }
 
+#[derive(Deserialize, Clone, Debug)]
+pub struct ScoreRequest {
+	/// Text to score
+	pub prompt: String,
+
+	/// Whether to include the log-likelihood of each individual token in the response
+	#[serde(default)]
+	pub per_token: bool,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ScoreResponse {
+	/// Total log-likelihood of the text (natural logarithm), excluding the first token (which has no context)
+	pub logprob: f64,
+
+	/// Perplexity of the text (not available when there are less than two tokens)
+	pub perplexity: Option<f64>,
+
+	/// Log-likelihood for each token (only when requested)
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub tokens: Option<Vec<TokenScore>>,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct TokenScore {
+	pub text: String,
+	pub token: TokenId,
+	pub logprob: f64,
+}
+
 #[derive(Serialize, Clone, Debug, Default)]
 pub struct TokenizationResponse {
 	pub tokens: Vec<TokenResponse>,
@@ -39,6 +184,16 @@ pub struct TokenResponse {
 	pub token: TokenId,
 }
 
+#[derive(Deserialize, Clone, Debug)]
+pub struct DetokenizationRequest {
+	pub tokens: Vec<TokenId>,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct DetokenizationResponse {
+	pub text: String,
+}
+
 impl From<TaskConfig> for InferenceParameters {
 	fn from(val: TaskConfig) -> Self {
 		InferenceParameters {
@@ -52,19 +207,166 @@ pub struct ModelsResponse {
 	pub models: Vec<String>,
 }
 
+#[derive(Serialize)]
+pub struct ModelFileResponse {
+	/// Size of the stored model file in bytes
+	pub size: u64,
+}
+
 #[derive(Serialize)]
 pub struct TasksResponse {
 	pub tasks: Vec<String>,
 }
 
+#[derive(Serialize)]
+pub struct AbortResponse {
+	/// Number of sessions that were signalled to stop generating
+	pub aborted: usize,
+}
+
+/// Response to the creation of a session that is kept between requests
+#[derive(Serialize, Debug, Clone)]
+pub struct CreateSessionResponse {
+	/// Identifier to use when continuing the session
+	pub id: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SessionInfo {
+	pub id: u64,
+	pub task: String,
+
+	/// Time at which the session was started (seconds since the Unix epoch)
+	pub started_at: u64,
+
+	/// Time since the session was started, in milliseconds
+	pub duration_ms: u128,
+
+	/// Number of tokens generated by the session so far
+	pub tokens_generated: usize,
+
+	/// User on whose behalf the session was started (if known)
+	pub sub: Option<String>,
+
+	/// Identifier of the request as chosen by the client (if any)
+	pub request_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SessionsResponse {
+	pub sessions: Vec<SessionInfo>,
+}
+
 #[derive(Serialize)]
 pub struct MemoriesResponse {
 	pub memories: Vec<String>,
+
+	/// Number of items stored in each memory (`None` when the memory could not be queried)
+	pub counts: HashMap<String, Option<usize>>,
+}
+
+/// The reason generation of a completion ended
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+	/// The model generated the end-of-text token (or the biaser did not allow any further tokens)
+	EndOfText,
+
+	/// A stop sequence was encountered
+	StopSequence,
+
+	/// The braces in the output were balanced (see [crate::config::TaskConfig::stop_on_balanced_braces])
+	BalancedBraces,
+
+	/// The maximum number of tokens was generated
+	MaxTokens,
+
+	/// The context of the model is full
+	ContextFull,
+
+	/// Generation was halted by the receiver of the tokens (e.g. because a client disconnected)
+	Halted,
+
+	/// Generation was aborted (e.g. by an operator or the client, see [crate::backend::Backend::abort_task])
+	Aborted,
+
+	/// An error occurred during inference
+	Error,
+}
+
+/// Warning on the quality of a generated completion
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionWarning {
+	/// The model assigned a low probability to all tokens allowed by the biaser for several consecutive tokens, starting at
+	/// the indicated (generated) token index
+	LowConfidence { token_index: usize, min_probability: f32 },
+}
+
+/// A call to a tool generated by a task that is configured with tools (see [crate::config::BiaserConfig::Tools])
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ToolCall {
+	/// Name of the selected tool
+	pub tool: String,
+
+	/// Arguments for the tool
+	pub arguments: Value,
+}
+
+impl ToolCall {
+	/// Parses the output of a task configured with tools. Returns `None` when the output is not a (complete) tool call,
+	/// e.g. because generation was stopped early.
+	pub fn parse(output: &str) -> Option<ToolCall> {
+		serde_json::from_str(output.trim()).ok()
+	}
+}
+
+#[derive(Serialize)]
+pub struct CompletionsResponse {
+	pub choices: Vec<GenerateResponse>,
 }
 
 #[derive(Serialize)]
 pub struct GenerateResponse {
 	pub text: String,
+
+	/// Why generation ended
+	pub finish_reason: FinishReason,
+
+	/// Warnings on the quality of the generated output
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub warnings: Vec<CompletionWarning>,
+
+	/// The parsed tool call (only for tasks that are configured with tools)
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub tool_call: Option<ToolCall>,
+
+	/// The parsed value of the output (only for tasks that are configured with a biaser)
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub value: Option<serde_json::Value>,
+
+	/// Number of tokens processed for this completion
+	pub usage: Usage,
+
+	/// Per-step biaser information (only when debug mode was requested)
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub debug: Option<Vec<BiasStep>>,
+}
+
+/// Diagnostic information on a single generation step
+#[derive(Serialize, Clone, Debug)]
+pub struct BiasStep {
+	/// Number of tokens the biaser allowed at this step (zero when generation is not biased)
+	pub allowed_tokens: usize,
+
+	/// Whether the end-of-text token was among the allowed tokens
+	pub eot_allowed: bool,
+
+	/// The token that was generated in this step
+	pub token: TokenId,
+
+	/// Text of the generated token
+	pub text: String,
 }
 
 #[derive(Serialize)]
@@ -73,6 +375,17 @@ pub enum Status {
 	Ok,
 }
 
+/// Changes made by reloading the configuration (see [crate::backend::Backend::reload])
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ReloadResponse {
+	pub added_models: Vec<String>,
+	pub removed_models: Vec<String>,
+	pub added_memories: Vec<String>,
+	pub removed_memories: Vec<String>,
+	pub added_tasks: Vec<String>,
+	pub removed_tasks: Vec<String>,
+}
+
 #[derive(Serialize)]
 pub struct StatusResponse {
 	pub status: Status,
@@ -83,6 +396,12 @@ pub enum BackendError {
 	#[error("task not found: {0}")]
 	TaskNotFound(String),
 
+	#[error("task does not have an output schema: {0}")]
+	TaskSchemaNotFound(String),
+
+	#[error("session not found: {0}")]
+	SessionNotFound(String),
+
 	#[error("model not found: {0}")]
 	ModelNotFound(String),
 
@@ -96,17 +415,76 @@ pub enum BackendError {
 	#[error("illegal token encountered")]
 	IllegalToken,
 
+	#[error("an assistant prefix cannot be used with task '{0}' because it constrains output to a schema")]
+	AssistantPrefixUnsupported(String),
+
+	#[error("biaser does not allow any further tokens, but output is incomplete (output so far: {0})")]
+	BiaserStuck(String),
+
 	#[error("memory error: {0}")]
 	Memory(#[from] MemoryError),
 
 	#[error("memory not found: {0}")]
 	MemoryNotFound(String),
 
+	#[error("memory is read-only: {0}")]
+	MemoryReadOnly(String),
+
 	#[error("invalid document supplied")]
 	InvalidDocument,
 
-	#[error("chunk separator '{0}' invalid: must consist of exactly one token")]
+	#[error("offset and limit of the requested items are out of range")]
+	InvalidItemsRange,
+
+	#[error("invalid parameter: {0}")]
+	InvalidParameter(String),
+
+	#[error("number of completions must be between 1 and {0}")]
+	InvalidCompletionCount(usize),
+
+	#[error("chunk separator or post-filter '{0}' invalid: separators must not be empty, post-filters must consist of exactly one token")]
 	InvalidChunkSeparator(String),
+
+	#[error("model does not produce embeddings: {0}")]
+	EmbeddingUnsupported(String),
+
+	#[error("logit bias token '{0}' invalid: must consist of exactly one token")]
+	InvalidLogitBiasToken(String),
+
+	#[error("token {0} is not in the vocabulary of the model")]
+	InvalidTokenId(TokenId),
+
+	#[error("model file too large: exceeds limit of {0} bytes")]
+	ModelFileTooLarge(u64),
+
+	#[error("model file checksum mismatch: expected {expected}, got {actual}")]
+	ModelFileChecksumMismatch { expected: String, actual: String },
+
+	#[error("model file upload failed: {0}")]
+	ModelFileUpload(String),
+
+	#[error("could not download model {0}: {1}")]
+	ModelDownload(String, String),
+
+	#[error("model file for model {0} not found at {1:?}")]
+	ModelFileNotFound(String, PathBuf),
+
+	#[error("invalid configuration: {0}")]
+	InvalidConfig(String),
+
+	#[error("JSON schema file {0:?} could not be read: {1}")]
+	SchemaFile(PathBuf, String),
+
+	#[error("JSON schema file {0:?} is not a supported schema: {1}")]
+	UnsupportedSchema(PathBuf, #[source] SchemaError),
+
+	#[error("could not load model {name} from {path:?}: {source}")]
+	ModelLoad {
+		name: String,
+		path: PathBuf,
+		#[source]
+		source: LoadError,
+	},
 }
 
 impl From<InferenceError> for BackendError {
@@ -114,3 +492,27 @@ impl From<InferenceError> for BackendError {
 		BackendError::InferenceError(e.to_string())
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use serde_json::json;
+
+	use super::ToolCall;
+
+	#[test]
+	fn test_parse_tool_call() {
+		let call = ToolCall::parse(r#"{"tool": "weather", "arguments": {"city": "Amsterdam"}}"#).unwrap();
+		assert_eq!(call.tool, "weather");
+		assert_eq!(call.arguments, json!({"city": "Amsterdam"}));
+
+		// Surrounding whitespace is allowed
+		let call = ToolCall::parse(" {\"tool\": \"time\", \"arguments\": {}}\n").unwrap();
+		assert_eq!(call.tool, "time");
+		assert_eq!(call.arguments, json!({}));
+
+		// Incomplete output (e.g. when generation was stopped at the token limit)
+		assert!(ToolCall::parse(r#"{"tool": "weather", "arguments": {"city": "Ams"#).is_none());
+		assert!(ToolCall::parse(r#"{"tool": "weather"}"#).is_none());
+		assert!(ToolCall::parse("").is_none());
+	}
+}
@@ -1,15 +1,23 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::sync::Arc;
 
 use llm::TokenizationError;
 use llm::{TokenId, Tokenizer};
+use regex::Regex;
+use regex_automata::dfa::{dense, Automaton, StartKind};
+use regex_automata::util::{primitives::StateID, start};
+use regex_automata::{Anchored, MatchKind};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_json::{json, Map};
 use thiserror::Error;
 
-use crate::{Biaser, TOKEN_ALLOWED};
+use crate::{vocabulary::StringTokens, Biaser, TOKEN_ALLOWED};
+
+mod standard;
+pub use standard::SchemaError;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -19,44 +27,145 @@ pub enum JsonSchema {
 	Object {
 		required: Vec<String>,
 		properties: HashMap<String, Box<JsonSchema>>,
+
+		/// Keys of properties that may be left out
+		#[serde(default)]
+		optional: Vec<String>,
+
+		/// Schema for the values of keys that are not in `properties` (when not set, such keys are not allowed)
+		#[serde(default)]
+		additional_properties: Option<Box<JsonSchema>>,
 	},
 	Number {
 		min: Option<f64>,
 		max: Option<f64>,
 		max_decimals: Option<usize>,
 	},
+	/// A whole number (never emitted with a decimal point)
+	Integer {
+		min: Option<i64>,
+		max: Option<i64>,
+	},
 	Array {
 		items: Box<JsonSchema>,
 		min_items: Option<usize>,
 		max_items: Option<usize>,
+
+		/// When true, all items of the array must be different
+		#[serde(default)]
+		unique_items: Option<bool>,
+	},
+	/// An array with a fixed number of items (at least one), each with its own schema (JSON Schema's `prefixItems`)
+	Tuple {
+		items: Vec<Box<JsonSchema>>,
 	},
 	String {
 		max_length: Option<usize>,
 		r#enum: Option<Vec<String>>,
+		min_length: Option<usize>,
+
+		/// Regular expression the string must match (anywhere in the string, use `^` and `$` to match the whole string)
+		pattern: Option<String>,
+	},
+	/// A fixed value (JSON Schema's `const`), which is emitted exactly as serialized
+	Const {
+		value: Value,
+	},
+	/// One of a list of fixed values (of any type), each of which is emitted exactly as serialized
+	Enum {
+		values: Vec<Value>,
+	},
+	/// A value that matches exactly one of the alternative schemas
+	OneOf {
+		alternatives: Vec<JsonSchema>,
+	},
+	/// A value that matches at least one of the alternative schemas
+	AnyOf {
+		alternatives: Vec<JsonSchema>,
+	},
+	/// A reference to a named schema, which is looked up in the definitions the biaser is created with (see
+	/// [JsonBiaser::with_definitions]). Definitions may refer to themselves to describe recursive structures.
+	Ref {
+		name: String,
 	},
 }
 
 impl JsonSchema {
+	/// Checks the schema for problems that would otherwise only become apparent when it is used. Currently verifies that
+	/// all string patterns compile (invalid patterns are ignored by the biaser).
+	pub fn check(&self) -> Result<(), SchemaError> {
+		match self {
+			JsonSchema::Object {
+				properties,
+				additional_properties,
+				..
+			} => {
+				for property_schema in properties.values() {
+					property_schema.check()?;
+				}
+				if let Some(additional_properties) = additional_properties {
+					additional_properties.check()?;
+				}
+				Ok(())
+			}
+			JsonSchema::Array { items, .. } => items.check(),
+			JsonSchema::Tuple { items } => items.iter().try_for_each(|item| item.check()),
+			JsonSchema::String { pattern: Some(pattern), .. } => StringPattern::new(pattern)
+				.map(|_| ())
+				.map_err(|e| SchemaError::InvalidPattern(pattern.clone(), e.to_string())),
+			JsonSchema::OneOf { alternatives } | JsonSchema::AnyOf { alternatives } => alternatives.iter().try_for_each(|a| a.check()),
+			JsonSchema::Boolean
+			| JsonSchema::Null
+			| JsonSchema::Number { .. }
+			| JsonSchema::Integer { .. }
+			| JsonSchema::String { pattern: None, .. }
+			| JsonSchema::Const { .. }
+			| JsonSchema::Enum { .. }
+			| JsonSchema::Ref { .. } => Ok(()),
+		}
+	}
+
 	pub fn is_valid(&self, value: &Value) -> bool {
+		self.is_valid_with_definitions(value, &HashMap::new())
+	}
+
+	/// Whether the value is valid according to this schema, resolving references using the definitions provided
+	pub fn is_valid_with_definitions(&self, value: &Value, definitions: &HashMap<String, JsonSchema>) -> bool {
 		match (self, value) {
 			(JsonSchema::Boolean, Value::Bool(_)) => true,
 			(JsonSchema::Null, Value::Null) => true,
-			(JsonSchema::Object { required, properties }, Value::Object(object_value)) => {
+			(
+				JsonSchema::Object {
+					required,
+					properties,
+					additional_properties,
+					..
+				},
+				Value::Object(object_value),
+			) => {
 				// All required keys must be present
 				if !required.iter().all(|field| object_value.contains_key(field)) {
 					false
 				} else {
 					// All keys that are in the object must conform to their schemas
 					object_value.iter().all(|(field, field_value)| {
-						let Some(field_schema) = properties.get(field) else {
+						let Some(field_schema) = properties.get(field).or(additional_properties.as_ref()) else {
 							return false; // No schema for this field
 						};
 
-						field_schema.is_valid(field_value)
+						field_schema.is_valid_with_definitions(field_value, definitions)
 					})
 				}
 			}
-			(JsonSchema::Array { items, min_items, max_items }, Value::Array(array_items)) => {
+			(
+				JsonSchema::Array {
+					items,
+					min_items,
+					max_items,
+					unique_items,
+				},
+				Value::Array(array_items),
+			) => {
 				if let Some(min_items) = min_items {
 					if *min_items > array_items.len() {
 						return false;
@@ -68,7 +177,17 @@ impl JsonSchema {
 						return false;
 					}
 				}
-				return array_items.iter().all(|item| items.is_valid(item));
+				if *unique_items == Some(true) && array_items.iter().enumerate().any(|(i, item)| array_items[..i].contains(item)) {
+					return false;
+				}
+				return array_items.iter().all(|item| items.is_valid_with_definitions(item, definitions));
+			}
+			(JsonSchema::Tuple { items }, Value::Array(array_items)) => {
+				items.len() == array_items.len()
+					&& items
+						.iter()
+						.zip(array_items.iter())
+						.all(|(item_schema, item)| item_schema.is_valid_with_definitions(item, definitions))
 			}
 			(JsonSchema::Number { min, max, .. }, Value::Number(v)) => {
 				if let Some(min) = min {
@@ -83,16 +202,80 @@ impl JsonSchema {
 				}
 				true
 			}
-			(JsonSchema::String { .. }, Value::String(_s)) => true,
+			(JsonSchema::Integer { min, max }, Value::Number(v)) => {
+				let Some(n) = v.as_i64().or_else(|| v.as_f64().filter(|f| f.fract() == 0.0).map(|f| f as i64)) else {
+					return false;
+				};
+				n >= min.unwrap_or(i64::MIN) && n <= max.unwrap_or(i64::MAX)
+			}
+			(JsonSchema::String { min_length, pattern, .. }, Value::String(s)) => {
+				if let Some(min_length) = min_length {
+					if s.len() < *min_length {
+						return false;
+					}
+				}
+				match pattern {
+					Some(pattern) => Regex::new(pattern).map(|re| re.is_match(s)).unwrap_or(false),
+					None => true,
+				}
+			}
+			(JsonSchema::Const { value: const_value }, v) => const_value == v,
+			(JsonSchema::Enum { values }, v) => values.contains(v),
+			(JsonSchema::OneOf { alternatives }, v) => alternatives.iter().filter(|a| a.is_valid_with_definitions(v, definitions)).count() == 1,
+			(JsonSchema::AnyOf { alternatives }, v) => alternatives.iter().any(|a| a.is_valid_with_definitions(v, definitions)),
+			(JsonSchema::Ref { .. }, v) => self
+				.resolve(definitions)
+				.is_some_and(|schema| schema.is_valid_with_definitions(v, definitions)),
 			_ => false,
 		}
 	}
+
+	/// The number of different values this schema allows, if that number is limited
+	fn distinct_values(&self) -> Option<usize> {
+		match self {
+			JsonSchema::Boolean => Some(2),
+			JsonSchema::Null | JsonSchema::Const { .. } => Some(1),
+			JsonSchema::Enum { values } => Some(values.len()),
+			JsonSchema::String {
+				r#enum: Some(string_values), ..
+			} => Some(string_values.len()),
+			_ => None,
+		}
+	}
+
+	/// The schema a reference refers to (following references to references). Returns `None` when a reference cannot be
+	/// resolved, or when references only refer to each other.
+	pub fn resolve<'a>(&'a self, definitions: &'a HashMap<String, JsonSchema>) -> Option<&'a JsonSchema> {
+		let mut schema = self;
+		for _ in 0..=definitions.len() {
+			match schema {
+				JsonSchema::Ref { name } => schema = definitions.get(name)?,
+				_ => return Some(schema),
+			}
+		}
+		None
+	}
 }
 
 #[derive(Clone)]
 struct JsonParserArrayState<'schema> {
 	items: Vec<Value>,
 	value_state: Box<JsonBiaser<'schema>>,
+
+	/// Schemas for each of the items, when parsing a tuple
+	tuple_items: Option<&'schema [Box<JsonSchema>]>,
+}
+
+impl<'schema> JsonParserArrayState<'schema> {
+	/// Whether the item currently being parsed is complete and equal to one of the earlier items
+	fn is_duplicate_item(&self) -> bool {
+		self.value_state.can_end() && self.value_state.state.value().is_some_and(|v| self.items.contains(&v))
+	}
+
+	/// Whether the item currently being parsed is the last one of a tuple
+	fn is_last_tuple_item(&self) -> bool {
+		self.tuple_items.is_some_and(|tuple_items| self.items.len() + 1 >= tuple_items.len())
+	}
 }
 
 // Temp, to hide schema in logs
@@ -115,6 +298,7 @@ enum JsonParserObjectPartState<'schema> {
 struct JsonParserObjectState<'schema> {
 	so_far: Map<String, Value>,
 	object_schema: &'schema JsonSchema,
+	definitions: Option<&'schema HashMap<String, JsonSchema>>,
 	part_state: JsonParserObjectPartState<'schema>,
 }
 
@@ -132,11 +316,24 @@ enum JsonParserState<'schema> {
 	/// Inside an integer (true = positive, false = negative)
 	InInteger(String),
 
+	/// Inside a whole number (for an integer schema)
+	InWholeNumber(String),
+
 	/// JSON value is finished, no further input acceptable
 	End(Value),
 
-	/// Inside a string
+	/// Inside a string (the decoded value so far)
 	InString(String),
+
+	/// Inside an escape sequence in a string (the decoded value so far, and the text of the escape sequence so far,
+	/// excluding the backslash)
+	InStringEscape(String, String),
+
+	/// Inside a constant value or one of the values of an enumeration (the serialized text emitted so far)
+	InConst(String),
+
+	/// Inside a union of schemas (the alternatives that are still possible given the input so far)
+	InUnion(Vec<JsonBiaser<'schema>>),
 }
 
 impl<'schema> Biaser for JsonBiaser<'schema> {
@@ -155,26 +352,12 @@ impl<'schema> Biaser for JsonBiaser<'schema> {
 						"no empty strings allowed in JSONToken::AnyOf"
 					);
 
-					let valid_tokens: Vec<TokenId> = (0..=(vocabulary.len() - 1) as TokenId)
-						.filter(|token_id| {
-							if *token_id == eot_token {
-								return false;
-							}
-							let bytes = vocabulary.token(*token_id as usize);
-							let Ok(s) = String::from_utf8(bytes) else {
-								return false;
-							};
-
-							if s.is_empty() {
-								return false;
-							}
-
-							if s.contains('\"') || s.contains('\n') || s.contains('\t') || s.contains('\r') {
-								return false;
-							}
-
-							return string_values.iter().any(|sv| sv.starts_with(&s));
-						})
+					let valid_tokens: Vec<TokenId> = self
+						.string_tokens
+						.get(vocabulary)
+						.iter()
+						.filter(|token| token.id != eot_token && string_values.iter().any(|sv| sv.starts_with(&token.text)))
+						.map(|token| token.id)
 						.collect();
 
 					tracing::debug!("any-of: total tokens: {} valid: {}", vocabulary.len(), valid_tokens.len());
@@ -183,38 +366,41 @@ impl<'schema> Biaser for JsonBiaser<'schema> {
 					valid_tokens.iter().map(|vt| (*vt, TOKEN_ALLOWED)).collect()
 				}
 
-				// Basically any token is allowed if it fits the max length. Filter them from the vocabulary
-				JsonToken::AnyString { max_length } => {
-					let mut valid_tokens: Vec<TokenId> = (0..=(vocabulary.len() - 1) as TokenId)
-						.filter(|token_id| {
-							if *token_id == eot_token {
+				// Basically any token is allowed if it fits the max length (and pattern). Filter them from the vocabulary
+				JsonToken::AnyString { max_length, pattern } => {
+					let valid_tokens: Vec<TokenId> = self
+						.string_tokens
+						.get(vocabulary)
+						.iter()
+						.filter(|token| {
+							if token.id == eot_token {
 								return false;
 							}
-							let bytes = vocabulary.token(*token_id as usize);
-							let Ok(s) = String::from_utf8(bytes) else {
-								return false;
-							};
 
 							// Reject tokens that would make the string go over the maximum length
 							if let Some(max_length) = max_length {
-								if *max_length < s.len() {
+								if *max_length < token.text.len() {
 									return false;
 								}
 							}
 
-							if s.contains('\"') || s.contains('\n') || s.contains('\t') || s.contains('\r') {
+							// Backslashes are only allowed as part of escape sequences
+							if token.text.contains('\\') {
 								return false;
 							}
-							true
+
+							// Reject tokens after which the string can no longer match the pattern
+							pattern.as_ref().map(|pattern| pattern.allows(&token.text)).unwrap_or(true)
 						})
+						.map(|token| token.id)
 						.collect();
 
-					valid_tokens.push(JsonToken::DoubleQuote.token_id(vocabulary).unwrap());
-
 					tracing::debug!("total tokens: {} valid: {}", vocabulary.len(), valid_tokens.len());
 
 					valid_tokens.iter().map(|vt| (*vt, TOKEN_ALLOWED)).collect()
 				}
+				// Not every vocabulary has a single token for each escape sequence (or for the backslash)
+				JsonToken::Backslash | JsonToken::EscapedChar(_) => json_token.token_id(vocabulary).map(|t| (t, TOKEN_ALLOWED)).into_iter().collect(),
 				json_token => {
 					vec![(
 						(*json_token).token_id(vocabulary).unwrap_or_else(|| panic!("token id for {json_token}")),
@@ -235,12 +421,36 @@ impl<'schema> Biaser for JsonBiaser<'schema> {
 		self.advance(&out_json_token).unwrap();
 		tracing::debug!("Token: {:?}, next valid tokens: {:?}", &out_json_token, self.next_valid_tokens());
 	}
+
+	fn partial_output(&self) -> Option<String> {
+		self.current_value().map(|value| value.to_string())
+	}
+
+	fn partial_value(&self) -> Option<Value> {
+		self.current_value()
+	}
+
+	fn completed_items(&self) -> Option<usize> {
+		match self.state {
+			JsonParserState::InArray(ref array_state) => Some(array_state.items.len()),
+			_ => None,
+		}
+	}
 }
 
 #[derive(Debug)]
 pub struct JsonBiaser<'schema> {
 	schema: &'schema JsonSchema,
 	state: JsonParserState<'schema>,
+
+	/// Compiled pattern (when the schema is a string schema with a pattern)
+	pattern: Option<StringPattern>,
+
+	/// Named schemas that references are resolved against
+	definitions: Option<&'schema HashMap<String, JsonSchema>>,
+
+	/// Tokens of the vocabulary that can appear in strings (see [JsonBiaser::with_string_tokens])
+	string_tokens: Arc<StringTokens>,
 }
 
 impl<'schema> Clone for JsonBiaser<'schema> {
@@ -248,14 +458,90 @@ impl<'schema> Clone for JsonBiaser<'schema> {
 		Self {
 			schema: self.schema,
 			state: JsonParserState::Start,
+			pattern: self.pattern.clone(),
+			definitions: self.definitions,
+			string_tokens: self.string_tokens.clone(),
+		}
+	}
+}
+
+/// Matcher for the `pattern` of a string schema that can tell whether a string can still be completed to a match, and
+/// that tracks the text of the string so far.
+#[derive(Clone)]
+pub struct StringPattern {
+	pattern: String,
+	dfa: Arc<dense::DFA<Vec<u32>>>,
+	state: StateID,
+}
+
+impl StringPattern {
+	pub fn new(pattern: &str) -> Result<StringPattern, Box<dense::BuildError>> {
+		// The pattern may match anywhere in the string, so allow any text before and after it. Reporting all matches
+		// prevents the automaton from stopping at the first match.
+		let dfa = dense::Builder::new()
+			.configure(dense::Config::new().match_kind(MatchKind::All).start_kind(StartKind::Anchored))
+			.build(&format!("(?s:.)*(?:{pattern})(?s:.)*"))
+			.map_err(Box::new)?;
+		let state = dfa
+			.start_state(&start::Config::new().anchored(Anchored::Yes))
+			.expect("anchored start state is available");
+		Ok(StringPattern {
+			pattern: pattern.to_string(),
+			dfa: Arc::new(dfa),
+			state,
+		})
+	}
+
+	/// The pattern after the indicated text has been appended to the string, or None when the string can no longer
+	/// match the pattern
+	pub fn advanced(&self, text: &str) -> Option<StringPattern> {
+		let mut state = self.state;
+		for byte in text.bytes() {
+			state = self.dfa.next_state(state, byte);
+			if self.dfa.is_dead_state(state) {
+				return None;
+			}
 		}
+		Some(StringPattern {
+			pattern: self.pattern.clone(),
+			dfa: self.dfa.clone(),
+			state,
+		})
+	}
+
+	/// Whether the string can still match the pattern after the indicated text is appended
+	pub fn allows(&self, text: &str) -> bool {
+		self.advanced(text).is_some()
+	}
+
+	/// Whether the string so far matches the pattern
+	pub fn is_match(&self) -> bool {
+		self.dfa.is_match_state(self.dfa.next_eoi_state(self.state))
+	}
+}
+
+impl PartialEq for StringPattern {
+	fn eq(&self, other: &Self) -> bool {
+		self.pattern == other.pattern && self.state == other.state
+	}
+}
+
+impl Eq for StringPattern {}
+
+impl std::fmt::Debug for StringPattern {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{:?}", self.pattern)
 	}
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum JsonToken {
-	AnyString { max_length: Option<usize> }, // Any string except double quote (used in next_valid_token)
-	AnyOf(Vec<String>),                      // Any string from the list (or a prefix of it)
+	AnyString {
+		max_length: Option<usize>,
+		pattern: Option<StringPattern>,
+	}, // Any string except double quote (used in next_valid_token)
+	AnyOf(Vec<String>), // Any string from the list (or a prefix of it)
+	Backslash,          // Start of an escape sequence in a string
 	BracketClose,
 	BracketOpen,
 	Colon,
@@ -265,6 +551,7 @@ pub enum JsonToken {
 	Decimal,
 	Digit(usize),
 	DoubleQuote,
+	EscapedChar(char), // A complete escape sequence in a string (e.g. `\n`), holding the character it represents
 	False,
 	Minus,
 	Null,
@@ -287,18 +574,34 @@ impl JsonToken {
 			"," => JsonToken::Comma,
 			"-" => JsonToken::Minus,
 			"\"" => JsonToken::DoubleQuote,
+			"\\" => JsonToken::Backslash,
 			s => {
 				if let Ok(n) = s.parse() {
 					JsonToken::Digit(n)
-				} else if s != "\\" {
-					JsonToken::String(s.to_string())
+				} else if let Some(c) = s.strip_prefix('\\').and_then(Self::unescape_char) {
+					JsonToken::EscapedChar(c)
 				} else {
-					return None;
+					JsonToken::String(s.to_string())
 				}
 			}
 		})
 	}
 
+	/// The character represented by a single-character escape sequence (the text after the backslash, e.g. `n`)
+	fn unescape_char(escape: &str) -> Option<char> {
+		Some(match escape {
+			"\"" => '"',
+			"\\" => '\\',
+			"/" => '/',
+			"b" => '\u{8}',
+			"f" => '\u{c}',
+			"n" => '\n',
+			"r" => '\r',
+			"t" => '\t',
+			_ => return None,
+		})
+	}
+
 	pub fn to_string(&self) -> Option<Cow<'static, str>> {
 		Some(match self {
 			JsonToken::True => Cow::from("true"),
@@ -314,6 +617,18 @@ impl JsonToken {
 			JsonToken::Decimal => Cow::from("."),
 			JsonToken::Digit(n) => Cow::from(format!("{n}")),
 			JsonToken::DoubleQuote => Cow::from("\""),
+			JsonToken::Backslash => Cow::from("\\"),
+			JsonToken::EscapedChar(c) => match c {
+				'"' => Cow::from("\\\""),
+				'\\' => Cow::from("\\\\"),
+				'/' => Cow::from("\\/"),
+				'\u{8}' => Cow::from("\\b"),
+				'\u{c}' => Cow::from("\\f"),
+				'\n' => Cow::from("\\n"),
+				'\r' => Cow::from("\\r"),
+				'\t' => Cow::from("\\t"),
+				c => Cow::from(format!("\\u{:04x}", *c as u32)),
+			},
 			JsonToken::String(s) => Cow::from(s.clone()),
 			JsonToken::AnyString { .. } | JsonToken::AnyOf(_) => return None,
 		})
@@ -325,6 +640,21 @@ impl JsonToken {
 		Self::from_text(&s).ok_or(TokenizationError::InvalidTokenId(token))
 	}
 
+	/// Whether the input token is one of the valid tokens (or matches one of the wildcard tokens among them)
+	pub fn is_accepted_by(input: &JsonToken, valid_tokens: &[JsonToken]) -> bool {
+		let input_text = input.to_string();
+		valid_tokens.iter().any(|valid_token| match (valid_token, &input_text) {
+			(JsonToken::AnyString { max_length, pattern }, Some(s)) => {
+				!s.contains('\"')
+					&& !s.contains('\\')
+					&& max_length.map(|ml| s.len() <= ml).unwrap_or(true)
+					&& pattern.as_ref().map(|pattern| pattern.allows(s)).unwrap_or(true)
+			}
+			(JsonToken::AnyOf(string_values), Some(s)) => !s.is_empty() && string_values.iter().any(|sv| sv.starts_with(s.as_ref())),
+			(valid_token, _) => valid_token == input,
+		})
+	}
+
 	pub fn token_id(&self, vocab: &Tokenizer) -> Option<TokenId> {
 		let s = self.to_string()?;
 
@@ -345,8 +675,9 @@ impl Display for JsonToken {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
 			JsonToken::AnyOf(s) => write!(f, "<any of: {}>", s.join(", ")),
-			JsonToken::AnyString { max_length } => write!(f, "<any string max_length={max_length:?}>"),
-			JsonToken::BracketClose
+			JsonToken::AnyString { max_length, pattern } => write!(f, "<any string max_length={max_length:?} pattern={pattern:?}>"),
+			JsonToken::Backslash
+			| JsonToken::BracketClose
 			| JsonToken::BracketOpen
 			| JsonToken::Comma
 			| JsonToken::Colon
@@ -355,6 +686,7 @@ impl Display for JsonToken {
 			| JsonToken::Decimal
 			| JsonToken::Digit(_)
 			| JsonToken::DoubleQuote
+			| JsonToken::EscapedChar(_)
 			| JsonToken::False
 			| JsonToken::Minus
 			| JsonToken::Null
@@ -370,9 +702,58 @@ pub enum BiaserError {
 	InvalidToken(JsonToken),
 }
 
+/// Whether the text of a whole number so far (digits, optionally preceded by a minus sign) can be completed to a number
+/// within the (inclusive) bounds
+fn is_viable_integer_prefix(so_far: &str, min: i64, max: i64) -> bool {
+	let (negative, digits) = match so_far.strip_prefix('-') {
+		Some(digits) => (true, digits),
+		None => (false, so_far),
+	};
+	if digits.is_empty() {
+		return if negative { min < 0 } else { min <= max };
+	}
+	let Ok(n) = digits.parse::<i128>() else {
+		return false;
+	};
+
+	// Each additional digit multiplies the range of numbers that can still be reached by ten
+	let max_extra_digits = if digits.starts_with('0') { 0 } else { 19 - digits.len().min(19) };
+	let mut scale: i128 = 1;
+	for _ in 0..=max_extra_digits {
+		let (low, high) = if negative {
+			(-((n + 1) * scale - 1), -(n * scale))
+		} else {
+			(n * scale, (n + 1) * scale - 1)
+		};
+		if low <= max as i128 && high >= min as i128 {
+			return true;
+		}
+		scale *= 10;
+	}
+	false
+}
+
+/// Characters that have a single-character escape sequence in JSON strings
+const SHORT_ESCAPES: [char; 8] = ['"', '\\', '/', '\u{8}', '\u{c}', '\n', '\r', '\t'];
+
+/// Whether the text is the (start of) the four hexadecimal digits of a unicode escape sequence (`\uXXXX`). Surrogates
+/// are not allowed, as they can only be used in pairs.
+fn is_unicode_escape_prefix(hex: &str) -> bool {
+	if hex.len() > 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+		return false;
+	}
+	let bytes = hex.to_ascii_lowercase().into_bytes();
+	!(bytes.len() >= 2 && bytes[0] == b'd' && matches!(bytes[1], b'8'..=b'9' | b'a'..=b'f'))
+}
+
 impl<'schema> JsonParserObjectState<'schema> {
 	pub fn advance(&mut self, input: &JsonToken) -> Result<(), BiaserError> {
-		let JsonSchema::Object { required: _, properties } = self.object_schema else {
+		let JsonSchema::Object {
+			properties,
+			additional_properties,
+			..
+		} = self.object_schema
+		else {
 			panic!("parsing a JSON object with some other schema than an object schema");
 		};
 
@@ -380,18 +761,35 @@ impl<'schema> JsonParserObjectState<'schema> {
 		let old_state = std::mem::replace(&mut self.part_state, JsonParserObjectPartState::Finished);
 
 		self.part_state = match (old_state, input) {
-			(JsonParserObjectPartState::BeforeKey, JsonToken::CurlyClose) => JsonParserObjectPartState::Finished,
+			(JsonParserObjectPartState::BeforeKey, JsonToken::CurlyClose)
+				if self.so_far.is_empty() && self.remaining_required_keys(None).is_empty() =>
+			{
+				JsonParserObjectPartState::Finished
+			}
 			(JsonParserObjectPartState::BeforeKey, JsonToken::DoubleQuote) => JsonParserObjectPartState::InKey(String::from("")),
 			(JsonParserObjectPartState::InKey(k), JsonToken::DoubleQuote) => JsonParserObjectPartState::AfterKey(k),
-			// TODO: accept other tokens (e.g. comma?) as next token
-			(JsonParserObjectPartState::InKey(k), JsonToken::String(s)) => JsonParserObjectPartState::InKey(format!("{k}{s}")),
+			(JsonParserObjectPartState::InKey(_), JsonToken::Backslash | JsonToken::EscapedChar(_)) => {
+				return Err(BiaserError::InvalidToken(input.clone()));
+			}
+			(JsonParserObjectPartState::InKey(k), t) => {
+				// Any other token is part of the key
+				let new_string = t.to_string().unwrap_or(Cow::from(""));
+				if new_string.contains('\"') {
+					return Err(BiaserError::InvalidToken(input.clone()));
+				}
+				JsonParserObjectPartState::InKey(format!("{k}{new_string}"))
+			}
 			(JsonParserObjectPartState::AfterKey(key), JsonToken::Colon) => {
-				let Some(value_schema) = properties.get(&key) else {
-					panic!("invalid key");
+				// Keys that are not part of the schema are only allowed when there is a schema for additional properties
+				let Some(value_schema) = properties.get(&key).or(additional_properties.as_ref()) else {
+					return Err(BiaserError::InvalidToken(input.clone()));
 				};
+				if self.so_far.contains_key(&key) {
+					return Err(BiaserError::InvalidToken(input.clone()));
+				}
 				JsonParserObjectPartState::InValue {
 					key,
-					value: Box::new(JsonBiaser::new(value_schema)),
+					value: Box::new(JsonBiaser::new_with_optional_definitions(value_schema, self.definitions)),
 				}
 			}
 			(JsonParserObjectPartState::InValue { key, value }, JsonToken::Comma) if value.can_end() => {
@@ -399,7 +797,7 @@ impl<'schema> JsonParserObjectState<'schema> {
 				JsonParserObjectPartState::BeforeKey
 			}
 			(JsonParserObjectPartState::InValue { key, value }, JsonToken::CurlyClose)
-				if value.can_end() && self.remaining_required_keys().len() == 1 =>
+				if value.can_end() && self.remaining_required_keys(Some(&key)).is_empty() =>
 			{
 				self.so_far.insert(key, value.state.value().unwrap());
 				JsonParserObjectPartState::Finished
@@ -414,42 +812,114 @@ impl<'schema> JsonParserObjectState<'schema> {
 		Ok(())
 	}
 
-	fn remaining_required_keys(&self) -> Vec<&'schema String> {
-		let JsonSchema::Object { required, properties: _ } = self.object_schema else {
+	/// Required keys that have not been written yet (not counting the key currently being written, if any)
+	fn remaining_required_keys(&self, current_key: Option<&str>) -> Vec<&'schema String> {
+		let JsonSchema::Object { required, .. } = self.object_schema else {
+			panic!("parsing a JSON object with some other schema than an object schema");
+		};
+
+		required
+			.iter()
+			.filter(|r| !self.so_far.contains_key(*r) && current_key != Some(r.as_str()))
+			.collect()
+	}
+
+	/// Optional keys that have not been written yet (not counting the key currently being written, if any)
+	fn remaining_optional_keys(&self, current_key: Option<&str>) -> Vec<&'schema String> {
+		let JsonSchema::Object { optional, .. } = self.object_schema else {
+			panic!("parsing a JSON object with some other schema than an object schema");
+		};
+
+		optional
+			.iter()
+			.filter(|r| !self.so_far.contains_key(*r) && current_key != Some(r.as_str()))
+			.collect()
+	}
+
+	/// Whether another key could be written after the current one
+	fn can_add_key(&self, current_key: Option<&str>) -> bool {
+		let JsonSchema::Object { additional_properties, .. } = self.object_schema else {
 			panic!("parsing a JSON object with some other schema than an object schema");
 		};
 
-		required.iter().filter(|r| !self.so_far.contains_key(*r)).collect()
+		additional_properties.is_some() || !self.remaining_optional_keys(current_key).is_empty()
 	}
 
 	pub fn next_valid_tokens(&self) -> Vec<JsonToken> {
+		let JsonSchema::Object {
+			properties,
+			additional_properties,
+			..
+		} = self.object_schema
+		else {
+			panic!("parsing a JSON object with some other schema than an object schema");
+		};
+
 		match &self.part_state {
 			JsonParserObjectPartState::Finished => vec![],
 			JsonParserObjectPartState::BeforeKey => {
-				if self.remaining_required_keys().is_empty() {
-					return vec![JsonToken::CurlyClose];
+				let required_left = !self.remaining_required_keys(None).is_empty();
+				if !self.so_far.is_empty() || required_left {
+					// After a comma, or when keys are still required, another key must follow
+					return vec![JsonToken::DoubleQuote];
+				}
+				if self.can_add_key(None) {
+					vec![JsonToken::DoubleQuote, JsonToken::CurlyClose]
+				} else {
+					vec![JsonToken::CurlyClose]
 				}
-				vec![JsonToken::DoubleQuote]
 			}
 			JsonParserObjectPartState::InKey(k) => {
-				let rk = self.remaining_required_keys();
-				let next_key = rk.first().unwrap();
-				let key_remainder = next_key.strip_prefix(k).unwrap_or("");
-				if key_remainder.is_empty() {
-					// key is finished
-					vec![JsonToken::DoubleQuote]
-				} else {
-					// waiting for a part of the next key still
-					vec![JsonToken::AnyOf(vec![key_remainder.to_string()])]
+				// Required keys are written first, in order
+				let rk = self.remaining_required_keys(None);
+				if let Some(next_key) = rk.first() {
+					let key_remainder = next_key.strip_prefix(k.as_str()).unwrap_or("");
+					return if key_remainder.is_empty() {
+						// key is finished
+						vec![JsonToken::DoubleQuote]
+					} else {
+						// waiting for a part of the next key still
+						vec![JsonToken::AnyOf(vec![key_remainder.to_string()])]
+					};
 				}
+
+				let mut valid_next = vec![];
+				let optional_remainders: Vec<String> = self
+					.remaining_optional_keys(None)
+					.iter()
+					.filter_map(|ok| ok.strip_prefix(k.as_str()))
+					.filter(|remainder| !remainder.is_empty())
+					.map(|remainder| remainder.to_string())
+					.collect();
+				if !optional_remainders.is_empty() {
+					valid_next.push(JsonToken::AnyOf(optional_remainders));
+				}
+
+				let is_optional_key = self.remaining_optional_keys(None).contains(&k);
+				let is_additional_key =
+					additional_properties.is_some() && !k.is_empty() && !properties.contains_key(k) && !self.so_far.contains_key(k);
+				if is_optional_key || is_additional_key {
+					valid_next.push(JsonToken::DoubleQuote);
+				}
+
+				if additional_properties.is_some() {
+					valid_next.push(JsonToken::AnyString {
+						max_length: None,
+						pattern: None,
+					});
+				}
+				valid_next
 			}
-			JsonParserObjectPartState::InValue { key: _, value } => {
+			JsonParserObjectPartState::InValue { key, value } => {
 				let mut valid_next = value.next_valid_tokens();
 				if value.can_end() {
-					if self.remaining_required_keys().len() == 1 {
-						valid_next.push(JsonToken::CurlyClose);
-					} else {
+					if !self.remaining_required_keys(Some(key)).is_empty() {
 						valid_next.push(JsonToken::Comma);
+					} else {
+						valid_next.push(JsonToken::CurlyClose);
+						if self.can_add_key(Some(key)) {
+							valid_next.push(JsonToken::Comma);
+						}
 					}
 				}
 				valid_next
@@ -467,7 +937,7 @@ impl<'schema> JsonParserState<'schema> {
 	pub fn value(&self) -> Option<Value> {
 		match self {
 			JsonParserState::Start => None,
-			JsonParserState::InString(s) => Some(Value::String(s.clone())),
+			JsonParserState::InString(s) | JsonParserState::InStringEscape(s, _) => Some(Value::String(s.clone())),
 			JsonParserState::InObject(object_state) => {
 				let mut object_value = object_state.so_far.clone();
 				match &object_state.part_state {
@@ -494,12 +964,51 @@ impl<'schema> JsonParserState<'schema> {
 				}
 				Some(Value::Array(items))
 			}
-			JsonParserState::InInteger(s) => Some(json! { s.parse::<f32>().unwrap() }),
+			JsonParserState::InInteger(s) => s.parse::<f64>().ok().map(|n| json! { n }),
+			JsonParserState::InWholeNumber(s) => s.parse::<i64>().ok().map(|n| json! { n }),
 			JsonParserState::End(v) => Some(v.clone()),
+			JsonParserState::InConst(s) => serde_json::from_str(s).ok(),
+			JsonParserState::InUnion(alternatives) => {
+				// Prefer an alternative that is complete
+				let alternative = alternatives.iter().find(|a| a.can_end()).or(alternatives.first())?;
+				alternative.state.value()
+			}
+		}
+	}
+
+	/// Like [JsonParserState::value], but also returns objects that are in the middle of a key or value
+	fn partial_value(&self) -> Option<Value> {
+		match self {
+			JsonParserState::InObject(object_state) => {
+				let mut object_value = object_state.so_far.clone();
+				if let JsonParserObjectPartState::InValue { key, value } = &object_state.part_state {
+					if let Some(jv) = value.current_value() {
+						object_value.insert(key.clone(), jv);
+					}
+				}
+				Some(Value::Object(object_value))
+			}
+			JsonParserState::InArray(array_state) => {
+				let mut items = array_state.items.clone();
+				if let Some(v) = array_state.value_state.current_value() {
+					items.push(v);
+				}
+				Some(Value::Array(items))
+			}
+			JsonParserState::InUnion(alternatives) => {
+				let alternative = alternatives.iter().find(|a| a.can_end()).or(alternatives.first())?;
+				alternative.current_value()
+			}
+			_ => self.value(),
 		}
 	}
 
-	pub fn advance(&mut self, input: &JsonToken, item_schema: Option<&'schema JsonSchema>) -> Result<(), BiaserError> {
+	pub fn advance(
+		&mut self,
+		input: &JsonToken,
+		item_schema: Option<&'schema JsonSchema>,
+		definitions: Option<&'schema HashMap<String, JsonSchema>>,
+	) -> Result<(), BiaserError> {
 		// Replace self with a temporary value so we can work with our owned copy
 		let old_self = std::mem::replace(self, JsonParserState::Start);
 		*self = match old_self {
@@ -510,12 +1019,24 @@ impl<'schema> JsonParserState<'schema> {
 				JsonToken::CurlyOpen => JsonParserState::InObject(JsonParserObjectState {
 					so_far: Map::new(),
 					object_schema: item_schema.unwrap(),
+					definitions,
 					part_state: JsonParserObjectPartState::BeforeKey,
 				}),
-				JsonToken::BracketOpen => JsonParserState::InArray(JsonParserArrayState {
-					items: vec![],
-					value_state: Box::new(JsonBiaser::new(item_schema.unwrap())),
-				}),
+				JsonToken::BracketOpen => {
+					// A tuple has a separate schema for each item
+					let (first_item_schema, tuple_items) = match item_schema.unwrap() {
+						JsonSchema::Tuple { items } => match items.first() {
+							Some(first_item_schema) => (first_item_schema.as_ref(), Some(items.as_slice())),
+							None => return Err(BiaserError::InvalidToken(input.clone())),
+						},
+						item_schema => (item_schema, None),
+					};
+					JsonParserState::InArray(JsonParserArrayState {
+						items: vec![],
+						value_state: Box::new(JsonBiaser::new_with_optional_definitions(first_item_schema, definitions)),
+						tuple_items,
+					})
+				}
 				JsonToken::Minus => JsonParserState::InInteger(String::from("-")),
 				JsonToken::Digit(n) => JsonParserState::InInteger(format!("{n}")),
 				JsonToken::DoubleQuote => JsonParserState::InString(String::from("")),
@@ -523,6 +1044,8 @@ impl<'schema> JsonParserState<'schema> {
 			},
 			JsonParserState::InString(s) => match input {
 				JsonToken::DoubleQuote => JsonParserState::End(json! { s }),
+				JsonToken::Backslash => JsonParserState::InStringEscape(s, String::new()),
+				JsonToken::EscapedChar(c) => JsonParserState::InString(format!("{s}{c}")),
 				JsonToken::String(new_string) => {
 					if new_string.ends_with('\"') {
 						let string_value = format!("{s}{}", new_string.strip_suffix('\"').unwrap_or(""));
@@ -539,8 +1062,33 @@ impl<'schema> JsonParserState<'schema> {
 					JsonParserState::InString(format!("{s}{new_string}"))
 				}
 			},
+			JsonParserState::InStringEscape(s, escape) => {
+				let Some(input_text) = input.to_string().filter(|_| !matches!(input, JsonToken::EscapedChar(_))) else {
+					return Err(BiaserError::InvalidToken(input.clone()));
+				};
+				let escape = format!("{escape}{input_text}");
+				if let Some(c) = JsonToken::unescape_char(&escape) {
+					JsonParserState::InString(format!("{s}{c}"))
+				} else if let Some(hex) = escape.strip_prefix('u').filter(|hex| is_unicode_escape_prefix(hex)) {
+					if hex.len() < 4 {
+						JsonParserState::InStringEscape(s, escape)
+					} else {
+						let Some(c) = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32) else {
+							return Err(BiaserError::InvalidToken(input.clone()));
+						};
+						JsonParserState::InString(format!("{s}{c}"))
+					}
+				} else {
+					return Err(BiaserError::InvalidToken(input.clone()));
+				}
+			}
 			JsonParserState::InInteger(num_string) => match input {
+				// No digits may follow a leading zero
+				JsonToken::Digit(_) if num_string.trim_start_matches('-') == "0" => return Err(BiaserError::InvalidToken(input.clone())),
 				JsonToken::Digit(n) => JsonParserState::InInteger(format!("{num_string}{n}")),
+
+				// A number has at most one decimal point, which must follow a digit
+				JsonToken::Decimal if num_string.contains('.') || num_string == "-" => return Err(BiaserError::InvalidToken(input.clone())),
 				JsonToken::Decimal => JsonParserState::InInteger(format!("{num_string}.")),
 				_ => return Err(BiaserError::InvalidToken(input.clone())),
 			},
@@ -549,14 +1097,22 @@ impl<'schema> JsonParserState<'schema> {
 				JsonParserState::InObject(object_state)
 			}
 			JsonParserState::InArray(mut array_state) => match input {
-				JsonToken::Comma if array_state.value_state.can_end() => {
+				JsonToken::Comma if array_state.value_state.can_end() && !array_state.is_last_tuple_item() => {
 					if let Some(v) = array_state.value_state.state.value() {
 						array_state.items.push(v);
 					}
-					array_state.value_state.state = JsonParserState::Start;
+					match array_state.tuple_items {
+						Some(tuple_items) => {
+							let item_schema = &tuple_items[array_state.items.len()];
+							array_state.value_state = Box::new(JsonBiaser::new_with_optional_definitions(item_schema, definitions));
+						}
+						None => array_state.value_state.state = JsonParserState::Start,
+					}
 					JsonParserState::InArray(array_state)
 				}
-				JsonToken::BracketClose if array_state.value_state.can_end() => {
+				JsonToken::BracketClose
+					if array_state.value_state.can_end() && (array_state.tuple_items.is_none() || array_state.is_last_tuple_item()) =>
+				{
 					if let Some(v) = array_state.value_state.state.value() {
 						array_state.items.push(v);
 					}
@@ -571,7 +1127,9 @@ impl<'schema> JsonParserState<'schema> {
 				}
 			},
 
-			JsonParserState::End(_) => return Err(BiaserError::InvalidToken(input.clone())),
+			JsonParserState::InConst(_) | JsonParserState::InUnion(_) | JsonParserState::InWholeNumber(_) | JsonParserState::End(_) => {
+				return Err(BiaserError::InvalidToken(input.clone()))
+			}
 		};
 		Ok(())
 	}
@@ -579,22 +1137,279 @@ impl<'schema> JsonParserState<'schema> {
 
 impl<'schema> JsonBiaser<'schema> {
 	pub fn new(schema: &'schema JsonSchema) -> JsonBiaser<'schema> {
+		Self::new_with_optional_definitions(schema, None)
+	}
+
+	/// Create a biaser for a schema that contains references (see [JsonSchema::Ref]) to the definitions provided. A
+	/// reference is only resolved when the biaser descends into it, so definitions can refer to themselves.
+	pub fn with_definitions(schema: &'schema JsonSchema, definitions: &'schema HashMap<String, JsonSchema>) -> JsonBiaser<'schema> {
+		Self::new_with_optional_definitions(schema, Some(definitions))
+	}
+
+	fn new_with_optional_definitions(schema: &'schema JsonSchema, definitions: Option<&'schema HashMap<String, JsonSchema>>) -> JsonBiaser<'schema> {
+		// Unresolvable references remain, and will not accept any input
+		let schema = match (schema, definitions) {
+			(JsonSchema::Ref { name }, Some(definitions)) => schema.resolve(definitions).unwrap_or_else(|| {
+				tracing::warn!("could not resolve reference to schema '{name}'");
+				schema
+			}),
+			(JsonSchema::Ref { name }, None) => {
+				tracing::warn!("reference to schema '{name}' cannot be resolved without definitions");
+				schema
+			}
+			_ => schema,
+		};
+
+		let pattern = match schema {
+			JsonSchema::String { pattern: Some(pattern), .. } => match StringPattern::new(pattern) {
+				Ok(pattern) => Some(pattern),
+				Err(e) => {
+					tracing::warn!("ignoring invalid string pattern '{pattern}': {e}");
+					None
+				}
+			},
+			_ => None,
+		};
+
 		JsonBiaser {
 			schema,
 			state: JsonParserState::Start,
+			pattern,
+			definitions,
+			string_tokens: Arc::default(),
 		}
 	}
 
+	/// Use a shared cache of the string tokens of the vocabulary, so that it is not scanned again for each new biaser. The
+	/// cache must belong to the vocabulary that is passed to [Biaser::bias].
+	pub fn with_string_tokens(mut self, string_tokens: Arc<StringTokens>) -> JsonBiaser<'schema> {
+		self.string_tokens = string_tokens;
+		self
+	}
+
+	/// Best-effort value accepted by the biaser so far. Unlike the final value, this includes incomplete values: objects
+	/// and arrays contain the keys and items accepted so far (including the value being generated, if it can already be
+	/// represented) and strings contain the characters accepted so far.
+	pub fn current_value(&self) -> Option<Value> {
+		self.state.partial_value()
+	}
+
 	fn child_item_schema(&self) -> Option<&'schema JsonSchema> {
 		match &self.schema {
 			JsonSchema::Array { items, .. } => Some(items.as_ref()),
-			JsonSchema::Object { .. } => Some(self.schema),
+			JsonSchema::Object { .. } | JsonSchema::Tuple { .. } => Some(self.schema),
 			_ => None,
 		}
 	}
 
 	pub fn advance(&mut self, input: &JsonToken) -> Result<(), BiaserError> {
-		self.state.advance(input, self.child_item_schema())
+		match self.schema {
+			JsonSchema::Const { .. } | JsonSchema::Enum { .. } => self.advance_const(input),
+			JsonSchema::Integer { .. } => self.advance_integer(input),
+			JsonSchema::OneOf { alternatives } | JsonSchema::AnyOf { alternatives } => self.advance_union(alternatives, input),
+			JsonSchema::Array {
+				unique_items: Some(true), ..
+			} if self.completes_duplicate_item(input) => Err(BiaserError::InvalidToken(input.clone())),
+			JsonSchema::Ref { .. } => Err(BiaserError::InvalidToken(input.clone())),
+			_ => self.state.advance(input, self.child_item_schema(), self.definitions),
+		}
+	}
+
+	/// Whether the input would end the current item of an array while it is equal to one of the earlier items
+	fn completes_duplicate_item(&self, input: &JsonToken) -> bool {
+		let JsonParserState::InArray(array_state) = &self.state else {
+			return false;
+		};
+		matches!(input, JsonToken::Comma | JsonToken::BracketClose) && array_state.is_duplicate_item()
+	}
+
+	/// Remove the tokens that would make the value equal to one of the existing values. This is only possible for values
+	/// of which the alternatives are known beforehand (constants, enums and strings with a list of allowed values).
+	fn prune_duplicate_tokens(&self, tokens: Vec<JsonToken>, existing: &[Value]) -> Vec<JsonToken> {
+		let is_existing = |s: String| existing.contains(&Value::String(s));
+		match (&self.state, self.schema) {
+			(
+				JsonParserState::Start,
+				JsonSchema::String {
+					r#enum: Some(string_values), ..
+				},
+			) => {
+				if string_values.iter().all(|sv| is_existing(sv.clone())) {
+					vec![]
+				} else {
+					tokens
+				}
+			}
+			(JsonParserState::InString(so_far), JsonSchema::String { r#enum: Some(_), .. }) => tokens
+				.into_iter()
+				.filter_map(|token| match token {
+					JsonToken::AnyOf(remainders) => {
+						let remainders: Vec<String> = remainders.into_iter().filter(|r| !is_existing(format!("{so_far}{r}"))).collect();
+						(!remainders.is_empty()).then_some(JsonToken::AnyOf(remainders))
+					}
+					JsonToken::DoubleQuote if is_existing(so_far.clone()) => None,
+					token => Some(token),
+				})
+				.collect(),
+			(JsonParserState::Start, JsonSchema::Const { .. } | JsonSchema::Enum { .. }) | (JsonParserState::InConst(_), _) => {
+				let so_far = match &self.state {
+					JsonParserState::InConst(so_far) => so_far.as_str(),
+					_ => "",
+				};
+				let values: Vec<&Value> = self.constant_values().into_iter().filter(|v| !existing.contains(v)).collect();
+				Self::next_valid_const_tokens(&values, so_far)
+			}
+			_ => tokens,
+		}
+	}
+
+	/// Advance all alternatives of a union that accept the input, and drop the ones that do not
+	fn advance_union(&mut self, alternatives: &'schema [JsonSchema], input: &JsonToken) -> Result<(), BiaserError> {
+		let remaining = match std::mem::replace(&mut self.state, JsonParserState::Start) {
+			JsonParserState::Start => alternatives
+				.iter()
+				.map(|a| JsonBiaser::new_with_optional_definitions(a, self.definitions))
+				.collect(),
+			JsonParserState::InUnion(remaining) => remaining,
+			_ => return Err(BiaserError::InvalidToken(input.clone())),
+		};
+
+		let remaining: Vec<JsonBiaser<'schema>> = remaining
+			.into_iter()
+			.filter_map(|mut alternative| {
+				if !JsonToken::is_accepted_by(input, &alternative.next_valid_tokens()) {
+					return None;
+				}
+				alternative.advance(input).ok().map(|_| alternative)
+			})
+			.collect();
+
+		let is_empty = remaining.is_empty();
+		self.state = JsonParserState::InUnion(remaining);
+		if is_empty {
+			return Err(BiaserError::InvalidToken(input.clone()));
+		}
+		Ok(())
+	}
+
+	/// Advance while emitting a whole number. Only a minus sign (at the start) and digits are accepted.
+	fn advance_integer(&mut self, input: &JsonToken) -> Result<(), BiaserError> {
+		let so_far = match &self.state {
+			JsonParserState::Start => "",
+			JsonParserState::InWholeNumber(s) => s.as_str(),
+			_ => return Err(BiaserError::InvalidToken(input.clone())),
+		};
+
+		self.state = match input {
+			JsonToken::Minus if so_far.is_empty() => JsonParserState::InWholeNumber(String::from("-")),
+			JsonToken::Digit(n) => JsonParserState::InWholeNumber(format!("{so_far}{n}")),
+			_ => return Err(BiaserError::InvalidToken(input.clone())),
+		};
+		Ok(())
+	}
+
+	/// The digits (and minus sign) that can follow the text of a whole number so far, given the bounds of the schema
+	fn next_valid_integer_tokens(so_far: &str, min: i64, max: i64) -> Vec<JsonToken> {
+		// No digits may follow a leading zero
+		if so_far.trim_start_matches('-').starts_with('0') {
+			return vec![];
+		}
+
+		// A leading zero is only allowed for zero itself (and there is no negative zero)
+		let first_digit = if so_far == "-" { 1 } else { 0 };
+		let mut tokens: Vec<JsonToken> = (first_digit..=9)
+			.filter(|d| is_viable_integer_prefix(&format!("{so_far}{d}"), min, max))
+			.map(JsonToken::Digit)
+			.collect();
+		if so_far.is_empty() && min < 0 {
+			tokens.push(JsonToken::Minus);
+		}
+		tokens
+	}
+
+	/// The values that are allowed by a const or enum schema
+	fn constant_values(&self) -> Vec<&'schema Value> {
+		match self.schema {
+			JsonSchema::Const { value } => vec![value],
+			JsonSchema::Enum { values } => values.iter().collect(),
+			_ => vec![],
+		}
+	}
+
+	/// Advance while emitting a constant value. The input is accepted as long as the text so far is a prefix of (one of)
+	/// the serialized value(s)
+	fn advance_const(&mut self, input: &JsonToken) -> Result<(), BiaserError> {
+		let so_far = match &self.state {
+			JsonParserState::Start => "",
+			JsonParserState::InConst(s) => s.as_str(),
+			_ => return Err(BiaserError::InvalidToken(input.clone())),
+		};
+		let Some(input_text) = input.to_string() else {
+			return Err(BiaserError::InvalidToken(input.clone()));
+		};
+
+		let new_so_far = format!("{so_far}{input_text}");
+		let candidates: Vec<(&Value, String)> = self
+			.constant_values()
+			.into_iter()
+			.map(|value| (value, value.to_string()))
+			.filter(|(_, text)| text.starts_with(&new_so_far))
+			.collect();
+
+		self.state = match candidates.as_slice() {
+			[] => return Err(BiaserError::InvalidToken(input.clone())),
+			[(value, text)] if *text == new_so_far => JsonParserState::End((*value).clone()),
+			_ => JsonParserState::InConst(new_so_far),
+		};
+		Ok(())
+	}
+
+	/// Whether a string schema allows the character to follow the string so far (according to its pattern)
+	fn string_allows(&self, string_so_far: &str, c: char) -> bool {
+		match self.pattern {
+			Some(ref pattern) => pattern.advanced(string_so_far).is_some_and(|pattern| pattern.allows(&c.to_string())),
+			None => true,
+		}
+	}
+
+	/// The next tokens for a union are the next tokens of each of the remaining alternatives
+	fn next_valid_union_tokens(alternatives: &[JsonBiaser]) -> Vec<JsonToken> {
+		let mut tokens: Vec<JsonToken> = vec![];
+		for token in alternatives.iter().flat_map(|a| a.next_valid_tokens()) {
+			if !tokens.contains(&token) {
+				tokens.push(token);
+			}
+		}
+		tokens
+	}
+
+	/// The next tokens for constant values of which the indicated text has already been emitted. Double quotes are
+	/// emitted as separate tokens as [JsonToken::AnyOf] does not allow them.
+	fn next_valid_const_tokens(values: &[&Value], so_far: &str) -> Vec<JsonToken> {
+		let mut prefixes: Vec<String> = vec![];
+		let mut double_quote = false;
+		for value in values {
+			let const_text = value.to_string();
+			let Some(remainder) = const_text.strip_prefix(so_far) else {
+				continue;
+			};
+			if remainder.starts_with('"') {
+				double_quote = true;
+			} else if let Some(until_quote) = remainder.split('"').next().filter(|s| !s.is_empty()) {
+				if !prefixes.iter().any(|p| p == until_quote) {
+					prefixes.push(until_quote.to_string());
+				}
+			}
+		}
+
+		let mut tokens = vec![];
+		if !prefixes.is_empty() {
+			tokens.push(JsonToken::AnyOf(prefixes));
+		}
+		if double_quote {
+			tokens.push(JsonToken::DoubleQuote);
+		}
+		tokens
 	}
 
 	pub fn can_end(&self) -> bool {
@@ -602,29 +1417,55 @@ impl<'schema> JsonBiaser<'schema> {
 			JsonParserState::Start => false,
 			JsonParserState::InObject(ref object_state) => object_state.can_end(),
 			JsonParserState::InArray(ref _array_state) => false,
-			JsonParserState::InInteger(ref s) => !s.is_empty() && s.parse::<f32>().is_ok() && !s.ends_with('.'),
+			JsonParserState::InInteger(ref s) => !s.is_empty() && s.parse::<f64>().is_ok() && !s.ends_with('.'),
+			JsonParserState::InWholeNumber(ref s) => match (self.schema, s.parse::<i64>()) {
+				(JsonSchema::Integer { min, max }, Ok(n)) => n >= min.unwrap_or(i64::MIN) && n <= max.unwrap_or(i64::MAX),
+				_ => false,
+			},
 			JsonParserState::End(_) => true,
-			JsonParserState::InString(_) => false,
+			JsonParserState::InString(_) | JsonParserState::InStringEscape(_, _) => false,
+			JsonParserState::InConst(ref so_far) => self.constant_values().iter().any(|value| value.to_string() == *so_far),
+			JsonParserState::InUnion(ref alternatives) => alternatives.iter().any(|a| a.can_end()),
 		}
 	}
 
 	pub fn next_valid_tokens(&self) -> Vec<JsonToken> {
 		match &self.state {
 			JsonParserState::End(_) => vec![],
+			JsonParserState::InWholeNumber(so_far) => {
+				let JsonSchema::Integer { min, max } = self.schema else {
+					panic!("in whole number without integer schema");
+				};
+				Self::next_valid_integer_tokens(so_far, min.unwrap_or(i64::MIN), max.unwrap_or(i64::MAX))
+			}
 			JsonParserState::InObject(object_state) => object_state.next_valid_tokens(),
+			JsonParserState::InConst(so_far) => Self::next_valid_const_tokens(&self.constant_values(), so_far),
+			JsonParserState::InUnion(alternatives) => Self::next_valid_union_tokens(alternatives),
 			JsonParserState::InString(string_so_far) => {
 				let JsonSchema::String {
 					max_length,
 					r#enum: string_values,
+					min_length,
+					..
 				} = self.schema
 				else {
 					panic!("in string without string schema");
 				};
 
-				let max_next_length = max_length.as_ref().map(|max_length| max_length - string_so_far.len());
+				// The string may only end when it is long enough and matches the pattern
+				let pattern = match self.pattern {
+					Some(ref pattern) => match pattern.advanced(string_so_far) {
+						Some(pattern) => Some(pattern),
+						None => return vec![],
+					},
+					None => None,
+				};
+				let can_close = string_so_far.len() >= min_length.unwrap_or(0) && pattern.as_ref().map(|p| p.is_match()).unwrap_or(true);
+
+				let max_next_length = max_length.as_ref().map(|max_length| max_length.saturating_sub(string_so_far.len()));
 				if max_next_length == Some(0) {
 					// Must end string now
-					return vec![JsonToken::DoubleQuote];
+					return if can_close { vec![JsonToken::DoubleQuote] } else { vec![] };
 				}
 
 				// There are pre-set string values
@@ -658,25 +1499,119 @@ impl<'schema> JsonBiaser<'schema> {
 					if !possible_remainders.is_empty() {
 						next_tokens.push(JsonToken::AnyOf(possible_remainders));
 					}
-					if has_valid {
+					if has_valid && can_close {
+						next_tokens.push(JsonToken::DoubleQuote);
+					}
+					return next_tokens;
+				}
+
+				// Any string, or an escape sequence (of a character the pattern allows)
+				let mut next_tokens = vec![];
+				if can_close {
+					next_tokens.push(JsonToken::DoubleQuote);
+				}
+				next_tokens.push(JsonToken::AnyString {
+					max_length: max_next_length,
+					pattern,
+				});
+				let escaped: Vec<JsonToken> = SHORT_ESCAPES
+					.iter()
+					.filter(|c| self.string_allows(string_so_far, **c))
+					.map(|c| JsonToken::EscapedChar(*c))
+					.collect();
+				if !escaped.is_empty() {
+					next_tokens.push(JsonToken::Backslash);
+				}
+				next_tokens.extend(escaped);
+				next_tokens
+			}
+			JsonParserState::InStringEscape(string_so_far, escape) => {
+				if escape.is_empty() {
+					// The character following the backslash determines the escape sequence
+					let mut next_tokens = vec![];
+					if self.string_allows(string_so_far, '"') {
 						next_tokens.push(JsonToken::DoubleQuote);
 					}
+					if self.string_allows(string_so_far, '\\') {
+						next_tokens.push(JsonToken::Backslash);
+					}
+					let mut letters: Vec<String> = ["/", "b", "f", "n", "r", "t"]
+						.iter()
+						.filter(|letter| {
+							JsonToken::unescape_char(letter)
+								.map(|c| self.string_allows(string_so_far, c))
+								.unwrap_or(false)
+						})
+						.map(|letter| letter.to_string())
+						.collect();
+
+					// Unicode escapes are not checked against the pattern, so only offer them when there is none
+					if self.pattern.is_none() {
+						letters.push("u".to_string());
+					}
+					if !letters.is_empty() {
+						next_tokens.push(JsonToken::AnyOf(letters));
+					}
 					return next_tokens;
 				}
 
-				// Any string
-				vec![JsonToken::DoubleQuote, JsonToken::AnyString { max_length: max_next_length }]
+				// Unicode escape sequence: one hexadecimal digit at a time
+				let hex = escape.strip_prefix('u').unwrap_or("");
+				let mut next_tokens: Vec<JsonToken> = (0..=9)
+					.filter(|d| is_unicode_escape_prefix(&format!("{hex}{d}")))
+					.map(JsonToken::Digit)
+					.collect();
+				let letters: Vec<String> = ('a'..='f')
+					.chain('A'..='F')
+					.filter(|c| is_unicode_escape_prefix(&format!("{hex}{c}")))
+					.map(|c| c.to_string())
+					.collect();
+				if !letters.is_empty() {
+					next_tokens.push(JsonToken::AnyOf(letters));
+				}
+				next_tokens
+			}
+			JsonParserState::InArray(array_state) if array_state.tuple_items.is_some() => {
+				// A tuple continues until all of its items have been written
+				let mut valid = array_state.value_state.next_valid_tokens();
+				if array_state.value_state.can_end() {
+					if array_state.is_last_tuple_item() {
+						valid.push(JsonToken::BracketClose);
+					} else {
+						valid.push(JsonToken::Comma);
+					}
+				}
+				valid
 			}
 			JsonParserState::InArray(array_state) => {
-				let JsonSchema::Array { min_items, max_items, .. } = self.schema else {
+				let JsonSchema::Array {
+					min_items,
+					max_items,
+					unique_items,
+					..
+				} = self.schema
+				else {
 					panic!();
 				};
+				let unique = *unique_items == Some(true);
 
 				let mut valid = array_state.value_state.next_valid_tokens();
+				if unique {
+					valid = array_state.value_state.prune_duplicate_tokens(valid, &array_state.items);
+				}
+
+				if array_state.value_state.can_end() && !(unique && array_state.is_duplicate_item()) {
+					// When items must be unique, another item can only follow if there are values left to choose from
+					let has_distinct_values_left = !unique
+						|| array_state
+							.value_state
+							.schema
+							.distinct_values()
+							.map(|n| array_state.items.len() + 1 < n)
+							.unwrap_or(true);
 
-				if array_state.value_state.can_end() {
 					// If the inner value can end (or must end, then valid = []), expect a comma (if we can accomodate more items)
-					if max_items.is_none() || (array_state.items.len() + 1) <= max_items.unwrap() {
+					if (max_items.is_none() || (array_state.items.len() + 1) <= max_items.unwrap()) && has_distinct_values_left {
 						valid.push(JsonToken::Comma);
 					}
 
@@ -708,8 +1643,13 @@ impl<'schema> JsonBiaser<'schema> {
 					}
 				}
 
-				// First digit cannot be zero
-				let mut digits: Vec<JsonToken> = if s == "-" {
+				// A leading zero can only be followed by a decimal point
+				if s.trim_start_matches('-') == "0" {
+					return if max_decimals > 0 { vec![JsonToken::Decimal] } else { vec![] };
+				}
+
+				// After a minus sign, zero can only be the first digit when it can be followed by decimals
+				let mut digits: Vec<JsonToken> = if s == "-" && max_decimals == 0 {
 					(1..=9).map(JsonToken::Digit).collect()
 				} else {
 					(0..=9).map(JsonToken::Digit).collect()
@@ -769,8 +1709,8 @@ impl<'schema> JsonBiaser<'schema> {
 					vec![JsonToken::DoubleQuote]
 				}
 				JsonSchema::Number { max, min, max_decimals: _ } => {
-					// First digit cannot be zero
-					let mut d: Vec<JsonToken> = (1..=9)
+					// A first digit of zero can only be followed by a decimal point
+					let mut d: Vec<JsonToken> = (0..=9)
 						.filter(|d| {
 							let df = *d as f64;
 							df <= max.unwrap_or(df) && df >= min.unwrap_or(df)
@@ -783,9 +1723,21 @@ impl<'schema> JsonBiaser<'schema> {
 					}
 					d
 				}
+				JsonSchema::Integer { min, max } => Self::next_valid_integer_tokens("", min.unwrap_or(i64::MIN), max.unwrap_or(i64::MAX)),
 				JsonSchema::Array { .. } => {
 					vec![JsonToken::BracketOpen]
 				}
+				JsonSchema::Tuple { items } if items.is_empty() => vec![],
+				JsonSchema::Tuple { .. } => vec![JsonToken::BracketOpen],
+				JsonSchema::Const { .. } | JsonSchema::Enum { .. } => Self::next_valid_const_tokens(&self.constant_values(), ""),
+				JsonSchema::OneOf { alternatives } | JsonSchema::AnyOf { alternatives } => {
+					let alternatives: Vec<JsonBiaser> = alternatives
+						.iter()
+						.map(|a| JsonBiaser::new_with_optional_definitions(a, self.definitions))
+						.collect();
+					Self::next_valid_union_tokens(&alternatives)
+				}
+				JsonSchema::Ref { .. } => vec![],
 			},
 		}
 	}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use super::JsonSchema;
+
+#[derive(Error, Debug)]
+pub enum SchemaError {
+	#[error("schema must be a JSON object")]
+	NotAnObject,
+
+	#[error("schema does not specify a type")]
+	MissingType,
+
+	#[error("unsupported type '{0}'")]
+	UnsupportedType(String),
+
+	#[error("keyword '{0}' is not supported")]
+	UnsupportedKeyword(String),
+
+	#[error("invalid value for keyword '{0}'")]
+	InvalidKeyword(String),
+
+	#[error("invalid pattern '{0}': {1}")]
+	InvalidPattern(String, String),
+}
+
+impl JsonSchema {
+	/// Convert a schema written in the standard JSON Schema vocabulary (e.g. `{"type": "object", "properties": ...}`)
+	/// into a [JsonSchema]. Supported are the keywords `type`, `properties`, `required`, `additionalProperties`,
+	/// `items`, `prefixItems`, `minItems`, `maxItems`, `uniqueItems`, `minimum`, `maximum`, `minLength`, `maxLength`,
+	/// `pattern`, `enum`, `const`, `oneOf` and `anyOf`. Other keywords (e.g. `description`) are ignored.
+	///
+	/// Unlike in standard JSON Schema, properties that are not described are not allowed in objects, unless a schema is
+	/// given for them in `additionalProperties`.
+	pub fn from_standard(value: &Value) -> Result<JsonSchema, SchemaError> {
+		let schema = value.as_object().ok_or(SchemaError::NotAnObject)?;
+
+		if schema.contains_key("$ref") {
+			return Err(SchemaError::UnsupportedKeyword(String::from("$ref")));
+		}
+
+		if let Some(value) = schema.get("const") {
+			return Ok(JsonSchema::Const { value: value.clone() });
+		}
+
+		if let Some(alternatives) = schema.get("oneOf") {
+			return Ok(JsonSchema::OneOf {
+				alternatives: schemas(alternatives, "oneOf")?,
+			});
+		}
+
+		if let Some(alternatives) = schema.get("anyOf") {
+			return Ok(JsonSchema::AnyOf {
+				alternatives: schemas(alternatives, "anyOf")?,
+			});
+		}
+
+		match schema.get("type") {
+			Some(Value::String(schema_type)) => from_standard_type(schema_type, schema),
+			Some(Value::Array(schema_types)) => {
+				// A value of any of the types
+				let alternatives = schema_types
+					.iter()
+					.map(|schema_type| match schema_type {
+						Value::String(schema_type) => from_standard_type(schema_type, schema),
+						_ => Err(SchemaError::InvalidKeyword(String::from("type"))),
+					})
+					.collect::<Result<Vec<JsonSchema>, SchemaError>>()?;
+				Ok(JsonSchema::AnyOf { alternatives })
+			}
+			Some(_) => Err(SchemaError::InvalidKeyword(String::from("type"))),
+			None => match schema.get("enum") {
+				Some(Value::Array(values)) => Ok(JsonSchema::Enum { values: values.clone() }),
+				Some(_) => Err(SchemaError::InvalidKeyword(String::from("enum"))),
+				None => Err(SchemaError::MissingType),
+			},
+		}
+	}
+}
+
+/// Convert a schema of a single type
+fn from_standard_type(schema_type: &str, schema: &Map<String, Value>) -> Result<JsonSchema, SchemaError> {
+	// Values from an enum are emitted as-is (strings are handled below, so they can be combined with other keywords)
+	if schema_type != "string" {
+		if let Some(values) = schema.get("enum") {
+			let Value::Array(values) = values else {
+				return Err(SchemaError::InvalidKeyword(String::from("enum")));
+			};
+			return Ok(JsonSchema::Enum { values: values.clone() });
+		}
+	}
+
+	Ok(match schema_type {
+		"boolean" => JsonSchema::Boolean,
+		"null" => JsonSchema::Null,
+		"number" => JsonSchema::Number {
+			min: float(schema, "minimum")?,
+			max: float(schema, "maximum")?,
+			max_decimals: None,
+		},
+		"integer" => JsonSchema::Integer {
+			min: integer(schema, "minimum")?,
+			max: integer(schema, "maximum")?,
+		},
+		"string" => JsonSchema::String {
+			max_length: size(schema, "maxLength")?,
+			r#enum: match schema.get("enum") {
+				None => None,
+				Some(Value::Array(values)) => Some(
+					values
+						.iter()
+						.map(|value| value.as_str().map(String::from))
+						.collect::<Option<Vec<String>>>()
+						.ok_or(SchemaError::InvalidKeyword(String::from("enum")))?,
+				),
+				Some(_) => return Err(SchemaError::InvalidKeyword(String::from("enum"))),
+			},
+			min_length: size(schema, "minLength")?,
+			pattern: match schema.get("pattern") {
+				None => None,
+				Some(Value::String(pattern)) => Some(pattern.clone()),
+				Some(_) => return Err(SchemaError::InvalidKeyword(String::from("pattern"))),
+			},
+		},
+		"array" => match (schema.get("prefixItems"), schema.get("items")) {
+			(Some(prefix_items), None) => JsonSchema::Tuple {
+				items: schemas(prefix_items, "prefixItems")?.into_iter().map(Box::new).collect(),
+			},
+			(Some(_), Some(_)) => return Err(SchemaError::UnsupportedKeyword(String::from("items"))),
+			(None, Some(items)) => JsonSchema::Array {
+				items: Box::new(JsonSchema::from_standard(items)?),
+				min_items: size(schema, "minItems")?,
+				max_items: size(schema, "maxItems")?,
+				unique_items: match schema.get("uniqueItems") {
+					None => None,
+					Some(Value::Bool(unique_items)) => Some(*unique_items),
+					Some(_) => return Err(SchemaError::InvalidKeyword(String::from("uniqueItems"))),
+				},
+			},
+			(None, None) => return Err(SchemaError::InvalidKeyword(String::from("items"))),
+		},
+		"object" => {
+			let properties: HashMap<String, Box<JsonSchema>> = match schema.get("properties") {
+				None => HashMap::new(),
+				Some(Value::Object(properties)) => properties
+					.iter()
+					.map(|(key, property)| Ok((key.clone(), Box::new(JsonSchema::from_standard(property)?))))
+					.collect::<Result<_, SchemaError>>()?,
+				Some(_) => return Err(SchemaError::InvalidKeyword(String::from("properties"))),
+			};
+
+			let required: Vec<String> = match schema.get("required") {
+				None => vec![],
+				Some(Value::Array(required)) => required
+					.iter()
+					.map(|key| key.as_str().filter(|key| properties.contains_key(*key)).map(String::from))
+					.collect::<Option<Vec<String>>>()
+					.ok_or(SchemaError::InvalidKeyword(String::from("required")))?,
+				Some(_) => return Err(SchemaError::InvalidKeyword(String::from("required"))),
+			};
+
+			// All properties that are not required are optional. These end up in alphabetical order rather than the order in
+			// which they are listed, as serde_json does not preserve the order of object keys (this does not affect output, as
+			// the biaser allows optional keys to be written in any order)
+			let optional: Vec<String> = match schema.get("properties") {
+				Some(Value::Object(properties)) => properties.keys().filter(|key| !required.contains(key)).cloned().collect(),
+				_ => vec![],
+			};
+
+			let additional_properties = match schema.get("additionalProperties") {
+				None | Some(Value::Bool(false)) => None,
+				Some(Value::Bool(true)) => return Err(SchemaError::UnsupportedKeyword(String::from("additionalProperties"))),
+				Some(additional_properties) => Some(Box::new(JsonSchema::from_standard(additional_properties)?)),
+			};
+
+			JsonSchema::Object {
+				required,
+				properties,
+				optional,
+				additional_properties,
+			}
+		}
+		schema_type => return Err(SchemaError::UnsupportedType(schema_type.to_string())),
+	})
+}
+
+/// Convert a list of schemas (e.g. the alternatives for `oneOf`)
+fn schemas(value: &Value, keyword: &str) -> Result<Vec<JsonSchema>, SchemaError> {
+	let Value::Array(schemas) = value else {
+		return Err(SchemaError::InvalidKeyword(keyword.to_string()));
+	};
+	schemas.iter().map(JsonSchema::from_standard).collect()
+}
+
+fn float(schema: &Map<String, Value>, keyword: &str) -> Result<Option<f64>, SchemaError> {
+	match schema.get(keyword) {
+		None => Ok(None),
+		Some(value) => value.as_f64().map(Some).ok_or(SchemaError::InvalidKeyword(keyword.to_string())),
+	}
+}
+
+fn integer(schema: &Map<String, Value>, keyword: &str) -> Result<Option<i64>, SchemaError> {
+	match schema.get(keyword) {
+		None => Ok(None),
+		Some(value) => value.as_i64().map(Some).ok_or(SchemaError::InvalidKeyword(keyword.to_string())),
+	}
+}
+
+fn size(schema: &Map<String, Value>, keyword: &str) -> Result<Option<usize>, SchemaError> {
+	match schema.get(keyword) {
+		None => Ok(None),
+		Some(value) => value
+			.as_u64()
+			.map(|size| Some(size as usize))
+			.ok_or(SchemaError::InvalidKeyword(keyword.to_string())),
+	}
+}
@@ -1,6 +1,9 @@
+use std::collections::{HashMap, HashSet};
+
 use llm::{TokenId, Tokenizer};
 
 pub mod json;
+pub mod vocabulary;
 
 /// Logit value to indicate a token is allowed to be present in the result
 pub const TOKEN_ALLOWED: f32 = 10000.0;
@@ -16,6 +19,22 @@ pub trait Biaser {
 	/// Advance the biaser by feeding it a single next token (must be one of the tokens allowed as described by the
 	/// result of a call to `bias`)
 	fn advance(&mut self, vocabulary: &Tokenizer, token: TokenId);
+
+	/// Return the output accepted by the biaser so far (if it can be represented), for diagnostic purposes
+	fn partial_output(&self) -> Option<String> {
+		None
+	}
+
+	/// Return the value accepted by the biaser so far (if it can be represented), e.g. to show progressive output
+	fn partial_value(&self) -> Option<serde_json::Value> {
+		None
+	}
+
+	/// When the biaser is generating an array, return the number of items of the array that have been completed (i.e.
+	/// that are followed by a comma)
+	fn completed_items(&self) -> Option<usize> {
+		None
+	}
 }
 
 /// A biaser that does not bias in any way
@@ -28,3 +47,129 @@ impl Biaser for NullBiaser {
 
 	fn advance(&mut self, _vocabulary: &Tokenizer, _token: TokenId) {}
 }
+
+/// A biaser that combines multiple biasers, only allowing tokens that are allowed by all of them. Tokens are fed to
+/// each of the biasers.
+pub struct AndBiaser<'a> {
+	biasers: Vec<Box<dyn Biaser + 'a>>,
+}
+
+impl<'a> AndBiaser<'a> {
+	pub fn new(biasers: Vec<Box<dyn Biaser + 'a>>) -> AndBiaser<'a> {
+		AndBiaser { biasers }
+	}
+}
+
+impl<'a> Biaser for AndBiaser<'a> {
+	fn bias(&self, vocabulary: &Tokenizer, eot_token: TokenId) -> Vec<(TokenId, f32)> {
+		intersect_biases(self.biasers.iter().map(|biaser| biaser.bias(vocabulary, eot_token)), eot_token)
+	}
+
+	fn advance(&mut self, vocabulary: &Tokenizer, token: TokenId) {
+		for biaser in self.biasers.iter_mut() {
+			biaser.advance(vocabulary, token);
+		}
+	}
+}
+
+/// Intersect sets of token biases. A set without any positive bias does not restrict which tokens are allowed;
+/// otherwise only tokens positively biased in all restricting sets remain allowed. Tokens that appear in multiple sets
+/// receive the lowest (strictest) of their biases. When no token is allowed by all restricting sets, only the
+/// end-of-text token is allowed.
+fn intersect_biases(sets: impl IntoIterator<Item = Vec<(TokenId, f32)>>, eot_token: TokenId) -> Vec<(TokenId, f32)> {
+	// Tokens allowed by all restricting sets so far (`None` when no set restricted the allowed tokens yet)
+	let mut allowed: Option<HashSet<TokenId>> = None;
+	let mut combined: HashMap<TokenId, f32> = HashMap::new();
+
+	for biases in sets {
+		let allowed_by: HashSet<TokenId> = biases.iter().filter(|(_, bias)| *bias > 0.0).map(|(token, _)| *token).collect();
+		if !allowed_by.is_empty() {
+			allowed = Some(match allowed {
+				Some(allowed) => allowed.intersection(&allowed_by).copied().collect(),
+				None => allowed_by,
+			});
+		}
+
+		for (token, bias) in biases {
+			combined.entry(token).and_modify(|b| *b = b.min(bias)).or_insert(bias);
+		}
+	}
+
+	if allowed.as_ref().is_some_and(|allowed| allowed.is_empty()) {
+		return vec![(eot_token, TOKEN_ALLOWED)];
+	}
+
+	let mut result: Vec<(TokenId, f32)> = combined
+		.into_iter()
+		.filter(|(token, bias)| *bias <= 0.0 || allowed.as_ref().map(|allowed| allowed.contains(token)).unwrap_or(true))
+		.collect();
+	result.sort_by_key(|(token, _)| *token);
+	result
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{intersect_biases, TOKEN_ALLOWED, TOKEN_FORBIDDEN};
+
+	const EOT: u32 = 0;
+
+	#[test]
+	pub fn test_intersect_biases() {
+		// No restrictions on either side
+		assert_eq!(intersect_biases([vec![], vec![]], EOT), vec![]);
+
+		// Unrestricted on one side
+		assert_eq!(
+			intersect_biases([vec![(1, TOKEN_ALLOWED), (2, TOKEN_ALLOWED)], vec![]], EOT),
+			vec![(1, TOKEN_ALLOWED), (2, TOKEN_ALLOWED)]
+		);
+		assert_eq!(
+			intersect_biases([vec![(3, TOKEN_FORBIDDEN)], vec![(1, TOKEN_ALLOWED), (3, TOKEN_ALLOWED)]], EOT),
+			vec![(1, TOKEN_ALLOWED), (3, TOKEN_FORBIDDEN)]
+		);
+
+		// Only tokens allowed on both sides remain, with the strictest bias
+		assert_eq!(
+			intersect_biases(
+				[
+					vec![(1, TOKEN_ALLOWED), (2, TOKEN_ALLOWED), (3, 1.0)],
+					vec![(2, TOKEN_ALLOWED), (3, TOKEN_ALLOWED), (4, TOKEN_ALLOWED)]
+				],
+				EOT
+			),
+			vec![(2, TOKEN_ALLOWED), (3, 1.0)]
+		);
+
+		// Nothing is allowed by both sides
+		assert_eq!(
+			intersect_biases([vec![(1, TOKEN_ALLOWED)], vec![(2, TOKEN_ALLOWED)]], EOT),
+			vec![(EOT, TOKEN_ALLOWED)]
+		);
+
+		// An empty intersection stays empty, also when a later set allows tokens again
+		assert_eq!(
+			intersect_biases(
+				[
+					vec![(1, TOKEN_ALLOWED)],
+					vec![(2, TOKEN_ALLOWED)],
+					vec![(1, TOKEN_ALLOWED), (2, TOKEN_ALLOWED), (3, TOKEN_ALLOWED)]
+				],
+				EOT
+			),
+			vec![(EOT, TOKEN_ALLOWED)]
+		);
+
+		// Unrestricted sets in between do not reset the intersection
+		assert_eq!(
+			intersect_biases(
+				[
+					vec![(1, TOKEN_ALLOWED), (2, TOKEN_ALLOWED)],
+					vec![(3, TOKEN_FORBIDDEN)],
+					vec![(2, TOKEN_ALLOWED), (3, TOKEN_ALLOWED)]
+				],
+				EOT
+			),
+			vec![(2, TOKEN_ALLOWED), (3, TOKEN_FORBIDDEN)]
+		);
+	}
+}
@@ -0,0 +1,59 @@
+use std::sync::{
+	atomic::{AtomicUsize, Ordering},
+	OnceLock,
+};
+
+use llm::{TokenId, Tokenizer};
+
+/// A token of which the text can appear in a JSON string as-is
+#[derive(Debug, Clone)]
+pub struct StringToken {
+	pub id: TokenId,
+	pub text: String,
+}
+
+/// The tokens of a single vocabulary that can appear in strings. The vocabulary is only scanned the first time the tokens
+/// are needed. Keep one cache for each tokenizer (e.g. together with the model that owns it) and share it between all
+/// biasers (also across sessions) that use that tokenizer, see [crate::json::JsonBiaser::with_string_tokens].
+#[derive(Default)]
+pub struct StringTokens {
+	tokens: OnceLock<Vec<StringToken>>,
+	scans: AtomicUsize,
+}
+
+impl std::fmt::Debug for StringTokens {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("StringTokens")
+			.field("len", &self.tokens.get().map(|tokens| tokens.len()))
+			.finish()
+	}
+}
+
+impl StringTokens {
+	/// Return the tokens of the vocabulary that decode to non-empty, valid UTF-8 text without double quotes, newlines,
+	/// tabs or carriage returns. The vocabulary must be the one this cache is kept for.
+	pub fn get(&self, vocabulary: &Tokenizer) -> &[StringToken] {
+		self.tokens.get_or_init(|| {
+			self.scans.fetch_add(1, Ordering::SeqCst);
+			scan(vocabulary)
+		})
+	}
+
+	/// The number of times the vocabulary has been scanned (i.e. the number of cache misses)
+	pub fn scans(&self) -> usize {
+		self.scans.load(Ordering::SeqCst)
+	}
+}
+
+fn scan(vocabulary: &Tokenizer) -> Vec<StringToken> {
+	tracing::debug!("scanning vocabulary of {} tokens for string tokens", vocabulary.len());
+	(0..vocabulary.len())
+		.filter_map(|index| {
+			let text = String::from_utf8(vocabulary.token(index)).ok()?;
+			if text.is_empty() || text.contains(['\"', '\n', '\t', '\r']) {
+				return None;
+			}
+			Some(StringToken { id: index as TokenId, text })
+		})
+		.collect()
+}
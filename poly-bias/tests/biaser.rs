@@ -13,7 +13,8 @@ use llm::{
 };
 
 use poly_bias::{
-	json::{BiaserError, JsonBiaser, JsonSchema, JsonToken},
+	json::{BiaserError, JsonBiaser, JsonSchema, JsonToken, SchemaError},
+	vocabulary::StringTokens,
 	Biaser,
 };
 use rand::SeedableRng;
@@ -40,6 +41,8 @@ pub fn test_string_parser() {
 	let schema = JsonSchema::String {
 		max_length: Some(10),
 		r#enum: None,
+		min_length: None,
+		pattern: None,
 	};
 	let mut bias = JsonBiaser::new(&schema);
 	assert_eq!(bias.next_valid_tokens(), vec![JsonToken::DoubleQuote]);
@@ -49,6 +52,174 @@ pub fn test_string_parser() {
 	assert_eq!(bias.next_valid_tokens(), vec![]);
 }
 
+/// Feed the tokens to a biaser for the schema, and return the text and the value accepted by the biaser
+fn parse_tokens(schema: &JsonSchema, tokens: &[JsonToken]) -> (String, Value) {
+	let mut biaser = JsonBiaser::new(schema);
+	let mut text = String::new();
+	for token in tokens {
+		assert!(
+			JsonToken::is_accepted_by(token, &biaser.next_valid_tokens()),
+			"{token:?} should be accepted"
+		);
+		biaser.advance(token).unwrap();
+		text.push_str(&token.to_string().unwrap());
+	}
+	assert!(biaser.can_end());
+	let value = serde_json::from_str(&biaser.partial_output().unwrap()).unwrap();
+	(text, value)
+}
+
+#[test]
+pub fn test_string_escape_parser() {
+	let schema = JsonSchema::String {
+		max_length: None,
+		r#enum: None,
+		min_length: None,
+		pattern: None,
+	};
+
+	// "a\"b" (escape entered as a backslash followed by the escaped character)
+	let (text, value) = parse_tokens(
+		&schema,
+		&[
+			JsonToken::DoubleQuote,
+			JsonToken::String("a".to_string()),
+			JsonToken::Backslash,
+			JsonToken::DoubleQuote,
+			JsonToken::String("b".to_string()),
+			JsonToken::DoubleQuote,
+		],
+	);
+	assert_eq!(text, "\"a\\\"b\"");
+	assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), serde_json::json!("a\"b"));
+	assert_eq!(value, serde_json::json!("a\"b"));
+
+	// "line\nbreak" (escape entered as a single token)
+	assert_eq!(JsonToken::from_text("\\n"), Some(JsonToken::EscapedChar('\n')));
+	let (text, value) = parse_tokens(
+		&schema,
+		&[
+			JsonToken::DoubleQuote,
+			JsonToken::String("line".to_string()),
+			JsonToken::EscapedChar('\n'),
+			JsonToken::String("break".to_string()),
+			JsonToken::DoubleQuote,
+		],
+	);
+	assert_eq!(text, "\"line\\nbreak\"");
+	assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), serde_json::json!("line\nbreak"));
+	assert_eq!(value, serde_json::json!("line\nbreak"));
+
+	// "caf\u00e9"
+	let (text, value) = parse_tokens(
+		&schema,
+		&[
+			JsonToken::DoubleQuote,
+			JsonToken::String("caf".to_string()),
+			JsonToken::Backslash,
+			JsonToken::String("u".to_string()),
+			JsonToken::Digit(0),
+			JsonToken::Digit(0),
+			JsonToken::String("e".to_string()),
+			JsonToken::Digit(9),
+			JsonToken::DoubleQuote,
+		],
+	);
+	assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), serde_json::json!("café"));
+	assert_eq!(value, serde_json::json!("café"));
+
+	// Unfinished and invalid escape sequences
+	let mut biaser = JsonBiaser::new(&schema);
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	biaser.advance(&JsonToken::Backslash).unwrap();
+	assert!(!biaser.can_end());
+	assert!(!biaser.next_valid_tokens().contains(&JsonToken::Digit(1)));
+	assert!(biaser.advance(&JsonToken::String("x".to_string())).is_err());
+
+	// Surrogates cannot be entered, as they are only valid in pairs
+	let mut biaser = JsonBiaser::new(&schema);
+	for token in [JsonToken::DoubleQuote, JsonToken::Backslash, JsonToken::String("ud".to_string())] {
+		biaser.advance(&token).unwrap();
+	}
+	assert!(!biaser.next_valid_tokens().contains(&JsonToken::Digit(8)));
+	assert!(biaser.next_valid_tokens().contains(&JsonToken::Digit(7)));
+}
+
+#[test]
+pub fn test_check_schema_patterns() {
+	let schema: JsonSchema = serde_json::from_value(serde_json::json!({
+		"type": "object",
+		"required": ["code"],
+		"properties": {
+			"code": { "type": "string", "pattern": "^[A-Z]{3}$" },
+			"tags": { "type": "array", "items": { "type": "one_of", "alternatives": [{ "type": "string", "pattern": "[a-z" }] } }
+		}
+	}))
+	.unwrap();
+	assert!(matches!(schema.check(), Err(SchemaError::InvalidPattern(pattern, _)) if pattern == "[a-z"));
+
+	let schema: JsonSchema = serde_json::from_value(serde_json::json!({
+		"type": "array",
+		"items": { "type": "string", "pattern": "^[A-Z]{3}$" }
+	}))
+	.unwrap();
+	assert!(schema.check().is_ok());
+}
+
+#[test]
+pub fn test_string_length_pattern_parser() {
+	let schema = JsonSchema::String {
+		max_length: None,
+		r#enum: None,
+		min_length: Some(3),
+		pattern: Some("^[a-z]+$".to_string()),
+	};
+	assert!(schema.is_valid(&serde_json::json!("abc")));
+	assert!(!schema.is_valid(&serde_json::json!("ab")));
+	assert!(!schema.is_valid(&serde_json::json!("abc1")));
+	assert!(!schema.is_valid(&serde_json::json!("ABC")));
+
+	let mut biaser = JsonBiaser::new(&schema);
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+
+	// The string cannot end before it has the minimum length, and tokens must keep it matching the pattern
+	let next_tokens = biaser.next_valid_tokens();
+	assert!(!next_tokens.contains(&JsonToken::DoubleQuote));
+	assert!(!next_tokens.contains(&JsonToken::Backslash));
+	assert!(JsonToken::is_accepted_by(&JsonToken::String("ab".to_string()), &next_tokens));
+	assert!(!JsonToken::is_accepted_by(&JsonToken::String("a1".to_string()), &next_tokens));
+	assert!(!JsonToken::is_accepted_by(&JsonToken::String("A".to_string()), &next_tokens));
+
+	biaser.advance(&JsonToken::String("ab".to_string())).unwrap();
+	assert!(!biaser.next_valid_tokens().contains(&JsonToken::DoubleQuote));
+	biaser.advance(&JsonToken::String("c".to_string())).unwrap();
+	assert!(biaser.next_valid_tokens().contains(&JsonToken::DoubleQuote));
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	assert!(biaser.can_end());
+	assert_eq!(biaser.partial_output(), Some("\"abc\"".to_string()));
+
+	// Minimum length without a pattern
+	let schema = JsonSchema::String {
+		max_length: None,
+		r#enum: None,
+		min_length: Some(3),
+		pattern: None,
+	};
+	assert!(!schema.is_valid(&serde_json::json!("ab")));
+	let (text, value) = parse_tokens(
+		&schema,
+		&[
+			JsonToken::DoubleQuote,
+			JsonToken::String("a b".to_string()),
+			JsonToken::Digit(1),
+			JsonToken::DoubleQuote,
+		],
+	);
+	assert_eq!(text, "\"a b1\"");
+	assert_eq!(value, serde_json::json!("a b1"));
+	assert!(schema.is_valid(&value));
+}
+
 #[test]
 pub fn test_string_enum_parser() {
 	setup();
@@ -56,6 +227,8 @@ pub fn test_string_enum_parser() {
 	let schema = JsonSchema::String {
 		max_length: Some(10),
 		r#enum: Some(words.clone()),
+		min_length: None,
+		pattern: None,
 	};
 	let mut bias = JsonBiaser::new(&schema);
 	assert_eq!(bias.next_valid_tokens(), vec![JsonToken::DoubleQuote]);
@@ -72,6 +245,8 @@ pub fn test_empty_object_parser() {
 	let schema = JsonSchema::Object {
 		required: vec![],
 		properties: HashMap::new(),
+		optional: vec![],
+		additional_properties: None,
 	};
 
 	let mut biaser = JsonBiaser::new(&schema);
@@ -84,6 +259,270 @@ pub fn test_empty_object_parser() {
 	assert_eq!(biaser.next_valid_tokens(), vec![]);
 }
 
+#[test]
+pub fn test_object_unknown_key() {
+	setup();
+	let schema = JsonSchema::Object {
+		required: vec![],
+		properties: HashMap::new(),
+		optional: vec![],
+		additional_properties: None,
+	};
+
+	let mut biaser = JsonBiaser::new(&schema);
+
+	// '{"foo":' should be rejected rather than panic
+	biaser.advance(&JsonToken::CurlyOpen).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	biaser.advance(&JsonToken::String(String::from("foo"))).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	assert!(matches!(
+		biaser.advance(&JsonToken::Colon),
+		Err(BiaserError::InvalidToken(JsonToken::Colon))
+	));
+}
+
+#[test]
+pub fn test_const_parser() {
+	setup();
+	let schema = JsonSchema::Const {
+		value: serde_json::json!({ "type": "circle" }),
+	};
+	assert!(schema.is_valid(&serde_json::json!({ "type": "circle" })));
+	assert!(!schema.is_valid(&serde_json::json!({ "type": "square" })));
+
+	let mut biaser = JsonBiaser::new(&schema);
+
+	// {"type":"circle"}
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::AnyOf(vec!["{".to_string()])]);
+	biaser.advance(&JsonToken::CurlyOpen).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::DoubleQuote]);
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::AnyOf(vec!["type".to_string()])]);
+	biaser.advance(&JsonToken::String("type".to_string())).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	biaser.advance(&JsonToken::Colon).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	assert!(biaser.advance(&JsonToken::String("sq".to_string())).is_err());
+	biaser.advance(&JsonToken::String("cir".to_string())).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::AnyOf(vec!["cle".to_string()])]);
+	biaser.advance(&JsonToken::String("cle".to_string())).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	assert!(!biaser.can_end());
+	biaser.advance(&JsonToken::CurlyClose).unwrap();
+	assert!(biaser.can_end());
+	assert_eq!(biaser.next_valid_tokens(), vec![]);
+}
+
+#[test]
+pub fn test_enum_parser() {
+	setup();
+	let schema = JsonSchema::Enum {
+		values: vec![
+			serde_json::json!(true),
+			serde_json::json!(null),
+			serde_json::json!(12),
+			serde_json::json!(1),
+		],
+	};
+	assert!(schema.is_valid(&serde_json::json!(null)));
+	assert!(schema.is_valid(&serde_json::json!(12)));
+	assert!(!schema.is_valid(&serde_json::json!(false)));
+
+	let mut biaser = JsonBiaser::new(&schema);
+	assert_eq!(
+		biaser.next_valid_tokens(),
+		vec![JsonToken::AnyOf(vec![
+			"true".to_string(),
+			"null".to_string(),
+			"12".to_string(),
+			"1".to_string()
+		])]
+	);
+
+	// '1' is a valid value but may also be continued to form '12'
+	biaser.advance(&JsonToken::Digit(1)).unwrap();
+	assert!(biaser.can_end());
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::AnyOf(vec!["2".to_string()])]);
+	assert!(biaser.advance(&JsonToken::Digit(3)).is_err());
+
+	let mut biaser = JsonBiaser::new(&schema);
+	biaser.advance(&JsonToken::Digit(1)).unwrap();
+	biaser.advance(&JsonToken::Digit(2)).unwrap();
+	assert!(biaser.can_end());
+	assert_eq!(biaser.next_valid_tokens(), vec![]);
+
+	let mut biaser = JsonBiaser::new(&schema);
+	biaser.advance(&JsonToken::Null).unwrap();
+	assert!(biaser.can_end());
+	assert!(biaser.advance(&JsonToken::True).is_err());
+}
+
+#[test]
+pub fn test_union_parser() {
+	setup();
+	let schema = JsonSchema::OneOf {
+		alternatives: vec![
+			JsonSchema::String {
+				max_length: None,
+				r#enum: None,
+				min_length: None,
+				pattern: None,
+			},
+			JsonSchema::Const {
+				value: serde_json::json!(true),
+			},
+			JsonSchema::Null,
+		],
+	};
+	assert!(schema.is_valid(&serde_json::json!("foo")));
+	assert!(schema.is_valid(&serde_json::json!(true)));
+	assert!(!schema.is_valid(&serde_json::json!(false)));
+
+	let mut biaser = JsonBiaser::new(&schema);
+	assert_eq!(
+		biaser.next_valid_tokens(),
+		vec![JsonToken::DoubleQuote, JsonToken::AnyOf(vec!["true".to_string()]), JsonToken::Null]
+	);
+
+	// Only the constant alternative accepts 'tr'
+	biaser.advance(&JsonToken::String("tr".to_string())).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::AnyOf(vec!["ue".to_string()])]);
+	assert!(!biaser.can_end());
+	biaser.advance(&JsonToken::String("ue".to_string())).unwrap();
+	assert!(biaser.can_end());
+
+	// No alternative accepts a number
+	let mut biaser = JsonBiaser::new(&schema);
+	assert!(biaser.advance(&JsonToken::Digit(1)).is_err());
+}
+
+#[test]
+pub fn test_boolean_number_union_parser() {
+	setup();
+	let schema = JsonSchema::OneOf {
+		alternatives: vec![
+			JsonSchema::Boolean,
+			JsonSchema::Number {
+				min: Some(-10.0),
+				max: Some(10.0),
+				max_decimals: Some(2),
+			},
+		],
+	};
+
+	// The first tokens are those of both alternatives
+	let biaser = JsonBiaser::new(&schema);
+	let first_tokens = biaser.next_valid_tokens();
+	for token in [JsonToken::True, JsonToken::False, JsonToken::Digit(1), JsonToken::Minus] {
+		assert!(first_tokens.contains(&token), "{token:?} should be allowed first");
+	}
+	assert!(!biaser.can_end());
+
+	let streams = vec![
+		vec![JsonToken::True],
+		vec![JsonToken::False],
+		vec![JsonToken::Digit(7)],
+		vec![
+			JsonToken::Minus,
+			JsonToken::Digit(3),
+			JsonToken::Decimal,
+			JsonToken::Digit(2),
+			JsonToken::Digit(5),
+		],
+	];
+	for stream in streams {
+		let mut biaser = JsonBiaser::new(&schema);
+		let mut text = String::new();
+		for token in stream.iter() {
+			biaser.advance(token).unwrap();
+			text.push_str(&token.to_string().unwrap());
+		}
+		assert!(biaser.can_end(), "{text} should be complete");
+		let value: Value = serde_json::from_str(&text).expect("valid JSON");
+		assert!(schema.is_valid(&value), "{text} should be valid");
+	}
+
+	// Once a number has started, only the number alternative remains
+	let mut biaser = JsonBiaser::new(&schema);
+	biaser.advance(&JsonToken::Digit(4)).unwrap();
+	assert!(!biaser.next_valid_tokens().contains(&JsonToken::True));
+	assert!(biaser.advance(&JsonToken::True).is_err());
+	assert!(!schema.is_valid(&serde_json::json!("true")));
+}
+
+#[test]
+pub fn test_tagged_union_parser() {
+	setup();
+	let variant = |tag: &str, field: &str| {
+		let mut properties = HashMap::new();
+		properties.insert(
+			"type".to_string(),
+			Box::new(JsonSchema::Const {
+				value: serde_json::json!(tag),
+			}),
+		);
+		properties.insert(
+			field.to_string(),
+			Box::new(JsonSchema::Number {
+				min: None,
+				max: None,
+				max_decimals: None,
+			}),
+		);
+		JsonSchema::Object {
+			required: vec!["type".to_string(), field.to_string()],
+			properties,
+			optional: vec![],
+			additional_properties: None,
+		}
+	};
+	let schema = JsonSchema::AnyOf {
+		alternatives: vec![variant("circle", "radius"), variant("square", "side")],
+	};
+
+	let mut biaser = JsonBiaser::new(&schema);
+
+	// {"type":"square","side":5}
+	let stream = vec![
+		JsonToken::CurlyOpen,
+		JsonToken::DoubleQuote,
+		JsonToken::String("type".to_string()),
+		JsonToken::DoubleQuote,
+		JsonToken::Colon,
+		JsonToken::DoubleQuote,
+		JsonToken::String("sq".to_string()),
+	];
+	for token in stream.iter() {
+		biaser.advance(token).unwrap();
+	}
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::AnyOf(vec!["uare".to_string()])]);
+
+	let stream = vec![
+		JsonToken::String("uare".to_string()),
+		JsonToken::DoubleQuote,
+		JsonToken::Comma,
+		JsonToken::DoubleQuote,
+	];
+	for token in stream.iter() {
+		biaser.advance(token).unwrap();
+	}
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::AnyOf(vec!["side".to_string()])]);
+
+	let stream = vec![
+		JsonToken::String("side".to_string()),
+		JsonToken::DoubleQuote,
+		JsonToken::Colon,
+		JsonToken::Digit(5),
+		JsonToken::CurlyClose,
+	];
+	for token in stream.iter() {
+		biaser.advance(token).unwrap();
+	}
+	assert!(biaser.can_end());
+	assert!(schema.is_valid(&serde_json::json!({ "type": "square", "side": 5 })));
+}
+
 #[test]
 pub fn test_nested_object_parser() {
 	setup();
@@ -102,14 +541,20 @@ pub fn test_nested_object_parser() {
 							Box::new(JsonSchema::String {
 								max_length: None,
 								r#enum: None,
+								min_length: None,
+								pattern: None,
 							}),
 						);
 						hn
 					},
+					optional: vec![],
+					additional_properties: None,
 				}),
 			);
 			hn
 		},
+		optional: vec![],
+		additional_properties: None,
 	};
 
 	let mut biaser = JsonBiaser::new(&schema);
@@ -148,6 +593,8 @@ pub fn test_object_parser() {
 		Box::new(JsonSchema::String {
 			max_length: Some(5),
 			r#enum: None,
+			min_length: None,
+			pattern: None,
 		}),
 	);
 	fields.insert(
@@ -155,11 +602,15 @@ pub fn test_object_parser() {
 		Box::new(JsonSchema::String {
 			max_length: Some(7),
 			r#enum: None,
+			min_length: None,
+			pattern: None,
 		}),
 	);
 	let schema = JsonSchema::Object {
 		required: vec!["first_name".to_string(), "last_name".to_string()],
 		properties: fields,
+		optional: vec![],
+		additional_properties: None,
 	};
 
 	let mut biaser = JsonBiaser::new(&schema);
@@ -203,6 +654,791 @@ pub fn test_object_parser() {
 	assert!(biaser.can_end());
 }
 
+#[test]
+pub fn test_optional_key_parser() {
+	setup();
+	let name_schema = JsonSchema::String {
+		max_length: None,
+		r#enum: None,
+		min_length: None,
+		pattern: None,
+	};
+	let age_schema = JsonSchema::Integer { min: Some(0), max: None };
+	let schema = JsonSchema::Object {
+		required: vec!["name".to_string()],
+		properties: HashMap::from([("name".to_string(), Box::new(name_schema)), ("age".to_string(), Box::new(age_schema))]),
+		optional: vec!["age".to_string()],
+		additional_properties: None,
+	};
+
+	let mut biaser = JsonBiaser::new(&schema);
+
+	// The required key comes first
+	biaser.advance(&JsonToken::CurlyOpen).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::DoubleQuote]);
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::AnyOf(vec!["name".to_string()])]);
+	biaser.advance(&JsonToken::String("name".to_string())).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	biaser.advance(&JsonToken::Colon).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	biaser.advance(&JsonToken::String("tommy".to_string())).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap(); // {"name":"tommy" at this point
+
+	// The object may end here, or continue with the optional key
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::CurlyClose, JsonToken::Comma]);
+	let (_, value) = parse_tokens(
+		&schema,
+		&[
+			JsonToken::CurlyOpen,
+			JsonToken::DoubleQuote,
+			JsonToken::String("name".to_string()),
+			JsonToken::DoubleQuote,
+			JsonToken::Colon,
+			JsonToken::DoubleQuote,
+			JsonToken::String("tommy".to_string()),
+			JsonToken::DoubleQuote,
+			JsonToken::CurlyClose,
+		],
+	);
+	assert_eq!(value, serde_json::json!({"name": "tommy"}));
+
+	biaser.advance(&JsonToken::Comma).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::DoubleQuote]);
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::AnyOf(vec!["age".to_string()])]);
+	biaser.advance(&JsonToken::String("age".to_string())).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::DoubleQuote]);
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	biaser.advance(&JsonToken::Colon).unwrap();
+	biaser.advance(&JsonToken::Digit(42)).unwrap();
+
+	// All keys have been written, so the object can only end
+	assert!(!biaser.next_valid_tokens().contains(&JsonToken::Comma));
+	biaser.advance(&JsonToken::CurlyClose).unwrap();
+	assert!(biaser.can_end());
+	assert_eq!(
+		serde_json::from_str::<Value>(&biaser.partial_output().unwrap()).unwrap(),
+		serde_json::json!({"name": "tommy", "age": 42})
+	);
+
+	// An object with only optional keys may be empty
+	let schema = JsonSchema::Object {
+		required: vec![],
+		properties: HashMap::from([("age".to_string(), Box::new(JsonSchema::Integer { min: None, max: None }))]),
+		optional: vec!["age".to_string()],
+		additional_properties: None,
+	};
+	let mut biaser = JsonBiaser::new(&schema);
+	biaser.advance(&JsonToken::CurlyOpen).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::DoubleQuote, JsonToken::CurlyClose]);
+	assert!(schema.is_valid(&serde_json::json!({})));
+	assert!(schema.is_valid(&serde_json::json!({"age": 3})));
+	assert!(!schema.is_valid(&serde_json::json!({"name": "tommy"})));
+}
+
+#[test]
+pub fn test_additional_properties_parser() {
+	setup();
+	let schema = JsonSchema::Object {
+		required: vec![],
+		properties: HashMap::new(),
+		optional: vec![],
+		additional_properties: Some(Box::new(JsonSchema::Boolean)),
+	};
+
+	let (text, value) = parse_tokens(
+		&schema,
+		&[
+			JsonToken::CurlyOpen,
+			JsonToken::DoubleQuote,
+			JsonToken::String("foo".to_string()),
+			JsonToken::Digit(1),
+			JsonToken::DoubleQuote,
+			JsonToken::Colon,
+			JsonToken::True,
+			JsonToken::Comma,
+			JsonToken::DoubleQuote,
+			JsonToken::String("bar".to_string()),
+			JsonToken::DoubleQuote,
+			JsonToken::Colon,
+			JsonToken::False,
+			JsonToken::CurlyClose,
+		],
+	);
+	assert_eq!(text, r#"{"foo1":true,"bar":false}"#);
+	assert!(schema.is_valid(&value));
+	assert!(!schema.is_valid(&serde_json::json!({"foo": 1})));
+
+	// The values of additional keys are biased against the schema for additional properties
+	let mut biaser = JsonBiaser::new(&schema);
+	for token in [JsonToken::CurlyOpen, JsonToken::DoubleQuote] {
+		biaser.advance(&token).unwrap();
+	}
+	assert!(!biaser.next_valid_tokens().contains(&JsonToken::DoubleQuote)); // Keys can't be empty
+	biaser.advance(&JsonToken::String("foo".to_string())).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	biaser.advance(&JsonToken::Colon).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::True, JsonToken::False]);
+
+	// A key can't be used twice
+	let mut biaser = JsonBiaser::new(&schema);
+	for token in [
+		JsonToken::CurlyOpen,
+		JsonToken::DoubleQuote,
+		JsonToken::String("foo".to_string()),
+		JsonToken::DoubleQuote,
+		JsonToken::Colon,
+		JsonToken::True,
+		JsonToken::Comma,
+		JsonToken::DoubleQuote,
+		JsonToken::String("foo".to_string()),
+	] {
+		biaser.advance(&token).unwrap();
+	}
+	assert!(!biaser.next_valid_tokens().contains(&JsonToken::DoubleQuote));
+}
+
+#[test]
+pub fn test_recursive_schema_parser() {
+	setup();
+	let tree_node = JsonSchema::Object {
+		required: vec!["value".to_string()],
+		properties: HashMap::from([
+			("value".to_string(), Box::new(JsonSchema::Integer { min: None, max: None })),
+			(
+				"children".to_string(),
+				Box::new(JsonSchema::Array {
+					items: Box::new(JsonSchema::Ref {
+						name: "TreeNode".to_string(),
+					}),
+					min_items: None,
+					max_items: None,
+					unique_items: None,
+				}),
+			),
+		]),
+		optional: vec!["children".to_string()],
+		additional_properties: None,
+	};
+	let definitions = HashMap::from([("TreeNode".to_string(), tree_node)]);
+	let schema = JsonSchema::Ref {
+		name: "TreeNode".to_string(),
+	};
+
+	// '{"value":1,"children":[{"value":2},{"value":3,"children":[{"value":4}]}]}'
+	let node = |value: usize, children: Vec<Vec<JsonToken>>| {
+		let mut tokens = vec![
+			JsonToken::CurlyOpen,
+			JsonToken::DoubleQuote,
+			JsonToken::String("value".to_string()),
+			JsonToken::DoubleQuote,
+			JsonToken::Colon,
+			JsonToken::Digit(value),
+		];
+		if !children.is_empty() {
+			tokens.extend([
+				JsonToken::Comma,
+				JsonToken::DoubleQuote,
+				JsonToken::String("children".to_string()),
+				JsonToken::DoubleQuote,
+				JsonToken::Colon,
+				JsonToken::BracketOpen,
+			]);
+			for (i, child) in children.into_iter().enumerate() {
+				if i > 0 {
+					tokens.push(JsonToken::Comma);
+				}
+				tokens.extend(child);
+			}
+			tokens.push(JsonToken::BracketClose);
+		}
+		tokens.push(JsonToken::CurlyClose);
+		tokens
+	};
+	let tokens = node(1, vec![node(2, vec![]), node(3, vec![node(4, vec![])])]);
+
+	let mut biaser = JsonBiaser::with_definitions(&schema, &definitions);
+	for token in &tokens {
+		assert!(
+			JsonToken::is_accepted_by(token, &biaser.next_valid_tokens()),
+			"{token:?} should be accepted"
+		);
+		biaser.advance(token).unwrap();
+	}
+	assert!(biaser.can_end());
+	let value: Value = serde_json::from_str(&biaser.partial_output().unwrap()).unwrap();
+	assert_eq!(
+		value,
+		serde_json::json!({"value": 1, "children": [{"value": 2}, {"value": 3, "children": [{"value": 4}]}]})
+	);
+	assert!(schema.is_valid_with_definitions(&value, &definitions));
+	assert!(!schema.is_valid_with_definitions(&serde_json::json!({"value": 1, "children": [{}]}), &definitions));
+
+	// References that cannot be resolved accept nothing
+	let biaser = JsonBiaser::new(&schema);
+	assert_eq!(biaser.next_valid_tokens(), vec![]);
+	let cyclic = HashMap::from([(
+		"TreeNode".to_string(),
+		JsonSchema::Ref {
+			name: "TreeNode".to_string(),
+		},
+	)]);
+	assert!(schema.resolve(&cyclic).is_none());
+	assert_eq!(JsonBiaser::with_definitions(&schema, &cyclic).next_valid_tokens(), vec![]);
+}
+
+#[test]
+pub fn test_tuple_parser() {
+	setup();
+	let schema = JsonSchema::Tuple {
+		items: vec![
+			Box::new(JsonSchema::String {
+				max_length: None,
+				r#enum: None,
+				min_length: None,
+				pattern: None,
+			}),
+			Box::new(JsonSchema::Number {
+				min: None,
+				max: None,
+				max_decimals: None,
+			}),
+			Box::new(JsonSchema::Object {
+				required: vec!["ok".to_string()],
+				properties: HashMap::from([("ok".to_string(), Box::new(JsonSchema::Boolean))]),
+				optional: vec![],
+				additional_properties: None,
+			}),
+		],
+	};
+
+	// '["a",1,{"ok":true}]'
+	let (text, value) = parse_tokens(
+		&schema,
+		&[
+			JsonToken::BracketOpen,
+			JsonToken::DoubleQuote,
+			JsonToken::String("a".to_string()),
+			JsonToken::DoubleQuote,
+			JsonToken::Comma,
+			JsonToken::Digit(1),
+			JsonToken::Comma,
+			JsonToken::CurlyOpen,
+			JsonToken::DoubleQuote,
+			JsonToken::String("ok".to_string()),
+			JsonToken::DoubleQuote,
+			JsonToken::Colon,
+			JsonToken::True,
+			JsonToken::CurlyClose,
+			JsonToken::BracketClose,
+		],
+	);
+	assert_eq!(text, r#"["a",1,{"ok":true}]"#);
+	assert_eq!(value, serde_json::json!(["a", 1.0, {"ok": true}]));
+	assert!(schema.is_valid(&value));
+	assert!(!schema.is_valid(&serde_json::json!(["a", 1])));
+	assert!(!schema.is_valid(&serde_json::json!(["a", 1, {"ok": true}, 2])));
+	assert!(!schema.is_valid(&serde_json::json!([1, "a", {"ok": true}])));
+
+	// Each position is biased against its own schema, and the array can only be closed after the last item
+	let mut biaser = JsonBiaser::new(&schema);
+	biaser.advance(&JsonToken::BracketOpen).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::DoubleQuote]);
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	biaser.advance(&JsonToken::String("a".to_string())).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::Comma]);
+	biaser.advance(&JsonToken::Comma).unwrap();
+	assert!(!biaser.next_valid_tokens().contains(&JsonToken::DoubleQuote));
+	biaser.advance(&JsonToken::Digit(1)).unwrap();
+	assert!(biaser.next_valid_tokens().contains(&JsonToken::Comma));
+	assert!(!biaser.next_valid_tokens().contains(&JsonToken::BracketClose));
+	assert!(biaser.advance(&JsonToken::BracketClose).is_err());
+}
+
+#[test]
+pub fn test_unique_items_parser() {
+	setup();
+	let schema = JsonSchema::Array {
+		items: Box::new(JsonSchema::String {
+			max_length: None,
+			r#enum: Some(vec!["red".to_string(), "green".to_string(), "blue".to_string()]),
+			min_length: None,
+			pattern: None,
+		}),
+		min_items: Some(2),
+		max_items: None,
+		unique_items: Some(true),
+	};
+	let mut biaser = JsonBiaser::new(&schema);
+
+	// '["red",'
+	biaser.advance(&JsonToken::BracketOpen).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	biaser.advance(&JsonToken::String("red".to_string())).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::Comma]);
+	biaser.advance(&JsonToken::Comma).unwrap();
+
+	// Values that were used before are no longer offered
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::DoubleQuote]);
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	assert_eq!(
+		biaser.next_valid_tokens(),
+		vec![JsonToken::AnyOf(vec!["green".to_string(), "blue".to_string()])]
+	);
+	biaser.advance(&JsonToken::String("green".to_string())).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap(); // '["red","green"'
+
+	// The minimum number of items is reached
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::Comma, JsonToken::BracketClose]);
+	biaser.advance(&JsonToken::Comma).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::AnyOf(vec!["blue".to_string()])]);
+	biaser.advance(&JsonToken::String("blue".to_string())).unwrap();
+	biaser.advance(&JsonToken::DoubleQuote).unwrap();
+
+	// All values have been used, so the array must end
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::BracketClose]);
+	biaser.advance(&JsonToken::BracketClose).unwrap();
+	assert_eq!(
+		serde_json::from_str::<Value>(&biaser.partial_output().unwrap()).unwrap(),
+		serde_json::json!(["red", "green", "blue"])
+	);
+
+	// A duplicate item cannot be completed
+	let mut biaser = JsonBiaser::new(&schema);
+	for token in [
+		JsonToken::BracketOpen,
+		JsonToken::DoubleQuote,
+		JsonToken::String("red".to_string()),
+		JsonToken::DoubleQuote,
+		JsonToken::Comma,
+		JsonToken::DoubleQuote,
+		JsonToken::String("red".to_string()),
+	] {
+		biaser.advance(&token).unwrap();
+	}
+	assert_eq!(biaser.next_valid_tokens(), vec![]);
+
+	assert!(schema.is_valid(&serde_json::json!(["red", "blue"])));
+	assert!(!schema.is_valid(&serde_json::json!(["red", "red"])));
+}
+
+/// Whether the partial value `next` is the same as or a continuation of the partial value `previous`
+fn extends(previous: &Value, next: &Value) -> bool {
+	match (previous, next) {
+		(Value::Object(previous), Value::Object(next)) => previous
+			.iter()
+			.all(|(key, previous_value)| next.get(key).is_some_and(|next_value| extends(previous_value, next_value))),
+		(Value::Array(previous), Value::Array(next)) => previous.len() <= next.len() && previous.iter().zip(next.iter()).all(|(p, n)| extends(p, n)),
+		(Value::String(previous), Value::String(next)) => next.starts_with(previous.as_str()),
+		(previous, next) => next.to_string().starts_with(&previous.to_string()),
+	}
+}
+
+#[test]
+pub fn test_current_value() {
+	setup();
+	let schema = JsonSchema::Object {
+		required: vec!["name".to_string(), "tags".to_string()],
+		properties: HashMap::from([
+			(
+				"name".to_string(),
+				Box::new(JsonSchema::String {
+					max_length: None,
+					r#enum: None,
+					min_length: None,
+					pattern: None,
+				}),
+			),
+			(
+				"tags".to_string(),
+				Box::new(JsonSchema::Array {
+					items: Box::new(JsonSchema::Integer { min: None, max: None }),
+					min_items: None,
+					max_items: None,
+					unique_items: None,
+				}),
+			),
+		]),
+		optional: vec![],
+		additional_properties: None,
+	};
+
+	// '{"name":"tommy","tags":[12,3]}'
+	let tokens = [
+		JsonToken::CurlyOpen,
+		JsonToken::DoubleQuote,
+		JsonToken::String("name".to_string()),
+		JsonToken::DoubleQuote,
+		JsonToken::Colon,
+		JsonToken::DoubleQuote,
+		JsonToken::String("tom".to_string()),
+		JsonToken::String("my".to_string()),
+		JsonToken::DoubleQuote,
+		JsonToken::Comma,
+		JsonToken::DoubleQuote,
+		JsonToken::String("tags".to_string()),
+		JsonToken::DoubleQuote,
+		JsonToken::Colon,
+		JsonToken::BracketOpen,
+		JsonToken::Digit(1),
+		JsonToken::Digit(2),
+		JsonToken::Comma,
+		JsonToken::Digit(3),
+		JsonToken::BracketClose,
+		JsonToken::CurlyClose,
+	];
+
+	let mut biaser = JsonBiaser::new(&schema);
+	assert_eq!(biaser.current_value(), None);
+	let mut values: Vec<Value> = vec![];
+	for token in &tokens {
+		biaser.advance(token).unwrap();
+		if let Some(value) = biaser.current_value() {
+			if let Some(previous) = values.last() {
+				assert!(extends(previous, &value), "{value} should extend {previous}");
+			}
+			if values.last() != Some(&value) {
+				values.push(value);
+			}
+		}
+	}
+
+	assert_eq!(
+		values,
+		vec![
+			serde_json::json!({}),
+			serde_json::json!({"name": ""}),
+			serde_json::json!({"name": "tom"}),
+			serde_json::json!({"name": "tommy"}),
+			serde_json::json!({"name": "tommy", "tags": []}),
+			serde_json::json!({"name": "tommy", "tags": [1]}),
+			serde_json::json!({"name": "tommy", "tags": [12]}),
+			serde_json::json!({"name": "tommy", "tags": [12, 3]}),
+		]
+	);
+	assert_eq!(biaser.partial_value(), biaser.current_value());
+}
+
+#[test]
+pub fn test_standard_schema() {
+	setup();
+	let standard = serde_json::json!({
+		"$schema": "https://json-schema.org/draft/2020-12/schema",
+		"description": "A car",
+		"type": "object",
+		"properties": {
+			"brand": { "type": "string", "enum": ["volvo", "saab"] },
+			"model": { "type": "string", "minLength": 1, "maxLength": 20, "pattern": "^[A-Z]" },
+			"year": { "type": "integer", "minimum": 1900, "maximum": 2100 },
+			"price": { "type": "number", "minimum": 0 },
+			"electric": { "type": "boolean" },
+			"owners": {
+				"type": "array",
+				"items": { "type": "string" },
+				"minItems": 1,
+				"maxItems": 5,
+				"uniqueItems": true
+			},
+			"position": { "type": "array", "prefixItems": [{ "type": "number" }, { "type": "number" }] },
+			"color": { "enum": ["red", 1, null] },
+			"plate": { "type": ["string", "null"] }
+		},
+		"required": ["brand", "year"]
+	});
+
+	let expected = JsonSchema::Object {
+		required: vec!["brand".to_string(), "year".to_string()],
+		properties: HashMap::from([
+			(
+				"brand".to_string(),
+				Box::new(JsonSchema::String {
+					max_length: None,
+					r#enum: Some(vec!["volvo".to_string(), "saab".to_string()]),
+					min_length: None,
+					pattern: None,
+				}),
+			),
+			(
+				"model".to_string(),
+				Box::new(JsonSchema::String {
+					max_length: Some(20),
+					r#enum: None,
+					min_length: Some(1),
+					pattern: Some("^[A-Z]".to_string()),
+				}),
+			),
+			(
+				"year".to_string(),
+				Box::new(JsonSchema::Integer {
+					min: Some(1900),
+					max: Some(2100),
+				}),
+			),
+			(
+				"price".to_string(),
+				Box::new(JsonSchema::Number {
+					min: Some(0.0),
+					max: None,
+					max_decimals: None,
+				}),
+			),
+			("electric".to_string(), Box::new(JsonSchema::Boolean)),
+			(
+				"owners".to_string(),
+				Box::new(JsonSchema::Array {
+					items: Box::new(JsonSchema::String {
+						max_length: None,
+						r#enum: None,
+						min_length: None,
+						pattern: None,
+					}),
+					min_items: Some(1),
+					max_items: Some(5),
+					unique_items: Some(true),
+				}),
+			),
+			(
+				"position".to_string(),
+				Box::new(JsonSchema::Tuple {
+					items: vec![
+						Box::new(JsonSchema::Number {
+							min: None,
+							max: None,
+							max_decimals: None,
+						}),
+						Box::new(JsonSchema::Number {
+							min: None,
+							max: None,
+							max_decimals: None,
+						}),
+					],
+				}),
+			),
+			(
+				"color".to_string(),
+				Box::new(JsonSchema::Enum {
+					values: vec![serde_json::json!("red"), serde_json::json!(1), Value::Null],
+				}),
+			),
+			(
+				"plate".to_string(),
+				Box::new(JsonSchema::AnyOf {
+					alternatives: vec![
+						JsonSchema::String {
+							max_length: None,
+							r#enum: None,
+							min_length: None,
+							pattern: None,
+						},
+						JsonSchema::Null,
+					],
+				}),
+			),
+		]),
+		optional: ["color", "electric", "model", "owners", "plate", "position", "price"]
+			.iter()
+			.map(|key| key.to_string())
+			.collect(),
+		additional_properties: None,
+	};
+
+	let schema = JsonSchema::from_standard(&standard).unwrap();
+	assert_eq!(serde_json::to_value(&schema).unwrap(), serde_json::to_value(&expected).unwrap());
+
+	// The converted schema survives serialization in the internal format
+	let round_tripped: JsonSchema = serde_json::from_value(serde_json::to_value(&schema).unwrap()).unwrap();
+	assert_eq!(serde_json::to_value(round_tripped).unwrap(), serde_json::to_value(&expected).unwrap());
+
+	let car = serde_json::json!({"brand": "saab", "year": 1998, "owners": ["tommy"], "plate": null});
+	assert!(schema.is_valid(&car));
+	assert!(!schema.is_valid(&serde_json::json!({"brand": "saab"})));
+
+	// Unsupported schemas are rejected
+	assert!(matches!(
+		JsonSchema::from_standard(&serde_json::json!({"type": "date"})),
+		Err(SchemaError::UnsupportedType(_))
+	));
+	assert!(matches!(
+		JsonSchema::from_standard(&serde_json::json!({"$ref": "#/$defs/car"})),
+		Err(SchemaError::UnsupportedKeyword(_))
+	));
+	assert!(matches!(
+		JsonSchema::from_standard(&serde_json::json!({"type": "string", "maxLength": -1})),
+		Err(SchemaError::InvalidKeyword(_))
+	));
+	assert!(matches!(JsonSchema::from_standard(&serde_json::json!({})), Err(SchemaError::MissingType)));
+	assert!(matches!(
+		JsonSchema::from_standard(&serde_json::json!(true)),
+		Err(SchemaError::NotAnObject)
+	));
+}
+
+#[test]
+pub fn test_completed_items() {
+	setup();
+	let schema = JsonSchema::Array {
+		items: Box::new(JsonSchema::Object {
+			required: vec!["ok".to_string()],
+			properties: HashMap::from([("ok".to_string(), Box::new(JsonSchema::Boolean))]),
+			optional: vec![],
+			additional_properties: None,
+		}),
+		min_items: None,
+		max_items: None,
+		unique_items: None,
+	};
+
+	// '[{"ok":true},{"ok":false},{"ok":true}]'
+	let mut tokens = vec![JsonToken::BracketOpen];
+	for (i, value) in [JsonToken::True, JsonToken::False, JsonToken::True].into_iter().enumerate() {
+		if i > 0 {
+			tokens.push(JsonToken::Comma);
+		}
+		tokens.extend([
+			JsonToken::CurlyOpen,
+			JsonToken::DoubleQuote,
+			JsonToken::String("ok".to_string()),
+			JsonToken::DoubleQuote,
+			JsonToken::Colon,
+			value,
+			JsonToken::CurlyClose,
+		]);
+	}
+	tokens.push(JsonToken::BracketClose);
+
+	// Moving on to the next item happens exactly once between each pair of items
+	let mut biaser = JsonBiaser::new(&schema);
+	assert_eq!(biaser.completed_items(), None);
+	let mut transitions = 0;
+	for token in &tokens {
+		let before = biaser.completed_items();
+		biaser.advance(token).unwrap();
+		if let (Some(before), Some(after)) = (before, biaser.completed_items()) {
+			if after > before {
+				assert_eq!(token, &JsonToken::Comma);
+				transitions += 1;
+			}
+		}
+	}
+	assert_eq!(transitions, 2);
+	assert_eq!(biaser.completed_items(), None);
+	assert!(biaser.can_end());
+}
+
+#[test]
+pub fn test_number_leading_zero_parser() {
+	let schema = JsonSchema::Number {
+		min: None,
+		max: None,
+		max_decimals: Some(2),
+	};
+	let mut biaser = JsonBiaser::new(&schema);
+	assert!(biaser.next_valid_tokens().contains(&JsonToken::Digit(0)));
+	biaser.advance(&JsonToken::Digit(0)).unwrap();
+	assert!(biaser.can_end());
+
+	// No further digits (e.g. 007), only a decimal point
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::Decimal]);
+	assert!(biaser.advance(&JsonToken::Digit(7)).is_err());
+
+	// Without decimals, zero is a complete number
+	let schema = JsonSchema::Number {
+		min: None,
+		max: None,
+		max_decimals: None,
+	};
+	let mut biaser = JsonBiaser::new(&schema);
+	biaser.advance(&JsonToken::Digit(0)).unwrap();
+	assert!(biaser.can_end());
+	assert_eq!(biaser.next_valid_tokens(), vec![]);
+}
+
+#[test]
+pub fn test_number_decimals_parser() {
+	let schema = JsonSchema::Number {
+		min: None,
+		max: None,
+		max_decimals: Some(2),
+	};
+	let mut biaser = JsonBiaser::new(&schema);
+	for token in [JsonToken::Digit(1), JsonToken::Decimal, JsonToken::Digit(2)] {
+		biaser.advance(&token).unwrap();
+	}
+
+	// After two decimals, no further tokens are valid
+	assert!(!biaser.next_valid_tokens().contains(&JsonToken::Decimal));
+	biaser.advance(&JsonToken::Digit(5)).unwrap();
+	assert_eq!(biaser.next_valid_tokens(), vec![]);
+	assert!(biaser.can_end());
+	assert_eq!(biaser.partial_output(), Some("1.25".to_string()));
+
+	// A second decimal point is never allowed
+	let mut biaser = JsonBiaser::new(&schema);
+	for token in [JsonToken::Digit(1), JsonToken::Decimal, JsonToken::Digit(2)] {
+		biaser.advance(&token).unwrap();
+	}
+	assert!(biaser.advance(&JsonToken::Decimal).is_err());
+
+	// Large values keep their precision
+	let mut biaser = JsonBiaser::new(&schema);
+	biaser.advance(&JsonToken::Digit(12345678)).unwrap();
+	for token in [JsonToken::Decimal, JsonToken::Digit(2), JsonToken::Digit(5)] {
+		biaser.advance(&token).unwrap();
+	}
+	assert_eq!(biaser.partial_output(), Some("12345678.25".to_string()));
+}
+
+#[test]
+pub fn test_integer_parser() {
+	let schema = JsonSchema::Integer {
+		min: Some(-20),
+		max: Some(-5),
+	};
+	assert!(schema.is_valid(&serde_json::json!(-12)));
+	assert!(schema.is_valid(&serde_json::json!(-5)));
+	assert!(!schema.is_valid(&serde_json::json!(-4)));
+	assert!(!schema.is_valid(&serde_json::json!(-21)));
+	assert!(!schema.is_valid(&serde_json::json!(-7.5)));
+
+	// Only negative numbers are in range
+	let mut biaser = JsonBiaser::new(&schema);
+	assert_eq!(biaser.next_valid_tokens(), vec![JsonToken::Minus]);
+	biaser.advance(&JsonToken::Minus).unwrap();
+
+	// -1x and -20 can still be reached, -3x and -4x cannot
+	assert_eq!(
+		biaser.next_valid_tokens(),
+		[1, 2, 5, 6, 7, 8, 9].into_iter().map(JsonToken::Digit).collect::<Vec<_>>()
+	);
+	biaser.advance(&JsonToken::Digit(1)).unwrap();
+	assert!(!biaser.can_end());
+	biaser.advance(&JsonToken::Digit(2)).unwrap();
+	assert!(biaser.can_end());
+	assert_eq!(biaser.next_valid_tokens(), vec![]);
+	assert_eq!(biaser.partial_output(), Some("-12".to_string()));
+
+	// Decimals are never accepted
+	let schema = JsonSchema::Integer { min: None, max: None };
+	assert!(schema.is_valid(&serde_json::json!(3)));
+	assert!(!schema.is_valid(&serde_json::json!(3.5)));
+	let mut biaser = JsonBiaser::new(&schema);
+	assert!(biaser.next_valid_tokens().contains(&JsonToken::Digit(0)));
+	assert!(biaser.next_valid_tokens().contains(&JsonToken::Minus));
+	biaser.advance(&JsonToken::Digit(3)).unwrap();
+	assert!(!biaser.next_valid_tokens().contains(&JsonToken::Decimal));
+	assert!(biaser.advance(&JsonToken::Decimal).is_err());
+
+	// Nothing follows a leading zero
+	let mut biaser = JsonBiaser::new(&schema);
+	biaser.advance(&JsonToken::Digit(0)).unwrap();
+	assert!(biaser.can_end());
+	assert_eq!(biaser.next_valid_tokens(), vec![]);
+}
+
 #[test]
 pub fn test_array_parser() {
 	setup();
@@ -210,6 +1446,7 @@ pub fn test_array_parser() {
 		items: Box::new(JsonSchema::Boolean),
 		min_items: Some(2),
 		max_items: Some(3),
+		unique_items: None,
 	};
 	let mut bias = JsonBiaser::new(&schema);
 
@@ -253,6 +1490,8 @@ pub fn test_json_biaser_objects() {
 		JsonSchema::Object {
 			required: vec![],
 			properties: HashMap::new(),
+			optional: vec![],
+			additional_properties: None,
 		},
 		model.as_ref(),
 	);
@@ -263,6 +1502,8 @@ pub fn test_json_biaser_objects() {
 		Box::new(JsonSchema::String {
 			max_length: Some(5),
 			r#enum: None,
+			min_length: None,
+			pattern: None,
 		}),
 	);
 	fields.insert(
@@ -270,6 +1511,8 @@ pub fn test_json_biaser_objects() {
 		Box::new(JsonSchema::String {
 			max_length: Some(7),
 			r#enum: None,
+			min_length: None,
+			pattern: None,
 		}),
 	);
 
@@ -277,11 +1520,56 @@ pub fn test_json_biaser_objects() {
 		JsonSchema::Object {
 			required: fields.keys().cloned().collect(),
 			properties: fields,
+			optional: vec![],
+			additional_properties: None,
 		},
 		model.as_ref(),
 	);
 }
 
+#[test]
+pub fn test_json_biaser_vocabulary_cache() {
+	setup();
+	let model = llm::load_dynamic(
+		Some(ModelArchitecture::Gpt2),
+		Path::new(MODEL_PATH),
+		llm::TokenizerSource::Embedded,
+		ModelParameters::default(),
+		|_progress| {},
+	)
+	.unwrap();
+	let vocab = model.tokenizer();
+
+	let schema = JsonSchema::String {
+		max_length: None,
+		r#enum: None,
+		min_length: None,
+		pattern: None,
+	};
+	let string_tokens = Arc::new(StringTokens::default());
+	let mut bias = JsonBiaser::new(&schema).with_string_tokens(string_tokens.clone());
+	bias.advance(&JsonToken::DoubleQuote).unwrap();
+
+	// The vocabulary is only scanned once
+	let first = bias.bias(vocab, model.eot_token_id());
+	for _ in 0..100 {
+		assert_eq!(bias.bias(vocab, model.eot_token_id()), first);
+	}
+	assert_eq!(string_tokens.scans(), 1);
+
+	// Other biasers (and other kinds of string tokens) can share the same cache
+	let enum_schema = JsonSchema::String {
+		max_length: None,
+		r#enum: Some(vec!["foo".to_string(), "bar".to_string()]),
+		min_length: None,
+		pattern: None,
+	};
+	let mut bias = JsonBiaser::new(&enum_schema).with_string_tokens(string_tokens.clone());
+	bias.advance(&JsonToken::DoubleQuote).unwrap();
+	assert!(!bias.bias(vocab, model.eot_token_id()).is_empty());
+	assert_eq!(string_tokens.scans(), 1);
+}
+
 #[test]
 pub fn test_json_biaser() {
 	setup();
@@ -306,6 +1594,8 @@ pub fn test_json_biaser() {
 				"Jumped over the".to_string(),
 				"The quick".to_string(),
 			]),
+			min_length: None,
+			pattern: None,
 		},
 		model.as_ref(),
 	);
@@ -314,6 +1604,8 @@ pub fn test_json_biaser() {
 		JsonSchema::String {
 			max_length: Some(20),
 			r#enum: None,
+			min_length: None,
+			pattern: None,
 		},
 		model.as_ref(),
 	);
@@ -327,12 +1619,28 @@ pub fn test_json_biaser() {
 		model.as_ref(),
 	);
 
+	// Boolean or number
+	test_json_bias(
+		JsonSchema::OneOf {
+			alternatives: vec![
+				JsonSchema::Boolean,
+				JsonSchema::Number {
+					max_decimals: Some(2),
+					min: Some(-10.0),
+					max: Some(10.0),
+				},
+			],
+		},
+		model.as_ref(),
+	);
+
 	// Array-of-bools
 	test_json_bias(
 		JsonSchema::Array {
 			items: Box::new(JsonSchema::Boolean),
 			min_items: Some(2),
 			max_items: Some(5),
+			unique_items: None,
 		},
 		model.as_ref(),
 	);
@@ -348,9 +1656,11 @@ pub fn test_json_biaser() {
 				}),
 				min_items: Some(2),
 				max_items: Some(4),
+				unique_items: None,
 			}),
 			min_items: Some(1),
 			max_items: Some(3),
+			unique_items: None,
 		},
 		model.as_ref(),
 	);
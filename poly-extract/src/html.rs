@@ -0,0 +1,187 @@
+use ego_tree::NodeRef;
+use scraper::{Html, Node};
+
+/// Elements whose contents are not text that is shown to a reader
+const SKIPPED_ELEMENTS: [&str; 6] = ["head", "script", "style", "noscript", "template", "svg"];
+
+/// Elements after which a line break is inserted (`pre` is handled separately, see [append_preformatted])
+const BLOCK_ELEMENTS: [&str; 23] = [
+	"address",
+	"article",
+	"aside",
+	"blockquote",
+	"br",
+	"dd",
+	"div",
+	"dl",
+	"dt",
+	"figcaption",
+	"footer",
+	"h1",
+	"h2",
+	"h3",
+	"h4",
+	"h5",
+	"h6",
+	"header",
+	"hr",
+	"li",
+	"p",
+	"section",
+	"tr",
+];
+
+/// Table cells, which are separated by a tab when they are on the same row
+const CELL_ELEMENTS: [&str; 2] = ["td", "th"];
+
+/// Retrieve plain text from an HTML document. Markup, scripts and style sheets are removed, and each block-level element
+/// (paragraph, heading, list item, table row, etc.) ends up on its own line. Cells in a table row are separated by a tab.
+/// Whitespace in preformatted text (`pre`) is kept as is.
+pub fn get_text_from_html(html: &str) -> String {
+	let document = Html::parse_document(html);
+	let mut text = HtmlText::default();
+	text.append(document.tree.root());
+	text.finish()
+}
+
+/// Text extracted from an HTML document
+#[derive(Default)]
+struct HtmlText {
+	/// Finished parts of the text (normalized running text and preformatted text)
+	parts: Vec<String>,
+
+	/// Running text that has not been normalized yet
+	running: String,
+}
+
+impl HtmlText {
+	fn append(&mut self, node: NodeRef<Node>) {
+		match node.value() {
+			Node::Text(node_text) => {
+				// Runs of whitespace in HTML text are shown as a single space
+				for c in node_text.chars() {
+					if !c.is_whitespace() {
+						self.running.push(c);
+					} else if !self.running.is_empty() && !self.running.ends_with(char::is_whitespace) {
+						self.running.push(' ');
+					}
+				}
+			}
+			Node::Element(element) => {
+				let name = element.name();
+				if SKIPPED_ELEMENTS.contains(&name) {
+					return;
+				}
+
+				if name == "pre" {
+					self.end_running_text();
+					let mut preformatted = String::new();
+					append_preformatted(node, &mut preformatted);
+					let preformatted = preformatted.trim_end_matches('\n');
+					if !preformatted.trim().is_empty() {
+						self.parts.push(preformatted.to_string());
+					}
+					return;
+				}
+
+				// Separate a cell from the previous cell in the same row
+				if CELL_ELEMENTS.contains(&name) && node.prev_siblings().any(|sibling| is_cell(&sibling)) {
+					self.running.truncate(self.running.trim_end_matches(' ').len());
+					self.running.push('\t');
+				}
+
+				for child in node.children() {
+					self.append(child);
+				}
+				if BLOCK_ELEMENTS.contains(&name) {
+					self.running.push('\n');
+				}
+			}
+			Node::Document | Node::Fragment => {
+				for child in node.children() {
+					self.append(child);
+				}
+			}
+			_ => {}
+		}
+	}
+
+	fn end_running_text(&mut self) {
+		let text = crate::normalize_lines(&self.running);
+		if !text.is_empty() {
+			self.parts.push(text);
+		}
+		self.running.clear();
+	}
+
+	fn finish(mut self) -> String {
+		self.end_running_text();
+		self.parts.join("\n")
+	}
+}
+
+fn is_cell(node: &NodeRef<Node>) -> bool {
+	node.value().as_element().is_some_and(|element| CELL_ELEMENTS.contains(&element.name()))
+}
+
+/// Append the text of a preformatted element without collapsing whitespace
+fn append_preformatted(node: NodeRef<Node>, text: &mut String) {
+	for child in node.children() {
+		match child.value() {
+			Node::Text(node_text) => text.push_str(node_text),
+			Node::Element(element) if element.name() == "br" => text.push('\n'),
+			Node::Element(element) if !SKIPPED_ELEMENTS.contains(&element.name()) => append_preformatted(child, text),
+			_ => {}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::get_text_from_html;
+
+	#[test]
+	pub fn test_get_text_from_html() {
+		let html = r#"<!DOCTYPE html>
+			<html>
+				<head>
+					<title>Page title</title>
+					<style>body { color: red; }</style>
+				</head>
+				<body>
+					<h1>Heading</h1>
+					<p>First   paragraph with <b>bold</b> and <a href="https://example.com">a link</a>.</p>
+					<script>alert("hello");</script>
+					<ul><li>One</li><li>Two</li></ul>
+					<!-- a comment -->
+					<div>Line<br>break &amp; entity</div>
+				</body>
+			</html>"#;
+		assert_eq!(
+			get_text_from_html(html),
+			"Heading\nFirst paragraph with bold and a link.\nOne\nTwo\nLine\nbreak & entity"
+		);
+	}
+
+	#[test]
+	pub fn test_tables_and_preformatted_text() {
+		let html = r#"<html>
+				<body>
+					<p>Intro</p>
+					<table>
+						<tr><th>Name</th><th>Age</th></tr>
+						<tr><td>Alice</td><td> 42 </td></tr>
+					</table>
+					<pre>fn main() {
+    println!("<b>hi</b>");
+
+}</pre>
+					<p>After</p>
+				</body>
+			</html>"#;
+		assert_eq!(
+			get_text_from_html(html),
+			"Intro\nName\tAge\nAlice\t42\nfn main() {\n    println!(\"hi\");\n\n}\nAfter"
+		);
+	}
+}
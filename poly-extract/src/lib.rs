@@ -1,5 +1,12 @@
 pub mod docx;
+pub mod html;
+pub mod markdown;
 pub mod pdf;
 
 #[cfg(feature = "axum")]
 pub mod middleware;
+
+/// Trim each line of extracted text and remove empty lines
+fn normalize_lines(text: &str) -> String {
+	text.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join("\n")
+}
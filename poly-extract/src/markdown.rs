@@ -0,0 +1,40 @@
+use pulldown_cmark::{Event, Parser, Tag};
+
+/// Retrieve plain text from a Markdown document. Formatting is removed (the text of links and images is kept), and each
+/// block (paragraph, heading, list item, etc.) ends up on its own line.
+pub fn get_text_from_markdown(markdown: &str) -> String {
+	let mut text = String::new();
+	for event in Parser::new(markdown) {
+		match event {
+			Event::Text(t) | Event::Code(t) => text.push_str(&t),
+			Event::SoftBreak => text.push(' '),
+			Event::HardBreak | Event::Rule => text.push('\n'),
+			Event::End(tag) => {
+				if matches!(
+					tag,
+					Tag::Paragraph | Tag::Heading(..) | Tag::BlockQuote | Tag::CodeBlock(_) | Tag::Item | Tag::TableHead | Tag::TableRow
+				) {
+					text.push('\n');
+				} else if matches!(tag, Tag::TableCell) {
+					text.push(' ');
+				}
+			}
+			_ => {}
+		}
+	}
+	crate::normalize_lines(&text)
+}
+
+#[cfg(test)]
+mod test {
+	use super::get_text_from_markdown;
+
+	#[test]
+	pub fn test_get_text_from_markdown() {
+		let markdown = "# Heading\n\nSome *emphasized* and **strong** text\nwith a [link](https://example.com) and `code`.\n\n- One\n- Two\n\n```rust\nfn main() {}\n```\n\n> Quoted\n";
+		assert_eq!(
+			get_text_from_markdown(markdown),
+			"Heading\nSome emphasized and strong text with a link and code.\nOne\nTwo\nfn main() {}\nQuoted"
+		);
+	}
+}
@@ -21,15 +21,13 @@ where
 
 		if let Some(content_type) = content_type {
 			if content_type.starts_with("text/plain") {
-				let Ok(bytes) = hyper::body::to_bytes(req.body_mut()).await else {
-					return Err(StatusCode::UNPROCESSABLE_ENTITY.into_response());
-				};
-
-				return Ok(Self(
-					std::str::from_utf8(&bytes)
-						.map_err(|_| StatusCode::UNPROCESSABLE_ENTITY.into_response())?
-						.to_string(),
-				));
+				return Ok(Self(body_text(&mut req).await?));
+			} else if content_type.starts_with("text/html") {
+				let html = body_text(&mut req).await?;
+				return Ok(Self(crate::html::get_text_from_html(&html)));
+			} else if content_type.starts_with("text/markdown") {
+				let markdown = body_text(&mut req).await?;
+				return Ok(Self(crate::markdown::get_text_from_markdown(&markdown)));
 			} else if content_type == "application/vnd.openxmlformats-officedocument.wordprocessingml.document" {
 				let Ok(bytes) = hyper::body::to_bytes(req.body_mut()).await else {
 					return Err(StatusCode::UNPROCESSABLE_ENTITY.into_response());
@@ -59,3 +57,14 @@ where
 		Err(StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response())
 	}
 }
+
+/// Read the body of a request as UTF-8 text
+async fn body_text(req: &mut Request<axum::body::Body>) -> Result<String, axum::response::Response> {
+	let Ok(bytes) = hyper::body::to_bytes(req.body_mut()).await else {
+		return Err(StatusCode::UNPROCESSABLE_ENTITY.into_response());
+	};
+
+	Ok(std::str::from_utf8(&bytes)
+		.map_err(|_| StatusCode::UNPROCESSABLE_ENTITY.into_response())?
+		.to_string())
+}
@@ -2,7 +2,9 @@ use axum::{http::StatusCode, response::IntoResponse};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use poly_backend::stats::TaskStats;
+use poly_backend::stats::{MemoryStats, ModelStats, TaskStats};
+
+use crate::queue::QueueStats;
 use poly_backend::types::BackendError as OriginalGenerateError;
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -12,6 +14,27 @@ pub struct JwtClaims {
 	pub tasks: Option<Vec<String>>,    // Optional list of tasks this token is allowed to use
 	pub models: Option<Vec<String>>,   // Optional list of models this token is allowed to use
 	pub memories: Option<Vec<String>>, // Optional list of memories this token is allowed to use
+	pub admin: Option<bool>,           // Whether this token grants access to administrative endpoints
+}
+
+impl JwtClaims {
+	/// Whether these claims allow use of the indicated task
+	pub fn allows_task(&self, task_name: &str) -> bool {
+		self.tasks.as_ref().map(|tasks| tasks.iter().any(|t| t == task_name)).unwrap_or(true)
+	}
+
+	/// Whether these claims allow use of the indicated model
+	pub fn allows_model(&self, model_name: &str) -> bool {
+		self.models.as_ref().map(|models| models.iter().any(|m| m == model_name)).unwrap_or(true)
+	}
+
+	/// Whether these claims allow use of the indicated memory
+	pub fn allows_memory(&self, memory_name: &str) -> bool {
+		self.memories
+			.as_ref()
+			.map(|memories| memories.iter().any(|m| m == memory_name))
+			.unwrap_or(true)
+	}
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -22,6 +45,9 @@ pub struct KeyQuery {
 #[derive(Serialize, Clone, Debug)]
 pub struct StatsResponse {
 	pub tasks: HashMap<String, TaskStats>,
+	pub models: HashMap<String, ModelStats>,
+	pub memories: HashMap<String, MemoryStats>,
+	pub queue: QueueStats,
 }
 
 #[derive(Deserialize, Clone, Debug, Default)]
@@ -37,13 +63,34 @@ pub struct BackendError(OriginalGenerateError);
 impl BackendError {
 	fn status_code(&self) -> StatusCode {
 		match self.0 {
-			OriginalGenerateError::TaskNotFound(_) | OriginalGenerateError::ModelNotFound(_) | OriginalGenerateError::MemoryNotFound(_) => {
-				StatusCode::NOT_FOUND
-			}
+			OriginalGenerateError::TaskNotFound(_)
+			| OriginalGenerateError::TaskSchemaNotFound(_)
+			| OriginalGenerateError::SessionNotFound(_)
+			| OriginalGenerateError::ModelNotFound(_)
+			| OriginalGenerateError::MemoryNotFound(_) => StatusCode::NOT_FOUND,
 			OriginalGenerateError::InferenceError(_) | OriginalGenerateError::TokenizationError(_) => StatusCode::INTERNAL_SERVER_ERROR,
 			OriginalGenerateError::Memory(_) => StatusCode::INTERNAL_SERVER_ERROR,
-			OriginalGenerateError::IllegalToken | OriginalGenerateError::InvalidDocument => StatusCode::BAD_REQUEST,
+			OriginalGenerateError::BiaserStuck(_) => StatusCode::INTERNAL_SERVER_ERROR,
+			OriginalGenerateError::IllegalToken
+			| OriginalGenerateError::InvalidDocument
+			| OriginalGenerateError::InvalidItemsRange
+			| OriginalGenerateError::InvalidParameter(_)
+			| OriginalGenerateError::InvalidCompletionCount(_)
+			| OriginalGenerateError::InvalidLogitBiasToken(_)
+			| OriginalGenerateError::InvalidTokenId(_)
+			| OriginalGenerateError::AssistantPrefixUnsupported(_) => StatusCode::BAD_REQUEST,
 			OriginalGenerateError::InvalidChunkSeparator(_) => StatusCode::INTERNAL_SERVER_ERROR,
+			OriginalGenerateError::EmbeddingUnsupported(_) => StatusCode::NOT_IMPLEMENTED,
+			OriginalGenerateError::MemoryReadOnly(_) => StatusCode::FORBIDDEN,
+			OriginalGenerateError::ModelFileTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+			OriginalGenerateError::ModelFileChecksumMismatch { .. } => StatusCode::BAD_REQUEST,
+			OriginalGenerateError::ModelFileUpload(_)
+			| OriginalGenerateError::ModelLoad { .. }
+			| OriginalGenerateError::ModelDownload(_, _)
+			| OriginalGenerateError::ModelFileNotFound(_, _)
+			| OriginalGenerateError::SchemaFile(_, _)
+			| OriginalGenerateError::UnsupportedSchema(_, _) => StatusCode::INTERNAL_SERVER_ERROR,
+			OriginalGenerateError::InvalidConfig(_) => StatusCode::UNPROCESSABLE_ENTITY,
 		}
 	}
 }
@@ -0,0 +1,122 @@
+use std::{
+	fs::OpenOptions,
+	path::PathBuf,
+	sync::Arc,
+	time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{extract::State, http::Request, middleware::Next, response::Response, Extension};
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, sync::mpsc::UnboundedSender};
+
+use crate::{api::JwtClaims, server::Server};
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct AuditLogConfig {
+	/// Path to the file to append audit records to (one JSON object per line)
+	pub path: PathBuf,
+
+	/// Whether to include the full text of prompts and completions in the audit log (by default only lengths are logged)
+	#[serde(default)]
+	pub include_text: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditRecord {
+	/// An API request was handled
+	Request {
+		sub: Option<String>,
+		method: String,
+		path: String,
+		status: u16,
+		duration_ms: u128,
+	},
+
+	/// A completion was generated
+	Completion {
+		sub: Option<String>,
+		task: String,
+		model: String,
+		prompt_length: usize,
+		output_length: usize,
+		prompt_tokens: usize,
+		predict_tokens: usize,
+		duration_ms: u128,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		prompt: Option<String>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		output: Option<String>,
+	},
+}
+
+#[derive(Serialize)]
+struct TimestampedAuditRecord<'a> {
+	timestamp: u64,
+
+	#[serde(flatten)]
+	record: &'a AuditRecord,
+}
+
+/// Appends audit records to a JSONL file. Records are written by a background task, so that recording does not block
+/// the (async or blocking) thread handling the request.
+pub struct AuditLog {
+	config: AuditLogConfig,
+	sender: UnboundedSender<String>,
+}
+
+impl AuditLog {
+	/// Opens the audit log and starts the task writing records to it. Must be called from within a Tokio runtime.
+	pub fn new(config: AuditLogConfig) -> std::io::Result<AuditLog> {
+		let file = OpenOptions::new().create(true).append(true).open(&config.path)?;
+		let mut file = tokio::fs::File::from_std(file);
+		let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<String>();
+		tokio::spawn(async move {
+			while let Some(line) = receiver.recv().await {
+				// Flush each record, so it is written even when the server is stopped abruptly
+				let result = async {
+					file.write_all(line.as_bytes()).await?;
+					file.flush().await
+				};
+				if let Err(e) = result.await {
+					tracing::error!("could not write to audit log: {e}");
+				}
+			}
+		});
+		Ok(AuditLog { config, sender })
+	}
+
+	/// Whether the full text of prompts and completions should be recorded
+	pub fn include_text(&self) -> bool {
+		self.config.include_text
+	}
+
+	pub fn record(&self, record: AuditRecord) {
+		let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+		let mut line = serde_json::to_string(&TimestampedAuditRecord { timestamp, record: &record }).unwrap();
+		line.push('\n');
+		if self.sender.send(line).is_err() {
+			tracing::error!("could not write to audit log: writer has stopped");
+		}
+	}
+}
+
+/// Middleware that records each request in the audit log (if enabled)
+pub async fn audit<T>(State(state): State<Arc<Server>>, Extension(claims): Extension<JwtClaims>, req: Request<T>, next: Next<T>) -> Response {
+	let Some(ref audit_log) = state.audit_log else {
+		return next.run(req).await;
+	};
+
+	let method = req.method().to_string();
+	let path = req.uri().path().to_string();
+	let start = Instant::now();
+	let response = next.run(req).await;
+	audit_log.record(AuditRecord::Request {
+		sub: claims.sub,
+		method,
+		path,
+		status: response.status().as_u16(),
+		duration_ms: start.elapsed().as_millis(),
+	});
+	response
+}
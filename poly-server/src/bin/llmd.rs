@@ -1,23 +1,29 @@
+use async_stream::stream;
 use axum::extract::State;
 use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
 use axum::http::{HeaderValue, Method, StatusCode};
 
 use axum::response::IntoResponse;
-use axum::routing::get;
-use axum::{Json, Router};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
 use clap::Parser;
+use futures_util::future::join_all;
 use poly_backend::backend::Backend;
+use poly_backend::check::check_config;
 use poly_backend::types::{Status, StatusResponse};
-use poly_server::api::StatsResponse;
+use poly_server::api::{BackendError, JwtClaims, StatsResponse};
+use poly_server::audit::audit;
 use poly_server::config::{Args, Config};
 use poly_server::middleware::authenticate;
+use poly_server::queue::queue;
 use poly_server::routes;
 use poly_server::server::Server;
 
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{fs::File, io::Read};
-use tower::limit::ConcurrencyLimitLayer;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
@@ -26,6 +32,9 @@ use tracing_subscriber::EnvFilter;
 
 pub use llm::InferenceFeedback;
 
+/// Prefix for bind addresses that indicates a Unix domain socket path
+const UNIX_SOCKET_PREFIX: &str = "unix:";
+
 #[tokio::main]
 async fn main() {
 	tracing_subscriber::fmt()
@@ -33,12 +42,18 @@ async fn main() {
 		.init();
 	// Read config file
 	let args = Args::parse();
-	let mut config_file = File::open(args.config_path).expect("open config file");
+	if args.check {
+		std::process::exit(check(&args.config_path));
+	}
+
+	let mut config_file = File::open(&args.config_path).expect("open config file");
 	let mut config_string = String::new();
 	config_file.read_to_string(&mut config_string).expect("read config file");
 	let config: Config = toml::from_str(&config_string).unwrap();
-	let bind_address: SocketAddr = config.bind_address.parse().unwrap();
-	info!("Starting llmd; bind address: {bind_address}",);
+	let bind_addresses: Vec<String> = std::iter::once(config.bind_address.clone())
+		.chain(config.additional_bind_addresses.iter().cloned())
+		.collect();
+	info!("Starting llmd; bind addresses: {bind_addresses:?}",);
 
 	// Set up CORS
 	let mut cors_layer = CorsLayer::new();
@@ -57,8 +72,11 @@ async fn main() {
 	cors_layer = cors_layer.allow_headers([CONTENT_TYPE, AUTHORIZATION]);
 	cors_layer = cors_layer.allow_methods([Method::GET, Method::POST, Method::OPTIONS, Method::PUT, Method::DELETE]);
 
-	let backend = Arc::new(Backend::from(config.backend_config.clone(), None).await);
-	let state = Arc::new(Server::new(backend, config));
+	let backend = match Backend::from(config.backend_config.clone(), None).await {
+		Ok(backend) => Arc::new(backend),
+		Err(e) => exit_with_startup_error(e),
+	};
+	let state = Arc::new(Server::new(backend, config, Some(args.config_path.clone())));
 
 	// Set up API server
 	let app = Router::new()
@@ -70,21 +88,140 @@ async fn main() {
 				.nest("/model", routes::models::router())
 				.nest("/task", routes::tasks::router())
 				.nest("/memory", routes::memories::router())
+				.nest("/admin", routes::admin::router())
 				.route("/stats", get(stats_handler))
+				.route(
+					"/stats/reset",
+					post(stats_reset_handler).layer(axum::middleware::from_fn(routes::admin::authorize)),
+				)
+				.layer(axum::middleware::from_fn_with_state(state.clone(), audit))
 				.layer(axum::middleware::from_fn_with_state(state.clone(), authenticate)),
 		)
 		.fallback(handler_not_found)
 		.layer(cors_layer)
-		.layer(ConcurrencyLimitLayer::new(state.config.max_concurrent))
+		.layer(axum::middleware::from_fn_with_state(state.clone(), queue))
 		.layer(TraceLayer::new_for_http())
 		.with_state(state);
 
-	axum::Server::bind(&bind_address).serve(app.into_make_service()).await.unwrap();
+	join_all(bind_addresses.iter().map(|bind_address| serve(bind_address, app.clone()))).await;
+}
+
+/// Serve on a TCP address (e.g. "0.0.0.0:3000") or on a Unix domain socket (e.g. "unix:/tmp/llmd.sock")
+async fn serve(bind_address: &str, app: Router) {
+	if let Some(socket_path) = bind_address.strip_prefix(UNIX_SOCKET_PREFIX) {
+		serve_unix(socket_path, app).await;
+	} else {
+		let bind_address: SocketAddr = bind_address.parse().unwrap();
+		axum::Server::bind(&bind_address).serve(app.into_make_service()).await.unwrap();
+	}
+}
+
+#[cfg(unix)]
+async fn serve_unix(socket_path: &str, app: Router) {
+	use std::os::unix::fs::FileTypeExt;
+
+	// Remove a socket that was left behind by an earlier run
+	if std::fs::metadata(socket_path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+		if let Err(e) = std::fs::remove_file(socket_path) {
+			exit_with_startup_error(format!("could not remove existing Unix socket {socket_path}: {e}"));
+		}
+	}
+
+	let listener = match tokio::net::UnixListener::bind(socket_path) {
+		Ok(listener) => listener,
+		Err(e) => exit_with_startup_error(format!("could not bind Unix socket {socket_path}: {e}")),
+	};
+	let incoming = stream! {
+		loop {
+			match listener.accept().await {
+				Ok((stream, _)) => {
+					yield Ok::<_, std::io::Error>(stream);
+				}
+				Err(e) => {
+					// Failing to accept a single connection (e.g. when out of file descriptors) should not stop the server
+					tracing::warn!("could not accept connection on Unix socket {socket_path}: {e}");
+					tokio::time::sleep(Duration::from_millis(100)).await;
+				}
+			}
+		}
+	};
+	if let Err(e) = axum::Server::builder(hyper::server::accept::from_stream(incoming))
+		.serve(app.into_make_service())
+		.await
+	{
+		tracing::error!("serving on Unix socket {socket_path} failed: {e}");
+		std::process::exit(1);
+	}
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(_socket_path: &str, _app: Router) {
+	panic!("Unix domain sockets are not supported on this platform");
+}
+
+/// Report an error that prevents llmd from starting and exit
+fn exit_with_startup_error(error: impl std::fmt::Display) -> ! {
+	eprintln!("Could not start llmd: {error}");
+	std::process::exit(1);
+}
+
+/// Validate the configuration file and print a report. Returns the exit code (non-zero when any problem was found).
+fn check(config_path: &Path) -> i32 {
+	let config_string = match std::fs::read_to_string(config_path) {
+		Ok(s) => s,
+		Err(e) => {
+			println!("FAIL config file {config_path:?} could not be read: {e}");
+			return 1;
+		}
+	};
+
+	let config: Config = match toml::from_str(&config_string) {
+		Ok(config) => config,
+		Err(e) => {
+			println!("FAIL config file {config_path:?} is invalid: {e}");
+			return 1;
+		}
+	};
+	println!("OK   config file {config_path:?}");
+
+	let results = check_config(&config.backend_config);
+	for result in &results {
+		println!("{result}");
+	}
+
+	let n_failed = results.iter().filter(|r| !r.is_ok()).count();
+	if n_failed > 0 {
+		println!("{n_failed} item(s) failed validation");
+		1
+	} else {
+		println!("configuration is valid");
+		0
+	}
+}
+
+/// Returns usage statistics (only for the tasks, models and memories the caller is allowed to use)
+async fn stats_handler(State(state): State<Arc<Server>>, Extension(claims): Extension<JwtClaims>) -> Result<Json<StatsResponse>, BackendError> {
+	let mut task_stats = state.backend.stats.task_stats.lock().unwrap().clone();
+	task_stats.retain(|task_name, _| claims.allows_task(task_name));
+	let mut model_stats = state.backend.stats.model_stats.lock().unwrap().clone();
+	model_stats.retain(|model_name, _| claims.allows_model(model_name));
+	let mut memory_stats = state.backend.stats.memory_stats.lock().unwrap().clone();
+	for (memory_name, count) in state.backend.memory_counts().await {
+		memory_stats.entry(memory_name).or_default().count = count;
+	}
+	memory_stats.retain(|memory_name, _| claims.allows_memory(memory_name));
+	Ok(Json(StatsResponse {
+		tasks: task_stats,
+		models: model_stats,
+		memories: memory_stats,
+		queue: state.queue.stats(),
+	}))
 }
 
-async fn stats_handler(State(state): State<Arc<Server>>) -> impl IntoResponse {
-	let task_stats = state.backend.stats.task_stats.lock().unwrap().clone();
-	Json(StatsResponse { tasks: task_stats })
+/// Clears all statistics (e.g. between benchmark runs)
+async fn stats_reset_handler(State(state): State<Arc<Server>>) -> StatusCode {
+	state.backend.stats.reset();
+	StatusCode::NO_CONTENT
 }
 
 async fn status_handler() -> impl IntoResponse {
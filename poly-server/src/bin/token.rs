@@ -27,6 +27,10 @@ pub struct Args {
 	/// User ID (`sub` claim) in token
 	#[arg(long, short = 's')]
 	pub sub: Option<String>,
+
+	/// Whether the token grants access to administrative endpoints
+	#[arg(long)]
+	pub admin: bool,
 }
 
 pub fn main() {
@@ -49,6 +53,7 @@ pub fn main() {
 					tasks: args.tasks,
 					models: args.models,
 					memories: args.memories,
+					admin: args.admin.then_some(true),
 				},
 				&ek,
 			)
@@ -5,6 +5,8 @@ use poly_backend::config::BackendConfig;
 use serde::Deserialize;
 use std::path::PathBuf;
 
+use crate::audit::AuditLogConfig;
+
 #[derive(Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum JwtPrivateKey {
@@ -14,9 +16,12 @@ pub enum JwtPrivateKey {
 #[derive(Deserialize, Clone, Debug)]
 #[serde(default)]
 pub struct Config {
-	/// Address and port to bind the server to ("0.0.0.0:1234")
+	/// Address and port to bind the server to ("0.0.0.0:1234"), or path to a Unix domain socket ("unix:/tmp/llmd.sock")
 	pub bind_address: String,
 
+	/// Additional addresses (or Unix domain sockets) to bind the server to
+	pub additional_bind_addresses: Vec<String>,
+
 	#[serde(flatten)]
 	pub backend_config: BackendConfig,
 
@@ -26,26 +31,52 @@ pub struct Config {
 	/// The maximum number of concurrent requests serviced
 	pub max_concurrent: usize,
 
+	/// The maximum number of requests that can wait for servicing when `max_concurrent` requests are already being
+	/// serviced (unbounded when not set). Requests beyond this limit are rejected with 503
+	pub max_queue_depth: Option<usize>,
+
+	/// The maximum number of seconds a request can wait in the queue before it is rejected with 503 (unbounded when not set)
+	pub queue_timeout_secs: Option<u64>,
+
+	/// The maximum number of completions that can be requested for a single prompt (`n`)
+	pub max_completions: usize,
+
 	/// Whether access is allowed without keys
 	pub public: bool,
 
 	/// Allowed static API keys
 	pub allowed_keys: Vec<String>,
 
+	/// Static API keys that also grant access to administrative endpoints
+	pub admin_keys: Vec<String>,
+
+	/// The maximum size (in bytes) of a model file that can be uploaded through the API
+	pub max_model_upload_size: u64,
+
 	/// Key for JWT signed keys
 	pub jwt_private_key: Option<JwtPrivateKey>,
+
+	/// When configured, requests and completions are recorded in an audit log
+	pub audit_log: Option<AuditLogConfig>,
 }
 
 impl Default for Config {
 	fn default() -> Self {
 		Self {
 			bind_address: String::from("0.0.0.0:3000"),
+			additional_bind_addresses: vec![],
 			backend_config: BackendConfig::default(),
 			allowed_origins: None,
 			max_concurrent: 8,
+			max_queue_depth: None,
+			queue_timeout_secs: None,
+			max_completions: 8,
 			allowed_keys: vec![],
+			admin_keys: vec![],
+			max_model_upload_size: 32 * 1024 * 1024 * 1024,
 			public: false,
 			jwt_private_key: None,
+			audit_log: None,
 		}
 	}
 }
@@ -56,6 +87,10 @@ pub struct Args {
 	/// Where to load the config file from
 	#[arg(long, short = 'm', default_value = "config.toml")]
 	pub config_path: PathBuf,
+
+	/// Validate the configuration (without loading models), print a report and exit
+	#[arg(long)]
+	pub check: bool,
 }
 
 impl JwtPrivateKey {
@@ -1,5 +1,7 @@
 pub mod api;
+pub mod audit;
 pub mod config;
 pub mod middleware;
+pub mod queue;
 pub mod routes;
 pub mod server;
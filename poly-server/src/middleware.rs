@@ -44,9 +44,10 @@ pub async fn authenticate<T>(
 	let claims: JwtClaims = match auth_token {
 		Some(auth_token) => {
 			// Check if key is allowed
-			if state.config.allowed_keys.contains(&auth_token) {
+			if state.config.allowed_keys.contains(&auth_token) || state.config.admin_keys.contains(&auth_token) {
 				// OK
 				JwtClaims {
+					admin: Some(state.config.admin_keys.contains(&auth_token)),
 					sub: Some(auth_token),
 					..Default::default()
 				}
@@ -0,0 +1,106 @@
+use std::{
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use axum::{
+	extract::State,
+	http::{header::RETRY_AFTER, Request, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use tokio::sync::Semaphore;
+
+use crate::server::Server;
+
+/// Number of seconds a client is asked to wait before retrying a request that was rejected by the queue
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// Bounded queue for requests that arrive while the maximum number of concurrent requests is already being serviced
+pub struct RequestQueue {
+	permits: Arc<Semaphore>,
+	max_concurrent: usize,
+	max_waiting: Option<usize>,
+	timeout: Option<Duration>,
+	waiting: AtomicUsize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct QueueStats {
+	/// Number of requests currently being serviced
+	pub active: usize,
+
+	/// Number of requests currently waiting in the queue
+	pub waiting: usize,
+}
+
+/// Decrements the number of waiting requests when a request leaves the queue (also when the request is dropped)
+struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for WaitingGuard<'a> {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+impl RequestQueue {
+	pub fn new(max_concurrent: usize, max_waiting: Option<usize>, timeout: Option<Duration>) -> Self {
+		RequestQueue {
+			permits: Arc::new(Semaphore::new(max_concurrent)),
+			max_concurrent,
+			max_waiting,
+			timeout,
+			waiting: AtomicUsize::new(0),
+		}
+	}
+
+	pub fn stats(&self) -> QueueStats {
+		QueueStats {
+			active: self.max_concurrent - self.permits.available_permits(),
+			waiting: self.waiting.load(Ordering::SeqCst),
+		}
+	}
+}
+
+fn service_unavailable(reason: &'static str) -> Response {
+	(StatusCode::SERVICE_UNAVAILABLE, [(RETRY_AFTER, RETRY_AFTER_SECS.to_string())], reason).into_response()
+}
+
+/// Middleware that limits the number of concurrently serviced requests. Requests that cannot be serviced immediately
+/// wait in a bounded queue, and are rejected with 503 when the queue is full or when they have waited too long.
+pub async fn queue<T>(State(state): State<Arc<Server>>, req: Request<T>, next: Next<T>) -> Response {
+	let queue = &state.queue;
+
+	let _permit = match queue.permits.clone().try_acquire_owned() {
+		Ok(permit) => permit,
+		Err(_) => {
+			let waiting = queue.waiting.fetch_add(1, Ordering::SeqCst);
+			let _waiting_guard = WaitingGuard(&queue.waiting);
+			if let Some(max_waiting) = queue.max_waiting {
+				if waiting >= max_waiting {
+					tracing::warn!(waiting, "request rejected because the queue is full");
+					return service_unavailable("queue is full");
+				}
+			}
+
+			let acquire = queue.permits.clone().acquire_owned();
+			let permit = match queue.timeout {
+				Some(timeout) => match tokio::time::timeout(timeout, acquire).await {
+					Ok(permit) => permit,
+					Err(_) => {
+						tracing::warn!("request rejected because it waited too long in the queue");
+						return service_unavailable("timed out waiting in queue");
+					}
+				},
+				None => acquire.await,
+			};
+			permit.expect("request semaphore is never closed")
+		}
+	};
+
+	next.run(req).await
+}
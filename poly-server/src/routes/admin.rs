@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use axum::{
+	extract::{Path, State},
+	http::{Request, StatusCode},
+	middleware::Next,
+	response::IntoResponse,
+	routing::{get, post},
+	Extension, Json, Router,
+};
+use poly_backend::types::{AbortResponse, BackendError as OriginalGenerateError, ReloadResponse, SessionsResponse};
+
+use crate::{
+	api::{BackendError, JwtClaims},
+	config::Config,
+	server::Server,
+};
+
+pub fn router() -> Router<Arc<Server>, axum::body::Body> {
+	Router::new()
+		.route("/task/:task/abort", post(abort_task_handler))
+		.route("/sessions", get(sessions_handler))
+		.route("/reload", post(reload_handler))
+		.layer(axum::middleware::from_fn(authorize))
+}
+
+/// Signals all in-flight completions for a task to stop generating
+async fn abort_task_handler(State(state): State<Arc<Server>>, Path(task_name): Path<String>) -> Result<Json<AbortResponse>, BackendError> {
+	Ok(Json(AbortResponse {
+		aborted: state.backend.abort_task(&task_name)?,
+	}))
+}
+
+/// Lists the sessions that are currently active (chat connections, live streams and in-flight completions)
+async fn sessions_handler(State(state): State<Arc<Server>>) -> Json<SessionsResponse> {
+	Json(SessionsResponse {
+		sessions: state.backend.sessions(),
+	})
+}
+
+/// Reads the configuration file again and applies the backend configuration (models, memories and tasks). Other
+/// settings (such as keys and bind addresses) only take effect after a restart.
+async fn reload_handler(State(state): State<Arc<Server>>) -> Result<Json<ReloadResponse>, BackendError> {
+	let Some(ref config_path) = state.config_path else {
+		return Err(OriginalGenerateError::InvalidConfig(String::from("configuration was not read from a file")).into());
+	};
+	let config_string = tokio::fs::read_to_string(config_path)
+		.await
+		.map_err(|e| OriginalGenerateError::InvalidConfig(format!("could not read {config_path:?}: {e}")))?;
+	let config: Config =
+		toml::from_str(&config_string).map_err(|e| OriginalGenerateError::InvalidConfig(format!("could not parse {config_path:?}: {e}")))?;
+	Ok(Json(state.backend.reload(config.backend_config).await?))
+}
+
+/// Middleware that checks whether the user has access to administrative endpoints.
+pub async fn authorize<T>(Extension(claims): Extension<JwtClaims>, req: Request<T>, next: Next<T>) -> Result<impl IntoResponse, StatusCode> {
+	if claims.admin != Some(true) {
+		return Err(StatusCode::UNAUTHORIZED);
+	}
+
+	Ok(next.run(req).await)
+}
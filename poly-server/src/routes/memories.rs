@@ -1,14 +1,15 @@
-use std::sync::Arc;
+use std::{convert::Infallible, sync::Arc};
 
+use async_stream::stream;
 use axum::{
 	extract::{Path, Query, State},
 	http::{Request, StatusCode},
 	middleware::Next,
-	response::IntoResponse,
+	response::{sse::Event, IntoResponse, Response, Sse},
 	routing::{delete, get, post, put},
 	Extension, Json, Router,
 };
-use poly_backend::types::MemoriesResponse;
+use poly_backend::types::{BackendError as OriginalGenerateError, MemoriesResponse, MemorizeSummary, RecalledChunk};
 use poly_extract::middleware::Plaintext;
 use serde::{Deserialize, Serialize};
 
@@ -25,37 +26,101 @@ pub fn router() -> Router<Arc<Server>, axum::body::Body> {
 			.route("/", get(get_memory_recall_handler))
 			.route("/", post(post_memory_recall_handler))
 			.route("/", put(put_memory_ingest_handler))
+			.route("/batch", put(put_memory_ingest_batch_handler))
+			.route("/items", get(memory_items_handler))
+			.route("/item", delete(delete_memory_item_handler))
 			.layer(axum::middleware::from_fn(authorize)),
 	)
 }
 
-async fn memories_handler(State(state): State<Arc<Server>>) -> impl IntoResponse {
-	Json(MemoriesResponse {
-		memories: state.config.backend_config.memories.keys().cloned().collect(),
-	})
+/// Lists the memories the caller is allowed to use
+async fn memories_handler(State(state): State<Arc<Server>>, Extension(claims): Extension<JwtClaims>) -> Result<Json<MemoriesResponse>, BackendError> {
+	let mut counts = state.backend.memory_counts().await;
+	counts.retain(|memory_name, _| claims.allows_memory(memory_name));
+	Ok(Json(MemoriesResponse {
+		memories: state
+			.backend
+			.config()
+			.memories
+			.keys()
+			.filter(|memory_name| claims.allows_memory(memory_name))
+			.cloned()
+			.collect(),
+		counts,
+	}))
 }
 
 #[derive(Deserialize)]
 pub struct RecallRequest {
 	pub prompt: String,
 	pub n: Option<usize>,
+
+	/// Skip items that are (nearly) identical to items that were already recalled
+	#[serde(default)]
+	pub dedup: bool,
+
+	/// Only return items with at least this score
+	pub min_score: Option<f32>,
 }
 
 #[derive(Serialize)]
 pub struct RecallResponse {
-	pub chunks: Vec<String>,
+	pub chunks: Vec<RecalledChunk>,
+
+	/// Number of items stored in the memory
+	pub count: usize,
+}
+
+/// Number of items listed when the request does not specify a limit
+const DEFAULT_ITEMS_LIMIT: usize = 100;
+
+/// Maximum number of items listed in a single request
+const MAX_ITEMS_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct ItemsRequest {
+	#[serde(default)]
+	pub offset: usize,
+	pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct ItemsResponse {
+	pub items: Vec<String>,
+
+	/// Total number of items in the memory
+	pub total: usize,
 }
 
 #[derive(Serialize)]
 pub struct ForgetResponse {}
 
+#[derive(Deserialize)]
+pub struct ForgetItemRequest {
+	pub text: String,
+}
+
+#[derive(Serialize)]
+pub struct ForgetItemResponse {
+	/// Whether an item with the requested text was found (and removed)
+	pub deleted: bool,
+}
+
 #[derive(Serialize)]
-pub struct RememberResponse {}
+pub struct RememberResponse {
+	/// How the document(s) were chunked and stored (not available when ingestion was deferred to the background)
+	#[serde(flatten)]
+	pub summary: Option<MemorizeSummary>,
+}
 
 #[derive(Deserialize)]
 pub struct IngestRequest {
 	#[serde(default = "default_wait")]
 	pub wait: bool,
+
+	/// Respond with a stream of server-sent events that report ingestion progress (implies waiting)
+	#[serde(default)]
+	pub stream: bool,
 }
 
 const fn default_wait() -> bool {
@@ -67,19 +132,79 @@ async fn put_memory_ingest_handler(
 	Path(memory_name): Path<String>,
 	Query(params): Query<IngestRequest>,
 	Plaintext(body): Plaintext,
-) -> Result<Json<RememberResponse>, BackendError> {
+) -> Result<Response, BackendError> {
+	if params.stream {
+		state.backend.check_memory_writable(&memory_name)?;
+		let backend = state.backend.clone();
+		let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(32);
+		let ingest = tokio::spawn(async move { backend.memorize_with_progress(&memory_name, &body, Some(progress_tx)).await });
+
+		// When the client disconnects, the progress receiver is dropped, which stops ingestion
+		let stream = stream! {
+			while let Some(progress) = progress_rx.recv().await {
+				yield Ok::<_, Infallible>(Event::default().event("progress").json_data(progress).unwrap());
+			}
+
+			// The progress channel is closed when ingestion has ended
+			match ingest.await.unwrap() {
+				Ok(summary) => yield Ok(Event::default().event("finish").json_data(RememberResponse { summary: Some(summary) }).unwrap()),
+				Err(e) => {
+					tracing::error!("error ingesting: {e}");
+					yield Ok(Event::default().event("error").data(e.to_string()))
+				}
+			}
+		};
+		return Ok(Sse::new(stream).into_response());
+	}
+
 	if params.wait {
-		state.backend.memorize(&memory_name, &body).await?;
-	} else {
-		// Defer to a background job
-		state
-			.ingest(IngestItem {
-				memory_name,
-				plaintext: body,
-			})
-			.await;
+		let summary = state.backend.memorize(&memory_name, &body).await?;
+		return Ok(Json(RememberResponse { summary: Some(summary) }).into_response());
+	}
+
+	// Defer to a background job
+	state.backend.check_memory_writable(&memory_name)?;
+	state
+		.ingest(IngestItem {
+			memory_name,
+			plaintext: body,
+		})
+		.await;
+	Ok(Json(RememberResponse { summary: None }).into_response())
+}
+
+/// Ingests multiple documents (provided as a JSON array of strings) at once
+async fn put_memory_ingest_batch_handler(
+	State(state): State<Arc<Server>>,
+	Path(memory_name): Path<String>,
+	Json(documents): Json<Vec<String>>,
+) -> Result<Json<RememberResponse>, BackendError> {
+	let summary = state.backend.memorize_batch(&memory_name, &documents).await?;
+	Ok(Json(RememberResponse { summary: Some(summary) }))
+}
+
+/// Lists the items stored in a memory, a page at a time
+async fn memory_items_handler(
+	State(state): State<Arc<Server>>,
+	Path(memory_name): Path<String>,
+	Query(request): Query<ItemsRequest>,
+) -> Result<Json<ItemsResponse>, BackendError> {
+	let limit = request.limit.unwrap_or(DEFAULT_ITEMS_LIMIT).min(MAX_ITEMS_LIMIT);
+	if request.offset.checked_add(limit).and_then(|end| u32::try_from(end).ok()).is_none() {
+		return Err(OriginalGenerateError::InvalidItemsRange.into());
 	}
-	Ok(Json(RememberResponse {}))
+	let (items, total) = state.backend.memory_items(&memory_name, request.offset, limit).await?;
+	Ok(Json(ItemsResponse { items, total }))
+}
+
+/// Removes a single item (identified by its text) from a memory
+async fn delete_memory_item_handler(
+	State(state): State<Arc<Server>>,
+	Path(memory_name): Path<String>,
+	Json(request): Json<ForgetItemRequest>,
+) -> Result<Json<ForgetItemResponse>, BackendError> {
+	let deleted = state.backend.forget_item(&memory_name, &request.text).await?;
+	Ok(Json(ForgetItemResponse { deleted }))
 }
 
 async fn delete_memory_items_handler(
@@ -108,9 +233,14 @@ async fn get_memory_recall_handler(
 
 async fn memory_recall_handler(state: Arc<Server>, memory_name: &str, request: RecallRequest) -> Result<RecallResponse, BackendError> {
 	let backend = state.backend.clone();
-	Ok(RecallResponse {
-		chunks: backend.recall(memory_name, &request.prompt, request.n.unwrap_or(1)).await?,
-	})
+	let mut chunks = backend
+		.recall(memory_name, &request.prompt, request.n.unwrap_or(1), request.dedup)
+		.await?;
+	if let Some(min_score) = request.min_score {
+		chunks.retain(|chunk| chunk.score >= min_score);
+	}
+	let count = backend.memory_count(memory_name).await?;
+	Ok(RecallResponse { chunks, count })
 }
 
 /// Middleware that checks whether the user has access to a certain model.
@@ -120,10 +250,8 @@ pub async fn authorize<T>(
 	req: Request<T>,
 	next: Next<T>,
 ) -> Result<impl IntoResponse, StatusCode> {
-	if let Some(memories) = &claims.memories {
-		if !memories.contains(&memory_name) {
-			return Err(StatusCode::UNAUTHORIZED);
-		}
+	if !claims.allows_memory(&memory_name) {
+		return Err(StatusCode::UNAUTHORIZED);
 	}
 
 	Ok(next.run(req).await)
@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod memories;
 pub mod models;
 pub mod tasks;
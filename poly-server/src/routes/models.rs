@@ -1,14 +1,20 @@
 use std::sync::Arc;
 
 use axum::{
-	extract::{Path, Query, State},
+	extract::{BodyStream, Path, Query, State},
 	http::{Request, StatusCode},
 	middleware::Next,
 	response::IntoResponse,
-	routing::{get, post},
+	routing::{get, post, put},
 	Extension, Json, Router,
 };
-use poly_backend::types::{EmbeddingResponse, ModelsResponse, PromptRequest, SessionAndPromptRequest, SessionRequest, TokenizationResponse};
+use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
+use poly_backend::types::{
+	DetokenizationRequest, DetokenizationResponse, EmbeddingResponse, ModelFileResponse, ModelsResponse, PromptRequest, ScoreRequest, ScoreResponse,
+	SessionAndPromptRequest, SessionRequest, TokenizationResponse,
+};
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
 	api::{BackendError, JwtClaims},
@@ -23,41 +29,106 @@ pub fn router() -> Router<Arc<Server>, axum::body::Body> {
 			.route("/embedding", get(get_model_embedding_handler))
 			.route("/tokenization", post(post_model_tokenize_handler))
 			.route("/tokenization", get(get_model_tokenize_handler))
+			.route("/detokenization", post(post_model_detokenize_handler))
+			.route("/score", post(post_model_score_handler))
+			.route(
+				"/file",
+				put(put_model_file_handler).layer(axum::middleware::from_fn(crate::routes::admin::authorize)),
+			)
 			.layer(axum::middleware::from_fn(authorize)),
 	)
 }
 
-async fn models_handler(State(state): State<Arc<Server>>) -> impl IntoResponse {
+/// Lists the models the caller is allowed to use
+async fn models_handler(State(state): State<Arc<Server>>, Extension(claims): Extension<JwtClaims>) -> impl IntoResponse {
 	Json(ModelsResponse {
-		models: state.config.backend_config.models.keys().cloned().collect(),
+		models: state
+			.backend
+			.config()
+			.models
+			.keys()
+			.filter(|model_name| claims.allows_model(model_name))
+			.cloned()
+			.collect(),
 	})
 }
 
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum EmbeddingEncoding {
+	/// Embedding as an array of numbers
+	#[default]
+	Float,
+
+	/// Embedding as base64-encoded little-endian 32-bit floats
+	Base64,
+}
+
+#[derive(Deserialize, Debug)]
+struct EmbeddingQuery {
+	/// How the embedding vector should be encoded in the response
+	#[serde(default)]
+	encoding: EmbeddingEncoding,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum EncodedEmbedding {
+	Float(Vec<f32>),
+	Base64(String),
+}
+
+#[derive(Serialize, Debug)]
+struct EncodedEmbeddingResponse {
+	embedding: EncodedEmbedding,
+}
+
+impl EncodedEmbeddingResponse {
+	fn encode(response: EmbeddingResponse, encoding: EmbeddingEncoding) -> Self {
+		let embedding = match encoding {
+			EmbeddingEncoding::Float => EncodedEmbedding::Float(response.embedding),
+			EmbeddingEncoding::Base64 => {
+				let bytes: Vec<u8> = response.embedding.iter().flat_map(|f| f.to_le_bytes()).collect();
+				EncodedEmbedding::Base64(BASE64_STANDARD.encode(bytes))
+			}
+		};
+		EncodedEmbeddingResponse { embedding }
+	}
+}
+
 async fn get_model_embedding_handler(
 	State(state): State<Arc<Server>>,
 	Path(endpoint_name): Path<String>,
+	Query(query): Query<EmbeddingQuery>,
 	Query(request): Query<SessionAndPromptRequest>,
-) -> Result<Json<EmbeddingResponse>, BackendError> {
+) -> Result<Json<EncodedEmbeddingResponse>, BackendError> {
 	let SessionAndPromptRequest { session, prompt } = request;
-	embedding_handler(state, &endpoint_name, &session, &prompt)
+	embedding_handler(state, endpoint_name, session, prompt, query.encoding).await
 }
 
 async fn post_model_embedding_handler(
 	State(state): State<Arc<Server>>,
 	Path(endpoint_name): Path<String>,
+	Query(query): Query<EmbeddingQuery>,
 	Json(request): Json<SessionAndPromptRequest>,
-) -> Result<Json<EmbeddingResponse>, BackendError> {
+) -> Result<Json<EncodedEmbeddingResponse>, BackendError> {
 	let SessionAndPromptRequest { session, prompt } = request;
-	embedding_handler(state, &endpoint_name, &session, &prompt)
+	embedding_handler(state, endpoint_name, session, prompt, query.encoding).await
 }
 
-fn embedding_handler(
+async fn embedding_handler(
 	state: Arc<Server>,
-	endpoint_name: &str,
-	_request: &SessionRequest,
-	prompt: &PromptRequest,
-) -> Result<Json<EmbeddingResponse>, BackendError> {
-	Ok(Json(state.backend.embedding(endpoint_name, prompt)?))
+	endpoint_name: String,
+	_request: SessionRequest,
+	prompt: PromptRequest,
+	encoding: EmbeddingEncoding,
+) -> Result<Json<EncodedEmbeddingResponse>, BackendError> {
+	tokio::task::spawn_blocking(move || {
+		let response = state.backend.embedding(&endpoint_name, &prompt)?;
+		Ok(Json(EncodedEmbeddingResponse::encode(response, encoding)))
+	})
+	.await
+	.unwrap()
 }
 
 async fn get_model_tokenize_handler(
@@ -66,7 +137,7 @@ async fn get_model_tokenize_handler(
 	Query(request): Query<SessionAndPromptRequest>,
 ) -> Result<Json<TokenizationResponse>, BackendError> {
 	let SessionAndPromptRequest { session, prompt } = request;
-	tokenize_handler(state, &endpoint_name, &session, &prompt)
+	tokenize_handler(state, endpoint_name, session, prompt).await
 }
 
 async fn post_model_tokenize_handler(
@@ -75,16 +146,67 @@ async fn post_model_tokenize_handler(
 	Json(request): Json<SessionAndPromptRequest>,
 ) -> Result<Json<TokenizationResponse>, BackendError> {
 	let SessionAndPromptRequest { session, prompt } = request;
-	tokenize_handler(state, &endpoint_name, &session, &prompt)
+	tokenize_handler(state, endpoint_name, session, prompt).await
 }
 
-fn tokenize_handler(
+async fn tokenize_handler(
 	state: Arc<Server>,
-	endpoint_name: &str,
-	_request: &SessionRequest,
-	prompt: &PromptRequest,
+	endpoint_name: String,
+	_request: SessionRequest,
+	prompt: PromptRequest,
 ) -> Result<Json<TokenizationResponse>, BackendError> {
-	Ok(Json(state.backend.tokenize(endpoint_name, prompt)?))
+	tokio::task::spawn_blocking(move || Ok(Json(state.backend.tokenize(&endpoint_name, &prompt)?)))
+		.await
+		.unwrap()
+}
+
+async fn post_model_detokenize_handler(
+	State(state): State<Arc<Server>>,
+	Path(endpoint_name): Path<String>,
+	Json(request): Json<DetokenizationRequest>,
+) -> Result<Json<DetokenizationResponse>, BackendError> {
+	tokio::task::spawn_blocking(move || Ok(Json(state.backend.detokenize(&endpoint_name, request.tokens)?)))
+		.await
+		.unwrap()
+}
+
+async fn post_model_score_handler(
+	State(state): State<Arc<Server>>,
+	Path(endpoint_name): Path<String>,
+	Json(request): Json<ScoreRequest>,
+) -> Result<Json<ScoreResponse>, BackendError> {
+	tokio::task::spawn_blocking(move || Ok(Json(state.backend.score(&endpoint_name, &request)?)))
+		.await
+		.unwrap()
+}
+
+#[derive(Deserialize, Debug)]
+struct ModelFileQuery {
+	/// Expected SHA-256 checksum (hex) of the file
+	sha256: Option<String>,
+
+	/// Whether to load the model after the file was stored
+	#[serde(default)]
+	load: bool,
+}
+
+/// Stores an uploaded model file at the location the model is loaded from
+async fn put_model_file_handler(
+	State(state): State<Arc<Server>>,
+	Path(model_name): Path<String>,
+	Query(query): Query<ModelFileQuery>,
+	body: BodyStream,
+) -> Result<Json<ModelFileResponse>, BackendError> {
+	let size = state
+		.backend
+		.store_model_file(&model_name, body, state.config.max_model_upload_size, query.sha256.as_deref())
+		.await?;
+
+	if query.load {
+		let backend = state.backend.clone();
+		tokio::task::spawn_blocking(move || backend.model(&model_name)).await.unwrap()?;
+	}
+	Ok(Json(ModelFileResponse { size }))
 }
 
 /// Middleware that checks whether the user has access to a certain model.
@@ -94,10 +216,8 @@ pub async fn authorize<T>(
 	req: Request<T>,
 	next: Next<T>,
 ) -> Result<impl IntoResponse, StatusCode> {
-	if let Some(models) = &claims.models {
-		if !models.contains(&model_name) {
-			return Err(StatusCode::UNAUTHORIZED);
-		}
+	if !claims.allows_model(&model_name) {
+		return Err(StatusCode::UNAUTHORIZED);
 	}
 
 	Ok(next.run(req).await)
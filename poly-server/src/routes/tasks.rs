@@ -1,4 +1,5 @@
 use std::{
+	collections::HashMap,
 	convert::Infallible,
 	sync::{
 		atomic::{AtomicBool, Ordering},
@@ -13,19 +14,28 @@ use axum::{
 		ws::{Message, WebSocket},
 		Path, Query, State, WebSocketUpgrade,
 	},
-	http::{Request, StatusCode},
+	http::{header::ACCEPT, HeaderMap, Request, StatusCode},
 	middleware::Next,
-	response::{sse::Event, IntoResponse, Sse},
+	response::{sse::Event, IntoResponse, Response, Sse},
 	routing::{get, post},
 	Extension, Json, Router,
 };
 use futures_util::Stream;
-use llm::InferenceResponse;
-use poly_backend::types::{GenerateResponse, PromptRequest, SessionAndPromptRequest, SessionRequest, Status, StatusResponse, TasksResponse};
+use llm::{InferenceResponse, InferenceStats};
+use poly_backend::cache::CachedCompletion;
+use poly_backend::config::{BiaserConfig, TaskConfig};
+use poly_backend::stats::Usage;
+use poly_backend::types::{
+	AbortResponse, BackendError as OriginalGenerateError, CompletionsResponse, CreateSessionResponse, FinishReason, GenerateResponse, PromptRequest,
+	SessionAndPromptRequest, SessionRequest, Status, StatusResponse, TasksResponse, ToolCall,
+};
+use poly_bias::json::JsonSchema;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, trace};
 
 use crate::{
 	api::{BackendError, JwtClaims},
+	audit::AuditRecord,
 	server::Server,
 };
 
@@ -33,21 +43,48 @@ pub fn router() -> Router<Arc<Server>, axum::body::Body> {
 	Router::new().route("/", get(tasks_handler)).nest(
 		"/:task",
 		Router::new()
+			.route("/", get(task_config_handler))
+			.route("/schema", get(task_schema_handler))
 			.route("/chat", get(ws_task_handler))
 			.route("/status", get(status_with_user_handler))
 			.route("/live", get(sse_task_handler))
 			.route("/completion", post(post_task_completion_handler))
 			.route("/completion", get(get_task_completion_handler))
+			.route("/cancel/:request_id", post(cancel_request_handler))
+			.route("/session", post(create_session_handler))
+			.route("/session/:session_id/completion", post(session_completion_handler))
 			.layer(axum::middleware::from_fn(authorize)),
 	)
 }
 
-async fn tasks_handler(State(state): State<Arc<Server>>) -> impl IntoResponse {
+/// Lists the tasks the caller is allowed to use
+async fn tasks_handler(State(state): State<Arc<Server>>, Extension(claims): Extension<JwtClaims>) -> impl IntoResponse {
 	Json(TasksResponse {
-		tasks: state.config.backend_config.tasks.keys().cloned().collect(),
+		tasks: state
+			.backend
+			.config()
+			.tasks
+			.keys()
+			.filter(|task_name| claims.allows_task(task_name))
+			.cloned()
+			.collect(),
 	})
 }
 
+/// Returns the effective configuration of a task (including any overrides specified as query parameters)
+async fn task_config_handler(
+	State(state): State<Arc<Server>>,
+	Path(task_name): Path<String>,
+	Query(request): Query<SessionRequest>,
+) -> Result<Json<TaskConfig>, BackendError> {
+	Ok(Json(state.backend.task_config(&task_name, &request)?))
+}
+
+/// Returns the JSON schema that the output of a task conforms to
+async fn task_schema_handler(State(state): State<Arc<Server>>, Path(task_name): Path<String>) -> Result<Json<JsonSchema>, BackendError> {
+	Ok(Json(state.backend.task_schema(&task_name)?))
+}
+
 async fn status_with_user_handler(Extension(current_user): Extension<JwtClaims>) -> impl IntoResponse {
 	tracing::info!("task request from user {:?}", current_user.sub);
 	Json(StatusResponse { status: Status::Ok })
@@ -56,34 +93,73 @@ async fn status_with_user_handler(Extension(current_user): Extension<JwtClaims>)
 async fn get_task_completion_handler(
 	State(state): State<Arc<Server>>,
 	Path(task_name): Path<String>,
+	Extension(claims): Extension<JwtClaims>,
+	headers: HeaderMap,
 	Query(request): Query<SessionRequest>,
 	Query(prompt): Query<PromptRequest>,
-) -> Result<Json<GenerateResponse>, BackendError> {
-	task_completion_handler(state, task_name, request, prompt).await
+) -> Result<Response, BackendError> {
+	task_completion_handler(state, task_name, claims, &headers, request, prompt).await
 }
 
 async fn post_task_completion_handler(
 	State(state): State<Arc<Server>>,
 	Path(task_name): Path<String>,
+	Extension(claims): Extension<JwtClaims>,
+	headers: HeaderMap,
 	Json(request): Json<SessionAndPromptRequest>,
-) -> Result<Json<GenerateResponse>, BackendError> {
-	task_completion_handler(state, task_name, request.session, request.prompt).await
+) -> Result<Response, BackendError> {
+	task_completion_handler(state, task_name, claims, &headers, request.session, request.prompt).await
+}
+
+/// Whether the client prefers a plain text response (`Accept: text/plain`) over the JSON envelope
+fn accepts_plaintext(headers: &HeaderMap) -> bool {
+	headers
+		.get(ACCEPT)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|accept| accept.split(',').next())
+		.is_some_and(|first| first.split(';').next().unwrap_or("").trim() == "text/plain")
 }
 
 async fn task_completion_handler(
 	state: Arc<Server>,
 	task_name: String,
+	claims: JwtClaims,
+	headers: &HeaderMap,
 	request: SessionRequest,
 	prompt: PromptRequest,
-) -> Result<Json<GenerateResponse>, BackendError> {
+) -> Result<Response, BackendError> {
+	let plaintext = accepts_plaintext(headers);
+	let n = request.n.unwrap_or(1);
+	if n == 0 || n > state.config.max_completions {
+		return Err(OriginalGenerateError::InvalidCompletionCount(state.config.max_completions).into());
+	}
+
+	let cache_key = state.backend.response_cache_key(&task_name, &request, &prompt)?;
+	let uses_tools = uses_tools(&state, &task_name)?;
+
 	tokio::task::spawn_blocking(move || {
-		let mut text = String::new();
-		state
-			.backend
-			.start(&task_name, &request, state.backend.clone())?
-			.complete(&prompt, |r| -> Result<_, poly_backend::types::BackendError> {
+		completion_response(request.n, plaintext, || {
+			// Deterministic responses may have been cached
+			if let Some(cached) = cache_key.as_ref().and_then(|key| state.backend.cached_response(key)) {
+				trace!("returning cached response");
+				return Ok(GenerateResponse {
+					tool_call: if uses_tools { ToolCall::parse(&cached.text) } else { None },
+					text: cached.text,
+					finish_reason: cached.finish_reason,
+					warnings: cached.warnings,
+					value: cached.value,
+					usage: cached.usage,
+					debug: None,
+				});
+			}
+
+			// Each completion is generated in a fresh session
+			let mut text = String::new();
+			let mut session = state.backend.start(&task_name, &request, state.backend.clone())?;
+			session.set_user(claims.sub.clone());
+			let result = session.complete(&prompt, |r| -> Result<_, poly_backend::types::BackendError> {
 				match r {
-					llm::InferenceResponse::InferredToken(t) => {
+					llm::InferenceResponse::InferredToken(t) | llm::InferenceResponse::PromptToken(t) => {
 						trace!("Output: {t}");
 						text += &t;
 						Ok(llm::InferenceFeedback::Continue)
@@ -91,7 +167,163 @@ async fn task_completion_handler(
 					_ => Ok(llm::InferenceFeedback::Continue),
 				}
 			})?;
-		Ok(Json(GenerateResponse { text }))
+
+			record_completion(&state, &claims, &task_name, &prompt, &text, &result.stats);
+
+			// Only cache completions that ended regularly
+			if let Some(ref key) = cache_key {
+				if !matches!(result.finish_reason, FinishReason::Error | FinishReason::Halted | FinishReason::Aborted) {
+					state.backend.cache_response(
+						key.clone(),
+						CachedCompletion {
+							text: text.clone(),
+							finish_reason: result.finish_reason,
+							warnings: result.warnings.clone(),
+							value: result.value.clone(),
+							usage: Usage::from(&result.stats),
+						},
+					);
+				}
+			}
+
+			Ok(GenerateResponse {
+				tool_call: if uses_tools { ToolCall::parse(&text) } else { None },
+				text,
+				finish_reason: result.finish_reason,
+				warnings: result.warnings,
+				value: result.value,
+				usage: Usage::from(&result.stats),
+				debug: session.take_bias_steps(),
+			})
+		})
+	})
+	.await
+	.unwrap()
+}
+
+/// Generates the requested number of completions (`n`, or a single one when not set) by calling `complete` for each, and
+/// builds the response. When `n` is set, the response lists the completions as choices; otherwise it is the single
+/// completion (as plain text when `plaintext` is set).
+fn completion_response(
+	n: Option<usize>,
+	plaintext: bool,
+	mut complete: impl FnMut() -> Result<GenerateResponse, OriginalGenerateError>,
+) -> Result<Response, BackendError> {
+	let mut choices = (0..n.unwrap_or(1)).map(|_| complete()).collect::<Result<Vec<_>, _>>()?;
+
+	if n.is_some() {
+		Ok(Json(CompletionsResponse { choices }).into_response())
+	} else {
+		let response = choices.pop().unwrap();
+		if plaintext {
+			Ok(response.text.into_response())
+		} else {
+			Ok(Json(response).into_response())
+		}
+	}
+}
+
+/// Signals generation for a request (started by the caller with the indicated `request_id`) to stop. The completion
+/// returns the output generated so far.
+async fn cancel_request_handler(
+	State(state): State<Arc<Server>>,
+	Path((task_name, request_id)): Path<(String, String)>,
+	Extension(claims): Extension<JwtClaims>,
+) -> Result<Json<AbortResponse>, BackendError> {
+	Ok(Json(AbortResponse {
+		aborted: state.backend.cancel_request(&task_name, &request_id, claims.sub.as_deref())?,
+	}))
+}
+
+/// Whether the output of a task is a tool call (i.e. the task uses a tools biaser)
+fn uses_tools(state: &Server, task_name: &str) -> Result<bool, BackendError> {
+	let config = state.backend.config();
+	let task_config = config
+		.tasks
+		.get(task_name)
+		.ok_or_else(|| OriginalGenerateError::TaskNotFound(task_name.to_string()))?;
+	Ok(matches!(task_config.biaser, Some(BiaserConfig::Tools(_))))
+}
+
+/// Records a completion in the audit log (if one is configured)
+fn record_completion(state: &Server, claims: &JwtClaims, task_name: &str, prompt: &PromptRequest, text: &str, stats: &InferenceStats) {
+	if let Some(ref audit_log) = state.audit_log {
+		audit_log.record(AuditRecord::Completion {
+			sub: claims.sub.clone(),
+			task: task_name.to_string(),
+			// The task may have been removed by a configuration reload while the completion was running
+			model: state.backend.config().tasks.get(task_name).map(|t| t.model.clone()).unwrap_or_default(),
+			prompt_length: prompt.prompt.chars().count(),
+			output_length: text.chars().count(),
+			prompt_tokens: stats.prompt_tokens,
+			predict_tokens: stats.predict_tokens,
+			duration_ms: (stats.feed_prompt_duration + stats.predict_duration).as_millis(),
+			prompt: audit_log.include_text().then(|| prompt.prompt.clone()),
+			output: audit_log.include_text().then(|| text.to_string()),
+		});
+	}
+}
+
+/// Starts a session that is kept so that it can be continued by later requests (see [session_completion_handler])
+async fn create_session_handler(
+	State(state): State<Arc<Server>>,
+	Path(task_name): Path<String>,
+	Extension(claims): Extension<JwtClaims>,
+	Json(request): Json<SessionRequest>,
+) -> Result<Json<CreateSessionResponse>, BackendError> {
+	let id = tokio::task::spawn_blocking(move || {
+		state
+			.backend
+			.create_session(&task_name, &request, claims.sub.clone(), state.backend.clone())
+	})
+	.await
+	.unwrap()?;
+	Ok(Json(CreateSessionResponse { id: id.to_string() }))
+}
+
+/// Continues a session created earlier by [create_session_handler]. The prompt is fed after the previous prompts and
+/// responses of the session, so these do not have to be repeated.
+async fn session_completion_handler(
+	State(state): State<Arc<Server>>,
+	Path((task_name, session_id)): Path<(String, String)>,
+	Extension(claims): Extension<JwtClaims>,
+	headers: HeaderMap,
+	Json(prompt): Json<PromptRequest>,
+) -> Result<Response, BackendError> {
+	let plaintext = accepts_plaintext(&headers);
+	let session = state.backend.stored_session(&task_name, &session_id)?;
+	let uses_tools = uses_tools(&state, &task_name)?;
+
+	tokio::task::spawn_blocking(move || {
+		let mut session = session.lock().unwrap();
+		session.set_user(claims.sub.clone());
+
+		let mut text = String::new();
+		let result = session.complete(&prompt, |r| -> Result<_, poly_backend::types::BackendError> {
+			match r {
+				llm::InferenceResponse::InferredToken(t) | llm::InferenceResponse::PromptToken(t) => {
+					trace!("Output: {t}");
+					text += &t;
+					Ok(llm::InferenceFeedback::Continue)
+				}
+				_ => Ok(llm::InferenceFeedback::Continue),
+			}
+		})?;
+		record_completion(&state, &claims, &task_name, &prompt, &text, &result.stats);
+
+		if plaintext {
+			return Ok(text.into_response());
+		}
+		Ok(Json(GenerateResponse {
+			tool_call: if uses_tools { ToolCall::parse(&text) } else { None },
+			text,
+			finish_reason: result.finish_reason,
+			warnings: result.warnings,
+			value: result.value,
+			usage: Usage::from(&result.stats),
+			debug: session.take_bias_steps(),
+		})
+		.into_response())
 	})
 	.await
 	.unwrap()
@@ -101,22 +333,74 @@ async fn ws_task_handler(
 	ws: WebSocketUpgrade,
 	State(state): State<Arc<Server>>,
 	Path(task_name): Path<String>,
+	Extension(claims): Extension<JwtClaims>,
 	Query(request): Query<SessionRequest>,
 ) -> impl IntoResponse {
 	debug!("New websocket connection for task '{}'", task_name.as_str());
-	ws.on_upgrade(move |socket| socket_task_handler(socket, state, task_name, request))
+	ws.on_upgrade(move |socket| socket_task_handler(socket, state, task_name, claims, request))
+}
+
+/// Control message that can be sent over a chat WebSocket instead of a prompt (e.g. `{"switch_task": "other_task"}`)
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum ChatControlMessage {
+	/// Start a new session for the indicated task
+	SwitchTask(String),
 }
 
-async fn socket_task_handler(mut ws: WebSocket, state: Arc<Server>, task_name: String, request: SessionRequest) {
+/// Message sent over a chat WebSocket after the output of a task that is configured with tools (before the empty
+/// message that ends the response)
+#[derive(Serialize)]
+struct ChatToolCall {
+	tool_call: ToolCall,
+}
+
+enum ChatCommand {
+	Prompt(String),
+	SwitchTask(String),
+}
+
+async fn socket_task_handler(mut ws: WebSocket, state: Arc<Server>, task_name: String, claims: JwtClaims, request: SessionRequest) {
 	// Spawn a blocking thread
 	let (tx_prompt, mut rx_prompt) = tokio::sync::mpsc::channel(16);
 	let (tx_response, mut rx_response) = tokio::sync::mpsc::channel::<Result<String, String>>(32);
+	let session_claims = claims.clone();
 	let t = tokio::task::spawn_blocking(move || {
+		let claims = session_claims;
+		let mut task_name = task_name;
 		let mut session = state.backend.start(&task_name, &request, state.backend.clone()).unwrap();
-		while let Some(prompt) = rx_prompt.blocking_recv() {
-			let prompt_request = PromptRequest { prompt };
+		session.set_user(claims.sub.clone());
+		while let Some(command) = rx_prompt.blocking_recv() {
+			let prompt = match command {
+				ChatCommand::Prompt(prompt) => prompt,
+				ChatCommand::SwitchTask(new_task_name) => {
+					let res = state
+						.backend
+						.start(&new_task_name, &request, state.backend.clone())
+						.map(|mut new_session| {
+							tracing::info!("WebSocket: switched to task {new_task_name}");
+							new_session.set_user(claims.sub.clone());
+							session = new_session;
+							task_name = new_task_name;
+							// Send empty token to signal the switch has completed
+							"".to_string()
+						});
+					if tx_response.blocking_send(res.map_err(|e| e.to_string())).is_err() {
+						// Output channel was probably dropped
+						break;
+					}
+					continue;
+				}
+			};
+
+			let prompt_request = PromptRequest {
+				prompt,
+				assistant_prefix: None,
+			};
+			let mut text = String::new();
 			let res = session.complete(&prompt_request, |r| match r {
-				InferenceResponse::InferredToken(token) => {
+				InferenceResponse::InferredToken(token) | InferenceResponse::PromptToken(token) => {
+					text += &token;
 					if tx_response.blocking_send(Ok(token)).is_err() {
 						// Connection is likely closed
 						return Ok(llm::InferenceFeedback::Halt);
@@ -124,11 +408,24 @@ async fn socket_task_handler(mut ws: WebSocket, state: Arc<Server>, task_name: S
 					Ok(llm::InferenceFeedback::Continue)
 				}
 				InferenceResponse::EotToken => Ok(llm::InferenceFeedback::Halt),
-				InferenceResponse::PromptToken(_) | InferenceResponse::SnapshotToken(_) => Ok(llm::InferenceFeedback::Continue),
+				InferenceResponse::SnapshotToken(_) => Ok(llm::InferenceFeedback::Continue),
 			});
 
 			match res {
-				Ok(_) => {
+				Ok(result) => {
+					record_completion(&state, &claims, &task_name, &prompt_request, &text, &result.stats);
+
+					// For tasks configured with tools, send the parsed tool call before ending the cycle
+					if uses_tools(&state, &task_name).unwrap_or(false) {
+						if let Some(tool_call) = ToolCall::parse(&text) {
+							let message = serde_json::to_string(&ChatToolCall { tool_call }).unwrap();
+							if tx_response.blocking_send(Ok(message)).is_err() {
+								// Output channel was probably dropped
+								break;
+							}
+						}
+					}
+
 					// Send empty token to signal this cycle has ended
 					if tx_response.blocking_send(Ok("".to_string())).is_err() {
 						// Output channel was probably dropped
@@ -157,8 +454,17 @@ async fn socket_task_handler(mut ws: WebSocket, state: Arc<Server>, task_name: S
 
 					match msg.unwrap() {
 						Message::Text(prompt) => {
-							tracing::trace!("WebSocket receive prompt text: {prompt}");
-							tx_prompt.send(prompt).await.unwrap();
+							if let Ok(ChatControlMessage::SwitchTask(task_name)) = serde_json::from_str(&prompt) {
+								if !claims.allows_task(&task_name) {
+									tracing::error!("WebSocket: not authorized to switch to task {task_name}");
+									_ = ws.close().await;
+									break;
+								}
+								tx_prompt.send(ChatCommand::SwitchTask(task_name)).await.unwrap();
+							} else {
+								tracing::trace!("WebSocket receive prompt text: {prompt}");
+								tx_prompt.send(ChatCommand::Prompt(prompt)).await.unwrap();
+							}
 						},
 						Message::Close(_close_frame) => {
 							_ = ws.close().await;
@@ -197,9 +503,71 @@ async fn socket_task_handler(mut ws: WebSocket, state: Arc<Server>, task_name: S
 	tracing::info!("WebSocket connection closed");
 }
 
+/// A token sent to clients of the live (server-sent events) endpoint in a `token` event
+#[derive(Serialize)]
+struct LiveToken {
+	token: String,
+
+	/// Index of the token in the stream (starting at zero)
+	index: usize,
+}
+
+/// Payload of the `done` event that is sent to clients of the live endpoint when generation has ended
+#[derive(Serialize)]
+struct LiveDone {
+	finish_reason: FinishReason,
+
+	/// The parsed tool call (only for tasks that are configured with tools)
+	#[serde(skip_serializing_if = "Option::is_none")]
+	tool_call: Option<ToolCall>,
+}
+
+/// Runs a completion (using `complete`, which returns the payload for the final event) and sends a `token` event for
+/// each token to `tx`, followed by a `done` event. Generation is halted when `active` is cleared or the receiver is
+/// dropped.
+fn stream_completion(
+	tx: &tokio::sync::mpsc::Sender<Event>,
+	active: &AtomicBool,
+	complete: impl FnOnce(
+		&mut dyn FnMut(InferenceResponse) -> Result<llm::InferenceFeedback, poly_backend::types::BackendError>,
+	) -> Result<LiveDone, poly_backend::types::BackendError>,
+) {
+	let mut index = 0;
+	let result = complete(&mut |r| match r {
+		InferenceResponse::InferredToken(token) | InferenceResponse::PromptToken(token) => {
+			let event = Event::default().event("token").json_data(LiveToken { token, index }).unwrap();
+			index += 1;
+
+			// Do not continue when client has disconnected
+			if tx.is_closed() || !active.load(Ordering::SeqCst) || tx.blocking_send(event).is_err() {
+				debug!("client has disconnected live session, halting generation");
+				return Ok(llm::InferenceFeedback::Halt);
+			}
+			Ok(llm::InferenceFeedback::Continue)
+		}
+		_ => Ok(llm::InferenceFeedback::Continue),
+	});
+
+	// Send a terminal event indicating why generation ended
+	let done = match result {
+		Ok(done) => done,
+		Err(e) => {
+			tracing::error!("live session ended with error: {e}");
+			LiveDone {
+				finish_reason: FinishReason::Error,
+				tool_call: None,
+			}
+		}
+	};
+
+	// This may fail when the client has disconnected, but we don't care (anymore)
+	_ = tx.blocking_send(Event::default().event("done").json_data(done).unwrap());
+}
+
 async fn sse_task_handler(
 	State(state): State<Arc<Server>>,
 	Path(task_name): Path<String>,
+	Extension(claims): Extension<JwtClaims>,
 	Query(request): Query<SessionRequest>,
 	Query(prompt): Query<PromptRequest>,
 ) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, BackendError> {
@@ -209,27 +577,28 @@ async fn sse_task_handler(
 	let active = Arc::new(AtomicBool::new(true));
 	let active_clone = active.clone();
 
-	let mut session = state.backend.start(&task_name, &request, state.backend.clone()).unwrap();
+	let uses_tools = uses_tools(&state, &task_name)?;
+	let backend = state.backend.clone();
+	let start_task_name = task_name.clone();
+	let mut session = tokio::task::spawn_blocking(move || backend.start(&start_task_name, &request, backend.clone()))
+		.await
+		.unwrap()?;
+	session.set_user(claims.sub.clone());
 
 	tokio::task::spawn_blocking(move || {
-		session.complete(&prompt, |r| -> Result<_, poly_backend::types::BackendError> {
-			match r {
-				llm::InferenceResponse::InferredToken(t) => {
-					let tx = tx.clone();
-
-					// Do not continue when client has disconnected
-					if tx.is_closed() || !active_clone.load(Ordering::SeqCst) {
-						debug!("client has disconnected live session, halting generation");
-						return Ok(llm::InferenceFeedback::Halt);
-					}
-					tokio::spawn(async move {
-						// This may fail when a client disconnects while we are generating a token, but we don't care (anymore).
-						tx.send(t).await
-					});
-					Ok(llm::InferenceFeedback::Continue)
+		stream_completion(&tx, &active_clone, |callback| {
+			let mut text = String::new();
+			let result = session.complete(&prompt, |r| {
+				if let InferenceResponse::InferredToken(ref t) | InferenceResponse::PromptToken(ref t) = r {
+					text += t;
 				}
-				_ => Ok(llm::InferenceFeedback::Continue),
-			}
+				callback(r)
+			})?;
+			record_completion(&state, &claims, &task_name, &prompt, &text, &result.stats);
+			Ok(LiveDone {
+				finish_reason: result.finish_reason,
+				tool_call: if uses_tools { ToolCall::parse(&text) } else { None },
+			})
 		})
 	});
 
@@ -247,8 +616,7 @@ async fn sse_task_handler(
 		let _guard = Guard{ flag: active };
 		loop {
 			match rx.recv().await {
-				Some(token) => {
-					let evt = Event::default().id("token").data(token);
+				Some(evt) => {
 					yield Ok(evt);
 				},
 				None => return
@@ -265,16 +633,156 @@ async fn sse_task_handler(
 
 /// Middleware that checks whether the user has access to a certain task.
 pub async fn authorize<T>(
-	Path(task_name): Path<String>,
+	Path(params): Path<HashMap<String, String>>,
 	Extension(claims): Extension<JwtClaims>,
 	req: Request<T>,
 	next: Next<T>,
 ) -> Result<impl IntoResponse, StatusCode> {
-	if let Some(tasks) = &claims.tasks {
-		if !tasks.contains(&task_name) {
-			return Err(StatusCode::UNAUTHORIZED);
-		}
+	// Nested routes may have other parameters besides the task name (e.g. the session ID)
+	let Some(task_name) = params.get("task") else {
+		return Err(StatusCode::UNAUTHORIZED);
+	};
+	if !claims.allows_task(task_name) {
+		return Err(StatusCode::UNAUTHORIZED);
 	}
 
 	Ok(next.run(req).await)
 }
+
+#[cfg(test)]
+mod test {
+	use std::{convert::Infallible, sync::atomic::AtomicBool};
+
+	use axum::response::{IntoResponse, Response, Sse};
+	use llm::{InferenceFeedback, InferenceResponse};
+	use poly_backend::{
+		stats::Usage,
+		types::{BackendError, FinishReason, GenerateResponse, ToolCall},
+	};
+
+	use super::{completion_response, stream_completion, LiveDone};
+
+	/// Streams the indicated tokens using [stream_completion] and returns the events as received by a client. The final
+	/// event is derived from the streamed text by `done`.
+	async fn stream_events(tokens: &'static [&'static str], done: fn(&str) -> LiveDone) -> Vec<String> {
+		let (tx, mut rx) = tokio::sync::mpsc::channel(32);
+		tokio::task::spawn_blocking(move || {
+			let active = AtomicBool::new(true);
+			stream_completion(&tx, &active, |callback| {
+				let mut text = String::new();
+				for token in tokens {
+					text += token;
+					assert!(matches!(
+						callback(InferenceResponse::InferredToken(token.to_string())),
+						Ok(InferenceFeedback::Continue)
+					));
+				}
+				Ok(done(&text))
+			});
+		})
+		.await
+		.unwrap();
+
+		// Consume the stream as a client would
+		let events = async_stream::stream! {
+			while let Some(event) = rx.recv().await {
+				yield Ok::<_, Infallible>(event);
+			}
+		};
+		let body = hyper::body::to_bytes(Sse::new(events).into_response().into_body()).await.unwrap();
+		let body = String::from_utf8(body.to_vec()).unwrap();
+		body.split("\n\n").filter(|e| !e.is_empty()).map(|e| e.to_string()).collect()
+	}
+
+	#[tokio::test]
+	async fn test_stream_completion() {
+		let events = stream_events(&["Hello", ",", " world"], |_| LiveDone {
+			finish_reason: FinishReason::MaxTokens,
+			tool_call: None,
+		})
+		.await;
+		assert_eq!(
+			events,
+			vec![
+				"event: token\ndata: {\"token\":\"Hello\",\"index\":0}",
+				"event: token\ndata: {\"token\":\",\",\"index\":1}",
+				"event: token\ndata: {\"token\":\" world\",\"index\":2}",
+				"event: done\ndata: {\"finish_reason\":\"max_tokens\"}",
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_stream_tool_call() {
+		let events = stream_events(&["{\"tool\": \"weather\", ", "\"arguments\": {\"city\": \"Delft\"}}"], |text| LiveDone {
+			finish_reason: FinishReason::EndOfText,
+			tool_call: ToolCall::parse(text),
+		})
+		.await;
+		assert_eq!(
+			events.last().unwrap(),
+			"event: done\ndata: {\"finish_reason\":\"end_of_text\",\"tool_call\":{\"tool\":\"weather\",\"arguments\":{\"city\":\"Delft\"}}}"
+		);
+	}
+
+	/// A completion with the indicated text
+	fn choice(text: &str) -> GenerateResponse {
+		GenerateResponse {
+			text: text.to_string(),
+			finish_reason: FinishReason::EndOfText,
+			warnings: vec![],
+			tool_call: None,
+			value: None,
+			usage: Usage::default(),
+			debug: None,
+		}
+	}
+
+	async fn response_body(response: Response) -> String {
+		let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+		String::from_utf8(body.to_vec()).unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_completion_response() {
+		// When three completions are requested, three choices are returned
+		let mut generated = 0;
+		let Ok(response) = completion_response(Some(3), false, || {
+			generated += 1;
+			Ok(choice(&format!("choice {generated}")))
+		}) else {
+			panic!("expected a response");
+		};
+		let body: serde_json::Value = serde_json::from_str(&response_body(response).await).unwrap();
+		let choices = body["choices"].as_array().unwrap();
+		assert_eq!(choices.len(), 3);
+		assert_eq!(
+			choices.iter().map(|c| c["text"].as_str().unwrap()).collect::<Vec<_>>(),
+			["choice 1", "choice 2", "choice 3"]
+		);
+
+		// Without a number of completions, the single completion is returned by itself
+		let Ok(response) = completion_response(None, false, || Ok(choice("single"))) else {
+			panic!("expected a response");
+		};
+		let body: serde_json::Value = serde_json::from_str(&response_body(response).await).unwrap();
+		assert_eq!(body["text"], "single");
+
+		let Ok(response) = completion_response(None, true, || Ok(choice("single"))) else {
+			panic!("expected a response");
+		};
+		assert_eq!(response_body(response).await, "single");
+
+		// An error for any of the completions fails the request
+		let mut generated = 0;
+		let result = completion_response(Some(3), false, || {
+			generated += 1;
+			if generated == 2 {
+				return Err(BackendError::InvalidParameter(String::from("test")));
+			}
+			Ok(choice("choice"))
+		});
+		assert!(result.is_err());
+		assert_eq!(generated, 2);
+	}
+}
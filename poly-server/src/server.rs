@@ -1,12 +1,23 @@
-use crate::config::Config;
-use std::sync::Arc;
+use crate::{audit::AuditLog, config::Config, queue::RequestQueue};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 use tokio::sync::mpsc::{channel, Sender};
 
 use poly_backend::backend::Backend;
 
+/// Interval at which is checked whether models should be unloaded because they are idle
+const IDLE_MODEL_CHECK_INTERVAL_SECS: u64 = 10;
+
+/// Interval at which stored sessions that have expired are discarded
+const EXPIRED_SESSION_CHECK_INTERVAL_SECS: u64 = 10;
+
 pub struct Server {
 	pub backend: Arc<Backend>,
 	pub config: Config,
+	pub audit_log: Option<AuditLog>,
+	pub queue: RequestQueue,
+
+	/// Path of the file the configuration was read from (used to reload the configuration)
+	pub config_path: Option<PathBuf>,
 	ingest_sender: Sender<IngestItem>,
 }
 
@@ -17,7 +28,7 @@ pub struct IngestItem {
 }
 
 impl Server {
-	pub fn new(backend: Arc<Backend>, config: Config) -> Self {
+	pub fn new(backend: Arc<Backend>, config: Config, config_path: Option<PathBuf>) -> Self {
 		// Queue for ingest
 		let ingest_backend = backend.clone();
 		let (tx, mut rx) = channel::<IngestItem>(32);
@@ -33,9 +44,44 @@ impl Server {
 			tracing::info!("ending ingest worker");
 		});
 
+		// Periodically unload models that are idle (also when no model is configured to be unloaded, as this may change
+		// when the configuration is reloaded)
+		let unload_backend = backend.clone();
+		tokio::spawn(async move {
+			let mut interval = tokio::time::interval(Duration::from_secs(IDLE_MODEL_CHECK_INTERVAL_SECS));
+			loop {
+				interval.tick().await;
+				unload_backend.unload_idle_models();
+			}
+		});
+
+		// Periodically discard stored sessions that have not been used for a while
+		let session_backend = backend.clone();
+		tokio::spawn(async move {
+			let mut interval = tokio::time::interval(Duration::from_secs(EXPIRED_SESSION_CHECK_INTERVAL_SECS));
+			loop {
+				interval.tick().await;
+				session_backend.evict_expired_sessions();
+			}
+		});
+
+		let audit_log = config
+			.audit_log
+			.as_ref()
+			.map(|audit_log_config| AuditLog::new(audit_log_config.clone()).expect("open audit log"));
+
+		let queue = RequestQueue::new(
+			config.max_concurrent,
+			config.max_queue_depth,
+			config.queue_timeout_secs.map(Duration::from_secs),
+		);
+
 		Server {
 			backend,
 			config,
+			audit_log,
+			queue,
+			config_path,
 			ingest_sender: tx,
 		}
 	}
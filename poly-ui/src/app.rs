@@ -19,6 +19,8 @@ pub struct App {
 	selected_task: Option<String>,
 	running: bool,
 	loading_progress: f64,
+	loading_error: Option<String>,
+	loading_attempt: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -27,6 +29,7 @@ pub enum AppMessage {
 	CopyText(String),
 	Interrupt,
 	Reset,
+	Retry,
 	Send,
 	Type(String),
 	WorkerEvent(LLMWorkerEvent),
@@ -46,6 +49,8 @@ impl Application for App {
 				sender: None,
 				running: false,
 				loading_progress: 0.0,
+				loading_error: None,
+				loading_attempt: 0,
 				tasks: vec![],
 				selected_task: None,
 			},
@@ -58,7 +63,7 @@ impl Application for App {
 	}
 
 	fn subscription(&self) -> Subscription<Self::Message> {
-		crate::worker::llm_worker().map(AppMessage::WorkerEvent)
+		crate::worker::llm_worker(self.loading_attempt).map(AppMessage::WorkerEvent)
 	}
 
 	fn update(&mut self, message: Self::Message) -> Command<AppMessage> {
@@ -115,6 +120,11 @@ impl Application for App {
 
 						return scrollable::snap_to(CHAT_MESSAGES_SCROLLABLE_ID.clone(), RelativeOffset::END);
 					}
+					LLMWorkerEvent::Error(message) => {
+						self.sender = None;
+						self.running = false;
+						self.loading_error = Some(message);
+					}
 				};
 			}
 			AppMessage::Send => {
@@ -127,6 +137,13 @@ impl Application for App {
 					sender.try_send(LLMWorkerCommand::Prompt(message)).unwrap();
 				}
 			}
+			AppMessage::Retry => {
+				// Changing the attempt number makes the subscription start a new worker
+				self.loading_error = None;
+				self.loading_progress = 0.0;
+				self.messages.clear();
+				self.loading_attempt += 1;
+			}
 			AppMessage::Reset => {
 				self.messages.clear();
 				if let Some(ref mut sender) = self.sender {
@@ -144,19 +161,29 @@ impl Application for App {
 
 	fn view(&self) -> Element<AppMessage> {
 		if self.sender.is_none() {
-			return container(
+			let status = if let Some(ref error) = self.loading_error {
+				column![
+					text("Could not load models").size(25).horizontal_alignment(Horizontal::Center),
+					text(error)
+						.horizontal_alignment(Horizontal::Center)
+						.style(iced::theme::Text::Color(Color::from_rgb8(200, 40, 40))),
+					button("Retry").on_press(AppMessage::Retry)
+				]
+				.align_items(Alignment::Center)
+			} else {
 				column![
 					text("Loading models...").size(25).horizontal_alignment(Horizontal::Center),
 					progress_bar(0.0..=1.0, self.loading_progress as f32)
 				]
-				.spacing(10),
-			)
-			.height(Length::Fill)
-			.width(Length::Fill)
-			.align_y(iced::alignment::Vertical::Center)
-			.align_x(Horizontal::Center)
-			.padding(30)
-			.into();
+			};
+
+			return container(status.spacing(10))
+				.height(Length::Fill)
+				.width(Length::Fill)
+				.align_y(iced::alignment::Vertical::Center)
+				.align_x(Horizontal::Center)
+				.padding(30)
+				.into();
 		}
 
 		let input: Element<AppMessage> = if self.running {
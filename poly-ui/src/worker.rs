@@ -15,7 +15,8 @@ use iced::{
 use poly_backend::{
 	backend::{Backend, InferenceFeedback, InferenceResponse},
 	config::BackendConfig,
-	types::{PromptRequest, SessionRequest},
+	session::BackendSession,
+	types::{BackendError, PromptRequest, SessionRequest},
 };
 use tokio::{select, task::spawn_blocking};
 
@@ -31,6 +32,7 @@ pub enum LLMWorkerEvent {
 	},
 	Running(bool),
 	ResponseToken(String),
+	Error(String),
 }
 
 pub enum LLMWorkerCommand {
@@ -44,64 +46,104 @@ enum LLMWorkerState {
 	Ready(mpsc::Receiver<LLMWorkerCommand>),
 }
 
-pub fn llm_worker() -> Subscription<LLMWorkerEvent> {
-	struct LLMWorker;
-
-	subscription::channel(std::any::TypeId::of::<LLMWorker>(), 100, move |mut output| async move {
-		let mut state = LLMWorkerState::Starting;
-
-		let mut config_file_path = resource_path("config.toml");
-
-		// Check if the user has a local override config
-		if let Some(proj_dirs) = ProjectDirs::from("nl", "Dialogic", "Poly") {
-			let config_dir = proj_dirs.config_dir();
-			let user_config_path = config_dir.join("config.toml");
-			tracing::info!("Looking for configuration file at {}", user_config_path.to_str().unwrap());
-			if user_config_path.exists() {
-				config_file_path = user_config_path;
-				tracing::info!("Using user configuration file");
-			} else {
-				tracing::info!("Using built-in configuration file");
+/// Load the configuration and instantiate the backend, reporting loading progress through `output`
+async fn load_backend(output: &mut mpsc::Sender<LLMWorkerEvent>) -> Result<(Arc<Backend>, Vec<String>, String), String> {
+	let mut config_file_path = resource_path("config.toml");
+
+	// Check if the user has a local override config
+	if let Some(proj_dirs) = ProjectDirs::from("nl", "Dialogic", "Poly") {
+		let config_dir = proj_dirs.config_dir();
+		let user_config_path = config_dir.join("config.toml");
+		tracing::info!("Looking for configuration file at {}", user_config_path.display());
+		if user_config_path.exists() {
+			config_file_path = user_config_path;
+			tracing::info!("Using user configuration file");
+		} else {
+			tracing::info!("Using built-in configuration file");
+		}
+	}
+
+	// Load the config file
+	let mut config_file =
+		File::open(&config_file_path).map_err(|e| format!("Could not open configuration file {}: {e}", config_file_path.display()))?;
+	let mut config_string = String::new();
+	config_file
+		.read_to_string(&mut config_string)
+		.map_err(|e| format!("Could not read configuration file {}: {e}", config_file_path.display()))?;
+
+	let mut config: BackendConfig = toml::from_str(&config_string).map_err(|e| format!("Invalid configuration file: {e}"))?;
+
+	// Update model paths
+	for (_k, model_config) in config.models.iter_mut() {
+		if let Some(ref model_path) = model_config.model_path {
+			let model_path_str = model_path.to_string_lossy();
+			// Paths that are prefixed with '@' are relative to the data folder
+			if let Some(relative_path) = model_path_str.strip_prefix('@') {
+				model_config.model_path = Some(resource_path(relative_path));
 			}
 		}
+	}
+
+	let mut task_names: Vec<String> = config.tasks.keys().cloned().collect();
+	task_names.sort();
+	let selected_task_name = config
+		.tasks
+		.keys()
+		.next()
+		.ok_or_else(|| String::from("The configuration does not define any tasks"))?
+		.clone();
+
+	// Load backend
+	let (ptx, mut prx) = tokio::sync::mpsc::channel(32);
+	let backend_future = Backend::from(config, Some(ptx));
+
+	let mut output2 = output.clone();
+	tokio::spawn(async move {
+		while let Some(progress) = prx.recv().await {
+			let _ = output2.send(LLMWorkerEvent::Loading(progress)).await;
+		}
+	});
 
-		// Load the config file
-		let mut config_file = File::open(config_file_path).expect("open config file");
-		let mut config_string = String::new();
-		config_file.read_to_string(&mut config_string).expect("read config file");
-
-		let mut config: BackendConfig = toml::from_str(&config_string).unwrap();
+	let backend = backend_future.await.map_err(|e| format!("Could not load models: {e}"))?;
 
-		// Update model paths
-		for (_k, model_config) in config.models.iter_mut() {
-			if let Some(ref model_path) = model_config.model_path {
-				let model_path_str = model_path.to_str().unwrap();
-				// Paths that are prefixed with '@' are relative to the data folder
-				if model_path_str.starts_with('@') {
-					model_config.model_path = Some(resource_path(model_path_str.strip_prefix('@').unwrap()));
-				}
-			}
-		}
+	Ok((Arc::new(backend), task_names, selected_task_name))
+}
 
-		let mut task_names: Vec<String> = config.tasks.keys().cloned().collect();
-		task_names.sort();
-		let mut selected_task_name = config.tasks.keys().next().unwrap().clone();
+/// Start a session for a task on a blocking thread (starting a session may load the model of the task)
+async fn start_session(backend: &Arc<Backend>, task_name: &str) -> Result<BackendSession, BackendError> {
+	let backend = backend.clone();
+	let task_name = task_name.to_string();
+	spawn_blocking(move || backend.start(&task_name, &SessionRequest::default(), backend.clone()))
+		.await
+		.unwrap()
+}
 
-		// Load backend
-		let backend = Arc::new({
-			let (ptx, mut prx) = tokio::sync::mpsc::channel(32);
-			let backend_future = Backend::from(config, Some(ptx));
+/// Subscription to the worker that runs the backend. A new worker is started (and the backend is loaded again) whenever
+/// `attempt` changes, which allows retrying after an error.
+pub fn llm_worker(attempt: usize) -> Subscription<LLMWorkerEvent> {
+	struct LLMWorker;
 
-			let mut output2 = output.clone();
-			tokio::spawn(async move {
-				while let Some(progress) = prx.recv().await {
-					output2.send(LLMWorkerEvent::Loading(progress)).await.unwrap();
-				}
-			});
+	subscription::channel((std::any::TypeId::of::<LLMWorker>(), attempt), 100, move |mut output| async move {
+		let mut state = LLMWorkerState::Starting;
 
-			tokio::spawn(backend_future).await.unwrap()
-		});
-		let mut session = backend.start(&selected_task_name, &SessionRequest {}, backend.clone()).unwrap();
+		let (backend, task_names, mut selected_task_name) = match load_backend(&mut output).await {
+			Ok(loaded) => loaded,
+			Err(message) => {
+				tracing::error!("error loading backend: {message}");
+				let _ = output.send(LLMWorkerEvent::Error(message)).await;
+				return std::future::pending().await;
+			}
+		};
+
+		let mut session = match start_session(&backend, &selected_task_name).await {
+			Ok(session) => session,
+			Err(e) => {
+				let _ = output
+					.send(LLMWorkerEvent::Error(format!("Could not start task {selected_task_name}: {e}")))
+					.await;
+				return std::future::pending().await;
+			}
+		};
 
 		loop {
 			match &mut state {
@@ -130,7 +172,15 @@ pub fn llm_worker() -> Subscription<LLMWorkerEvent> {
 						LLMWorkerCommand::Reset { task_name } => {
 							// Create a new session
 							selected_task_name = task_name;
-							session = backend.start(&selected_task_name, &SessionRequest {}, backend.clone()).unwrap();
+							session = match start_session(&backend, &selected_task_name).await {
+								Ok(session) => session,
+								Err(e) => {
+									let _ = output
+										.send(LLMWorkerEvent::Error(format!("Could not start task {selected_task_name}: {e}")))
+										.await;
+									return std::future::pending().await;
+								}
+							};
 						}
 
 						LLMWorkerCommand::Interrupt => {}
@@ -146,7 +196,11 @@ pub fn llm_worker() -> Subscription<LLMWorkerEvent> {
 							let session_fut = spawn_blocking(move || {
 								// Swallow errors. Typically 'context full'
 								// TODO handle this in a better way
-								let _ = session.complete(&PromptRequest { prompt }, |feo| {
+								let request = PromptRequest {
+									prompt,
+									assistant_prefix: None,
+								};
+								let _ = session.complete(&request, |feo| {
 									match feo {
 										InferenceResponse::SnapshotToken(_) => {}
 										InferenceResponse::PromptToken(_) => {}